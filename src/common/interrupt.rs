@@ -0,0 +1,87 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+// A shared interrupt request line
+// On real hardware the line is open collector, so it is asserted while any device is pulling it low
+// Each device drives its own bit of the line so that releasing it does not affect other devices
+#[derive(Clone, Default)]
+pub struct InterruptLine {
+    sources: Rc<Cell<u32>>,
+}
+
+impl InterruptLine {
+    pub fn new() -> InterruptLine {
+        InterruptLine {
+            sources: Rc::new(Cell::new(0)),
+        }
+    }
+
+    // Assert or release the line on behalf of a single source
+    pub fn set(&self, source: InterruptSource, asserted: bool) {
+        let bit: u32 = 1 << (source as u32);
+        if asserted {
+            self.sources.set(self.sources.get() | bit);
+        } else {
+            self.sources.set(self.sources.get() & !bit);
+        }
+    }
+
+    // Check if any source is asserting the line
+    pub fn is_asserted(&self) -> bool {
+        self.sources.get() != 0
+    }
+}
+
+// The devices that are able to drive an interrupt line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptSource {
+    Mapper,
+    Ppu,
+    Apu,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_is_shared_between_clones() {
+        let line: InterruptLine = InterruptLine::new();
+        let device_line: InterruptLine = line.clone();
+
+        // Verify line is initially released
+        assert!(!line.is_asserted());
+
+        // Assert the line from the device side
+        device_line.set(InterruptSource::Mapper, true);
+        assert!(line.is_asserted());
+
+        // Release the line from the device side
+        device_line.set(InterruptSource::Mapper, false);
+        assert!(!line.is_asserted());
+    }
+}
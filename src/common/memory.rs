@@ -22,6 +22,26 @@
 // SOFTWARE.
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Trait for devices that respond to reads and writes within a range of the address space,
+// such as cartridge mappers or memory mapped registers
+pub trait MemoryMappedDevice {
+    // Read a single byte from the device at an absolute address
+    fn read_byte(&mut self, address: usize) -> u8;
+
+    // Write a single byte to the device at an absolute address
+    fn write_byte(&mut self, address: usize, value: u8);
+}
+
+// Structure for a device mapped into an inclusive range of the address space
+struct MemoryMapping {
+    start: usize,
+    end: usize,
+    device: Rc<RefCell<dyn MemoryMappedDevice>>,
+}
+
 // Structure for Memory
 pub struct Memory {
     // The size of the memory pool in bytes
@@ -29,6 +49,12 @@ pub struct Memory {
 
     // The raw memory stored as a vector of bytes (u8)
     raw_memory: Vec<u8>,
+
+    // Devices which take over part of the address space from the raw memory
+    mappings: Vec<MemoryMapping>,
+
+    // The address of the most recent access, if it was a read
+    last_read_address: Option<usize>,
 }
 
 // Implementation for Memory
@@ -39,7 +65,32 @@ impl Memory {
         let raw_memory: Vec<u8> = vec![0; size];
 
         // Create the Memory object and return it
-        Ok(Memory { size, raw_memory })
+        Ok(Memory {
+            size,
+            raw_memory,
+            mappings: Vec::new(),
+            last_read_address: None,
+        })
+    }
+
+    // Map a device into the inclusive address range from start to end
+    // Any reads or writes in this range will be handled by the device instead of the raw memory
+    pub fn map_device(
+        &mut self,
+        start: usize,
+        end: usize,
+        device: Rc<RefCell<dyn MemoryMappedDevice>>,
+    ) {
+        assert!(start <= end);
+        self.mappings.push(MemoryMapping { start, end, device });
+    }
+
+    // Find the device mapped at an address, if there is one
+    fn get_device(&self, address: usize) -> Option<Rc<RefCell<dyn MemoryMappedDevice>>> {
+        self.mappings
+            .iter()
+            .find(|mapping| address >= mapping.start && address <= mapping.end)
+            .map(|mapping| Rc::clone(&mapping.device))
     }
 
     // Helper method used to validate that the inputs to the read/write functions are valid
@@ -54,6 +105,12 @@ impl Memory {
         assert!(offset + data_length <= self.size);
     }
 
+    // Get the address of the most recent access, unless it was a write
+    // A DMA that halts the CPU repeats its last read, which some registers notice
+    pub fn get_last_read_address(&self) -> Option<usize> {
+        self.last_read_address
+    }
+
     // Get the size of the memory
     pub fn get_size(&mut self) -> usize {
         self.size
@@ -66,7 +123,21 @@ impl Memory {
 
         // Each element in the array is one byte
         // Therefore we want to return a number of elements, where the number is the number of bytes
-        self.raw_memory[offset..(offset + data_length)].to_vec()
+        let mut data: Vec<u8> = self.raw_memory[offset..(offset + data_length)].to_vec();
+        if data_length > 0 {
+            self.last_read_address = Some(offset + data_length - 1);
+        }
+
+        // Any bytes that fall within a mapped device must be read from the device instead
+        if !self.mappings.is_empty() {
+            for (index, byte) in data.iter_mut().enumerate() {
+                if let Some(device) = self.get_device(offset + index) {
+                    *byte = device.borrow_mut().read_byte(offset + index);
+                }
+            }
+        }
+
+        data
     }
 
     // Write a set number of bytes from memory at a provided offset
@@ -76,9 +147,20 @@ impl Memory {
 
         // Assert that the input offset and length were valid
         self.assert_valid_inputs(offset, data_length);
+        self.last_read_address = None;
 
         // For each input byte, overwrite the corresponding byte in the memory pool
-        self.raw_memory[offset..(data_length + offset)].copy_from_slice(&data[..data_length]);
+        // Bytes that fall within a mapped device are handed to the device instead
+        if self.mappings.is_empty() {
+            self.raw_memory[offset..(data_length + offset)].copy_from_slice(&data[..data_length]);
+        } else {
+            for (index, byte) in data.iter().enumerate() {
+                match self.get_device(offset + index) {
+                    Some(device) => device.borrow_mut().write_byte(offset + index, *byte),
+                    None => self.raw_memory[offset + index] = *byte,
+                }
+            }
+        }
     }
 
     // Set a single bit in memory
@@ -362,4 +444,51 @@ pub mod tests {
         // Verify result
         assert_eq!(memory.read(0, 1)[0], 127);
     }
+
+    // A device that records the last write and reads back a fixed pattern
+    struct TestDevice {
+        last_write: Option<(usize, u8)>,
+    }
+
+    impl MemoryMappedDevice for TestDevice {
+        fn read_byte(&mut self, address: usize) -> u8 {
+            (address as u8) ^ 0xFF
+        }
+
+        fn write_byte(&mut self, address: usize, value: u8) {
+            self.last_write = Some((address, value));
+        }
+    }
+
+    #[test]
+    fn mapped_device_handles_reads_and_writes() {
+        // Fetch a test instance of memory
+        let mut memory: Memory = get_test_memory(8);
+
+        // Map a device over the upper half of the memory
+        let device = Rc::new(RefCell::new(TestDevice { last_write: None }));
+        memory.map_device(4, 7, device.clone());
+
+        // Write across the boundary between raw memory and the device
+        memory.write(3, [0xAA, 0xBB].to_vec());
+
+        // The first byte lands in raw memory and the second is handed to the device
+        assert_eq!(device.borrow().last_write, Some((4, 0xBB)));
+        assert_eq!(memory.read(2, 4), [0x00, 0xAA, 0xFB, 0xFA].to_vec());
+    }
+
+    #[test]
+    fn last_read_address_is_cleared_by_writes() {
+        // Fetch a test instance of memory
+        let mut memory: Memory = get_test_memory(8);
+        assert_eq!(memory.get_last_read_address(), None);
+
+        // A read leaves the address of its final byte
+        memory.read(2, 3);
+        assert_eq!(memory.get_last_read_address(), Some(4));
+
+        // A write means the last access was no longer a read
+        memory.write(0, [0x01].to_vec());
+        assert_eq!(memory.get_last_read_address(), None);
+    }
 }
@@ -126,6 +126,82 @@ impl Utils {
         address
     }
 
+    // The CRC-32 used by zip files and ROM databases, with the reflected polynomial $EDB88320
+    pub fn get_crc32(data: &[u8]) -> u32 {
+        let mut crc: u32 = 0xFFFF_FFFF;
+        for byte in data {
+            crc ^= u32::from(*byte);
+            for _ in 0..8 {
+                let mask: u32 = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+        !crc
+    }
+
+    // The SHA-1 digest of the data, as used by ROM databases
+    // https://datatracker.ietf.org/doc/html/rfc3174
+    pub fn get_sha1(data: &[u8]) -> [u8; 20] {
+        let mut state: [u32; 5] = [
+            0x6745_2301,
+            0xEFCD_AB89,
+            0x98BA_DCFE,
+            0x1032_5476,
+            0xC3D2_E1F0,
+        ];
+
+        // The message is padded with a 1 bit, zeros, then its length in bits, to a multiple of 64 bytes
+        let mut message: Vec<u8> = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend(((data.len() as u64) * 8).to_be_bytes());
+
+        for block in message.chunks(64) {
+            let mut words: [u32; 80] = [0; 80];
+            for (index, word) in block.chunks(4).enumerate() {
+                words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            }
+            for index in 16..80 {
+                words[index] =
+                    (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                        .rotate_left(1);
+            }
+
+            let [mut a, mut b, mut c, mut d, mut e] = state;
+            for (index, word) in words.iter().enumerate() {
+                let (f, k): (u32, u32) = match index {
+                    0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                    20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                    40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                    _ => (b ^ c ^ d, 0xCA62_C1D6),
+                };
+                let temp: u32 = a
+                    .rotate_left(5)
+                    .wrapping_add(f)
+                    .wrapping_add(e)
+                    .wrapping_add(k)
+                    .wrapping_add(*word);
+                e = d;
+                d = c;
+                c = b.rotate_left(30);
+                b = a;
+                a = temp;
+            }
+
+            for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+                *value = value.wrapping_add(add);
+            }
+        }
+
+        let mut digest: [u8; 20] = [0; 20];
+        for (index, value) in state.iter().enumerate() {
+            digest[index * 4..index * 4 + 4].copy_from_slice(&value.to_be_bytes());
+        }
+        digest
+    }
+
     pub fn get_absolute_address(index: u8, operand: u16) -> u16 {
         // Turn the u8 into a u16
         let mut address: u16 = index.into();
@@ -166,6 +242,25 @@ mod tests {
         assert_eq!(result.1, 0xCD);
     }
 
+    #[test]
+    fn test_crc32() {
+        assert_eq!(Utils::get_crc32(b""), 0x0000_0000);
+        assert_eq!(Utils::get_crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(
+            Utils::get_sha1(b"abc"),
+            [
+                0xA9, 0x99, 0x3E, 0x36, 0x47, 0x06, 0x81, 0x6A, 0xBA, 0x3E, 0x25, 0x71, 0x78, 0x50,
+                0xC2, 0x6C, 0x9C, 0xD0, 0xD8, 0x9D
+            ]
+        );
+        // Long enough that the padding spills into a second block
+        assert_eq!(Utils::get_sha1(&[b'a'; 56])[..4], [0xC2, 0xDB, 0x33, 0x0F]);
+    }
+
     #[test]
     fn test_twos_complement() {
         let inputs: Vec<usize> = [
@@ -24,75 +24,884 @@
 
 mod common {
     pub mod clock;
+    pub mod interrupt;
     pub mod memory;
     pub mod utils;
 }
 
 mod models {
+    pub mod audio;
+    pub mod cartridge;
     pub mod mos6502;
+    pub mod nsf;
+    pub mod ppu;
+    pub mod region;
 }
 
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::common::utils::Utils;
+use crate::models::audio::apu::Apu;
+use crate::models::audio::mixer::{self, Mixer};
+use crate::models::audio::wav;
+use crate::models::cartridge::archive;
+use crate::models::cartridge::database::Database;
+use crate::models::cartridge::ines::{self, INesHeader};
+use crate::models::cartridge::mappers::fds::disk::DiskImage;
+use crate::models::cartridge::mappers::fds::Fds;
+use crate::models::cartridge::mappers::mmc3::{self, IrqRevision};
+use crate::models::cartridge::mappers::{self, Mapper};
+use crate::models::cartridge::patch;
+use crate::models::cartridge::save::SaveFile;
+use crate::models::cartridge::unif;
+use crate::models::cartridge::{Cartridge, CartridgeError, CartridgeSlot, WriteMonitor};
+use crate::models::mos6502::dma::DmaClient;
 use crate::models::mos6502::Mos6502;
+use crate::models::nsf::player;
+use crate::models::nsf::Nsf;
+use crate::models::ppu::palette::{NtscSettings, Palette};
+use crate::models::ppu::screenshot::{self, Overscan, Screenshot};
+use crate::models::ppu::Ppu;
+use crate::models::region::{Region, RegionProfile};
+use std::cell::RefCell;
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 fn main() {
     nes();
 }
 
 fn nes() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+
+    // Disk images need the Famicom Disk System BIOS, which is not included
+    let fds_bios_path: &Path =
+        Path::new(get_option_value(&arguments, "--fds-bios").unwrap_or("disksys.rom"));
+
+    // Load the ROM given on the command line, or nestest when there is none
+    let rom_path: &Path =
+        Path::new(get_rom_argument(&arguments).unwrap_or("build/target/debug/nestest.nes"));
+    let rom_content: Vec<u8> = fs::read(rom_path).unwrap();
+
+    // Zip, gzip and 7z archives hold the ROM, which is the first NES, FDS or NSF file unless
+    // another entry is chosen with --entry
+    let rom_content: Vec<u8> = if archive::is_archive(&rom_content) {
+        match archive::extract_rom(&rom_content, get_option_value(&arguments, "--entry")) {
+            Ok((name, data)) => {
+                println!("Loading {} from archive.", name);
+                data
+            }
+            Err(error) => panic!("{}", error),
+        }
+    } else {
+        rom_content
+    };
+
+    // A same-named IPS, UPS or BPS patch next to the ROM is applied before anything reads it
+    let rom_content: Vec<u8> = match patch::find_patch(rom_path) {
+        Some(patch_path) => {
+            println!("Applying patch {}.", patch_path.display());
+            let patch_content: Vec<u8> = fs::read(&patch_path).unwrap();
+            match patch::apply(&rom_content, &patch_content) {
+                Ok(patched) => patched,
+                Err(error) => panic!("{}", error),
+            }
+        }
+        None => rom_content,
+    };
+
+    // Music files are rendered to audio rather than run as a game
+    if Nsf::is_nsf(&rom_content) {
+        render_nsf(&rom_content, &arguments);
+        return;
+    }
+
+    // --mmc3-irq picks the old or new MMC3 scanline counter for dumps that do not say which
+    let mmc3_irq: Option<IrqRevision> =
+        get_option_value(&arguments, "--mmc3-irq").map(|name| match IrqRevision::from_name(name) {
+            Some(irq_revision) => irq_revision,
+            None => panic!("--mmc3-irq takes old or new"),
+        });
+
+    let database: Option<Database> = load_database(&arguments);
+    let (mapper, region): (Box<dyn Mapper>, Region) =
+        load_mapper(&rom_content, database.as_ref(), fds_bios_path, mmc3_irq);
+
+    // The region comes from the header or the ROM database, unless it is picked with --region
+    let region: Region = match get_option_value(&arguments, "--region") {
+        Some(name) => match Region::from_name(name) {
+            Some(region) => region,
+            None => panic!("--region takes ntsc, pal, multi or dendy"),
+        },
+        None => region,
+    };
+    let profile: &'static RegionProfile = region.get_profile();
+    println!(
+        "Running with {} timing, {} scanlines of vblank.",
+        profile.name,
+        profile.get_vblank_length()
+    );
+
     let mut mos6502 = Mos6502::new(
         // Memory size
         1024 * 1000 * 2 + 1024 * 1000 * 1000,
         // Clock speed
-        236250000.0 / 11.0,
+        profile.get_cpu_clock_hz(),
     );
+    let mapper: Rc<RefCell<Box<dyn Mapper>>> = write_nes_rom_to_memory(&mut mos6502, mapper);
+    let mut peripherals: Peripherals = connect_peripherals(&mut mos6502, mapper.clone(), profile);
+
+    // --wav records the console's audio, including any expansion audio from the cartridge, for
+    // runs with a set number of --frames
+    let wav_path: Option<&Path> = get_option_value(&arguments, "--wav").map(Path::new);
+    if wav_path.is_some() {
+        peripherals.record_audio(WAV_SAMPLE_RATE);
+    }
 
-    // Load nestest rom
-    let nestest_rom: Vec<u8> = fs::read("build/target/debug/nestest.nes").unwrap();
-    write_nes_rom_to_memory(&mut mos6502, nestest_rom);
+    // Restore the cartridge's save memory, if it has any, and keep it written while running
+    let mut save_file: SaveFile = SaveFile::open(mapper.clone(), rom_path, &rom_content).unwrap();
 
     // Automation mode is defined on github
     // https://github.com/christopherpow/nes-test-roms/blob/master/other/nestest.txt#L67
     let mut pc_data: Vec<u8> = Vec::new();
-    // Set program counter to 0xc000
-    mos6502.program_counter = 0xc000;
+    mos6502.program_counter = get_start_address(&mut mos6502, &arguments);
 
-    // Start the system
-    mos6502.run();
+    // --frames runs without waiting for the clock for that many frames, then exits after taking
+    // a screenshot, so the emulator can be checked where there is no display
+    let frame_limit: Option<u64> =
+        get_option_value(&arguments, "--frames").map(|value| value.parse::<u64>().unwrap());
+    let screenshots: Option<ScreenshotSettings> =
+        get_screenshot_settings(&arguments, frame_limit.is_some());
+    if wav_path.is_some() && frame_limit.is_none() {
+        panic!("--wav records games for a number of --frames");
+    }
+    let disk_swaps: Vec<DiskSwap> = get_disk_swaps(&arguments);
+    let mut frame_count: u64 = 0;
+
+    // Start the system, the save is written one last time when save_file is dropped
+    loop {
+        let cycles: u8 = if frame_limit.is_some() {
+            mos6502.execute_next_instruction()
+        } else {
+            mos6502.step()
+        };
+        peripherals.catch_up(&mut mos6502, cycles);
+        save_file.flush_if_due().unwrap();
+
+        // Screenshots are taken as each frame is completed
+        let completed_frames: u64 = peripherals.ppu.borrow().get_frame_count();
+        if completed_frames != frame_count {
+            frame_count = completed_frames;
+            swap_disks(mapper.borrow_mut().as_mut(), &disk_swaps, frame_count);
+            if let Some(settings) = &screenshots {
+                settings.take_for_frame(&peripherals.ppu.borrow(), frame_count, frame_limit);
+            }
+            if frame_limit == Some(frame_count) {
+                break;
+            }
+        }
+    }
+
+    if let Some(path) = wav_path {
+        let samples: Vec<f32> = peripherals.take_audio();
+        wav::write_file(path, &samples, WAV_SAMPLE_RATE).unwrap();
+        println!(
+            "Recorded {:.1} seconds of audio to {}.",
+            samples.len() as f64 / f64::from(WAV_SAMPLE_RATE),
+            path.display()
+        );
+    }
 }
 
-fn write_nes_rom_to_memory(system: &mut Mos6502, rom_content: Vec<u8>) {
-    // This should be compliant with the iNES and NES2.0 file format specifications
-    // iNES: https://wiki.nesdev.com/w/index.php/INES
-    // NES2.0: https://wiki.nesdev.com/w/index.php/NES_2.0
+// A disk change from --disk-swaps, which takes a list like 600:2,1200:eject to put in the second
+// side at frame 600 and take the disk out at frame 1200
+struct DiskSwap {
+    frame: u64,
+    // The side to put in the drive, counted from 0, or None to eject the disk
+    side: Option<usize>,
+}
+
+fn get_disk_swaps(arguments: &[String]) -> Vec<DiskSwap> {
+    let swaps: &str = match get_option_value(arguments, "--disk-swaps") {
+        Some(swaps) => swaps,
+        None => return Vec::new(),
+    };
+    swaps
+        .split(',')
+        .map(|swap| match swap.trim().split_once(':') {
+            Some((frame, "eject")) => DiskSwap {
+                frame: frame.parse::<u64>().unwrap(),
+                side: None,
+            },
+            Some((frame, side)) => DiskSwap {
+                frame: frame.parse::<u64>().unwrap(),
+                side: Some(side.parse::<usize>().unwrap().saturating_sub(1)),
+            },
+            None => panic!("--disk-swaps takes a list of frame:side or frame:eject"),
+        })
+        .collect()
+}
+
+// Make the disk changes that are due at the end of a frame
+fn swap_disks(mapper: &mut dyn Mapper, swaps: &[DiskSwap], frame: u64) {
+    for swap in swaps.iter().filter(|swap| swap.frame == frame) {
+        match swap.side {
+            Some(side) if side < mapper.get_disk_side_count() => {
+                println!("Inserting disk side {} at frame {}.", side + 1, frame);
+                mapper.insert_disk_side(side);
+            }
+            Some(side) => panic!("There is no disk side {}", side + 1),
+            None => {
+                if let Some(side) = mapper.get_inserted_disk_side() {
+                    println!("Ejecting disk side {} at frame {}.", side + 1, frame);
+                }
+                mapper.eject_disk();
+            }
+        }
+    }
+}
+
+// Where screenshots are written and how they are taken
+struct ScreenshotSettings {
+    path: PathBuf,
+    // A numbered screenshot is also taken every this many frames
+    interval: Option<u64>,
+    overscan: Overscan,
+    palette: Palette,
+}
+
+impl ScreenshotSettings {
+    // Take the screenshots due at the end of a frame
+    fn take_for_frame(&self, ppu: &Ppu, frame: u64, frame_limit: Option<u64>) {
+        if self
+            .interval
+            .is_some_and(|interval| frame.is_multiple_of(interval))
+        {
+            self.take(ppu, &screenshot::get_numbered_path(&self.path, frame));
+        }
+        if frame_limit == Some(frame) {
+            self.take(ppu, &self.path);
+        }
+    }
+
+    fn take(&self, ppu: &Ppu, path: &Path) {
+        let screenshot: Screenshot =
+            Screenshot::capture(ppu.get_frame_buffer(), &self.palette, &self.overscan);
+        screenshot.write_file(path).unwrap();
+        println!("Saved screenshot {}.", path.display());
+    }
+}
+
+// Read the screenshot options, which are only needed when running headless or when a screenshot
+// option is given
+// --screenshot sets the file, as PNG or as PPM when it ends in .ppm, defaulting to screenshot.png
+// --screenshot-every also saves a numbered screenshot every that many frames
+// --overscan crops pixels from the edges, given as one number for every edge or as four numbers
+// for the top, bottom, left and right edges, such as 8,8,0,0
+// --palette loads the colours from a .pal file, rather than generating them from the NTSC signal
+fn get_screenshot_settings(arguments: &[String], is_headless: bool) -> Option<ScreenshotSettings> {
+    let path: Option<&str> = get_option_value(arguments, "--screenshot");
+    let interval: Option<u64> = get_option_value(arguments, "--screenshot-every")
+        .map(|value| value.parse::<u64>().unwrap());
+    if path.is_none() && interval.is_none() && !is_headless {
+        return None;
+    }
+
+    let edges: Vec<usize> = get_option_value(arguments, "--overscan")
+        .unwrap_or("0")
+        .split(',')
+        .map(|value| value.trim().parse::<usize>().unwrap())
+        .collect();
+    let overscan: Overscan = match edges[..] {
+        [all] => Overscan {
+            top: all,
+            bottom: all,
+            left: all,
+            right: all,
+        },
+        [top, bottom, left, right] => Overscan {
+            top,
+            bottom,
+            left,
+            right,
+        },
+        _ => panic!("--overscan takes one number or four numbers separated by commas"),
+    };
+
+    let palette: Palette = match get_option_value(arguments, "--palette") {
+        Some(palette_path) => match Palette::from_pal_file(&fs::read(palette_path).unwrap()) {
+            Ok(palette) => palette,
+            Err(error) => panic!("{}", error),
+        },
+        None => Palette::generate_ntsc(&NtscSettings::default()),
+    };
+
+    Some(ScreenshotSettings {
+        path: PathBuf::from(path.unwrap_or("screenshot.png")),
+        interval: interval.filter(|interval| *interval > 0),
+        overscan,
+        palette,
+    })
+}
+
+// The hardware that runs alongside the CPU
+struct Peripherals {
+    ppu: Rc<RefCell<Ppu>>,
+    apu: Rc<RefCell<Apu>>,
+    // The cartridge, for boards with counters and timers that run on CPU cycles and for their
+    // expansion audio
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    irq_line: InterruptLine,
+    region: &'static RegionProfile,
+    // Master clock cycles the PPU has still to run, as PAL consoles run 3.2 dots per CPU cycle
+    master_clocks: u32,
+    // Collects the mixed audio while it is being recorded
+    mixer: Option<Mixer>,
+}
+
+impl Peripherals {
+    fn new(
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+        mapper: Rc<RefCell<Box<dyn Mapper>>>,
+        irq_line: InterruptLine,
+        region: &'static RegionProfile,
+    ) -> Peripherals {
+        Peripherals {
+            ppu,
+            apu,
+            mapper,
+            irq_line,
+            region,
+            master_clocks: 0,
+            mixer: None,
+        }
+    }
+
+    // Start mixing the console's audio with the cartridge's expansion audio
+    fn record_audio(&mut self, sample_rate: u32) {
+        self.mixer = Some(Mixer::new(
+            self.region.get_cpu_clock_hz(),
+            f64::from(sample_rate),
+        ));
+    }
+
+    // Take the audio samples recorded so far
+    fn take_audio(&mut self) -> Vec<f32> {
+        self.mixer
+            .as_mut()
+            .map_or(Vec::new(), |mixer| mixer.take_samples())
+    }
+
+    // The peripherals run alongside the CPU, catching up after each instruction and through any
+    // DMA the instruction started
+    fn catch_up(&mut self, mos6502: &mut Mos6502, cycles: u8) {
+        for _ in 0..cycles {
+            self.clock_cpu();
+        }
+        mos6502.run_dma(self);
+    }
+}
+
+impl DmaClient for Peripherals {
+    // Called for every CPU cycle, including the cycles the CPU is stalled for DMA
+    fn clock_cpu(&mut self) {
+        self.master_clocks += self.region.cpu_divider;
+        while self.master_clocks >= self.region.ppu_divider {
+            self.master_clocks -= self.region.ppu_divider;
+            self.ppu.borrow_mut().clock();
+        }
+        self.mapper.borrow_mut().clock_cpu();
+
+        let mut apu = self.apu.borrow_mut();
+        apu.clock_cpu();
+        self.irq_line
+            .set(InterruptSource::Apu, apu.is_irq_asserted());
+        if let Some(mixer) = self.mixer.as_mut() {
+            let expansion: f32 = self.mapper.borrow().get_audio_output();
+            mixer.push(mixer::mix(&apu.get_levels(), expansion));
+        }
+    }
+
+    // The DMC reads its samples through DMA
+    fn get_dmc_fetch_address(&self) -> Option<u16> {
+        self.apu.borrow().dmc.get_fetch_address()
+    }
+
+    fn load_dmc_sample(&mut self, value: u8) {
+        self.apu.borrow_mut().dmc.load_sample_byte(value);
+    }
+}
+
+// Options that are followed by a value
+const OPTIONS_WITH_VALUES: [&str; 14] = [
+    "--fds-bios",
+    "--database",
+    "--wav",
+    "--track",
+    "--seconds",
+    "--entry",
+    "--frames",
+    "--screenshot",
+    "--screenshot-every",
+    "--overscan",
+    "--palette",
+    "--region",
+    "--mmc3-irq",
+    "--disk-swaps",
+];
+
+// Sample rate of rendered audio files
+const WAV_SAMPLE_RATE: u32 = 44100;
 
-    // The header is the first 16 bytes of the rom content
+// Get the value that follows an option on the command line
+fn get_option_value<'a>(arguments: &'a [String], name: &str) -> Option<&'a str> {
+    let index: usize = arguments.iter().position(|argument| argument == name)?;
+    arguments.get(index + 1).map(|value| value.as_str())
+}
+
+// Get the first argument that is neither an option nor an option's value
+fn get_rom_argument(arguments: &[String]) -> Option<&str> {
+    let mut index: usize = 0;
+    while index < arguments.len() {
+        let argument: &str = &arguments[index];
+        if OPTIONS_WITH_VALUES.contains(&argument) {
+            index += 2;
+        } else if argument.starts_with("--") {
+            index += 1;
+        } else {
+            return Some(argument);
+        }
+    }
+    None
+}
+
+// Render one track of an NSF or NSFe file to the WAV file given with --wav
+// --track picks the track counting from 1, defaulting to the file's starting track, and
+// --seconds sets the length, defaulting to the track's length and fade when the file has them
+// https://www.nesdev.org/wiki/NSF
+fn render_nsf(rom_content: &[u8], arguments: &[String]) {
+    let nsf: Nsf = match Nsf::parse(rom_content) {
+        Ok(nsf) => nsf,
+        Err(error) => panic!("{}", error),
+    };
+    println!(
+        "NSF format detected: {} by {}, {} tracks.",
+        nsf.title,
+        nsf.artist,
+        nsf.tracks.len()
+    );
+    if !nsf.copyright.is_empty() {
+        println!("Copyright: {}", nsf.copyright);
+    }
+    for (index, track) in nsf.tracks.iter().enumerate() {
+        if let Some(name) = &track.name {
+            println!("Track {}: {}", index + 1, name);
+        }
+    }
 
-    // The first three bytes should be 'N' (0x4E), 'E' (0x45), and 'S' (0x53), followed by EOF (0x1A)
-    // This is derived from https://wiki.nesdev.com/w/index.php/NES_2.0#Identification
-    assert!(rom_content[0] == 0x4E);
-    assert!(rom_content[1] == 0x45);
-    assert!(rom_content[2] == 0x53);
-    assert!(rom_content[3] == 0x1A);
+    let wav_path: &Path = match get_option_value(arguments, "--wav") {
+        Some(path) => Path::new(path),
+        None => panic!("NSF files are rendered to a WAV file given with --wav"),
+    };
+    let track: usize = match get_option_value(arguments, "--track") {
+        Some(value) => value.parse::<usize>().unwrap().saturating_sub(1),
+        None => nsf.starting_track,
+    };
+    let seconds: Option<f64> =
+        get_option_value(arguments, "--seconds").map(|value| value.parse::<f64>().unwrap());
+
+    let samples: Vec<f32> = match player::render_track(&nsf, track, seconds, WAV_SAMPLE_RATE) {
+        Ok(samples) => samples,
+        Err(error) => panic!("{}", error),
+    };
+    wav::write_file(wav_path, &samples, WAV_SAMPLE_RATE).unwrap();
+    println!("Rendered track {} to {}.", track + 1, wav_path.display());
+}
+
+// Games start from the reset vector, nestest starts at 0xc000 when it is run in automation mode
+// with --automation
+fn get_start_address(mos6502: &mut Mos6502, arguments: &[String]) -> u16 {
+    if arguments.iter().any(|argument| argument == "--automation") {
+        return 0xc000;
+    }
+    let vector: Vec<u8> = mos6502.memory.read(0xFFFC, 2);
+    Utils::get_u16_from_u8_pair(vector[1], vector[0])
+}
 
-    let mut nes2: bool = false;
-    // The nes 2.0 specification is that from the 7th byte of the header, that bit 2 is clear and bit 3 is set
-    let id_byte: u8 = rom_content[7];
-    if id_byte & 0b0000_0100 == 0b0000_0000 && id_byte & 0b0000_1000 == 0b0000_1000 {
-        nes2 = true;
+// The ROM database corrects bad headers, it is the embedded one unless a full nes20db.xml is given
+// with --database, and it is turned off with --no-database
+fn load_database(arguments: &[String]) -> Option<Database> {
+    if arguments.iter().any(|argument| argument == "--no-database") {
+        return None;
+    }
+    let database_path: &Path = match get_option_value(arguments, "--database") {
+        Some(path) => Path::new(path),
+        None => return Some(Database::get_embedded()),
     };
+    let xml: String = fs::read_to_string(database_path).unwrap();
+    match Database::parse(&xml) {
+        Ok(database) => {
+            println!(
+                "Loaded {} games from {}.",
+                database.len(),
+                database_path.display()
+            );
+            Some(database)
+        }
+        Err(error) => panic!("{}", error),
+    }
+}
 
-    //TODO: Load the rom properly. This is a temporary hack sourced from Stack Overflow
-    // https://stackoverflow.com/questions/46998060/how-do-i-load-nestest-rom/47036424#47036424
-    // Skip copying the header as it is not expected to be in memory
-    let mut data: Vec<u8> = rom_content.clone();
-    data.drain(0..16);
-    system.memory.write(0x8000, data.clone());
-    system.memory.write(0xC000, data.clone());
+// Build the mapper for a ROM file, which can be a disk image, a UNIF file or an iNES file, along
+// with the region the game was made for
+fn load_mapper(
+    rom_content: &[u8],
+    database: Option<&Database>,
+    fds_bios_path: &Path,
+    mmc3_irq: Option<IrqRevision>,
+) -> (Box<dyn Mapper>, Region) {
+    // Disk images are played through the RAM adapter and its BIOS
+    // https://www.nesdev.org/wiki/Family_Computer_Disk_System
+    if DiskImage::is_disk_image(rom_content) {
+        println!("FDS disk image detected.");
+        let bios: Vec<u8> = fs::read(fds_bios_path).unwrap();
+        let loaded: Result<Fds, CartridgeError> =
+            DiskImage::parse(rom_content).and_then(|disk| Fds::new(bios, disk));
+        // The disk system was only sold in Japan
+        return match loaded {
+            Ok(fds) => (Box::new(fds), Region::Ntsc),
+            Err(error) => panic!("{}", error),
+        };
+    }
 
-    if nes2 {
-        println!("NES2.0 format detected.")
+    // UNIF files are recognised by their own header, anything else should be iNES or NES2.0
+    // UNIF: https://www.nesdev.org/wiki/UNIF
+    // iNES: https://wiki.nesdev.com/w/index.php/INES
+    // NES2.0: https://wiki.nesdev.com/w/index.php/NES_2.0
+    let loaded: Result<Cartridge, CartridgeError> = if unif::is_unif(rom_content) {
+        println!("UNIF format detected.");
+        unif::load_cartridge(rom_content)
     } else {
-        println!("iNES format detected.")
+        let header: INesHeader = INesHeader::parse(rom_content).unwrap();
+        if header.is_nes2 {
+            println!("NES2.0 format detected.")
+        } else {
+            println!("iNES format detected.")
+        }
+        ines::load_cartridge(rom_content, database)
+    };
+
+    // Build the mapper for the cartridge
+    let mut cartridge: Cartridge = match loaded {
+        Ok(cartridge) => cartridge,
+        Err(error) => panic!("{}", error),
+    };
+    if let Some(irq_revision) = mmc3_irq {
+        mmc3::select_irq_revision(&mut cartridge, irq_revision);
+    }
+    let trainer: Vec<u8> = cartridge.trainer.clone();
+    let region: Region = cartridge.region;
+    let mut mapper: Box<dyn Mapper> = match mappers::new_mapper(cartridge) {
+        Ok(mapper) => mapper,
+        Err(error) => panic!("{}", error),
+    };
+    mapper.load_trainer(&trainer);
+    (mapper, region)
+}
+
+// Connect the PPU, the APU and the DMA register to the CPU's memory map
+fn connect_peripherals(
+    system: &mut Mos6502,
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    region: &'static RegionProfile,
+) -> Peripherals {
+    // The PPU's registers sit between $2000 and $3FFF, and it reaches the cartridge through the
+    // mapper, which also sees every write to the registers
+    let ppu: Rc<RefCell<Ppu>> = Rc::new(RefCell::new(Ppu::new(
+        mapper.clone(),
+        system.nmi_line.clone(),
+        region,
+    )));
+    let ppu_registers = WriteMonitor::new(ppu.clone(), mapper.clone());
+    system
+        .memory
+        .map_device(0x2000, 0x3FFF, Rc::new(RefCell::new(ppu_registers)));
+
+    // The APU's registers sit between $4000 and $4017, apart from the OAM DMA register and the
+    // first controller port
+    let apu: Rc<RefCell<Apu>> = Rc::new(RefCell::new(Apu::new(region)));
+    system.memory.map_device(0x4000, 0x4013, apu.clone());
+    system.memory.map_device(0x4015, 0x4015, apu.clone());
+    system.memory.map_device(0x4017, 0x4017, apu.clone());
+
+    // Writing a page number to $4014 copies that page into the PPU's sprite memory
+    let oam_dma_register = Rc::new(RefCell::new(system.dma.get_register()));
+    system.memory.map_device(0x4014, 0x4014, oam_dma_register);
+
+    Peripherals::new(ppu, apu, mapper, system.irq_line.clone(), region)
+}
+
+// Plug the mapper into the cartridge address space
+fn write_nes_rom_to_memory(
+    system: &mut Mos6502,
+    mut mapper: Box<dyn Mapper>,
+) -> Rc<RefCell<Box<dyn Mapper>>> {
+    mapper.connect_irq_line(system.irq_line.clone());
+    let mapper: Rc<RefCell<Box<dyn Mapper>>> = Rc::new(RefCell::new(mapper));
+    let slot = CartridgeSlot::new(mapper.clone());
+    system
+        .memory
+        .map_device(0x4020, 0xFFFF, Rc::new(RefCell::new(slot)));
+    mapper
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::database::tests::get_test_database;
+    use crate::models::cartridge::mappers::fds::tests::get_test_fds;
+    use crate::models::cartridge::tests::get_test_cartridge;
+    use crate::models::region::NTSC;
+
+    // Helper function for the tests to build a console around a cartridge, with the CPU spinning
+    // on a JMP to itself in RAM and interrupts masked, so that the tests can watch the IRQ line
+    fn get_test_console(cartridge: Cartridge) -> (Mos6502, Peripherals) {
+        let mut mos6502: Mos6502 = Mos6502::new(0x10000, NTSC.get_cpu_clock_hz());
+        let mapper: Box<dyn Mapper> = mappers::new_mapper(cartridge).unwrap();
+        let mapper: Rc<RefCell<Box<dyn Mapper>>> = write_nes_rom_to_memory(&mut mos6502, mapper);
+        let peripherals: Peripherals = connect_peripherals(&mut mos6502, mapper, &NTSC);
+
+        mos6502.memory.write(0x0000, vec![0x4C, 0x00, 0x00]);
+        mos6502.program_counter = 0x0000;
+        mos6502.set_i_flag();
+        (mos6502, peripherals)
+    }
+
+    // Run instructions until the PPU has completed the given number of frames
+    fn run_to_frame(mos6502: &mut Mos6502, peripherals: &mut Peripherals, frame: u64) {
+        while peripherals.ppu.borrow().get_frame_count() < frame {
+            let cycles: u8 = mos6502.execute_next_instruction();
+            peripherals.catch_up(mos6502, cycles);
+        }
+    }
+
+    #[test]
+    fn test_mmc3_scanline_irq() {
+        // Prep for the test, an IRQ after 20 scanlines with sprites fetched from $1000 so that A12
+        // rises once per scanline
+        let (mut mos6502, mut peripherals): (Mos6502, Peripherals) =
+            get_test_console(get_test_cartridge(4, 0, 4, 0x2000, 8, 0x0400));
+        mos6502.memory.write(0xC000, vec![20]);
+        mos6502.memory.write(0xC001, vec![0]);
+        mos6502.memory.write(0xE001, vec![0]);
+        mos6502.memory.write(0x2000, vec![0b0000_1000]);
+        run_to_frame(&mut mos6502, &mut peripherals, 1);
+        assert!(!mos6502.irq_line.is_asserted());
+
+        // Assert results, the counter only runs while rendering is enabled
+        mos6502.memory.write(0x2001, vec![0b0001_1000]);
+        run_to_frame(&mut mos6502, &mut peripherals, 2);
+        assert!(mos6502.irq_line.is_asserted());
+    }
+
+    #[test]
+    fn test_mmc5_watches_ppu_registers() {
+        // Prep for the test, 1KB CHR banks with the background set B at banks 20-23 written
+        // before the sprite set A at banks 1-8, so A is the set used outside of 8x16 mode
+        let (mut mos6502, mut peripherals): (Mos6502, Peripherals) =
+            get_test_console(get_test_cartridge(5, 0, 4, 0x2000, 32, 0x0400));
+        mos6502.memory.write(0x5101, vec![3]);
+        for register in 0..4 {
+            mos6502
+                .memory
+                .write(0x5128 + register, vec![20 + register as u8]);
+        }
+        for register in 0..8 {
+            mos6502
+                .memory
+                .write(0x5120 + register, vec![1 + register as u8]);
+        }
+        for (register, value) in [
+            (0x2006, 0x3F),
+            (0x2006, 0x00),
+            (0x2007, 0x0F),
+            (0x2007, 0x11),
+        ]
+        .into_iter()
+        .chain([(0x2007, 0x12), (0x2007, 0x30)])
+        {
+            mos6502.memory.write(register, vec![value]);
+        }
+
+        // 8x16 sprites and the background shown, all written through the CPU's bus
+        mos6502.memory.write(0x2000, vec![0b0010_0000]);
+        mos6502.memory.write(0x2001, vec![0b0000_1010]);
+        run_to_frame(&mut mos6502, &mut peripherals, 2);
+
+        // Assert results, the background came from bank 20, where every byte of both planes is
+        // 0b0001_0100, rather than from bank 1 of set A
+        let row: Vec<u16> = peripherals.ppu.borrow().get_frame_buffer()[2560..2568].to_vec();
+        assert_eq!(row, [0x0F, 0x0F, 0x0F, 0x30, 0x0F, 0x30, 0x0F, 0x0F]);
+
+        // Turning rendering off ends the frame straight away, as reported by $5204
+        mos6502.memory.write(0x2001, vec![0]);
+        assert_eq!(mos6502.memory.read(0x5204, 1)[0] & 0b0100_0000, 0);
+    }
+
+    #[test]
+    fn test_mmc3_irq_option() {
+        // Prep for the test, an iNES MMC3 ROM with 32KB of PRG and 8KB of CHR
+        let mut rom: Vec<u8> = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x40, 0x00];
+        rom.resize(16 + 0x8000 + 0x2000, 0);
+        let get_irq_line = |mmc3_irq: Option<IrqRevision>| -> InterruptLine {
+            let (mut mapper, _): (Box<dyn Mapper>, Region) =
+                load_mapper(&rom, None, Path::new("disksys.rom"), mmc3_irq);
+            let line: InterruptLine = InterruptLine::new();
+            mapper.connect_irq_line(line.clone());
+
+            // A latch of zero, then one filtered rising edge of A12
+            mapper.cpu_write(0xC000, 0);
+            mapper.cpu_write(0xE001, 0);
+            mapper.notify_ppu_address(0x0000);
+            for _ in 0..3 {
+                mapper.clock_cpu();
+            }
+            mapper.notify_ppu_address(0x1000);
+            line
+        };
+
+        // Assert results, the old counter needs a reload through $C001 before a zero latch
+        // raises an IRQ
+        assert!(get_irq_line(None).is_asserted());
+        assert!(get_irq_line(Some(IrqRevision::New)).is_asserted());
+        assert!(!get_irq_line(Some(IrqRevision::Old)).is_asserted());
+    }
+
+    #[test]
+    fn test_start_address() {
+        // Prep for the test, an NROM cartridge with its reset vector pointing at 0x8123
+        let mut cartridge: Cartridge = get_test_cartridge(0, 0, 2, 0x2000, 1, 0x2000);
+        cartridge.prg_rom[0x3FFC] = 0x23;
+        cartridge.prg_rom[0x3FFD] = 0x81;
+        let (mut mos6502, _): (Mos6502, Peripherals) = get_test_console(cartridge);
+        let automation: Vec<String> = vec![String::from("--automation")];
+
+        // Assert results
+        assert_eq!(get_start_address(&mut mos6502, &[]), 0x8123);
+        assert_eq!(get_start_address(&mut mos6502, &automation), 0xc000);
+    }
+
+    #[test]
+    fn test_disk_swaps() {
+        // Prep for the test, flip to the second side at frame 10 and eject it at frame 20
+        let arguments: Vec<String> =
+            vec![String::from("--disk-swaps"), String::from("10:2,20:eject")];
+        let disk_swaps: Vec<DiskSwap> = get_disk_swaps(&arguments);
+        let mut mapper: Fds = get_test_fds();
+        swap_disks(&mut mapper, &disk_swaps, 9);
+        let before_swap: Option<usize> = mapper.get_inserted_disk_side();
+        swap_disks(&mut mapper, &disk_swaps, 10);
+        while mapper.get_inserted_disk_side().is_none() {
+            mapper.clock_cpu();
+        }
+        let after_swap: Option<usize> = mapper.get_inserted_disk_side();
+        swap_disks(&mut mapper, &disk_swaps, 20);
+
+        // Assert results
+        assert_eq!(before_swap, Some(0));
+        assert_eq!(after_swap, Some(1));
+        assert_eq!(mapper.get_inserted_disk_side(), None);
+    }
+
+    #[test]
+    fn test_database_option() {
+        // Prep for the test, an iNES NROM ROM that the header says is for NTSC consoles, and a
+        // database file that says it is a PAL game
+        let mut rom: Vec<u8> = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        rom.resize(16 + 0x4000 + 0x2000, 0xEA);
+        let xml: String = get_test_database(
+            &rom[16..],
+            "mapper=\"0\" submapper=\"0\" mirroring=\"H\" battery=\"0\"",
+            "<console type=\"0\" region=\"1\"/>",
+        );
+        let database_path: PathBuf = env::temp_dir().join("rusty-nes-database-option.xml");
+        fs::write(&database_path, xml).unwrap();
+        let get_region = |arguments: &[&str]| -> Region {
+            let arguments: Vec<String> = arguments.iter().map(|arg| arg.to_string()).collect();
+            let database: Option<Database> = load_database(&arguments);
+            load_mapper(&rom, database.as_ref(), Path::new("disksys.rom"), None).1
+        };
+
+        // Assert results, the embedded database does not know the ROM, the given one corrects
+        // its region unless the database is turned off
+        let database_argument: &str = database_path.to_str().unwrap();
+        assert_eq!(get_region(&[]), Region::Ntsc);
+        assert_eq!(get_region(&["--database", database_argument]), Region::Pal);
+        assert_eq!(
+            get_region(&["--database", database_argument, "--no-database"]),
+            Region::Ntsc
+        );
+        fs::remove_file(&database_path).unwrap();
+    }
+
+    #[test]
+    fn test_expansion_audio_is_mixed() {
+        // Prep for the test, a VRC6 pulse at a constant volume of 15 and the console's first pulse
+        // channel at a constant volume of 8
+        let (mut mos6502, mut peripherals): (Mos6502, Peripherals) =
+            get_test_console(get_test_cartridge(24, 0, 16, 0x2000, 8, 0x0400));
+        peripherals.record_audio(WAV_SAMPLE_RATE);
+        mos6502.memory.write(0x9000, vec![0x8F]);
+        mos6502.memory.write(0x9002, vec![0x80]);
+        run_to_frame(&mut mos6502, &mut peripherals, 1);
+        let expansion_only: Vec<f32> = peripherals.take_audio();
+        let expansion: f32 = peripherals.mapper.borrow().get_audio_output();
+        let silent_console: f32 = mixer::mix(&peripherals.apu.borrow().get_levels(), expansion);
+        for (register, value) in [
+            (0x4015, 0x01),
+            (0x4000, 0x38),
+            (0x4002, 0x80),
+            (0x4003, 0x08),
+        ] {
+            mos6502.memory.write(register, vec![value]);
+        }
+        run_to_frame(&mut mos6502, &mut peripherals, 2);
+        let mixed: Vec<f32> = peripherals.take_audio();
+
+        // Assert results, a frame is about a 60th of a second of samples, the idle console
+        // channels only add a constant level, and the console's pulse is mixed on top of that
+        assert!(expansion > 0.0);
+        assert!((700..=800).contains(&mixed.len()));
+        assert!(expansion_only[100..]
+            .iter()
+            .all(|sample| (sample - silent_console).abs() < 1e-4));
+        assert!(mixed[100..]
+            .iter()
+            .any(|sample| *sample > silent_console + 0.01));
+    }
+
+    #[test]
+    fn test_apu_irq_and_dmc_dma() {
+        // Prep for the test, a one byte DMC sample at $C000 and the frame IRQ enabled
+        let (mut mos6502, mut peripherals): (Mos6502, Peripherals) =
+            get_test_console(get_test_cartridge(0, 0, 2, 0x4000, 1, 0x2000));
+        for (register, value) in [
+            (0x4010, 0x0F),
+            (0x4012, 0x00),
+            (0x4013, 0x00),
+            (0x4015, 0x10),
+        ] {
+            mos6502.memory.write(register, vec![value]);
+        }
+        mos6502.memory.write(0x4017, vec![0x00]);
+        assert_eq!(mos6502.memory.read(0x4015, 1)[0] & 0b0001_0000, 0b0001_0000);
+        run_to_frame(&mut mos6502, &mut peripherals, 2);
+
+        // Assert results, the sample was fetched through DMA and the frame counter has raised an
+        // IRQ on the CPU's line
+        assert_eq!(mos6502.memory.read(0x4015, 1)[0] & 0b0001_0000, 0);
+        assert!(mos6502.irq_line.is_asserted());
     }
 }
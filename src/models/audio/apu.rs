@@ -0,0 +1,340 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// The console's audio processing unit, the register block at $4000-$4017 with its five channels
+// and the frame counter that drives their envelopes, length counters and sweeps
+// https://www.nesdev.org/wiki/APU
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+
+use crate::common::memory::MemoryMappedDevice;
+use crate::models::audio::dmc::Dmc;
+use crate::models::audio::mixer::ChannelLevels;
+use crate::models::audio::noise::Noise;
+use crate::models::audio::pulse::Pulse;
+use crate::models::audio::sweep::Sweep;
+use crate::models::audio::triangle::Triangle;
+use crate::models::region::RegionProfile;
+
+// CPU cycles at which the frame counter steps happen, which are further apart on PAL consoles
+pub struct FrameCounterTiming {
+    pub quarter_frame_steps: [u32; 4],
+    pub five_step_last_step: u32,
+    pub four_step_period: u32,
+    pub five_step_period: u32,
+}
+
+pub const NTSC_FRAME_COUNTER: FrameCounterTiming = FrameCounterTiming {
+    quarter_frame_steps: [7457, 14913, 22371, 29829],
+    five_step_last_step: 37281,
+    four_step_period: 29830,
+    five_step_period: 37282,
+};
+
+pub const PAL_FRAME_COUNTER: FrameCounterTiming = FrameCounterTiming {
+    quarter_frame_steps: [8313, 16627, 24939, 33252],
+    five_step_last_step: 41565,
+    four_step_period: 33253,
+    five_step_period: 41566,
+};
+
+pub struct Apu {
+    pulses: [Pulse; 2],
+    sweeps: [Sweep; 2],
+    triangle: Triangle,
+    noise: Noise,
+    pub dmc: Dmc,
+    // The pulse timers run at half the CPU clock
+    odd_cycle: bool,
+    // Frame counter
+    frame_timing: &'static FrameCounterTiming,
+    frame_cycle: u32,
+    five_step_mode: bool,
+    frame_irq_inhibited: bool,
+    frame_irq_pending: bool,
+}
+
+impl Apu {
+    pub fn new(region: &RegionProfile) -> Apu {
+        Apu {
+            pulses: [Pulse::new(), Pulse::new()],
+            sweeps: [Sweep::new(true), Sweep::new(false)],
+            triangle: Triangle::new(),
+            noise: Noise::new(region.noise_periods),
+            dmc: Dmc::new(region.dmc_rates),
+            odd_cycle: false,
+            frame_timing: region.frame_counter,
+            frame_cycle: 0,
+            five_step_mode: false,
+            frame_irq_inhibited: false,
+            frame_irq_pending: false,
+        }
+    }
+
+    // Write one of the registers between $4000 and $4017
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        let register: u8 = (address & 0b11) as u8;
+        match address {
+            0x4001 => self.sweeps[0].write_control(value),
+            0x4005 => self.sweeps[1].write_control(value),
+            0x4000..=0x4003 => self.pulses[0].write_register(register, value),
+            0x4004..=0x4007 => self.pulses[1].write_register(register, value),
+            0x4008..=0x400B => self.triangle.write_register(register, value),
+            0x400C..=0x400F => self.noise.write_register(register, value),
+            0x4010..=0x4013 => self.dmc.write_register(register, value),
+            0x4015 => {
+                // ---D NT21
+                self.pulses[0]
+                    .length_counter
+                    .set_enabled(value & 0b0000_0001 == 0b0000_0001);
+                self.pulses[1]
+                    .length_counter
+                    .set_enabled(value & 0b0000_0010 == 0b0000_0010);
+                self.triangle
+                    .length_counter
+                    .set_enabled(value & 0b0000_0100 == 0b0000_0100);
+                self.noise
+                    .length_counter
+                    .set_enabled(value & 0b0000_1000 == 0b0000_1000);
+                self.dmc.set_enabled(value & 0b0001_0000 == 0b0001_0000);
+            }
+            0x4017 => {
+                // MI-- ----
+                self.five_step_mode = value & 0b1000_0000 == 0b1000_0000;
+                self.frame_irq_inhibited = value & 0b0100_0000 == 0b0100_0000;
+                if self.frame_irq_inhibited {
+                    self.frame_irq_pending = false;
+                }
+
+                // The sequence restarts, and the five step mode clocks everything straight away
+                self.frame_cycle = 0;
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Read the status register at $4015, which acknowledges the frame IRQ
+    pub fn read_status(&mut self) -> u8 {
+        let value: u8 = u8::from(self.pulses[0].length_counter.is_active())
+            | u8::from(self.pulses[1].length_counter.is_active()) << 1
+            | u8::from(self.triangle.length_counter.is_active()) << 2
+            | u8::from(self.noise.length_counter.is_active()) << 3
+            | u8::from(self.dmc.is_active()) << 4
+            | u8::from(self.frame_irq_pending) << 6
+            | u8::from(self.dmc.is_irq_pending()) << 7;
+        self.frame_irq_pending = false;
+        value
+    }
+
+    // Whether the frame counter or the DMC is requesting an interrupt
+    pub fn is_irq_asserted(&self) -> bool {
+        self.frame_irq_pending || self.dmc.is_irq_pending()
+    }
+
+    pub fn clock_cpu(&mut self) {
+        if self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_timer();
+            }
+        }
+        self.odd_cycle = !self.odd_cycle;
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.frame_cycle += 1;
+        let timing: &FrameCounterTiming = self.frame_timing;
+        let steps: [u32; 4] = timing.quarter_frame_steps;
+        match self.frame_cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => {
+                self.clock_quarter_frame();
+            }
+            cycle if cycle == steps[1] => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            cycle if cycle == steps[3] && !self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.frame_irq_inhibited {
+                    self.frame_irq_pending = true;
+                }
+            }
+            cycle if cycle == timing.five_step_last_step && self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            _ => {}
+        }
+
+        let period: u32 = if self.five_step_mode {
+            timing.five_step_period
+        } else {
+            timing.four_step_period
+        };
+        if self.frame_cycle >= period {
+            self.frame_cycle = 0;
+        }
+    }
+
+    // The output level of each channel, with pulses muted by their sweep units
+    pub fn get_levels(&self) -> ChannelLevels {
+        let pulse_level = |index: usize| -> u8 {
+            if self.sweeps[index].is_muting(self.pulses[index].get_timer_period()) {
+                0
+            } else {
+                self.pulses[index].get_output()
+            }
+        };
+        ChannelLevels {
+            pulse1: pulse_level(0),
+            pulse2: pulse_level(1),
+            triangle: self.triangle.get_output(),
+            noise: self.noise.get_output(),
+            dmc: self.dmc.get_output(),
+        }
+    }
+
+    // Envelopes and the triangle's linear counter
+    fn clock_quarter_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.envelope.clock();
+        }
+        self.triangle.clock_linear_counter();
+        self.noise.envelope.clock();
+    }
+
+    // Length counters and sweeps
+    fn clock_half_frame(&mut self) {
+        for index in 0..2 {
+            self.pulses[index].length_counter.clock();
+            let period: u16 = self.sweeps[index].clock(self.pulses[index].get_timer_period());
+            self.pulses[index].set_timer_period(period);
+        }
+        self.triangle.length_counter.clock();
+        self.noise.length_counter.clock();
+    }
+}
+
+// Only $4015 can be read, the other registers are write only
+impl MemoryMappedDevice for Apu {
+    fn read_byte(&mut self, address: usize) -> u8 {
+        if address == 0x4015 {
+            self.read_status()
+        } else {
+            0
+        }
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) {
+        self.write_register(address as u16, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::region::{NTSC, PAL};
+
+    #[test]
+    fn test_status_and_length() {
+        // Prep for the test, enable the first pulse and load a length of 2
+        let mut apu: Apu = Apu::new(&NTSC);
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4000, 0b0001_1111);
+        apu.write_register(0x4003, 3 << 3);
+        assert_eq!(apu.read_status(), 0b0000_0001);
+
+        // Two half frames empty the length counter
+        for _ in 0..NTSC_FRAME_COUNTER.quarter_frame_steps[3] {
+            apu.clock_cpu();
+        }
+
+        // Assert results, the four step sequence also raised the frame IRQ
+        assert!(apu.is_irq_asserted());
+        assert_eq!(apu.read_status(), 0b0100_0000);
+        assert!(!apu.is_irq_asserted());
+    }
+
+    #[test]
+    fn test_pal_frame_counter() {
+        // Prep for the test
+        let mut apu: Apu = Apu::new(&PAL);
+
+        // Assert results, the frame IRQ comes later than on NTSC consoles
+        for _ in 0..NTSC_FRAME_COUNTER.quarter_frame_steps[3] {
+            apu.clock_cpu();
+        }
+        assert!(!apu.is_irq_asserted());
+        for _ in NTSC_FRAME_COUNTER.quarter_frame_steps[3]..PAL_FRAME_COUNTER.quarter_frame_steps[3]
+        {
+            apu.clock_cpu();
+        }
+        assert!(apu.is_irq_asserted());
+    }
+
+    #[test]
+    fn test_five_step_mode() {
+        // Prep for the test, the five step mode clocks the half frame on the write
+        let mut apu: Apu = Apu::new(&NTSC);
+        apu.write_register(0x4015, 0b0000_1000);
+        apu.write_register(0x400C, 0b0001_1111);
+        apu.write_register(0x400F, 3 << 3);
+        apu.write_register(0x4017, 0b1000_0000);
+        assert_eq!(apu.read_status(), 0b0000_1000);
+
+        // The second half frame comes at the second step, and no IRQ is raised by the sequence
+        for _ in 0..NTSC_FRAME_COUNTER.five_step_period {
+            apu.clock_cpu();
+        }
+        assert_eq!(apu.read_status(), 0);
+        assert!(!apu.is_irq_asserted());
+    }
+
+    #[test]
+    fn test_sweep_mute() {
+        // Prep for the test, a period below 8 mutes the first pulse at constant volume 15
+        let mut apu: Apu = Apu::new(&NTSC);
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4002, 0x07);
+        apu.write_register(0x4003, 0b0000_1000);
+        for _ in 0..16 {
+            apu.clock_cpu();
+            assert_eq!(apu.get_levels().pulse1, 0);
+        }
+
+        // Raising the period lets the waveform through
+        apu.write_register(0x4002, 0x08);
+        let mut heard: bool = false;
+        for _ in 0..64 {
+            apu.clock_cpu();
+            heard |= apu.get_levels().pulse1 == 15;
+        }
+        assert!(heard);
+    }
+}
@@ -0,0 +1,243 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Delta modulation channel, playing 1 bit delta encoded samples read from CPU memory
+// The channel does not read memory itself, its owner fetches the bytes it asks for
+// https://www.nesdev.org/wiki/APU_DMC
+
+// Timer periods in CPU cycles selected by the rate register, on NTSC consoles and on PAL
+// consoles where the CPU is slower
+pub const NTSC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+pub const PAL_RATE_TABLE: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+pub struct Dmc {
+    irq_enabled: bool,
+    irq_pending: bool,
+    looping: bool,
+    rate_table: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    // 7 bit output level
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    // Memory reader
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    // Output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silenced: bool,
+}
+
+impl Dmc {
+    pub fn new(rate_table: &'static [u16; 16]) -> Dmc {
+        Dmc {
+            irq_enabled: false,
+            irq_pending: false,
+            looping: false,
+            rate_table,
+            timer_period: rate_table[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silenced: true,
+        }
+    }
+
+    // Write one of the four channel registers
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        match register & 0b11 {
+            0 => {
+                // IL-- RRRR
+                self.irq_enabled = value & 0b1000_0000 == 0b1000_0000;
+                self.looping = value & 0b0100_0000 == 0b0100_0000;
+                self.timer_period = self.rate_table[usize::from(value & 0b1111)];
+                if !self.irq_enabled {
+                    self.irq_pending = false;
+                }
+            }
+            1 => self.output_level = value & 0b0111_1111,
+            2 => self.sample_address = 0xC000 + u16::from(value) * 64,
+            _ => self.sample_length = u16::from(value) * 16 + 1,
+        }
+    }
+
+    // Enabling or disabling through the status register, which also acknowledges the IRQ
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_pending = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn is_irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    // The address of the next sample byte, when the buffer is empty and the sample has not ended
+    pub fn get_fetch_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    // Fill the sample buffer with the byte read from the fetch address
+    pub fn load_sample_byte(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+
+        // The address wraps from $FFFF around to $8000
+        self.current_address = if self.current_address == 0xFFFF {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    // Clocked on every CPU cycle, as the rate table is already in CPU cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        // Each bit moves the level up or down by 2, unless that would leave the 7 bit range
+        if !self.silenced {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        // Start a new output cycle with the buffered byte, or stay silent when there is none
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(value) => {
+                    self.shift_register = value;
+                    self.silenced = false;
+                }
+                None => self.silenced = true,
+            }
+        }
+    }
+
+    // The current output level between 0 and 127
+    pub fn get_output(&self) -> u8 {
+        self.output_level
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_reader() {
+        // Prep for the test, a one byte sample at $C040 with the IRQ enabled
+        let mut dmc: Dmc = Dmc::new(&NTSC_RATE_TABLE);
+        dmc.write_register(0, 0b1000_0000);
+        dmc.write_register(2, 1);
+        dmc.write_register(3, 0);
+        assert_eq!(dmc.get_fetch_address(), None);
+
+        // Enabling the channel starts the sample
+        dmc.set_enabled(true);
+        assert_eq!(dmc.get_fetch_address(), Some(0xC040));
+        dmc.load_sample_byte(0xFF);
+
+        // Assert results, the sample ended and raised the IRQ
+        assert_eq!(dmc.get_fetch_address(), None);
+        assert!(!dmc.is_active());
+        assert!(dmc.is_irq_pending());
+        dmc.set_enabled(false);
+        assert!(!dmc.is_irq_pending());
+    }
+
+    #[test]
+    fn test_output_unit() {
+        // Prep for the test, the fastest rate starting from a level of 10
+        let mut dmc: Dmc = Dmc::new(&NTSC_RATE_TABLE);
+        dmc.write_register(0, 0x0F);
+        dmc.write_register(1, 10);
+        dmc.write_register(3, 0);
+        dmc.set_enabled(true);
+        dmc.load_sample_byte(0b0000_0011);
+
+        // The first output cycle of 8 bits is silent, then the buffered byte is loaded
+        for _ in 0..1 + 7 * 54 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.get_output(), 10);
+
+        // Two set bits raise the level, the rest lower it
+        let mut levels: Vec<u8> = Vec::new();
+        for _ in 0..4 {
+            for _ in 0..54 {
+                dmc.clock_timer();
+            }
+            levels.push(dmc.get_output());
+        }
+        assert_eq!(levels, [12, 14, 12, 10].to_vec());
+    }
+}
@@ -0,0 +1,126 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Volume envelope generator
+// https://www.nesdev.org/wiki/APU_Envelope
+
+#[derive(Default)]
+pub struct Envelope {
+    // Set by a write to the channel's length register, restarts the envelope on the next clock
+    start: bool,
+    // When set the envelope restarts at 15 after reaching zero
+    looping: bool,
+    // When set the volume is used directly instead of the decay level
+    constant_volume: bool,
+    // The constant volume, which doubles as the divider period
+    volume: u8,
+    divider: u8,
+    decay_level: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope::default()
+    }
+
+    // Load the settings from a channel's control register, in the form --LC VVVV
+    pub fn write_control(&mut self, value: u8) {
+        self.looping = value & 0b0010_0000 == 0b0010_0000;
+        self.constant_volume = value & 0b0001_0000 == 0b0001_0000;
+        self.volume = value & 0b0000_1111;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // Clocked by the quarter frame signal
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay_level = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay_level > 0 {
+                self.decay_level -= 1;
+            } else if self.looping {
+                self.decay_level = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay_level
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        // Prep for the test, a period of 0 decays by one level on every clock
+        let mut envelope: Envelope = Envelope::new();
+        envelope.write_control(0b0000_0000);
+        envelope.restart();
+
+        // The first clock starts the envelope at full volume
+        envelope.clock();
+        assert_eq!(envelope.get_volume(), 15);
+
+        // Each further clock decays it until it rests at zero
+        for level in (0..15).rev() {
+            envelope.clock();
+            assert_eq!(envelope.get_volume(), level);
+        }
+        envelope.clock();
+        assert_eq!(envelope.get_volume(), 0);
+    }
+
+    #[test]
+    fn test_loop_and_constant_volume() {
+        // Prep for the test, a looping envelope
+        let mut envelope: Envelope = Envelope::new();
+        envelope.write_control(0b0010_0000);
+        envelope.restart();
+        for _ in 0..17 {
+            envelope.clock();
+        }
+
+        // It wraps back to 15 after reaching zero
+        assert_eq!(envelope.get_volume(), 15);
+
+        // Constant volume ignores the decay level
+        envelope.write_control(0b0001_0111);
+        assert_eq!(envelope.get_volume(), 7);
+    }
+}
@@ -0,0 +1,115 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Length counter, silences a channel after a number of half frames
+// https://www.nesdev.org/wiki/APU_Length_Counter
+
+// Lengths loaded by the upper 5 bits of a channel's length register
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub fn new() -> LengthCounter {
+        LengthCounter::default()
+    }
+
+    // Enabling or disabling through the status register, disabling clears the counter
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    // Load the counter from the upper 5 bits of a length register write
+    pub fn load(&mut self, value: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[usize::from(value >> 3)];
+        }
+    }
+
+    // Clocked by the half frame signal
+    pub fn clock(&mut self) {
+        if !self.halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_clock() {
+        // Prep for the test, an index of 3 loads a length of 2
+        let mut length: LengthCounter = LengthCounter::new();
+        length.set_enabled(true);
+        length.load(3 << 3);
+
+        // Assert results
+        assert!(length.is_active());
+        length.clock();
+        assert!(length.is_active());
+        length.clock();
+        assert!(!length.is_active());
+    }
+
+    #[test]
+    fn test_halt_and_disable() {
+        // Prep for the test
+        let mut length: LengthCounter = LengthCounter::new();
+        length.set_enabled(true);
+        length.load(3 << 3);
+
+        // A halted counter does not count down
+        length.set_halted(true);
+        length.clock();
+        length.clock();
+        assert!(length.is_active());
+
+        // Disabling the channel clears the counter and blocks loads
+        length.set_enabled(false);
+        assert!(!length.is_active());
+        length.load(3 << 3);
+        assert!(!length.is_active());
+    }
+}
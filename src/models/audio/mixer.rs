@@ -0,0 +1,155 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Console audio output, mixing the console's own channels with cartridge expansion audio
+// https://www.nesdev.org/wiki/APU_Mixer
+
+// The output level of each of the console's own channels
+#[derive(Clone, Copy, Default)]
+pub struct ChannelLevels {
+    // 0 to 15
+    pub pulse1: u8,
+    // 0 to 15
+    pub pulse2: u8,
+    // 0 to 15
+    pub triangle: u8,
+    // 0 to 15
+    pub noise: u8,
+    // 0 to 127
+    pub dmc: u8,
+}
+
+// The output of a single pulse channel at full volume
+// Expansion audio is scaled relative to this, as that is how its levels are usually documented
+pub const PULSE_FULL_VOLUME: f32 = 95.88 / (8128.0 / 15.0 + 100.0);
+
+// Mix the console's channels with the non-linear formulas from the nesdev wiki, then add the
+// cartridge's expansion audio, which is summed linearly on the cartridge connector
+pub fn mix(levels: &ChannelLevels, expansion: f32) -> f32 {
+    let pulse_sum: f32 = f32::from(levels.pulse1) + f32::from(levels.pulse2);
+    let pulse_out: f32 = if pulse_sum == 0.0 {
+        0.0
+    } else {
+        95.88 / (8128.0 / pulse_sum + 100.0)
+    };
+
+    let tnd_sum: f32 = f32::from(levels.triangle) / 8227.0
+        + f32::from(levels.noise) / 12241.0
+        + f32::from(levels.dmc) / 22638.0;
+    let tnd_out: f32 = if tnd_sum == 0.0 {
+        0.0
+    } else {
+        159.79 / (1.0 / tnd_sum + 100.0)
+    };
+
+    pulse_out + tnd_out + expansion
+}
+
+// Collects one mixed level per CPU cycle and averages them down to the output sample rate
+pub struct Mixer {
+    cpu_cycles_per_sample: f64,
+    cycles_until_sample: f64,
+    accumulated: f32,
+    accumulated_cycles: u32,
+    samples: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(cpu_clock_hz: f64, sample_rate_hz: f64) -> Mixer {
+        let cpu_cycles_per_sample: f64 = cpu_clock_hz / sample_rate_hz;
+        Mixer {
+            cpu_cycles_per_sample,
+            cycles_until_sample: cpu_cycles_per_sample,
+            accumulated: 0.0,
+            accumulated_cycles: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    // Add the mixed level for one CPU cycle
+    pub fn push(&mut self, level: f32) {
+        self.accumulated += level;
+        self.accumulated_cycles += 1;
+        self.cycles_until_sample -= 1.0;
+
+        if self.cycles_until_sample <= 0.0 {
+            self.cycles_until_sample += self.cpu_cycles_per_sample;
+            self.samples
+                .push(self.accumulated / self.accumulated_cycles as f32);
+            self.accumulated = 0.0;
+            self.accumulated_cycles = 0;
+        }
+    }
+
+    // Take the samples produced so far
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_levels() {
+        // Silence mixes to zero
+        let silent: ChannelLevels = ChannelLevels::default();
+        assert_eq!(mix(&silent, 0.0), 0.0);
+
+        // A single pulse at full volume matches the reference level
+        let pulse: ChannelLevels = ChannelLevels {
+            pulse1: 15,
+            ..ChannelLevels::default()
+        };
+        assert!((mix(&pulse, 0.0) - PULSE_FULL_VOLUME).abs() < 0.0001);
+
+        // Everything at full volume stays close to 1.0
+        let full: ChannelLevels = ChannelLevels {
+            pulse1: 15,
+            pulse2: 15,
+            triangle: 15,
+            noise: 15,
+            dmc: 127,
+        };
+        let level: f32 = mix(&full, 0.0);
+        assert!(level > 0.95 && level < 1.05);
+
+        // Expansion audio adds linearly
+        assert!((mix(&pulse, 0.25) - PULSE_FULL_VOLUME - 0.25).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_mixer_downsamples() {
+        // Prep for the test, 4 CPU cycles per sample
+        let mut mixer: Mixer = Mixer::new(4000.0, 1000.0);
+        for level in [1.0, 1.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5] {
+            mixer.push(level);
+        }
+
+        // Assert results
+        assert_eq!(mixer.take_samples(), [0.5, 0.5].to_vec());
+        assert!(mixer.take_samples().is_empty());
+    }
+}
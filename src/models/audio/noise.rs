@@ -0,0 +1,135 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Noise channel, a pseudo-random bit stream from a 15 bit linear feedback shift register
+// https://www.nesdev.org/wiki/APU_Noise
+
+use crate::models::audio::envelope::Envelope;
+use crate::models::audio::length_counter::LengthCounter;
+
+// Timer periods in CPU cycles selected by the period register, on NTSC consoles and on PAL
+// consoles where the CPU is slower
+pub const NTSC_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+pub struct Noise {
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+    // Short mode feeds back from bit 6 instead of bit 1, giving a metallic tone
+    short_mode: bool,
+    period_table: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+}
+
+impl Noise {
+    pub fn new(period_table: &'static [u16; 16]) -> Noise {
+        Noise {
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            short_mode: false,
+            period_table,
+            timer_period: period_table[0],
+            timer: 0,
+            // The shift register is loaded with 1 at power on
+            shift_register: 1,
+        }
+    }
+
+    // Write one of the four channel registers, register 1 is unused
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        match register & 0b11 {
+            0 => {
+                // --LC VVVV
+                self.length_counter
+                    .set_halted(value & 0b0010_0000 == 0b0010_0000);
+                self.envelope.write_control(value);
+            }
+            1 => {}
+            2 => {
+                // M--- PPPP
+                self.short_mode = value & 0b1000_0000 == 0b1000_0000;
+                self.timer_period = self.period_table[usize::from(value & 0b1111)];
+            }
+            _ => {
+                // LLLL L---
+                self.length_counter.load(value);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    // Clocked on every CPU cycle, as the period table is already in CPU cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap: u16 = if self.short_mode { 6 } else { 1 };
+            let feedback: u16 = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // The current output level between 0 and 15, silenced while bit 0 of the shift register is set
+    pub fn get_output(&self) -> u8 {
+        if self.shift_register & 1 == 0 && self.length_counter.is_active() {
+            self.envelope.get_volume()
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_register() {
+        // Prep for the test, the shortest period at constant volume 5
+        let mut noise: Noise = Noise::new(&NTSC_PERIOD_TABLE);
+        noise.length_counter.set_enabled(true);
+        noise.write_register(0, 0b0001_0101);
+        noise.write_register(2, 0);
+        noise.write_register(3, 0b0000_1000);
+
+        // Bit 0 starts set, so the channel is silent until the register shifts
+        assert_eq!(noise.get_output(), 0);
+        noise.clock_timer();
+        assert_eq!(noise.shift_register, 0b0100_0000_0000_0000);
+        assert_eq!(noise.get_output(), 5);
+
+        // The next shift happens after a full period of 4 cycles
+        for _ in 0..4 {
+            noise.clock_timer();
+        }
+        assert_eq!(noise.shift_register, 0b0010_0000_0000_0000);
+    }
+}
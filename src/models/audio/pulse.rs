@@ -0,0 +1,140 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Pulse (square wave) channel, without the sweep unit
+// The sweep unit is left to the owner of the channel as the MMC5 pulse channels do not have one
+// https://www.nesdev.org/wiki/APU_Pulse
+
+use crate::models::audio::envelope::Envelope;
+use crate::models::audio::length_counter::LengthCounter;
+
+// The four duty cycle waveforms, read from the highest bit down
+const DUTY_TABLE: [u8; 4] = [0b0100_0000, 0b0110_0000, 0b0111_1000, 0b1001_1111];
+
+#[derive(Default)]
+pub struct Pulse {
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+    duty: u8,
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Pulse {
+    pub fn new() -> Pulse {
+        Pulse::default()
+    }
+
+    // Write one of the four channel registers
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        match register & 0b11 {
+            0 => {
+                // DDLC VVVV
+                self.duty = value >> 6;
+                self.length_counter
+                    .set_halted(value & 0b0010_0000 == 0b0010_0000);
+                self.envelope.write_control(value);
+            }
+            // Register 1 holds the sweep unit, which is handled by the owner of the channel
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(value),
+            _ => {
+                // LLLL LTTT
+                self.timer_period = (self.timer_period & 0x00FF) | (u16::from(value & 0b111) << 8);
+                self.length_counter.load(value);
+                self.sequence_step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn get_timer_period(&self) -> u16 {
+        self.timer_period
+    }
+
+    pub fn set_timer_period(&mut self, timer_period: u16) {
+        self.timer_period = timer_period;
+    }
+
+    // Clocked once every APU cycle, which is every second CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // The current output level between 0 and 15
+    pub fn get_output(&self) -> u8 {
+        let high: bool =
+            DUTY_TABLE[usize::from(self.duty)] & (0b1000_0000 >> self.sequence_step) != 0;
+        if high && self.length_counter.is_active() {
+            self.envelope.get_volume()
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waveform() {
+        // Prep for the test, 50% duty at constant volume 9 with a period of 1
+        let mut pulse: Pulse = Pulse::new();
+        pulse.length_counter.set_enabled(true);
+        pulse.write_register(0, 0b1001_1001);
+        pulse.write_register(2, 1);
+        pulse.write_register(3, 0b0000_1000);
+
+        // Collect one full period of the waveform, two timer clocks per step
+        let mut samples: Vec<u8> = Vec::new();
+        for _ in 0..8 {
+            samples.push(pulse.get_output());
+            pulse.clock_timer();
+            pulse.clock_timer();
+        }
+
+        // Assert results
+        assert_eq!(samples, [0, 9, 9, 9, 9, 0, 0, 0].to_vec());
+    }
+
+    #[test]
+    fn test_silent_without_length() {
+        // Prep for the test, the channel is not enabled so the length counter cannot be loaded
+        let mut pulse: Pulse = Pulse::new();
+        pulse.write_register(0, 0b1011_1111);
+        pulse.write_register(3, 0b0000_1000);
+
+        for _ in 0..8 {
+            assert_eq!(pulse.get_output(), 0);
+            pulse.clock_timer();
+        }
+    }
+}
@@ -0,0 +1,124 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Pulse channel sweep unit, which periodically moves the channel's period up or down
+// https://www.nesdev.org/wiki/APU_Sweep
+
+#[derive(Default)]
+pub struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+    // The first pulse channel negates with the ones' complement, so it subtracts one extra
+    ones_complement: bool,
+}
+
+impl Sweep {
+    pub fn new(ones_complement: bool) -> Sweep {
+        Sweep {
+            ones_complement,
+            ..Sweep::default()
+        }
+    }
+
+    // Load the settings from the sweep register, in the form EPPP NSSS
+    pub fn write_control(&mut self, value: u8) {
+        self.enabled = value & 0b1000_0000 == 0b1000_0000;
+        self.period = (value >> 4) & 0b111;
+        self.negate = value & 0b0000_1000 == 0b0000_1000;
+        self.shift = value & 0b111;
+        self.reload = true;
+    }
+
+    // The period the sweep is moving the channel towards, which is calculated continuously
+    pub fn get_target_period(&self, timer_period: u16) -> u16 {
+        let change: u16 = timer_period >> self.shift;
+        if self.negate {
+            timer_period.saturating_sub(change + u16::from(self.ones_complement))
+        } else {
+            timer_period + change
+        }
+    }
+
+    // The channel is muted while its period is too short or the target overflows,
+    // even when the sweep is disabled
+    pub fn is_muting(&self, timer_period: u16) -> bool {
+        timer_period < 8 || self.get_target_period(timer_period) > 0x07FF
+    }
+
+    // Clocked by the half frame signal, returning the channel's new period
+    pub fn clock(&mut self, timer_period: u16) -> u16 {
+        let mut new_period: u16 = timer_period;
+        if self.divider == 0 && self.enabled && self.shift > 0 && !self.is_muting(timer_period) {
+            new_period = self.get_target_period(timer_period);
+        }
+
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+        new_period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_period() {
+        // Prep for the test, negating by a shift of 1
+        let mut first: Sweep = Sweep::new(true);
+        let mut second: Sweep = Sweep::new(false);
+        first.write_control(0b0000_1001);
+        second.write_control(0b0000_1001);
+
+        // Assert results, the first channel subtracts one more than the second
+        assert_eq!(first.get_target_period(0x100), 0x7F);
+        assert_eq!(second.get_target_period(0x100), 0x80);
+
+        // Adding past $7FF mutes the channel, as does a period below 8
+        second.write_control(0b0000_0001);
+        assert!(second.is_muting(0x600));
+        assert!(!second.is_muting(0x500));
+        assert!(second.is_muting(7));
+    }
+
+    #[test]
+    fn test_clock() {
+        // Prep for the test, enabled with a divider period of 1 and a shift of 2
+        let mut sweep: Sweep = Sweep::new(false);
+        sweep.write_control(0b1001_0010);
+
+        // The divider starts at zero so the period changes at once, then on every second clock
+        assert_eq!(sweep.clock(0x100), 0x140);
+        assert_eq!(sweep.clock(0x140), 0x140);
+        assert_eq!(sweep.clock(0x140), 0x190);
+    }
+}
@@ -0,0 +1,143 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Triangle channel, stepping through a 32 step sequence gated by the linear and length counters
+// https://www.nesdev.org/wiki/APU_Triangle
+
+use crate::models::audio::length_counter::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Default)]
+pub struct Triangle {
+    pub length_counter: LengthCounter,
+    // The control flag doubles as the length counter halt
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Triangle {
+    pub fn new() -> Triangle {
+        Triangle::default()
+    }
+
+    // Write one of the four channel registers, register 1 is unused
+    pub fn write_register(&mut self, register: u8, value: u8) {
+        match register & 0b11 {
+            0 => {
+                // CRRR RRRR
+                self.control = value & 0b1000_0000 == 0b1000_0000;
+                self.length_counter.set_halted(self.control);
+                self.linear_reload_value = value & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(value),
+            _ => {
+                // LLLL LTTT
+                self.timer_period = (self.timer_period & 0x00FF) | (u16::from(value & 0b111) << 8);
+                self.length_counter.load(value);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    // Clocked on every CPU cycle, unlike the other channels
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length_counter.is_active() {
+                self.sequence_step = (self.sequence_step + 1) & 0b1_1111;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // Clocked by the quarter frame signal
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    // The current output level between 0 and 15
+    // Silencing the channel freezes the sequence rather than dropping the output to zero
+    pub fn get_output(&self) -> u8 {
+        SEQUENCE[usize::from(self.sequence_step)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waveform() {
+        // Prep for the test, a period of 0 steps the sequence on every clock
+        let mut triangle: Triangle = Triangle::new();
+        triangle.length_counter.set_enabled(true);
+        triangle.write_register(0, 0b0000_0100);
+        triangle.write_register(3, 0b0000_1000);
+        triangle.clock_linear_counter();
+
+        // Collect the first half of the sequence
+        let mut samples: Vec<u8> = Vec::new();
+        for _ in 0..4 {
+            triangle.clock_timer();
+            samples.push(triangle.get_output());
+        }
+
+        // Assert results
+        assert_eq!(samples, [14, 13, 12, 11].to_vec());
+    }
+
+    #[test]
+    fn test_linear_counter() {
+        // Prep for the test, a linear counter of 2 that is not held by the control flag
+        let mut triangle: Triangle = Triangle::new();
+        triangle.length_counter.set_enabled(true);
+        triangle.write_register(0, 0b0000_0010);
+        triangle.write_register(3, 0b0000_1000);
+        for _ in 0..3 {
+            triangle.clock_linear_counter();
+        }
+
+        // The sequence is frozen once the counter runs out
+        triangle.clock_timer();
+        assert_eq!(triangle.get_output(), 15);
+    }
+}
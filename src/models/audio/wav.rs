@@ -0,0 +1,86 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Writing audio to 16 bit mono PCM WAV files
+// http://soundfile.sapp.org/doc/WaveFormat/
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BITS_PER_SAMPLE: u16 = 16;
+const CHANNEL_COUNT: u16 = 1;
+const HEADER_SIZE: usize = 44;
+
+// Encode samples between -1.0 and 1.0 as a WAV file, clipping anything outside that range
+pub fn encode(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let block_align: u16 = CHANNEL_COUNT * BITS_PER_SAMPLE / 8;
+    let data_size: u32 = (samples.len() * usize::from(block_align)) as u32;
+
+    let mut wav: Vec<u8> = Vec::with_capacity(HEADER_SIZE + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    // Format chunk, 1 is uncompressed PCM
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&CHANNEL_COUNT.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        let value: i16 = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        wav.extend_from_slice(&value.to_le_bytes());
+    }
+    wav
+}
+
+pub fn write_file(path: &Path, samples: &[f32], sample_rate: u32) -> io::Result<()> {
+    fs::write(path, encode(samples, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        // Prep for the test
+        let wav: Vec<u8> = encode(&[0.0, 1.0, -2.0], 44100);
+
+        // Assert results
+        assert_eq!(wav.len(), HEADER_SIZE + 6);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], 42u32.to_le_bytes());
+        assert_eq!(wav[24..28], 44100u32.to_le_bytes());
+        assert_eq!(wav[40..44], 6u32.to_le_bytes());
+        assert_eq!(wav[44..50], [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+}
@@ -0,0 +1,150 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// gzip files, a single DEFLATE stream with an optional file name and a CRC-32 of the contents
+// https://www.rfc-editor.org/rfc/rfc1952
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::archive::inflate;
+use crate::models::cartridge::archive::ArchiveError;
+
+pub const MAGIC: &[u8; 2] = &[0x1F, 0x8B];
+
+const HEADER_SIZE: usize = 10;
+const METHOD_DEFLATE: u8 = 8;
+
+// Header flags
+const FLAG_HEADER_CRC: u8 = 0b0000_0010;
+const FLAG_EXTRA: u8 = 0b0000_0100;
+const FLAG_NAME: u8 = 0b0000_1000;
+const FLAG_COMMENT: u8 = 0b0001_0000;
+
+// The file's contents and the name it was compressed from, which is empty if it was not stored
+pub fn extract(content: &[u8]) -> Result<(String, Vec<u8>), ArchiveError> {
+    if !content.starts_with(MAGIC) || content.len() < HEADER_SIZE {
+        return Err(ArchiveError::InvalidHeader);
+    }
+    if content[2] != METHOD_DEFLATE {
+        return Err(ArchiveError::UnsupportedMethod(format!(
+            "gzip method {}",
+            content[2]
+        )));
+    }
+    let flags: u8 = content[3];
+
+    // Optional fields follow the fixed header in this order
+    let mut position: usize = HEADER_SIZE;
+    if flags & FLAG_EXTRA == FLAG_EXTRA {
+        let length: &[u8] = content
+            .get(position..position + 2)
+            .ok_or(ArchiveError::TruncatedData)?;
+        position += 2 + usize::from(u16::from_le_bytes([length[0], length[1]]));
+    }
+    let mut name: String = String::new();
+    if flags & FLAG_NAME == FLAG_NAME {
+        let (field, end): (&[u8], usize) = read_zero_terminated(content, position)?;
+        name = String::from_utf8_lossy(field).to_string();
+        position = end;
+    }
+    if flags & FLAG_COMMENT == FLAG_COMMENT {
+        position = read_zero_terminated(content, position)?.1;
+    }
+    if flags & FLAG_HEADER_CRC == FLAG_HEADER_CRC {
+        position += 2;
+    }
+
+    // The stream is followed by the CRC-32 and the size of the contents
+    let stream: &[u8] = content.get(position..).ok_or(ArchiveError::TruncatedData)?;
+    let (data, length): (Vec<u8>, usize) = inflate::inflate_with_length(stream)?;
+    let trailer: &[u8] = stream
+        .get(length..length + 8)
+        .ok_or(ArchiveError::TruncatedData)?;
+    let crc: u32 = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if Utils::get_crc32(&data) != crc {
+        return Err(ArchiveError::ChecksumMismatch);
+    }
+    Ok((name, data))
+}
+
+// A zero terminated field, and the position after its terminator
+fn read_zero_terminated(content: &[u8], start: usize) -> Result<(&[u8], usize), ArchiveError> {
+    let field: &[u8] = content.get(start..).ok_or(ArchiveError::TruncatedData)?;
+    let length: usize = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .ok_or(ArchiveError::TruncatedData)?;
+    Ok((&field[..length], start + length + 1))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // game.nes holding an iNES header magic followed by 12 zeros
+    pub const TEST_GZIP: [u8; 35] = [
+        0x1F, 0x8B, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xFF, 0x67, 0x61, 0x6D, 0x65, 0x2E,
+        0x6E, 0x65, 0x73, 0x00, 0xF3, 0x73, 0x0D, 0x96, 0x62, 0x40, 0x02, 0x00, 0x48, 0xA0, 0x09,
+        0xCE, 0x10, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_extract() {
+        // Prep for the test
+        let mut expected: Vec<u8> = b"NES\x1A".to_vec();
+        expected.resize(16, 0);
+
+        // Assert results
+        assert_eq!(extract(&TEST_GZIP), Ok(("game.nes".to_string(), expected)));
+    }
+
+    #[test]
+    fn test_extract_without_name() {
+        // Prep for the test
+        let content: [u8; 24] = [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xFF, 0xF3, 0x73, 0x0D, 0x96,
+            0x02, 0x00, 0xD4, 0x48, 0x2A, 0x40, 0x04, 0x00, 0x00, 0x00,
+        ];
+
+        // Assert results
+        assert_eq!(extract(&content), Ok((String::new(), b"NES\x1A".to_vec())));
+    }
+
+    #[test]
+    fn test_errors() {
+        // Prep for the test
+        let mut corrupted: [u8; 35] = TEST_GZIP;
+        corrupted[27] ^= 0xFF;
+        let mut method: [u8; 35] = TEST_GZIP;
+        method[2] = 0;
+
+        // Assert results
+        assert_eq!(extract(&corrupted), Err(ArchiveError::ChecksumMismatch));
+        assert_eq!(
+            extract(&method),
+            Err(ArchiveError::UnsupportedMethod("gzip method 0".to_string()))
+        );
+        assert_eq!(extract(&TEST_GZIP[..30]), Err(ArchiveError::TruncatedData));
+        assert_eq!(extract(b"PK"), Err(ArchiveError::InvalidHeader));
+    }
+}
@@ -0,0 +1,337 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Decompression of DEFLATE streams, as used by zip and gzip files
+// https://www.rfc-editor.org/rfc/rfc1951
+//
+// Huffman codes are decoded a bit at a time from the count of codes of each length, which is
+// slower than a lookup table but plenty for ROM sized files.
+
+use crate::models::cartridge::archive::ArchiveError;
+
+const MAX_CODE_LENGTH: usize = 15;
+
+// Base lengths and extra bits for length symbols 257 to 285
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// Base distances and extra bits for distance symbols 0 to 29
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// The order code length code lengths are stored in by dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// Reads bits from the least significant end of each byte first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn read_bits(&mut self, count: u8) -> Result<u32, ArchiveError> {
+        let mut value: u32 = 0;
+        for index in 0..count {
+            let byte: u8 = *self
+                .data
+                .get(self.position)
+                .ok_or(ArchiveError::TruncatedData)?;
+            value |= u32::from((byte >> self.bit) & 1) << index;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    // Stored blocks start on a byte boundary
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the symbols in code order
+struct Huffman {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts: [u16; MAX_CODE_LENGTH + 1] = [0; MAX_CODE_LENGTH + 1];
+        for length in lengths {
+            counts[usize::from(*length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<u16> = Vec::with_capacity(lengths.len());
+        for length in 1..=MAX_CODE_LENGTH {
+            for (symbol, symbol_length) in lengths.iter().enumerate() {
+                if usize::from(*symbol_length) == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ArchiveError> {
+        // Codes of each length follow on from the last code of the length before
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code |= reader.read_bits(1)? as i32;
+            let count: i32 = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(ArchiveError::InvalidData)
+    }
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, ArchiveError> {
+    inflate_with_length(data).map(|(output, _)| output)
+}
+
+// Inflate a stream, also returning the number of bytes it took up so data after it can be found
+pub fn inflate_with_length(data: &[u8]) -> Result<(Vec<u8>, usize), ArchiveError> {
+    let mut reader: BitReader = BitReader {
+        data,
+        position: 0,
+        bit: 0,
+    };
+    let mut output: Vec<u8> = Vec::new();
+    loop {
+        let is_final: bool = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => inflate_stored(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances): (Huffman, Huffman) = get_fixed_codes();
+                inflate_codes(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances): (Huffman, Huffman) = read_dynamic_codes(&mut reader)?;
+                inflate_codes(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(ArchiveError::InvalidData),
+        }
+        if is_final {
+            reader.align_to_byte();
+            return Ok((output, reader.position));
+        }
+    }
+}
+
+// A length, its ones' complement, then that many bytes as they are
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), ArchiveError> {
+    reader.align_to_byte();
+    let header: &[u8] = reader
+        .data
+        .get(reader.position..reader.position + 4)
+        .ok_or(ArchiveError::TruncatedData)?;
+    let length: usize = usize::from(u16::from_le_bytes([header[0], header[1]]));
+    let complement: u16 = u16::from_le_bytes([header[2], header[3]]);
+    if length as u16 != !complement {
+        return Err(ArchiveError::InvalidData);
+    }
+    let start: usize = reader.position + 4;
+    let bytes: &[u8] = reader
+        .data
+        .get(start..start + length)
+        .ok_or(ArchiveError::TruncatedData)?;
+    output.extend_from_slice(bytes);
+    reader.position = start + length;
+    Ok(())
+}
+
+// https://www.rfc-editor.org/rfc/rfc1951#page-12
+fn get_fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths: [u8; 288] = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+// https://www.rfc-editor.org/rfc/rfc1951#page-13
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ArchiveError> {
+    let literal_count: usize = reader.read_bits(5)? as usize + 257;
+    let distance_count: usize = reader.read_bits(5)? as usize + 1;
+    let code_length_count: usize = reader.read_bits(4)? as usize + 4;
+
+    let mut code_length_lengths: [u8; 19] = [0; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[*index] = reader.read_bits(3)? as u8;
+    }
+    let code_lengths: Huffman = Huffman::new(&code_length_lengths);
+
+    // The literal and distance code lengths are one run, so repeats can cross between them
+    let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol: u16 = code_lengths.decode(reader)?;
+        let (value, repeat): (u8, u32) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or(ArchiveError::InvalidData)?,
+                3 + reader.read_bits(2)?,
+            ),
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(ArchiveError::InvalidData);
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_codes(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ArchiveError> {
+    loop {
+        let symbol: usize = usize::from(literals.decode(reader)?);
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        // A length and distance back into the output, which can overlap the bytes being written
+        let length_index: usize = symbol - 257;
+        if length_index >= LENGTH_BASES.len() {
+            return Err(ArchiveError::InvalidData);
+        }
+        let length: usize = usize::from(LENGTH_BASES[length_index])
+            + reader.read_bits(LENGTH_EXTRA_BITS[length_index])? as usize;
+        let distance_index: usize = usize::from(distances.decode(reader)?);
+        if distance_index >= DISTANCE_BASES.len() {
+            return Err(ArchiveError::InvalidData);
+        }
+        let distance: usize = usize::from(DISTANCE_BASES[distance_index])
+            + reader.read_bits(DISTANCE_EXTRA_BITS[distance_index])? as usize;
+        if distance > output.len() {
+            return Err(ArchiveError::InvalidData);
+        }
+        let start: usize = output.len() - distance;
+        for index in 0..length {
+            output.push(output[start + index]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_block() {
+        // Prep for the test
+        let data: [u8; 11] = [
+            0x01, 0x06, 0x00, 0xF9, 0xFF, 0x73, 0x74, 0x6F, 0x72, 0x65, 0x64,
+        ];
+
+        // Assert results
+        assert_eq!(inflate(&data), Ok(b"stored".to_vec()));
+        assert_eq!(inflate(&data[..8]), Err(ArchiveError::TruncatedData));
+    }
+
+    #[test]
+    fn test_fixed_codes() {
+        // Prep for the test, with back references that overlap the bytes they write
+        let data: [u8; 14] = [
+            0x73, 0x74, 0x72, 0x84, 0x43, 0x85, 0x8C, 0xD4, 0x9C, 0x9C, 0x7C, 0x08, 0x09, 0x00,
+        ];
+
+        // Assert results
+        assert_eq!(
+            inflate_with_length(&[&data[..], b"tail"].concat()),
+            Ok((b"ABABABABABAB hello hello".to_vec(), data.len()))
+        );
+    }
+
+    #[test]
+    fn test_dynamic_codes() {
+        // Prep for the test, pseudo-random letters with an uneven spread compress with their own codes
+        let letters: &[u8; 15] = b"aaaaaaaabbbbccd";
+        let mut seed: u32 = 1;
+        let mut expected: Vec<u8> = Vec::new();
+        for _ in 0..120 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345) & 0x7FFF_FFFF;
+            expected.push(letters[(seed >> 16) as usize % letters.len()]);
+        }
+        let data: [u8; 52] = [
+            0x2D, 0x8C, 0x81, 0x0D, 0x00, 0x30, 0x08, 0xC2, 0x6E, 0x85, 0xFA, 0xFF, 0x0D, 0x13,
+            0x66, 0x62, 0x8C, 0x96, 0x06, 0x23, 0x4B, 0x32, 0x59, 0x3B, 0x16, 0x7D, 0x28, 0x1D,
+            0x4D, 0x28, 0x0D, 0x22, 0xF8, 0x6C, 0x82, 0xA2, 0x86, 0xCF, 0x8F, 0xD3, 0xE4, 0x8D,
+            0xAA, 0xFC, 0x32, 0x33, 0xD7, 0x78, 0x6A, 0x31, 0x7B, 0x3E,
+        ];
+
+        // Assert results
+        assert_eq!(inflate(&data), Ok(expected));
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        // Assert results, block type 3 is reserved and stored lengths must match their complement
+        assert_eq!(inflate(&[0x07]), Err(ArchiveError::InvalidData));
+        assert_eq!(
+            inflate(&[0x01, 0x01, 0x00, 0x00, 0x00, 0x41]),
+            Err(ArchiveError::InvalidData)
+        );
+    }
+}
@@ -0,0 +1,630 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// LZMA and LZMA2 decompression for 7z archives, following the reference decoder in the LZMA SDK
+// https://www.7-zip.org/sdk.html
+//
+// The whole output is kept in memory and doubles as the dictionary, since ROMs are small enough
+// that there is no need for a sliding window.
+
+use crate::models::cartridge::archive::ArchiveError;
+
+// Probabilities are 11 bit fixed point values that start at one half
+const PROBABILITY_BITS: u32 = 11;
+const PROBABILITY_INITIAL: u16 = 1 << (PROBABILITY_BITS - 1);
+const MOVE_BITS: u32 = 5;
+const TOP_VALUE: u32 = 1 << 24;
+
+const STATE_COUNT: usize = 12;
+const POSITION_STATES_MAX: usize = 1 << 4;
+const LITERAL_SIZE: usize = 0x300;
+
+// Distances are coded as a slot followed by extra bits, from a bit tree for the short ones and
+// directly with four aligned bits for the long ones
+const LENGTH_TO_POSITION_STATES: usize = 4;
+const POSITION_SLOT_BITS: usize = 6;
+const END_POSITION_MODEL_INDEX: u32 = 14;
+const FULL_DISTANCES: usize = 1 << (END_POSITION_MODEL_INDEX >> 1);
+const ALIGN_BITS: usize = 4;
+const MATCH_MIN_LENGTH: usize = 2;
+
+// The distance that marks the end of a stream instead of a match
+const END_MARKER_DISTANCE: u32 = 0xFFFF_FFFF;
+
+// The literal context and position bits share a limit in LZMA2
+const LZMA2_LITERAL_BITS_MAX: u8 = 4;
+const LZMA2_DICTIONARY_PROPERTY_MAX: u8 = 40;
+
+// Decompress an LZMA stream with the 5 property bytes stored alongside it, stopping at the end
+// marker or once the given size has been produced
+pub fn decompress_lzma(
+    data: &[u8],
+    properties: &[u8],
+    unpacked_size: usize,
+) -> Result<Vec<u8>, ArchiveError> {
+    if properties.len() != 5 {
+        return Err(ArchiveError::InvalidData);
+    }
+    let mut decoder: LzmaDecoder = LzmaDecoder::new(properties[0])?;
+    let mut range_decoder: RangeDecoder = RangeDecoder::new(data)?;
+    let mut output: Vec<u8> = Vec::new();
+    decoder.decode(&mut range_decoder, &mut output, 0, unpacked_size)?;
+    Ok(output)
+}
+
+// Decompress an LZMA2 stream, a series of LZMA and uncompressed chunks, with the dictionary size
+// property byte stored alongside it
+pub fn decompress_lzma2(data: &[u8], properties: &[u8]) -> Result<Vec<u8>, ArchiveError> {
+    match properties {
+        [dictionary] if *dictionary <= LZMA2_DICTIONARY_PROPERTY_MAX => {}
+        _ => return Err(ArchiveError::InvalidData),
+    }
+
+    let mut output: Vec<u8> = Vec::new();
+    let mut decoder: Option<LzmaDecoder> = None;
+    let mut dictionary_start: usize = 0;
+    let mut position: usize = 0;
+    loop {
+        let control: u8 = *data.get(position).ok_or(ArchiveError::TruncatedData)?;
+        position += 1;
+        match control {
+            0x00 => return Ok(output),
+            0x01 | 0x02 => {
+                // Uncompressed chunk, where 1 also resets the dictionary
+                if control == 0x01 {
+                    dictionary_start = output.len();
+                }
+                let size: usize = read_u16_be(data, position)? + 1;
+                position += 2;
+                let chunk: &[u8] = data
+                    .get(position..position + size)
+                    .ok_or(ArchiveError::TruncatedData)?;
+                output.extend_from_slice(chunk);
+                position += size;
+            }
+            0x80..=0xFF => {
+                // LZMA chunk, where bits 5 and 6 say how much of the decoder is reset
+                let unpacked_size: usize =
+                    (usize::from(control & 0x1F) << 16) + read_u16_be(data, position)? + 1;
+                let packed_size: usize = read_u16_be(data, position + 2)? + 1;
+                position += 4;
+                let reset: u8 = (control >> 5) & 0b11;
+                if reset == 3 {
+                    dictionary_start = output.len();
+                }
+                if reset >= 2 {
+                    let properties: u8 = *data.get(position).ok_or(ArchiveError::TruncatedData)?;
+                    position += 1;
+                    let new_decoder: LzmaDecoder = LzmaDecoder::new(properties)?;
+                    if new_decoder.literal_context_bits + new_decoder.literal_position_bits
+                        > LZMA2_LITERAL_BITS_MAX
+                    {
+                        return Err(ArchiveError::InvalidData);
+                    }
+                    decoder = Some(new_decoder);
+                }
+                let lzma: &mut LzmaDecoder = decoder.as_mut().ok_or(ArchiveError::InvalidData)?;
+                if reset >= 1 {
+                    lzma.reset()?;
+                }
+
+                let chunk: &[u8] = data
+                    .get(position..position + packed_size)
+                    .ok_or(ArchiveError::TruncatedData)?;
+                let mut range_decoder: RangeDecoder = RangeDecoder::new(chunk)?;
+                let end: usize = output.len() + unpacked_size;
+                lzma.decode(&mut range_decoder, &mut output, dictionary_start, end)?;
+                if output.len() != end {
+                    return Err(ArchiveError::InvalidData);
+                }
+                position += packed_size;
+            }
+            _ => return Err(ArchiveError::InvalidData),
+        }
+    }
+}
+
+fn read_u16_be(data: &[u8], position: usize) -> Result<usize, ArchiveError> {
+    let bytes: &[u8] = data
+        .get(position..position + 2)
+        .ok_or(ArchiveError::TruncatedData)?;
+    Ok(usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+}
+
+// Arithmetic decoder that every bit of the stream is read through
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> Result<RangeDecoder<'a>, ArchiveError> {
+        let start: &[u8] = data.get(0..5).ok_or(ArchiveError::TruncatedData)?;
+        let code: u32 = u32::from_be_bytes([start[1], start[2], start[3], start[4]]);
+        if start[0] != 0 || code == 0xFFFF_FFFF {
+            return Err(ArchiveError::InvalidData);
+        }
+        Ok(RangeDecoder {
+            data,
+            position: 5,
+            range: 0xFFFF_FFFF,
+            code,
+        })
+    }
+
+    fn normalize(&mut self) -> Result<(), ArchiveError> {
+        if self.range < TOP_VALUE {
+            let byte: u8 = *self
+                .data
+                .get(self.position)
+                .ok_or(ArchiveError::TruncatedData)?;
+            self.position += 1;
+            self.range <<= 8;
+            self.code = (self.code << 8) | u32::from(byte);
+        }
+        Ok(())
+    }
+
+    // Bits with an even chance of being either value, used for the middle of long distances
+    fn decode_direct_bits(&mut self, count: usize) -> Result<u32, ArchiveError> {
+        let mut result: u32 = 0;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit: u32 = u32::from(self.code >= self.range);
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            self.normalize()?;
+            result = (result << 1) | bit;
+        }
+        Ok(result)
+    }
+
+    // A bit whose probability of being zero adapts to the values decoded so far
+    fn decode_bit(&mut self, probability: &mut u16) -> Result<u32, ArchiveError> {
+        let bound: u32 = (self.range >> PROBABILITY_BITS) * u32::from(*probability);
+        let bit: u32 = if self.code < bound {
+            *probability += ((1 << PROBABILITY_BITS) - *probability) >> MOVE_BITS;
+            self.range = bound;
+            0
+        } else {
+            *probability -= *probability >> MOVE_BITS;
+            self.code -= bound;
+            self.range -= bound;
+            1
+        };
+        self.normalize()?;
+        Ok(bit)
+    }
+
+    // A value read most significant bit first, where each bit has its own probability
+    fn decode_tree(&mut self, probabilities: &mut [u16], bits: usize) -> Result<u32, ArchiveError> {
+        let mut index: usize = 1;
+        for _ in 0..bits {
+            index = (index << 1) | self.decode_bit(&mut probabilities[index])? as usize;
+        }
+        Ok((index - (1 << bits)) as u32)
+    }
+
+    // A value read least significant bit first, where each bit has its own probability
+    fn decode_reverse_tree(
+        &mut self,
+        probabilities: &mut [u16],
+        bits: usize,
+    ) -> Result<u32, ArchiveError> {
+        let mut index: usize = 1;
+        let mut result: u32 = 0;
+        for bit_index in 0..bits {
+            let bit: u32 = self.decode_bit(&mut probabilities[index])?;
+            index = (index << 1) | bit as usize;
+            result |= bit << bit_index;
+        }
+        Ok(result)
+    }
+}
+
+// Match lengths are coded in three ranges, the first two of which depend on the position
+struct LengthDecoder {
+    choice: u16,
+    choice_2: u16,
+    low: Vec<u16>,
+    middle: Vec<u16>,
+    high: Vec<u16>,
+}
+
+impl LengthDecoder {
+    const LOW_BITS: usize = 3;
+    const MIDDLE_BITS: usize = 3;
+    const HIGH_BITS: usize = 8;
+
+    fn new() -> LengthDecoder {
+        LengthDecoder {
+            choice: PROBABILITY_INITIAL,
+            choice_2: PROBABILITY_INITIAL,
+            low: vec![PROBABILITY_INITIAL; POSITION_STATES_MAX << LengthDecoder::LOW_BITS],
+            middle: vec![PROBABILITY_INITIAL; POSITION_STATES_MAX << LengthDecoder::MIDDLE_BITS],
+            high: vec![PROBABILITY_INITIAL; 1 << LengthDecoder::HIGH_BITS],
+        }
+    }
+
+    fn decode(
+        &mut self,
+        range_decoder: &mut RangeDecoder,
+        position_state: usize,
+    ) -> Result<usize, ArchiveError> {
+        if range_decoder.decode_bit(&mut self.choice)? == 0 {
+            let start: usize = position_state << LengthDecoder::LOW_BITS;
+            return Ok(
+                range_decoder.decode_tree(&mut self.low[start..], LengthDecoder::LOW_BITS)?
+                    as usize,
+            );
+        }
+        if range_decoder.decode_bit(&mut self.choice_2)? == 0 {
+            let start: usize = position_state << LengthDecoder::MIDDLE_BITS;
+            return Ok(8 + range_decoder
+                .decode_tree(&mut self.middle[start..], LengthDecoder::MIDDLE_BITS)?
+                as usize);
+        }
+        Ok(16 + range_decoder.decode_tree(&mut self.high, LengthDecoder::HIGH_BITS)? as usize)
+    }
+}
+
+struct LzmaDecoder {
+    properties: u8,
+    literal_context_bits: u8,
+    literal_position_bits: u8,
+    position_bits: u8,
+    literals: Vec<u16>,
+    position_slots: Vec<u16>,
+    position_decoders: Vec<u16>,
+    align: Vec<u16>,
+    is_match: Vec<u16>,
+    is_rep: Vec<u16>,
+    is_rep_g0: Vec<u16>,
+    is_rep_g1: Vec<u16>,
+    is_rep_g2: Vec<u16>,
+    is_rep0_long: Vec<u16>,
+    length_decoder: LengthDecoder,
+    rep_length_decoder: LengthDecoder,
+    // Which kinds of packet came last, from 0 to 11, with 7 and above following a match
+    state: usize,
+    // The four most recently used distances
+    reps: [u32; 4],
+}
+
+impl LzmaDecoder {
+    // The properties byte packs the literal context bits, literal position bits and position bits
+    fn new(properties: u8) -> Result<LzmaDecoder, ArchiveError> {
+        if properties >= 9 * 5 * 5 {
+            return Err(ArchiveError::InvalidData);
+        }
+        let literal_context_bits: u8 = properties % 9;
+        let literal_position_bits: u8 = (properties / 9) % 5;
+        let position_bits: u8 = properties / 45;
+        let literal_states: usize = 1 << (literal_context_bits + literal_position_bits);
+        Ok(LzmaDecoder {
+            properties,
+            literal_context_bits,
+            literal_position_bits,
+            position_bits,
+            literals: vec![PROBABILITY_INITIAL; LITERAL_SIZE * literal_states],
+            position_slots: vec![
+                PROBABILITY_INITIAL;
+                LENGTH_TO_POSITION_STATES << POSITION_SLOT_BITS
+            ],
+            position_decoders: vec![
+                PROBABILITY_INITIAL;
+                1 + FULL_DISTANCES - END_POSITION_MODEL_INDEX as usize
+            ],
+            align: vec![PROBABILITY_INITIAL; 1 << ALIGN_BITS],
+            is_match: vec![PROBABILITY_INITIAL; STATE_COUNT * POSITION_STATES_MAX],
+            is_rep: vec![PROBABILITY_INITIAL; STATE_COUNT],
+            is_rep_g0: vec![PROBABILITY_INITIAL; STATE_COUNT],
+            is_rep_g1: vec![PROBABILITY_INITIAL; STATE_COUNT],
+            is_rep_g2: vec![PROBABILITY_INITIAL; STATE_COUNT],
+            is_rep0_long: vec![PROBABILITY_INITIAL; STATE_COUNT * POSITION_STATES_MAX],
+            length_decoder: LengthDecoder::new(),
+            rep_length_decoder: LengthDecoder::new(),
+            state: 0,
+            reps: [0; 4],
+        })
+    }
+
+    // Return to the initial state while keeping the properties, as LZMA2 chunks can ask for
+    fn reset(&mut self) -> Result<(), ArchiveError> {
+        *self = LzmaDecoder::new(self.properties)?;
+        Ok(())
+    }
+
+    // Decode into the output until it reaches the end or the end marker is found, where matches
+    // can only reach back as far as the start of the dictionary
+    fn decode(
+        &mut self,
+        range_decoder: &mut RangeDecoder,
+        output: &mut Vec<u8>,
+        dictionary_start: usize,
+        end: usize,
+    ) -> Result<(), ArchiveError> {
+        let position_mask: usize = (1 << self.position_bits) - 1;
+        while output.len() < end {
+            let dictionary_length: usize = output.len() - dictionary_start;
+            let position_state: usize = dictionary_length & position_mask;
+            let match_index: usize = (self.state << 4) + position_state;
+
+            if range_decoder.decode_bit(&mut self.is_match[match_index])? == 0 {
+                self.decode_literal(range_decoder, output, dictionary_start)?;
+                self.state = match self.state {
+                    0..=3 => 0,
+                    4..=9 => self.state - 3,
+                    _ => self.state - 6,
+                };
+                continue;
+            }
+
+            let length: usize = if range_decoder.decode_bit(&mut self.is_rep[self.state])? == 1 {
+                if dictionary_length == 0 {
+                    return Err(ArchiveError::InvalidData);
+                }
+                if range_decoder.decode_bit(&mut self.is_rep_g0[self.state])? == 0 {
+                    // A single byte from the last distance
+                    if range_decoder.decode_bit(&mut self.is_rep0_long[match_index])? == 0 {
+                        self.state = if self.state < 7 { 9 } else { 11 };
+                        let byte: u8 = output[output.len() - self.reps[0] as usize - 1];
+                        output.push(byte);
+                        continue;
+                    }
+                } else {
+                    // One of the older distances, which moves to the front
+                    let distance: u32 =
+                        if range_decoder.decode_bit(&mut self.is_rep_g1[self.state])? == 0 {
+                            self.reps[1]
+                        } else if range_decoder.decode_bit(&mut self.is_rep_g2[self.state])? == 0 {
+                            let distance: u32 = self.reps[2];
+                            self.reps[2] = self.reps[1];
+                            distance
+                        } else {
+                            let distance: u32 = self.reps[3];
+                            self.reps[3] = self.reps[2];
+                            self.reps[2] = self.reps[1];
+                            distance
+                        };
+                    self.reps[1] = self.reps[0];
+                    self.reps[0] = distance;
+                }
+                self.state = if self.state < 7 { 8 } else { 11 };
+                self.rep_length_decoder
+                    .decode(range_decoder, position_state)?
+            } else {
+                // A match with a new distance
+                self.reps[3] = self.reps[2];
+                self.reps[2] = self.reps[1];
+                self.reps[1] = self.reps[0];
+                let length: usize = self.length_decoder.decode(range_decoder, position_state)?;
+                self.state = if self.state < 7 { 7 } else { 10 };
+                self.reps[0] = self.decode_distance(range_decoder, length)?;
+                if self.reps[0] == END_MARKER_DISTANCE {
+                    return Ok(());
+                }
+                if self.reps[0] as usize >= dictionary_length {
+                    return Err(ArchiveError::InvalidData);
+                }
+                length
+            };
+
+            // The copy is cut short if it runs past the end, and can overlap with itself when the
+            // distance is shorter than the length
+            let length: usize = (length + MATCH_MIN_LENGTH).min(end - output.len());
+            let source: usize = output.len() - self.reps[0] as usize - 1;
+            for index in 0..length {
+                output.push(output[source + index]);
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_literal(
+        &mut self,
+        range_decoder: &mut RangeDecoder,
+        output: &mut Vec<u8>,
+        dictionary_start: usize,
+    ) -> Result<(), ArchiveError> {
+        let dictionary_length: usize = output.len() - dictionary_start;
+        let previous_byte: u8 = match dictionary_length {
+            0 => 0,
+            _ => output[output.len() - 1],
+        };
+        let literal_state: usize = ((dictionary_length & ((1 << self.literal_position_bits) - 1))
+            << self.literal_context_bits)
+            + (usize::from(previous_byte) >> (8 - self.literal_context_bits));
+        let probabilities: &mut [u16] = &mut self.literals[LITERAL_SIZE * literal_state..];
+
+        let mut symbol: usize = 1;
+        if self.state >= 7 {
+            // After a match the byte at the last distance predicts the literal, until a bit differs
+            let mut match_byte: usize =
+                usize::from(output[output.len() - self.reps[0] as usize - 1]);
+            while symbol < 0x100 {
+                let match_bit: usize = (match_byte >> 7) & 1;
+                match_byte <<= 1;
+                let bit: usize = range_decoder
+                    .decode_bit(&mut probabilities[((1 + match_bit) << 8) + symbol])?
+                    as usize;
+                symbol = (symbol << 1) | bit;
+                if match_bit != bit {
+                    break;
+                }
+            }
+        }
+        while symbol < 0x100 {
+            symbol = (symbol << 1) | range_decoder.decode_bit(&mut probabilities[symbol])? as usize;
+        }
+        output.push((symbol - 0x100) as u8);
+        Ok(())
+    }
+
+    fn decode_distance(
+        &mut self,
+        range_decoder: &mut RangeDecoder,
+        length: usize,
+    ) -> Result<u32, ArchiveError> {
+        let length_state: usize = length.min(LENGTH_TO_POSITION_STATES - 1);
+        let slot: u32 = range_decoder.decode_tree(
+            &mut self.position_slots[length_state << POSITION_SLOT_BITS..],
+            POSITION_SLOT_BITS,
+        )?;
+        if slot < 4 {
+            return Ok(slot);
+        }
+
+        let direct_bits: usize = ((slot >> 1) - 1) as usize;
+        let distance: u32 = (2 | (slot & 1)) << direct_bits;
+        if slot < END_POSITION_MODEL_INDEX {
+            let start: usize = (distance - slot) as usize;
+            return Ok(distance
+                + range_decoder
+                    .decode_reverse_tree(&mut self.position_decoders[start..], direct_bits)?);
+        }
+        let middle: u32 = range_decoder.decode_direct_bits(direct_bits - ALIGN_BITS)?;
+        let low: u32 = range_decoder.decode_reverse_tree(&mut self.align, ALIGN_BITS)?;
+        Ok(distance
+            .wrapping_add(middle << ALIGN_BITS)
+            .wrapping_add(low))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub const TEST_LZMA_PROPERTIES: [u8; 5] = [0x5D, 0x00, 0x00, 0x00, 0x04];
+    pub const TEST_LZMA2_PROPERTIES: [u8; 1] = [16];
+
+    // get_test_text compressed as LZMA with the properties in TEST_LZMA_PROPERTIES and an end marker
+    pub const TEST_LZMA: [u8; 118] = [
+        0x00, 0x31, 0x1B, 0xCB, 0x56, 0x02, 0x5B, 0x29, 0x7A, 0xB6, 0x70, 0xAD, 0x25, 0x73, 0x3F,
+        0x10, 0xB8, 0xFE, 0x77, 0xB6, 0xF8, 0x66, 0x25, 0x17, 0x2E, 0x00, 0x9F, 0xD0, 0xF0, 0x15,
+        0xC2, 0x79, 0x46, 0x19, 0x82, 0xB8, 0xF0, 0x42, 0xDB, 0xB6, 0x2C, 0x6E, 0xEA, 0x58, 0x8A,
+        0x89, 0x36, 0x35, 0x60, 0x24, 0xF6, 0xB1, 0x3D, 0x8B, 0xA4, 0x1A, 0xB8, 0x09, 0x5A, 0x8B,
+        0x7B, 0x69, 0xCD, 0xF8, 0xD0, 0x0A, 0xC7, 0x5D, 0x65, 0xA6, 0x3F, 0x44, 0x03, 0x9D, 0x2C,
+        0x1F, 0x42, 0xB2, 0x78, 0xDF, 0xF1, 0xE4, 0x14, 0xD9, 0x7C, 0x64, 0xED, 0x26, 0x9F, 0x46,
+        0x3A, 0x12, 0x76, 0xA8, 0x50, 0x4F, 0xFB, 0xD6, 0x8F, 0x06, 0x2B, 0xA7, 0x3A, 0xAA, 0xD9,
+        0x1A, 0x41, 0x13, 0xE5, 0x8B, 0xFF, 0xA7, 0x5D, 0xFF, 0xF6, 0x59, 0xBD, 0xC0,
+    ];
+    // get_test_text compressed as LZMA2 with a 64KB dictionary
+    pub const TEST_LZMA2: [u8; 120] = [
+        0xE0, 0x01, 0x8F, 0x00, 0x70, 0x5D, 0x00, 0x31, 0x1B, 0xCB, 0x56, 0x02, 0x5B, 0x29, 0x7A,
+        0xB6, 0x70, 0xAD, 0x25, 0x73, 0x3F, 0x10, 0xB8, 0xFE, 0x77, 0xB6, 0xF8, 0x66, 0x25, 0x17,
+        0x2E, 0x00, 0x9F, 0xD0, 0xF0, 0x15, 0xC2, 0x79, 0x46, 0x19, 0x82, 0xB8, 0xF0, 0x42, 0xDB,
+        0xB6, 0x2C, 0x6E, 0xEA, 0x58, 0x8A, 0x89, 0x36, 0x35, 0x60, 0x24, 0xF6, 0xB1, 0x3D, 0x8B,
+        0xA4, 0x1A, 0xB8, 0x09, 0x5A, 0x8B, 0x7B, 0x69, 0xCD, 0xF8, 0xD0, 0x0A, 0xC7, 0x5D, 0x65,
+        0xA6, 0x3F, 0x44, 0x03, 0x9D, 0x2C, 0x1F, 0x42, 0xB2, 0x78, 0xDF, 0xF1, 0xE4, 0x14, 0xD9,
+        0x7C, 0x64, 0xED, 0x26, 0x9F, 0x46, 0x3A, 0x12, 0x76, 0xA8, 0x50, 0x4F, 0xFB, 0xD6, 0x8F,
+        0x06, 0x2B, 0xA7, 0x3A, 0xAA, 0xD9, 0x1A, 0x41, 0x13, 0xE5, 0x82, 0xAF, 0x18, 0x1F, 0x00,
+    ];
+
+    // 400 bytes of repeated names, with matches both near and far
+    pub fn get_test_text() -> Vec<u8> {
+        let names: [&str; 7] = [
+            "mario ", "luigi ", "peach ", "bowser ", "toad ", "koopa ", "goomba ",
+        ];
+        let mut text: Vec<u8> = Vec::new();
+        let mut seed: u32 = 1;
+        while text.len() < 400 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345) & 0x7FFF_FFFF;
+            text.extend_from_slice(names[(seed >> 16) as usize % names.len()].as_bytes());
+        }
+        text.truncate(400);
+        text
+    }
+
+    #[test]
+    fn test_decompress_lzma() {
+        // Prep for the test
+        let text: Vec<u8> = get_test_text();
+
+        // Assert results, where the end marker stops decoding early when the size is too large
+        assert_eq!(
+            decompress_lzma(&TEST_LZMA, &TEST_LZMA_PROPERTIES, 400),
+            Ok(text.clone())
+        );
+        assert_eq!(
+            decompress_lzma(&TEST_LZMA, &TEST_LZMA_PROPERTIES, 1000),
+            Ok(text.clone())
+        );
+        assert_eq!(
+            decompress_lzma(&TEST_LZMA, &TEST_LZMA_PROPERTIES, 100),
+            Ok(text[..100].to_vec())
+        );
+    }
+
+    #[test]
+    fn test_decompress_lzma2() {
+        // Prep for the test, a dictionary reset uncompressed chunk then a plain one
+        let uncompressed: [u8; 11] = [
+            0x01, 0x00, 0x02, b'a', b'b', b'c', 0x02, 0x00, 0x00, b'd', 0x00,
+        ];
+
+        // Assert results
+        assert_eq!(
+            decompress_lzma2(&TEST_LZMA2, &TEST_LZMA2_PROPERTIES),
+            Ok(get_test_text())
+        );
+        assert_eq!(
+            decompress_lzma2(&uncompressed, &TEST_LZMA2_PROPERTIES),
+            Ok(b"abcd".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        // Assert results
+        assert_eq!(
+            decompress_lzma(&TEST_LZMA, &[225, 0x00, 0x00, 0x00, 0x04], 400),
+            Err(ArchiveError::InvalidData)
+        );
+        assert_eq!(
+            decompress_lzma(&TEST_LZMA[..50], &TEST_LZMA_PROPERTIES, 400),
+            Err(ArchiveError::TruncatedData)
+        );
+        assert_eq!(
+            decompress_lzma2(&TEST_LZMA2, &[41]),
+            Err(ArchiveError::InvalidData)
+        );
+        assert_eq!(
+            decompress_lzma2(&[0x03], &TEST_LZMA2_PROPERTIES),
+            Err(ArchiveError::InvalidData)
+        );
+        // An LZMA chunk has to set properties before it can be decoded
+        assert_eq!(
+            decompress_lzma2(&[0x80, 0x00, 0x00, 0x00, 0x05], &TEST_LZMA2_PROPERTIES),
+            Err(ArchiveError::InvalidData)
+        );
+        assert_eq!(
+            decompress_lzma2(&TEST_LZMA2[..TEST_LZMA2.len() - 1], &TEST_LZMA2_PROPERTIES),
+            Err(ArchiveError::TruncatedData)
+        );
+    }
+}
@@ -0,0 +1,217 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Archive formats that ROMs are commonly distributed in
+// A ROM found inside an archive is extracted before patches are applied or its header is read
+
+pub mod gzip;
+pub mod inflate;
+pub mod lzma;
+pub mod seven_zip;
+pub mod zip;
+
+use std::fmt;
+use std::path::Path;
+
+// The extensions of files that can be loaded from an archive
+const ROM_EXTENSIONS: [&str; 7] = ["nes", "fds", "qd", "nsf", "nsfe", "unf", "unif"];
+
+// Errors that can occur while reading an archive
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    // The archive does not start with a recognised header, or its directory is not valid
+    InvalidHeader,
+    // The archive ends part way through an entry or header
+    TruncatedData,
+    // Compressed data could not be decoded
+    InvalidData,
+    // A checksum of a header or an extracted entry does not match
+    ChecksumMismatch,
+    // The archive uses a compression method, filter or feature that is not supported
+    UnsupportedMethod(String),
+    // The archive has no entry with the requested name
+    EntryNotFound(String),
+    // None of the archive's entries, which are listed, is a ROM
+    NoRomEntry(Vec<String>),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::InvalidHeader => write!(f, "Archive header is not valid"),
+            ArchiveError::TruncatedData => write!(f, "Archive data ends part way through"),
+            ArchiveError::InvalidData => write!(f, "Archive data could not be decompressed"),
+            ArchiveError::ChecksumMismatch => write!(f, "Archive checksum does not match"),
+            ArchiveError::UnsupportedMethod(method) => {
+                write!(f, "Archive uses unsupported {}", method)
+            }
+            ArchiveError::EntryNotFound(name) => write!(f, "Archive has no entry named {}", name),
+            ArchiveError::NoRomEntry(names) => write!(
+                f,
+                "Archive has no NES, FDS or NSF file, choose one of [{}] with --entry",
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+pub fn is_archive(content: &[u8]) -> bool {
+    content.starts_with(zip::MAGIC)
+        || content.starts_with(gzip::MAGIC)
+        || content.starts_with(seven_zip::MAGIC)
+}
+
+// The names of the files in an archive of any supported format, where a gzip file has the one
+// name it was compressed from, which is empty if it was not stored
+pub fn get_entry_names(content: &[u8]) -> Result<Vec<String>, ArchiveError> {
+    if content.starts_with(zip::MAGIC) {
+        zip::get_entry_names(content)
+    } else if content.starts_with(gzip::MAGIC) {
+        Ok(vec![gzip::extract(content)?.0])
+    } else if content.starts_with(seven_zip::MAGIC) {
+        seven_zip::get_entry_names(content)
+    } else {
+        Err(ArchiveError::InvalidHeader)
+    }
+}
+
+pub fn extract(content: &[u8], name: &str) -> Result<Vec<u8>, ArchiveError> {
+    if content.starts_with(zip::MAGIC) {
+        zip::extract(content, name)
+    } else if content.starts_with(gzip::MAGIC) {
+        match gzip::extract(content)? {
+            (entry_name, data) if entry_name == name => Ok(data),
+            _ => Err(ArchiveError::EntryNotFound(name.to_string())),
+        }
+    } else if content.starts_with(seven_zip::MAGIC) {
+        seven_zip::extract(content, name)
+    } else {
+        Err(ArchiveError::InvalidHeader)
+    }
+}
+
+// Extract the named entry, or the first ROM when no name is given, along with the entry's name
+pub fn extract_rom(
+    content: &[u8],
+    entry_name: Option<&str>,
+) -> Result<(String, Vec<u8>), ArchiveError> {
+    let name: String = choose_entry(get_entry_names(content)?, entry_name)?;
+    let data: Vec<u8> = extract(content, &name)?;
+    Ok((name, data))
+}
+
+// Entries are matched to a ROM by their extension, apart from an unnamed gzip entry whose type can
+// only be told from its contents
+fn choose_entry(names: Vec<String>, entry_name: Option<&str>) -> Result<String, ArchiveError> {
+    if let Some(entry_name) = entry_name {
+        if !names.iter().any(|name| name == entry_name) {
+            return Err(ArchiveError::EntryNotFound(entry_name.to_string()));
+        }
+        return Ok(entry_name.to_string());
+    }
+    let is_rom = |name: &String| -> bool {
+        name.is_empty()
+            || Path::new(name)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                })
+    };
+    match names.iter().position(is_rom) {
+        Some(index) => Ok(names[index].clone()),
+        None => Err(ArchiveError::NoRomEntry(names)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_archive() {
+        // Assert results
+        assert!(is_archive(&zip::tests::TEST_ZIP));
+        assert!(is_archive(&gzip::tests::TEST_GZIP));
+        assert!(is_archive(&seven_zip::tests::TEST_7Z));
+        assert!(!is_archive(b"NES\x1A"));
+    }
+
+    #[test]
+    fn test_extract_rom() {
+        // Prep for the test
+        let mut expected: Vec<u8> = b"NES\x1A".to_vec();
+        expected.resize(16, 0);
+
+        // Assert results, where the readme before the ROM is passed over unless it is chosen
+        assert_eq!(
+            extract_rom(&zip::tests::TEST_ZIP, None),
+            Ok(("game.nes".to_string(), expected.clone()))
+        );
+        assert_eq!(
+            extract_rom(&zip::tests::TEST_ZIP, Some("readme.txt")),
+            Ok(("readme.txt".to_string(), b"hi".to_vec()))
+        );
+        assert_eq!(
+            extract_rom(&gzip::tests::TEST_GZIP, None),
+            Ok(("game.nes".to_string(), expected.clone()))
+        );
+        assert_eq!(
+            extract_rom(&seven_zip::tests::TEST_7Z, None),
+            Ok(("game.nes".to_string(), expected))
+        );
+        assert_eq!(
+            extract_rom(&gzip::tests::TEST_GZIP, Some("other.nes")),
+            Err(ArchiveError::EntryNotFound("other.nes".to_string()))
+        );
+        assert_eq!(
+            extract_rom(b"NES\x1A", None),
+            Err(ArchiveError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn test_choose_entry() {
+        // Prep for the test
+        let names: Vec<String> = ["readme.txt", "docs/manual.pdf"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+
+        // Assert results
+        assert_eq!(
+            choose_entry(vec!["Game (USA).NES".to_string()], None),
+            Ok("Game (USA).NES".to_string())
+        );
+        assert_eq!(
+            choose_entry(vec!["disk.qd".to_string(), "music.nsfe".to_string()], None),
+            Ok("disk.qd".to_string())
+        );
+        assert_eq!(choose_entry(vec![String::new()], None), Ok(String::new()));
+        assert_eq!(
+            choose_entry(names.clone(), None),
+            Err(ArchiveError::NoRomEntry(names))
+        );
+    }
+}
@@ -0,0 +1,763 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// 7z archives, read through the header at the end of the file
+// https://py7zr.readthedocs.io/en/latest/archive_format.html
+//
+// Folders with a single Copy, LZMA or LZMA2 coder are supported, which covers archives made with
+// the default settings. Filters such as the x86 branch converter and encrypted archives are
+// reported as unsupported.
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::archive::lzma;
+use crate::models::cartridge::archive::ArchiveError;
+
+pub const MAGIC: &[u8; 6] = b"7z\xBC\xAF\x27\x1C";
+
+const SIGNATURE_HEADER_SIZE: usize = 32;
+
+// Property IDs that mark each part of the header
+const PROPERTY_END: u64 = 0x00;
+const PROPERTY_HEADER: u64 = 0x01;
+const PROPERTY_ARCHIVE_PROPERTIES: u64 = 0x02;
+const PROPERTY_ADDITIONAL_STREAMS_INFO: u64 = 0x03;
+const PROPERTY_MAIN_STREAMS_INFO: u64 = 0x04;
+const PROPERTY_FILES_INFO: u64 = 0x05;
+const PROPERTY_PACK_INFO: u64 = 0x06;
+const PROPERTY_UNPACK_INFO: u64 = 0x07;
+const PROPERTY_SUBSTREAMS_INFO: u64 = 0x08;
+const PROPERTY_SIZE: u64 = 0x09;
+const PROPERTY_CRC: u64 = 0x0A;
+const PROPERTY_FOLDER: u64 = 0x0B;
+const PROPERTY_CODERS_UNPACK_SIZE: u64 = 0x0C;
+const PROPERTY_UNPACK_STREAM_COUNT: u64 = 0x0D;
+const PROPERTY_EMPTY_STREAM: u64 = 0x0E;
+const PROPERTY_EMPTY_FILE: u64 = 0x0F;
+const PROPERTY_NAME: u64 = 0x11;
+const PROPERTY_ENCODED_HEADER: u64 = 0x17;
+
+// Coder flags
+const CODER_ID_SIZE_MASK: u8 = 0b0000_1111;
+const CODER_COMPLEX: u8 = 0b0001_0000;
+const CODER_HAS_PROPERTIES: u8 = 0b0010_0000;
+
+// Coder IDs
+const CODER_COPY: &[u8] = &[0x00];
+const CODER_LZMA: &[u8] = &[0x03, 0x01, 0x01];
+const CODER_LZMA2: &[u8] = &[0x21];
+
+struct Coder {
+    id: Vec<u8>,
+    properties: Vec<u8>,
+    input_count: usize,
+    output_count: usize,
+}
+
+// A set of coders whose output is one or more files stored back to back
+struct Folder {
+    coders: Vec<Coder>,
+    // Coder outputs that feed another coder rather than the folder's output
+    bound_outputs: Vec<usize>,
+    packed_stream_count: usize,
+    unpack_sizes: Vec<usize>,
+    crc: Option<u32>,
+}
+
+impl Folder {
+    // The size of the one coder output that is not bound to another coder
+    fn get_unpack_size(&self) -> usize {
+        (0..self.unpack_sizes.len())
+            .find(|index| !self.bound_outputs.contains(index))
+            .map_or(0, |index| self.unpack_sizes[index])
+    }
+}
+
+// Where a file's contents sit within a folder's output
+struct SubStream {
+    folder: usize,
+    offset: usize,
+    size: usize,
+    crc: Option<u32>,
+}
+
+#[derive(Default)]
+struct StreamsInfo {
+    // Offset of the first packed stream from the end of the signature header
+    pack_position: usize,
+    pack_sizes: Vec<usize>,
+    folders: Vec<Folder>,
+    substreams: Vec<SubStream>,
+}
+
+struct Entry {
+    name: String,
+    // Index of the entry's substream, or none for empty files
+    substream: Option<usize>,
+}
+
+// The names of the files in the archive, in the order they are stored, leaving out directories
+pub fn get_entry_names(content: &[u8]) -> Result<Vec<String>, ArchiveError> {
+    Ok(read_header(content)?
+        .1
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect())
+}
+
+pub fn extract(content: &[u8], name: &str) -> Result<Vec<u8>, ArchiveError> {
+    let (streams, entries): (StreamsInfo, Vec<Entry>) = read_header(content)?;
+    let entry: Entry = entries
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| ArchiveError::EntryNotFound(name.to_string()))?;
+    let substream: &SubStream = match entry.substream {
+        Some(index) => &streams.substreams[index],
+        None => return Ok(Vec::new()),
+    };
+
+    let folder_data: Vec<u8> = decode_folder(content, &streams, substream.folder)?;
+    let data: Vec<u8> = folder_data
+        .get(substream.offset..substream.offset + substream.size)
+        .ok_or(ArchiveError::InvalidData)?
+        .to_vec();
+    if substream
+        .crc
+        .is_some_and(|crc| Utils::get_crc32(&data) != crc)
+    {
+        return Err(ArchiveError::ChecksumMismatch);
+    }
+    Ok(data)
+}
+
+fn read_header(content: &[u8]) -> Result<(StreamsInfo, Vec<Entry>), ArchiveError> {
+    // The signature header gives the location of the header at the end of the file
+    let signature: &[u8] = content
+        .get(..SIGNATURE_HEADER_SIZE)
+        .ok_or(ArchiveError::InvalidHeader)?;
+    if !signature.starts_with(MAGIC) {
+        return Err(ArchiveError::InvalidHeader);
+    }
+    let mut reader: HeaderReader = HeaderReader::new(&signature[8..]);
+    let start_header_crc: u32 = reader.read_u32()?;
+    if Utils::get_crc32(&signature[12..]) != start_header_crc {
+        return Err(ArchiveError::ChecksumMismatch);
+    }
+    let offset: usize = reader.read_u64()? as usize;
+    let size: usize = reader.read_u64()? as usize;
+    let crc: u32 = reader.read_u32()?;
+    let start: usize = SIGNATURE_HEADER_SIZE
+        .checked_add(offset)
+        .ok_or(ArchiveError::InvalidHeader)?;
+    let mut header: Vec<u8> = content
+        .get(start..start.saturating_add(size))
+        .ok_or(ArchiveError::TruncatedData)?
+        .to_vec();
+    if Utils::get_crc32(&header) != crc {
+        return Err(ArchiveError::ChecksumMismatch);
+    }
+
+    // The header is usually compressed itself, described by streams info of its own
+    loop {
+        let mut reader: HeaderReader = HeaderReader::new(&header);
+        if reader.read_number()? != PROPERTY_ENCODED_HEADER {
+            break;
+        }
+        let streams: StreamsInfo = reader.read_streams_info()?;
+        if streams.folders.is_empty() {
+            return Err(ArchiveError::InvalidHeader);
+        }
+        header = decode_folder(content, &streams, 0)?;
+    }
+    HeaderReader::new(&header).read_header()
+}
+
+// Decompress all of a folder's output, checking it against the folder's CRC
+fn decode_folder(
+    content: &[u8],
+    streams: &StreamsInfo,
+    folder_index: usize,
+) -> Result<Vec<u8>, ArchiveError> {
+    let folder: &Folder = &streams.folders[folder_index];
+    let coder: &Coder = match folder.coders.as_slice() {
+        [coder] if coder.input_count == 1 && coder.output_count == 1 => coder,
+        _ => {
+            return Err(ArchiveError::UnsupportedMethod(
+                "7z folder with several coders".to_string(),
+            ))
+        }
+    };
+
+    // Folders take their packed streams in order, each following the last
+    let pack_index: usize = streams.folders[..folder_index]
+        .iter()
+        .map(|folder| folder.packed_stream_count)
+        .sum();
+    let previous_sizes: &[usize] = streams
+        .pack_sizes
+        .get(..pack_index)
+        .ok_or(ArchiveError::InvalidHeader)?;
+    let start: usize = previous_sizes.iter().fold(
+        SIGNATURE_HEADER_SIZE + streams.pack_position,
+        |start, size| start.saturating_add(*size),
+    );
+    let size: usize = *streams
+        .pack_sizes
+        .get(pack_index)
+        .ok_or(ArchiveError::InvalidHeader)?;
+    let packed: &[u8] = content
+        .get(start..start.saturating_add(size))
+        .ok_or(ArchiveError::TruncatedData)?;
+
+    let unpack_size: usize = folder.get_unpack_size();
+    let data: Vec<u8> = match coder.id.as_slice() {
+        CODER_COPY => packed.to_vec(),
+        CODER_LZMA => lzma::decompress_lzma(packed, &coder.properties, unpack_size)?,
+        CODER_LZMA2 => lzma::decompress_lzma2(packed, &coder.properties)?,
+        id => {
+            let hex: Vec<String> = id.iter().map(|byte| format!("{:02X}", byte)).collect();
+            return Err(ArchiveError::UnsupportedMethod(format!(
+                "7z coder {}",
+                hex.concat()
+            )));
+        }
+    };
+    if data.len() != unpack_size {
+        return Err(ArchiveError::InvalidData);
+    }
+    if folder.crc.is_some_and(|crc| Utils::get_crc32(&data) != crc) {
+        return Err(ArchiveError::ChecksumMismatch);
+    }
+    Ok(data)
+}
+
+struct HeaderReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+    fn new(data: &'a [u8]) -> HeaderReader<'a> {
+        HeaderReader { data, position: 0 }
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], ArchiveError> {
+        let bytes: &[u8] = self
+            .data
+            .get(self.position..self.position.saturating_add(count))
+            .ok_or(ArchiveError::TruncatedData)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, ArchiveError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ArchiveError> {
+        let bytes: &[u8] = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, ArchiveError> {
+        let bytes: &[u8] = self.read_bytes(8)?;
+        let mut value: [u8; 8] = [0; 8];
+        value.copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    // Numbers take one to nine bytes, where the count of leading one bits in the first byte is the
+    // count of little endian bytes that follow, and the rest of its bits are the highest
+    fn read_number(&mut self) -> Result<u64, ArchiveError> {
+        let first: u8 = self.read_byte()?;
+        let mut value: u64 = 0;
+        for index in 0..8 {
+            let mask: u8 = 0x80 >> index;
+            if first & mask == 0 {
+                let high: u64 = u64::from(first & mask.wrapping_sub(1));
+                return Ok(value | (high << (8 * index)));
+            }
+            value |= u64::from(self.read_byte()?) << (8 * index);
+        }
+        Ok(value)
+    }
+
+    fn read_size(&mut self) -> Result<usize, ArchiveError> {
+        usize::try_from(self.read_number()?).map_err(|_| ArchiveError::InvalidHeader)
+    }
+
+    // A count of items that each take at least a byte of the header, so it cannot be more than the
+    // bytes that are left
+    fn read_count(&mut self) -> Result<usize, ArchiveError> {
+        let count: usize = self.read_size()?;
+        if count > self.data.len() - self.position {
+            return Err(ArchiveError::InvalidHeader);
+        }
+        Ok(count)
+    }
+
+    fn expect(&mut self, property: u64) -> Result<(), ArchiveError> {
+        if self.read_number()? != property {
+            return Err(ArchiveError::InvalidHeader);
+        }
+        Ok(())
+    }
+
+    // Bit vectors are stored most significant bit first
+    fn read_bits(&mut self, count: usize) -> Result<Vec<bool>, ArchiveError> {
+        let bytes: &[u8] = self.read_bytes(count.div_ceil(8))?;
+        Ok((0..count)
+            .map(|index| bytes[index / 8] & (0x80 >> (index % 8)) != 0)
+            .collect())
+    }
+
+    // CRCs for some or all of the given count of items
+    fn read_digests(&mut self, count: usize) -> Result<Vec<Option<u32>>, ArchiveError> {
+        let defined: Vec<bool> = match self.read_byte()? {
+            0 => self.read_bits(count)?,
+            _ => vec![true; count],
+        };
+        defined
+            .into_iter()
+            .map(|is_defined| {
+                if is_defined {
+                    self.read_u32().map(Some)
+                } else {
+                    Ok(None)
+                }
+            })
+            .collect()
+    }
+
+    fn skip_properties(&mut self) -> Result<(), ArchiveError> {
+        while self.read_number()? != PROPERTY_END {
+            let size: usize = self.read_size()?;
+            self.read_bytes(size)?;
+        }
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> Result<(StreamsInfo, Vec<Entry>), ArchiveError> {
+        self.expect(PROPERTY_HEADER)?;
+        let mut property: u64 = self.read_number()?;
+        if property == PROPERTY_ARCHIVE_PROPERTIES {
+            self.skip_properties()?;
+            property = self.read_number()?;
+        }
+        if property == PROPERTY_ADDITIONAL_STREAMS_INFO {
+            self.read_streams_info()?;
+            property = self.read_number()?;
+        }
+        let mut streams: StreamsInfo = StreamsInfo::default();
+        if property == PROPERTY_MAIN_STREAMS_INFO {
+            streams = self.read_streams_info()?;
+            property = self.read_number()?;
+        }
+        let mut entries: Vec<Entry> = Vec::new();
+        if property == PROPERTY_FILES_INFO {
+            entries = self.read_files_info(streams.substreams.len())?;
+            property = self.read_number()?;
+        }
+        match property {
+            PROPERTY_END => Ok((streams, entries)),
+            _ => Err(ArchiveError::InvalidHeader),
+        }
+    }
+
+    fn read_streams_info(&mut self) -> Result<StreamsInfo, ArchiveError> {
+        let mut streams: StreamsInfo = StreamsInfo::default();
+        let mut property: u64 = self.read_number()?;
+        if property == PROPERTY_PACK_INFO {
+            streams.pack_position = self.read_size()?;
+            let count: usize = self.read_count()?;
+            property = self.read_number()?;
+            if property == PROPERTY_SIZE {
+                streams.pack_sizes = (0..count)
+                    .map(|_| self.read_size())
+                    .collect::<Result<Vec<usize>, ArchiveError>>()?;
+                property = self.read_number()?;
+            }
+            if property == PROPERTY_CRC {
+                self.read_digests(count)?;
+                property = self.read_number()?;
+            }
+            if property != PROPERTY_END {
+                return Err(ArchiveError::InvalidHeader);
+            }
+            property = self.read_number()?;
+        }
+        if property == PROPERTY_UNPACK_INFO {
+            streams.folders = self.read_unpack_info()?;
+            property = self.read_number()?;
+        }
+
+        // Without substreams info each folder holds one file with the folder's CRC
+        let mut stream_counts: Vec<usize> = vec![1; streams.folders.len()];
+        let mut sizes: Vec<usize> = Vec::new();
+        let mut crcs: Vec<Option<u32>> = Vec::new();
+        if property == PROPERTY_SUBSTREAMS_INFO {
+            property = self.read_number()?;
+            if property == PROPERTY_UNPACK_STREAM_COUNT {
+                for count in stream_counts.iter_mut() {
+                    *count = self.read_count()?;
+                }
+                property = self.read_number()?;
+            }
+            if property == PROPERTY_SIZE {
+                for count in stream_counts.iter() {
+                    for _ in 1..*count {
+                        sizes.push(self.read_size()?);
+                    }
+                }
+                property = self.read_number()?;
+            }
+            if property == PROPERTY_CRC {
+                let count: usize = streams
+                    .folders
+                    .iter()
+                    .zip(stream_counts.iter())
+                    .filter(|(folder, count)| **count != 1 || folder.crc.is_none())
+                    .map(|(_, count)| *count)
+                    .sum();
+                crcs = self.read_digests(count)?;
+                property = self.read_number()?;
+            }
+            if property != PROPERTY_END {
+                return Err(ArchiveError::InvalidHeader);
+            }
+            property = self.read_number()?;
+        }
+        if property != PROPERTY_END {
+            return Err(ArchiveError::InvalidHeader);
+        }
+
+        // Lay the files out within their folders, where the last one takes what is left
+        let mut sizes = sizes.into_iter();
+        let mut crcs = crcs.into_iter();
+        for (folder_index, (folder, count)) in streams.folders.iter().zip(stream_counts).enumerate()
+        {
+            let mut offset: usize = 0;
+            for index in 0..count {
+                let size: usize = if index + 1 == count {
+                    folder
+                        .get_unpack_size()
+                        .checked_sub(offset)
+                        .ok_or(ArchiveError::InvalidHeader)?
+                } else {
+                    sizes.next().ok_or(ArchiveError::InvalidHeader)?
+                };
+                let crc: Option<u32> = match (count, folder.crc) {
+                    (1, Some(crc)) => Some(crc),
+                    _ => crcs.next().flatten(),
+                };
+                streams.substreams.push(SubStream {
+                    folder: folder_index,
+                    offset,
+                    size,
+                    crc,
+                });
+                offset = offset
+                    .checked_add(size)
+                    .ok_or(ArchiveError::InvalidHeader)?;
+            }
+        }
+        Ok(streams)
+    }
+
+    fn read_unpack_info(&mut self) -> Result<Vec<Folder>, ArchiveError> {
+        self.expect(PROPERTY_FOLDER)?;
+        let count: usize = self.read_count()?;
+        if self.read_byte()? != 0 {
+            return Err(ArchiveError::UnsupportedMethod(
+                "7z external folders".to_string(),
+            ));
+        }
+        let mut folders: Vec<Folder> = (0..count)
+            .map(|_| self.read_folder())
+            .collect::<Result<Vec<Folder>, ArchiveError>>()?;
+
+        self.expect(PROPERTY_CODERS_UNPACK_SIZE)?;
+        for folder in folders.iter_mut() {
+            let output_count: usize = folder.coders.iter().map(|coder| coder.output_count).sum();
+            folder.unpack_sizes = (0..output_count)
+                .map(|_| self.read_size())
+                .collect::<Result<Vec<usize>, ArchiveError>>()?;
+        }
+        let mut property: u64 = self.read_number()?;
+        if property == PROPERTY_CRC {
+            for (folder, crc) in folders.iter_mut().zip(self.read_digests(count)?) {
+                folder.crc = crc;
+            }
+            property = self.read_number()?;
+        }
+        match property {
+            PROPERTY_END => Ok(folders),
+            _ => Err(ArchiveError::InvalidHeader),
+        }
+    }
+
+    fn read_folder(&mut self) -> Result<Folder, ArchiveError> {
+        let coder_count: usize = self.read_count()?;
+        let mut coders: Vec<Coder> = Vec::new();
+        for _ in 0..coder_count {
+            let flags: u8 = self.read_byte()?;
+            let id: Vec<u8> = self
+                .read_bytes(usize::from(flags & CODER_ID_SIZE_MASK))?
+                .to_vec();
+            let (input_count, output_count): (usize, usize) =
+                if flags & CODER_COMPLEX == CODER_COMPLEX {
+                    (self.read_count()?, self.read_count()?)
+                } else {
+                    (1, 1)
+                };
+            let properties: Vec<u8> = if flags & CODER_HAS_PROPERTIES == CODER_HAS_PROPERTIES {
+                let size: usize = self.read_size()?;
+                self.read_bytes(size)?.to_vec()
+            } else {
+                Vec::new()
+            };
+            coders.push(Coder {
+                id,
+                properties,
+                input_count,
+                output_count,
+            });
+        }
+
+        // Bind pairs connect every output but the last to another coder's input, and any inputs
+        // left over read packed streams
+        let input_count: usize = coders.iter().map(|coder| coder.input_count).sum();
+        let output_count: usize = coders.iter().map(|coder| coder.output_count).sum();
+        let bind_pair_count: usize = output_count
+            .checked_sub(1)
+            .ok_or(ArchiveError::InvalidHeader)?;
+        let mut bound_outputs: Vec<usize> = Vec::new();
+        for _ in 0..bind_pair_count {
+            self.read_size()?;
+            bound_outputs.push(self.read_size()?);
+        }
+        let packed_stream_count: usize = input_count
+            .checked_sub(bind_pair_count)
+            .ok_or(ArchiveError::InvalidHeader)?;
+        if packed_stream_count > 1 {
+            for _ in 0..packed_stream_count {
+                self.read_size()?;
+            }
+        }
+        Ok(Folder {
+            coders,
+            bound_outputs,
+            packed_stream_count,
+            unpack_sizes: Vec::new(),
+            crc: None,
+        })
+    }
+
+    // File names and which files have no contents, which are matched to substreams in order
+    fn read_files_info(&mut self, substream_count: usize) -> Result<Vec<Entry>, ArchiveError> {
+        let count: usize = self.read_count()?;
+        let mut empty_streams: Vec<bool> = vec![false; count];
+        let mut empty_files: Vec<bool> = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        loop {
+            let property: u64 = self.read_number()?;
+            if property == PROPERTY_END {
+                break;
+            }
+            let size: usize = self.read_size()?;
+            let end: usize = self.position.saturating_add(size);
+            match property {
+                PROPERTY_EMPTY_STREAM => empty_streams = self.read_bits(count)?,
+                PROPERTY_EMPTY_FILE => {
+                    let empty_count: usize = empty_streams.iter().filter(|empty| **empty).count();
+                    empty_files = self.read_bits(empty_count)?;
+                }
+                PROPERTY_NAME => {
+                    if self.read_byte()? != 0 {
+                        return Err(ArchiveError::UnsupportedMethod(
+                            "7z external names".to_string(),
+                        ));
+                    }
+                    // Names are zero terminated UTF-16
+                    let units: Vec<u16> = self
+                        .read_bytes(end.saturating_sub(self.position))?
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                        .collect();
+                    names = units
+                        .split(|unit| *unit == 0)
+                        .take(count)
+                        .map(String::from_utf16_lossy)
+                        .collect();
+                }
+                _ => {}
+            }
+            self.position = end;
+        }
+        if names.len() != count {
+            return Err(ArchiveError::InvalidHeader);
+        }
+
+        // Empty streams are directories unless they are marked as empty files
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut substream: usize = 0;
+        let mut empty_index: usize = 0;
+        for (name, empty_stream) in names.into_iter().zip(empty_streams) {
+            if !empty_stream {
+                if substream >= substream_count {
+                    return Err(ArchiveError::InvalidHeader);
+                }
+                entries.push(Entry {
+                    name,
+                    substream: Some(substream),
+                });
+                substream += 1;
+                continue;
+            }
+            if empty_files.get(empty_index) == Some(&true) {
+                entries.push(Entry {
+                    name,
+                    substream: None,
+                });
+            }
+            empty_index += 1;
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // readme.txt and game.nes in one LZMA folder, with a directory and an empty file
+    pub const TEST_7Z: [u8; 178] = [
+        0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C, 0x00, 0x04, 0xD8, 0x4C, 0x59, 0x77, 0x14, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x96, 0x64,
+        0xB2, 0x14, 0x00, 0x34, 0x1A, 0x3D, 0x44, 0x96, 0xD4, 0x8C, 0xCC, 0xDB, 0xF0, 0xEB, 0x5A,
+        0x9F, 0xFF, 0xFF, 0xDB, 0x26, 0x00, 0x00, 0x01, 0x04, 0x06, 0x00, 0x01, 0x09, 0x14, 0x00,
+        0x07, 0x0B, 0x01, 0x00, 0x01, 0x23, 0x03, 0x01, 0x01, 0x05, 0x5D, 0x00, 0x00, 0x01, 0x00,
+        0x0C, 0x13, 0x00, 0x08, 0x0D, 0x02, 0x09, 0x03, 0x0A, 0x01, 0x7A, 0x7A, 0x6F, 0xED, 0x48,
+        0xA0, 0x09, 0xCE, 0x00, 0x00, 0x05, 0x04, 0x0E, 0x01, 0x60, 0x0F, 0x01, 0x40, 0x11, 0x47,
+        0x00, 0x72, 0x00, 0x65, 0x00, 0x61, 0x00, 0x64, 0x00, 0x6D, 0x00, 0x65, 0x00, 0x2E, 0x00,
+        0x74, 0x00, 0x78, 0x00, 0x74, 0x00, 0x00, 0x00, 0x64, 0x00, 0x6F, 0x00, 0x63, 0x00, 0x73,
+        0x00, 0x00, 0x00, 0x65, 0x00, 0x6D, 0x00, 0x70, 0x00, 0x74, 0x00, 0x79, 0x00, 0x2E, 0x00,
+        0x74, 0x00, 0x78, 0x00, 0x74, 0x00, 0x00, 0x00, 0x67, 0x00, 0x61, 0x00, 0x6D, 0x00, 0x65,
+        0x00, 0x2E, 0x00, 0x6E, 0x00, 0x65, 0x00, 0x73, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // game.fds in an LZMA2 folder, with the header compressed by LZMA
+    pub const TEST_7Z_ENCODED_HEADER: [u8; 189] = [
+        0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C, 0x00, 0x04, 0x62, 0x1C, 0xF7, 0x8F, 0x7D, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xE7,
+        0x07, 0xF4, 0x01, 0x00, 0x3F, 0x01, 0x2A, 0x4E, 0x49, 0x4E, 0x54, 0x45, 0x4E, 0x44, 0x4F,
+        0x2D, 0x48, 0x56, 0x43, 0x2A, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09,
+        0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18,
+        0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27,
+        0x28, 0x29, 0x2A, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F, 0x30, 0x00, 0x00, 0x00, 0x81, 0x33, 0x07,
+        0xAE, 0x0F, 0xD2, 0x26, 0x98, 0xFD, 0x40, 0xC0, 0x90, 0xD2, 0xFF, 0x74, 0xA1, 0xCD, 0x89,
+        0xD0, 0xA8, 0xFD, 0x1C, 0x14, 0xBD, 0x1F, 0x01, 0xF0, 0x13, 0x7C, 0x35, 0xD2, 0x86, 0x47,
+        0x51, 0x40, 0x2F, 0xCE, 0xDD, 0x72, 0xCA, 0xC7, 0xB2, 0x45, 0xA8, 0x02, 0xFB, 0xA4, 0xC2,
+        0x7F, 0xFF, 0xFF, 0xF3, 0xDB, 0x00, 0x00, 0x17, 0x06, 0x44, 0x01, 0x09, 0x39, 0x00, 0x07,
+        0x0B, 0x01, 0x00, 0x01, 0x23, 0x03, 0x01, 0x01, 0x05, 0x5D, 0x00, 0x00, 0x01, 0x00, 0x0C,
+        0x34, 0x0A, 0x01, 0xFE, 0x5B, 0xDA, 0x4B, 0x00, 0x00,
+    ];
+
+    // game.nes stored behind the x86 branch converter
+    pub const TEST_7Z_UNSUPPORTED: [u8; 101] = [
+        0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C, 0x00, 0x04, 0xC7, 0xE6, 0x00, 0x78, 0x10, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x35, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x57, 0x5D,
+        0x98, 0x0F, 0x4E, 0x45, 0x53, 0x1A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01, 0x04, 0x06, 0x00, 0x01, 0x09, 0x10, 0x00, 0x07, 0x0B, 0x01, 0x00,
+        0x01, 0x04, 0x03, 0x03, 0x01, 0x03, 0x0C, 0x10, 0x0A, 0x01, 0x48, 0xA0, 0x09, 0xCE, 0x00,
+        0x00, 0x05, 0x01, 0x11, 0x13, 0x00, 0x67, 0x00, 0x61, 0x00, 0x6D, 0x00, 0x65, 0x00, 0x2E,
+        0x00, 0x6E, 0x00, 0x65, 0x00, 0x73, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_entry_names() {
+        // Assert results, where the directory is left out
+        assert_eq!(
+            get_entry_names(&TEST_7Z),
+            Ok([
+                "readme.txt".to_string(),
+                "empty.txt".to_string(),
+                "game.nes".to_string()
+            ]
+            .to_vec())
+        );
+        assert_eq!(
+            get_entry_names(&TEST_7Z_ENCODED_HEADER),
+            Ok(["game.fds".to_string()].to_vec())
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        // Prep for the test
+        let mut expected_rom: Vec<u8> = b"NES\x1A".to_vec();
+        expected_rom.resize(16, 0);
+        let mut expected_disk: Vec<u8> = b"\x01*NINTENDO-HVC*".to_vec();
+        expected_disk.extend(0..49);
+
+        // Assert results
+        assert_eq!(extract(&TEST_7Z, "readme.txt"), Ok(b"hi\n".to_vec()));
+        assert_eq!(extract(&TEST_7Z, "empty.txt"), Ok(Vec::new()));
+        assert_eq!(extract(&TEST_7Z, "game.nes"), Ok(expected_rom));
+        assert_eq!(
+            extract(&TEST_7Z, "docs"),
+            Err(ArchiveError::EntryNotFound("docs".to_string()))
+        );
+        assert_eq!(
+            extract(&TEST_7Z_ENCODED_HEADER, "game.fds"),
+            Ok(expected_disk)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        // Prep for the test, change where the signature header says the header is
+        let mut corrupted: [u8; 178] = TEST_7Z;
+        corrupted[12] ^= 0x01;
+
+        // Assert results
+        assert_eq!(
+            extract(&TEST_7Z_UNSUPPORTED, "game.nes"),
+            Err(ArchiveError::UnsupportedMethod(
+                "7z coder 03030103".to_string()
+            ))
+        );
+        assert_eq!(
+            get_entry_names(&corrupted),
+            Err(ArchiveError::ChecksumMismatch)
+        );
+        assert_eq!(
+            get_entry_names(&TEST_7Z[..100]),
+            Err(ArchiveError::TruncatedData)
+        );
+        assert_eq!(
+            get_entry_names(&TEST_7Z[1..]),
+            Err(ArchiveError::InvalidHeader)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// zip files, read through the central directory at the end of the file
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+//
+// Only stored and deflated entries are supported, which covers the zip tools ROM sets are built
+// with. Zip64 and encrypted archives are reported as unsupported.
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::archive::inflate;
+use crate::models::cartridge::archive::ArchiveError;
+
+pub const MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+const END_OF_DIRECTORY_SIGNATURE: &[u8; 4] = b"PK\x05\x06";
+const DIRECTORY_ENTRY_SIGNATURE: &[u8; 4] = b"PK\x01\x02";
+const END_OF_DIRECTORY_SIZE: usize = 22;
+const DIRECTORY_ENTRY_SIZE: usize = 46;
+const LOCAL_HEADER_SIZE: usize = 30;
+
+// The end of directory record can be followed by a comment of up to 64KB
+const MAX_COMMENT_SIZE: usize = 0xFFFF;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 0b0000_0001;
+
+// Sizes this large mean the real size is in a Zip64 record
+const ZIP64_MARKER: u32 = 0xFFFF_FFFF;
+
+// An entry from the central directory
+struct Entry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    local_header_offset: usize,
+}
+
+// The names of the files in the archive, in the order they are stored
+pub fn get_entry_names(content: &[u8]) -> Result<Vec<String>, ArchiveError> {
+    Ok(read_directory(content)?
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect())
+}
+
+pub fn extract(content: &[u8], name: &str) -> Result<Vec<u8>, ArchiveError> {
+    let entry: Entry = read_directory(content)?
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| ArchiveError::EntryNotFound(name.to_string()))?;
+    if entry.flags & FLAG_ENCRYPTED == FLAG_ENCRYPTED {
+        return Err(ArchiveError::UnsupportedMethod(
+            "encrypted zip entry".to_string(),
+        ));
+    }
+
+    // The data follows the local header, whose name and extra field can differ from the directory
+    let header: &[u8] = content
+        .get(entry.local_header_offset..entry.local_header_offset + LOCAL_HEADER_SIZE)
+        .ok_or(ArchiveError::TruncatedData)?;
+    if !header.starts_with(MAGIC) {
+        return Err(ArchiveError::InvalidHeader);
+    }
+    let start: usize = entry.local_header_offset
+        + LOCAL_HEADER_SIZE
+        + usize::from(get_u16(header, 26))
+        + usize::from(get_u16(header, 28));
+    let data: &[u8] = content
+        .get(start..start + entry.compressed_size)
+        .ok_or(ArchiveError::TruncatedData)?;
+
+    let extracted: Vec<u8> = match entry.method {
+        METHOD_STORED => data.to_vec(),
+        METHOD_DEFLATED => inflate::inflate(data)?,
+        method => {
+            return Err(ArchiveError::UnsupportedMethod(format!(
+                "zip method {}",
+                method
+            )))
+        }
+    };
+    if Utils::get_crc32(&extracted) != entry.crc {
+        return Err(ArchiveError::ChecksumMismatch);
+    }
+    Ok(extracted)
+}
+
+fn read_directory(content: &[u8]) -> Result<Vec<Entry>, ArchiveError> {
+    // Search backwards for the end of directory record, past any comment
+    let last_start: usize = content
+        .len()
+        .checked_sub(END_OF_DIRECTORY_SIZE)
+        .ok_or(ArchiveError::InvalidHeader)?;
+    let first_start: usize = last_start.saturating_sub(MAX_COMMENT_SIZE);
+    let end_offset: usize = (first_start..=last_start)
+        .rev()
+        .find(|offset| content[*offset..].starts_with(END_OF_DIRECTORY_SIGNATURE))
+        .ok_or(ArchiveError::InvalidHeader)?;
+    let end: &[u8] = &content[end_offset..end_offset + END_OF_DIRECTORY_SIZE];
+    let entry_count: usize = usize::from(get_u16(end, 10));
+    let directory_offset: u32 = get_u32(end, 16);
+    if directory_offset == ZIP64_MARKER {
+        return Err(ArchiveError::UnsupportedMethod("zip64".to_string()));
+    }
+
+    let mut entries: Vec<Entry> = Vec::with_capacity(entry_count);
+    let mut position: usize = directory_offset as usize;
+    for _ in 0..entry_count {
+        let header: &[u8] = content
+            .get(position..position + DIRECTORY_ENTRY_SIZE)
+            .ok_or(ArchiveError::TruncatedData)?;
+        if !header.starts_with(DIRECTORY_ENTRY_SIGNATURE) {
+            return Err(ArchiveError::InvalidHeader);
+        }
+        let name_length: usize = usize::from(get_u16(header, 28));
+        let extra_length: usize = usize::from(get_u16(header, 30));
+        let comment_length: usize = usize::from(get_u16(header, 32));
+        let name: &[u8] = content
+            .get(position + DIRECTORY_ENTRY_SIZE..position + DIRECTORY_ENTRY_SIZE + name_length)
+            .ok_or(ArchiveError::TruncatedData)?;
+        let compressed_size: u32 = get_u32(header, 20);
+        let local_header_offset: u32 = get_u32(header, 42);
+        if compressed_size == ZIP64_MARKER || local_header_offset == ZIP64_MARKER {
+            return Err(ArchiveError::UnsupportedMethod("zip64".to_string()));
+        }
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).to_string(),
+            flags: get_u16(header, 8),
+            method: get_u16(header, 10),
+            crc: get_u32(header, 16),
+            compressed_size: compressed_size as usize,
+            local_header_offset: local_header_offset as usize,
+        });
+        position += DIRECTORY_ENTRY_SIZE + name_length + extra_length + comment_length;
+    }
+    Ok(entries)
+}
+
+fn get_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn get_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // readme.txt stored and game.nes deflated, with an archive comment
+    pub const TEST_ZIP: [u8; 223] = [
+        0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0xAC,
+        0x2A, 0x93, 0xD8, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00,
+        0x72, 0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x68, 0x69, 0x50, 0x4B, 0x03,
+        0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x48, 0xA0, 0x09, 0xCE,
+        0x08, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D,
+        0x65, 0x2E, 0x6E, 0x65, 0x73, 0xF3, 0x73, 0x0D, 0x96, 0x62, 0x40, 0x02, 0x00, 0x50, 0x4B,
+        0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0xAC,
+        0x2A, 0x93, 0xD8, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x72,
+        0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03,
+        0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x48, 0xA0, 0x09, 0xCE, 0x08,
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x2A, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D, 0x65, 0x2E,
+        0x6E, 0x65, 0x73, 0x50, 0x4B, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00,
+        0x6E, 0x00, 0x00, 0x00, 0x58, 0x00, 0x00, 0x00, 0x03, 0x00, 0x73, 0x65, 0x74,
+    ];
+
+    #[test]
+    fn test_entry_names() {
+        // Assert results
+        assert_eq!(
+            get_entry_names(&TEST_ZIP),
+            Ok(["readme.txt".to_string(), "game.nes".to_string()].to_vec())
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        // Prep for the test
+        let mut expected: Vec<u8> = b"NES\x1A".to_vec();
+        expected.resize(16, 0);
+
+        // Assert results
+        assert_eq!(extract(&TEST_ZIP, "readme.txt"), Ok(b"hi".to_vec()));
+        assert_eq!(extract(&TEST_ZIP, "game.nes"), Ok(expected));
+        assert_eq!(
+            extract(&TEST_ZIP, "other.nes"),
+            Err(ArchiveError::EntryNotFound("other.nes".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        // Prep for the test, corrupt the stored entry and change the deflated entry's method
+        let mut corrupted: [u8; 223] = TEST_ZIP;
+        corrupted[40] ^= 0xFF;
+        corrupted[0x9A] = 12;
+
+        // Assert results
+        assert_eq!(
+            extract(&corrupted, "readme.txt"),
+            Err(ArchiveError::ChecksumMismatch)
+        );
+        assert_eq!(
+            extract(&corrupted, "game.nes"),
+            Err(ArchiveError::UnsupportedMethod("zip method 12".to_string()))
+        );
+        assert_eq!(
+            get_entry_names(&TEST_ZIP[..100]),
+            Err(ArchiveError::InvalidHeader)
+        );
+    }
+}
@@ -0,0 +1,516 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Game database for correcting the headers of ROMs that were dumped with the wrong details
+// The format is that of the NES 2.0 XML database, of which only the parts needed here are read
+// https://www.nesdev.org/wiki/NES_2.0_XML_Database
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::ines::INesHeader;
+use crate::models::cartridge::Mirroring;
+use crate::models::region::Region;
+use std::fmt;
+
+// The database that is built into the emulator
+const EMBEDDED_DATABASE: &str = include_str!("nes20db.xml");
+
+// Errors that can occur while reading a database
+#[derive(Debug, PartialEq, Eq)]
+pub enum DatabaseError {
+    // The text could not be read as XML
+    InvalidXml,
+    // An attribute that is needed has a value that could not be read
+    InvalidAttribute(String),
+    // A game does not have the hash that it is found by
+    MissingHash,
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::InvalidXml => write!(f, "Database is not valid XML"),
+            DatabaseError::InvalidAttribute(name) => {
+                write!(f, "Database attribute {} is not valid", name)
+            }
+            DatabaseError::MissingHash => write!(f, "Database game has no ROM hash"),
+        }
+    }
+}
+
+// The details the database holds for one game
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameEntry {
+    // The hashes of the PRG-ROM followed by the CHR-ROM
+    pub rom_crc32: Option<u32>,
+    pub rom_sha1: Option<[u8; 20]>,
+
+    pub mapper_number: u16,
+    pub submapper_number: u8,
+    // None when the mapper controls the mirroring, in which case the header's is kept
+    pub mirroring: Option<Mirroring>,
+    pub has_battery: bool,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    // None when the entry does not say which consoles the game runs on
+    pub region: Option<Region>,
+}
+
+impl GameEntry {
+    // Correct a header with the entry, returning a description of each field that changed
+    pub fn apply_to(&self, header: &mut INesHeader) -> Vec<String> {
+        let mut changes: Vec<String> = Vec::new();
+
+        if header.mapper_number != self.mapper_number {
+            changes.push(format!(
+                "mapper {} -> {}",
+                header.mapper_number, self.mapper_number
+            ));
+            header.mapper_number = self.mapper_number;
+        }
+        if header.submapper_number != self.submapper_number {
+            changes.push(format!(
+                "submapper {} -> {}",
+                header.submapper_number, self.submapper_number
+            ));
+            header.submapper_number = self.submapper_number;
+        }
+        if let Some(mirroring) = self.mirroring {
+            if header.mirroring != mirroring {
+                changes.push(format!(
+                    "mirroring {:?} -> {:?}",
+                    header.mirroring, mirroring
+                ));
+                header.mirroring = mirroring;
+                if mirroring != Mirroring::FourScreen {
+                    header.mirroring_flag = mirroring == Mirroring::Vertical;
+                }
+            }
+        }
+        if header.has_battery != self.has_battery {
+            changes.push(format!(
+                "battery {} -> {}",
+                header.has_battery, self.has_battery
+            ));
+            header.has_battery = self.has_battery;
+        }
+
+        if let Some(region) = self.region {
+            if header.region != region {
+                changes.push(format!("region {:?} -> {:?}", header.region, region));
+                header.region = region;
+            }
+        }
+
+        let sizes: [(&str, &mut usize, usize); 4] = [
+            ("PRG-RAM", &mut header.prg_ram_size, self.prg_ram_size),
+            ("PRG-NVRAM", &mut header.prg_nvram_size, self.prg_nvram_size),
+            ("CHR-RAM", &mut header.chr_ram_size, self.chr_ram_size),
+            ("CHR-NVRAM", &mut header.chr_nvram_size, self.chr_nvram_size),
+        ];
+        for (name, size, corrected) in sizes {
+            if *size != corrected {
+                changes.push(format!("{} size {} -> {}", name, size, corrected));
+                *size = corrected;
+            }
+        }
+
+        changes
+    }
+}
+
+pub struct Database {
+    games: Vec<GameEntry>,
+}
+
+impl Database {
+    // The database that is built into the emulator
+    pub fn get_embedded() -> Database {
+        Database::parse(EMBEDDED_DATABASE).unwrap()
+    }
+
+    pub fn parse(xml: &str) -> Result<Database, DatabaseError> {
+        let mut games: Vec<GameEntry> = Vec::new();
+        let mut game: Option<GameEntry> = None;
+
+        for tag in parse_tags(xml)? {
+            match (tag.name, tag.is_closing, game.as_mut()) {
+                ("game", false, _) => game = Some(GameEntry::default()),
+                ("game", true, Some(_)) => {
+                    let entry: GameEntry = game.take().unwrap();
+                    if entry.rom_crc32.is_none() && entry.rom_sha1.is_none() {
+                        return Err(DatabaseError::MissingHash);
+                    }
+                    games.push(entry);
+                }
+                ("game", true, None) => return Err(DatabaseError::InvalidXml),
+                ("rom", false, Some(entry)) => {
+                    if let Some(value) = tag.get_attribute("crc32") {
+                        entry.rom_crc32 = Some(parse_crc32(value)?);
+                    }
+                    if let Some(value) = tag.get_attribute("sha1") {
+                        entry.rom_sha1 = Some(parse_sha1(value)?);
+                    }
+                }
+                ("pcb", false, Some(entry)) => {
+                    entry.mapper_number = tag.get_number("mapper")?;
+                    entry.submapper_number = tag.get_number("submapper")?;
+                    entry.has_battery = tag.get_number::<u8>("battery")? != 0;
+                    entry.mirroring = match tag.get_attribute("mirroring") {
+                        Some("H") => Some(Mirroring::Horizontal),
+                        Some("V") => Some(Mirroring::Vertical),
+                        Some("4") => Some(Mirroring::FourScreen),
+                        _ => None,
+                    };
+                }
+                ("console", false, Some(entry)) if tag.get_attribute("region").is_some() => {
+                    entry.region = Some(Region::from_timing(tag.get_number("region")?));
+                }
+                ("prgram", false, Some(entry)) => entry.prg_ram_size = tag.get_number("size")?,
+                ("prgnvram", false, Some(entry)) => {
+                    entry.prg_nvram_size = tag.get_number("size")?
+                }
+                ("chrram", false, Some(entry)) => entry.chr_ram_size = tag.get_number("size")?,
+                ("chrnvram", false, Some(entry)) => {
+                    entry.chr_nvram_size = tag.get_number("size")?
+                }
+                _ => {}
+            }
+        }
+
+        if game.is_some() {
+            return Err(DatabaseError::InvalidXml);
+        }
+        Ok(Database { games })
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    // Find the entry for a ROM from its PRG-ROM followed by its CHR-ROM
+    // The CRC-32 finds the candidates and the SHA-1, when the entry has one, confirms the match
+    pub fn find(&self, rom: &[u8]) -> Option<&GameEntry> {
+        let crc32: u32 = Utils::get_crc32(rom);
+        let mut sha1: Option<[u8; 20]> = None;
+
+        self.games.iter().find(|game| {
+            if game.rom_crc32.is_some_and(|value| value != crc32) {
+                return false;
+            }
+            match game.rom_sha1 {
+                Some(value) => *sha1.get_or_insert_with(|| Utils::get_sha1(rom)) == value,
+                None => true,
+            }
+        })
+    }
+}
+
+// An element's start or end tag
+struct Tag<'a> {
+    name: &'a str,
+    is_closing: bool,
+    attributes: Vec<(&'a str, String)>,
+}
+
+impl<'a> Tag<'a> {
+    fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Read a decimal attribute, which is zero when left out
+    fn get_number<T: std::str::FromStr + Default>(&self, name: &str) -> Result<T, DatabaseError> {
+        match self.get_attribute(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| DatabaseError::InvalidAttribute(name.to_string())),
+            None => Ok(T::default()),
+        }
+    }
+}
+
+// Split XML into its tags, skipping text, comments, processing instructions and declarations
+// A self closing tag is returned as a start tag followed by an end tag
+fn parse_tags(xml: &str) -> Result<Vec<Tag<'_>>, DatabaseError> {
+    let mut tags: Vec<Tag> = Vec::new();
+    let mut rest: &str = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let terminator: &str = if rest.starts_with("<!--") {
+            "-->"
+        } else if rest.starts_with("<?") {
+            "?>"
+        } else {
+            ">"
+        };
+        let end: usize = rest.find(terminator).ok_or(DatabaseError::InvalidXml)?;
+        let body: &str = &rest[1..end];
+        rest = &rest[end + terminator.len()..];
+        if terminator != ">" || body.starts_with('!') {
+            continue;
+        }
+
+        if let Some(name) = body.strip_prefix('/') {
+            tags.push(Tag {
+                name: name.trim(),
+                is_closing: true,
+                attributes: Vec::new(),
+            });
+            continue;
+        }
+
+        let (body, is_self_closing) = match body.strip_suffix('/') {
+            Some(body) => (body, true),
+            None => (body, false),
+        };
+        let name_end: usize = body
+            .find(|character: char| character.is_whitespace())
+            .unwrap_or(body.len());
+        let name: &str = &body[..name_end];
+        tags.push(Tag {
+            name,
+            is_closing: false,
+            attributes: parse_attributes(&body[name_end..])?,
+        });
+        if is_self_closing {
+            tags.push(Tag {
+                name,
+                is_closing: true,
+                attributes: Vec::new(),
+            });
+        }
+    }
+
+    Ok(tags)
+}
+
+// Read the name="value" pairs of a tag
+fn parse_attributes(mut text: &str) -> Result<Vec<(&str, String)>, DatabaseError> {
+    let mut attributes: Vec<(&str, String)> = Vec::new();
+
+    loop {
+        text = text.trim_start();
+        if text.is_empty() {
+            return Ok(attributes);
+        }
+        let equals: usize = text.find('=').ok_or(DatabaseError::InvalidXml)?;
+        let name: &str = text[..equals].trim();
+        text = text[equals + 1..].trim_start();
+
+        // Values can be quoted with either kind of quote
+        let quote: char = text.chars().next().ok_or(DatabaseError::InvalidXml)?;
+        if quote != '"' && quote != '\'' {
+            return Err(DatabaseError::InvalidXml);
+        }
+        let end: usize = text[1..].find(quote).ok_or(DatabaseError::InvalidXml)? + 1;
+        attributes.push((name, unescape(&text[1..end])));
+        text = &text[end + 1..];
+    }
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_crc32(value: &str) -> Result<u32, DatabaseError> {
+    u32::from_str_radix(value, 16).map_err(|_| DatabaseError::InvalidAttribute("crc32".to_string()))
+}
+
+fn parse_sha1(value: &str) -> Result<[u8; 20], DatabaseError> {
+    let error = || DatabaseError::InvalidAttribute("sha1".to_string());
+    if value.len() != 40 || !value.is_ascii() {
+        return Err(error());
+    }
+    let mut digest: [u8; 20] = [0; 20];
+    for (index, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[index * 2..index * 2 + 2], 16).map_err(|_| error())?;
+    }
+    Ok(digest)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Helper function for the tests to build a database holding one game for the given ROM data
+    pub fn get_test_database(rom: &[u8], pcb: &str, extra: &str) -> String {
+        let sha1: String = Utils::get_sha1(rom)
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        format!(
+            "<?xml version=\"1.0\"?>\n<nes20db>\n<game>\n<!-- Test Game -->\n\
+             <rom size=\"{}\" crc32=\"{:08X}\" sha1=\"{}\"/>\n<pcb {}/>\n{}\n</game>\n</nes20db>\n",
+            rom.len(),
+            Utils::get_crc32(rom),
+            sha1,
+            pcb,
+            extra
+        )
+    }
+
+    #[test]
+    fn test_embedded_database_parses() {
+        // Assert results, a broken embedded database would fail every ROM load
+        assert!(Database::parse(EMBEDDED_DATABASE).is_ok());
+    }
+
+    #[test]
+    fn test_embedded_database_corrects_header() {
+        // Prep for the test, a Super Mario Bros. dump whose header says horizontal mirroring and a
+        // battery, as found in some old dumps
+        let database: Database = Database::get_embedded();
+        let entry: &GameEntry = database
+            .games
+            .iter()
+            .find(|game| game.rom_crc32 == Some(0x3337EC46))
+            .unwrap();
+        let mut header: INesHeader = INesHeader::parse(&[
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ])
+        .unwrap();
+        let changes: Vec<String> = entry.apply_to(&mut header);
+
+        // Assert results
+        assert_eq!(
+            changes,
+            vec![
+                "mirroring Horizontal -> Vertical",
+                "battery true -> false",
+                "PRG-NVRAM size 8192 -> 0",
+            ]
+        );
+        assert_eq!(header.mapper_number, 0);
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert!(!header.has_battery);
+    }
+
+    #[test]
+    fn test_parse_and_find() {
+        // Prep for the test
+        let rom: Vec<u8> = vec![0x42; 0x6000];
+        let xml: String = get_test_database(
+            &rom,
+            "mapper=\"4\" submapper=\"1\" mirroring=\"V\" battery=\"1\"",
+            "<prgnvram size='8192'/><chrram size=\"8192\"/><console type=\"0\" region=\"1\"/>",
+        );
+        let database: Database = Database::parse(&xml).unwrap();
+
+        // Assert results
+        assert_eq!(database.len(), 1);
+        let entry: &GameEntry = database.find(&rom).unwrap();
+        assert_eq!(entry.mapper_number, 4);
+        assert_eq!(entry.submapper_number, 1);
+        assert_eq!(entry.mirroring, Some(Mirroring::Vertical));
+        assert!(entry.has_battery);
+        assert_eq!(entry.prg_ram_size, 0);
+        assert_eq!(entry.prg_nvram_size, 0x2000);
+        assert_eq!(entry.chr_ram_size, 0x2000);
+        assert_eq!(entry.region, Some(Region::Pal));
+        assert!(database.find(&rom[1..]).is_none());
+    }
+
+    #[test]
+    fn test_sha1_confirms_crc32_match() {
+        // Prep for the test, an entry with the right CRC-32 and the wrong SHA-1
+        let rom: Vec<u8> = vec![0x42; 0x100];
+        let xml: String = format!(
+            "<nes20db><game><rom crc32=\"{:08X}\" sha1=\"{}\"/><pcb mapper=\"1\"/></game></nes20db>",
+            Utils::get_crc32(&rom),
+            "00".repeat(20)
+        );
+        let database: Database = Database::parse(&xml).unwrap();
+
+        // Assert results
+        assert!(database.find(&rom).is_none());
+    }
+
+    #[test]
+    fn test_apply_to_header() {
+        // Prep for the test, an iNES header for mapper 0 with a battery
+        let mut header: INesHeader = INesHeader::parse(&[
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ])
+        .unwrap();
+        let entry: GameEntry = GameEntry {
+            rom_crc32: Some(0),
+            mapper_number: 1,
+            mirroring: Some(Mirroring::Vertical),
+            prg_ram_size: 0x2000,
+            region: Some(Region::Dendy),
+            ..GameEntry::default()
+        };
+        let changes: Vec<String> = entry.apply_to(&mut header);
+
+        // Assert results
+        assert_eq!(
+            changes,
+            vec![
+                "mapper 0 -> 1",
+                "mirroring Horizontal -> Vertical",
+                "battery true -> false",
+                "region Ntsc -> Dendy",
+                "PRG-RAM size 0 -> 8192",
+                "PRG-NVRAM size 8192 -> 0",
+            ]
+        );
+        assert_eq!(header.mapper_number, 1);
+        assert!(header.mirroring_flag);
+        assert_eq!(header.region, Region::Dendy);
+        assert!(entry.apply_to(&mut header).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_databases() {
+        // Assert results
+        assert_eq!(
+            Database::parse("<nes20db><game><pcb mapper=\"1\"/></game></nes20db>").err(),
+            Some(DatabaseError::MissingHash)
+        );
+        assert_eq!(
+            Database::parse("<game><rom crc32=\"XYZ\"/></game>").err(),
+            Some(DatabaseError::InvalidAttribute("crc32".to_string()))
+        );
+        assert_eq!(
+            Database::parse("<game><rom crc32=\"0\"/>").err(),
+            Some(DatabaseError::InvalidXml)
+        );
+        assert_eq!(
+            Database::parse("<game><rom crc32=0/>").err(),
+            Some(DatabaseError::InvalidXml)
+        );
+    }
+}
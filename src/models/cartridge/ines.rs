@@ -0,0 +1,445 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Loader for the iNES and NES 2.0 file formats
+// iNES: https://www.nesdev.org/wiki/INES
+// NES2.0: https://www.nesdev.org/wiki/NES_2.0
+
+use crate::models::cartridge::database::{Database, GameEntry};
+use crate::models::cartridge::{Cartridge, CartridgeError, Mirroring};
+use crate::models::region::Region;
+
+// The header is the first 16 bytes of the rom content
+pub const HEADER_SIZE: usize = 16;
+
+// A trainer, when present, sits between the header and the PRG-ROM
+pub const TRAINER_SIZE: usize = 512;
+
+// The decoded contents of an iNES or NES 2.0 header
+#[derive(Debug, PartialEq, Eq)]
+pub struct INesHeader {
+    pub is_nes2: bool,
+    pub mapper_number: u16,
+    pub submapper_number: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub mirroring: Mirroring,
+    pub mirroring_flag: bool,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    // iNES has a TV system bit, but so few files set it that only the NES 2.0 field is trusted
+    pub region: Region,
+}
+
+impl INesHeader {
+    pub fn parse(rom_content: &[u8]) -> Result<INesHeader, CartridgeError> {
+        if rom_content.len() < HEADER_SIZE {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        // The first three bytes should be 'N' (0x4E), 'E' (0x45), and 'S' (0x53), followed by EOF (0x1A)
+        // This is derived from https://www.nesdev.org/wiki/NES_2.0#Identification
+        if rom_content[0..4] != [0x4E, 0x45, 0x53, 0x1A] {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        // The nes 2.0 specification is that from the 7th byte of the header, that bit 2 is clear and bit 3 is set
+        let is_nes2: bool = rom_content[7] & 0b0000_1100 == 0b0000_1000;
+
+        // Byte 6 holds the mirroring, battery and trainer flags along with the low nibble of the mapper
+        let flags6: u8 = rom_content[6];
+        let mirroring: Mirroring = if flags6 & 0b0000_1000 == 0b0000_1000 {
+            Mirroring::FourScreen
+        } else if flags6 & 0b0000_0001 == 0b0000_0001 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mirroring_flag: bool = flags6 & 0b0000_0001 == 0b0000_0001;
+        let has_battery: bool = flags6 & 0b0000_0010 == 0b0000_0010;
+        let has_trainer: bool = flags6 & 0b0000_0100 == 0b0000_0100;
+
+        // Old dumping tools wrote a signature such as "DiskDude!" into bytes 7 to 15
+        // When that happens the high nibble of the mapper number in byte 7 is garbage and must be ignored
+        let has_garbage: bool = !is_nes2 && rom_content[12..16].iter().any(|byte| *byte != 0);
+        let mut mapper_number: u16 = u16::from(flags6 >> 4);
+        if !has_garbage {
+            mapper_number |= u16::from(rom_content[7] & 0b1111_0000);
+        }
+
+        if is_nes2 {
+            // Byte 8 holds the highest nibble of the mapper and the submapper
+            mapper_number |= u16::from(rom_content[8] & 0b0000_1111) << 8;
+            let submapper_number: u8 = rom_content[8] >> 4;
+
+            // Byte 9 holds the most significant bits of the ROM sizes
+            let prg_rom_size: usize =
+                INesHeader::get_nes2_rom_size(rom_content[4], rom_content[9] & 0x0F, 0x4000);
+            let chr_rom_size: usize =
+                INesHeader::get_nes2_rom_size(rom_content[5], rom_content[9] >> 4, 0x2000);
+
+            // Bytes 10 and 11 hold the RAM sizes as shift counts
+            Ok(INesHeader {
+                is_nes2,
+                mapper_number,
+                submapper_number,
+                prg_rom_size,
+                chr_rom_size,
+                prg_ram_size: INesHeader::get_nes2_ram_size(rom_content[10] & 0x0F),
+                prg_nvram_size: INesHeader::get_nes2_ram_size(rom_content[10] >> 4),
+                chr_ram_size: INesHeader::get_nes2_ram_size(rom_content[11] & 0x0F),
+                chr_nvram_size: INesHeader::get_nes2_ram_size(rom_content[11] >> 4),
+                mirroring,
+                mirroring_flag,
+                has_battery,
+                has_trainer,
+                // Byte 12 holds the CPU and PPU timing
+                region: Region::from_timing(rom_content[12]),
+            })
+        } else {
+            let prg_rom_size: usize = usize::from(rom_content[4]) * 0x4000;
+            let chr_rom_size: usize = usize::from(rom_content[5]) * 0x2000;
+
+            // iNES byte 8 is the PRG-RAM size in 8KB units, where zero means 8KB for compatibility
+            let prg_ram_units: usize = if has_garbage {
+                1
+            } else {
+                usize::from(rom_content[8]).max(1)
+            };
+            let prg_ram_size: usize = prg_ram_units * 0x2000;
+
+            // iNES has no field for CHR-RAM, so boards without CHR-ROM are assumed to have 8KB
+            let chr_ram_size: usize = if chr_rom_size == 0 { 0x2000 } else { 0 };
+
+            // iNES cannot tell volatile and battery backed RAM apart, so the battery flag decides
+            let (prg_ram_size, prg_nvram_size) = if has_battery {
+                (0, prg_ram_size)
+            } else {
+                (prg_ram_size, 0)
+            };
+
+            Ok(INesHeader {
+                is_nes2,
+                mapper_number,
+                submapper_number: 0,
+                prg_rom_size,
+                chr_rom_size,
+                prg_ram_size,
+                prg_nvram_size,
+                chr_ram_size,
+                chr_nvram_size: 0,
+                mirroring,
+                mirroring_flag,
+                has_battery,
+                has_trainer,
+                region: Region::Ntsc,
+            })
+        }
+    }
+
+    // Decode a NES 2.0 ROM size from its least significant byte and most significant nibble
+    fn get_nes2_rom_size(lsb: u8, msb: u8, unit_size: usize) -> usize {
+        if msb == 0x0F {
+            // Exponent-multiplier notation, the size is 2^E * (MM * 2 + 1) bytes
+            let exponent: u32 = u32::from(lsb >> 2);
+            let multiplier: usize = usize::from(lsb & 0b0000_0011) * 2 + 1;
+            (1usize << exponent) * multiplier
+        } else {
+            (usize::from(msb) << 8 | usize::from(lsb)) * unit_size
+        }
+    }
+
+    // Decode a NES 2.0 RAM size shift count, where zero means no RAM
+    fn get_nes2_ram_size(shift: u8) -> usize {
+        if shift == 0 {
+            0
+        } else {
+            64 << shift
+        }
+    }
+}
+
+// Load an iNES or NES 2.0 file into a cartridge description
+// When a database is given, a ROM found in it has its header corrected from the database entry
+pub fn load_cartridge(
+    rom_content: &[u8],
+    database: Option<&Database>,
+) -> Result<Cartridge, CartridgeError> {
+    let mut header: INesHeader = INesHeader::parse(rom_content)?;
+
+    // The PRG-ROM starts after the trainer when there is one
+    let trainer_size: usize = if header.has_trainer { TRAINER_SIZE } else { 0 };
+    let prg_start: usize = HEADER_SIZE + trainer_size;
+    let chr_start: usize = prg_start + header.prg_rom_size;
+    let chr_end: usize = chr_start + header.chr_rom_size;
+    if rom_content.len() < chr_end {
+        return Err(CartridgeError::TruncatedData);
+    }
+
+    // Games are looked up by the PRG-ROM followed by the CHR-ROM
+    let entry: Option<&GameEntry> =
+        database.and_then(|database| database.find(&rom_content[prg_start..chr_end]));
+    if let Some(entry) = entry {
+        let changes: Vec<String> = entry.apply_to(&mut header);
+        if !changes.is_empty() {
+            println!("ROM database corrected the header: {}", changes.join(", "));
+        }
+    }
+
+    // The trainer is loaded into PRG-RAM, so the board needs some even if the header says none
+    let mut prg_ram_size: usize = header.prg_ram_size + header.prg_nvram_size;
+    if header.has_trainer {
+        prg_ram_size = prg_ram_size.max(0x2000);
+    }
+
+    Ok(Cartridge {
+        mapper_number: header.mapper_number,
+        submapper_number: header.submapper_number,
+        prg_rom: rom_content[prg_start..chr_start].to_vec(),
+        chr_rom: rom_content[chr_start..chr_end].to_vec(),
+        prg_ram_size,
+        chr_ram_size: header.chr_ram_size + header.chr_nvram_size,
+        prg_nvram_size: if header.has_battery {
+            header.prg_nvram_size
+        } else {
+            0
+        },
+        mirroring: header.mirroring,
+        mirroring_flag: header.mirroring_flag,
+        has_battery: header.has_battery,
+        trainer: rom_content[HEADER_SIZE..prg_start].to_vec(),
+        region: header.region,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cartridge::database::tests::get_test_database;
+    use crate::models::cartridge::mappers::{self, Mapper};
+
+    // Helper function for the tests to build a rom image with the given header and zeroed contents
+    pub fn get_test_rom(
+        header: [u8; HEADER_SIZE],
+        prg_rom_size: usize,
+        chr_rom_size: usize,
+    ) -> Vec<u8> {
+        let mut rom: Vec<u8> = header.to_vec();
+        rom.extend(vec![0; prg_rom_size + chr_rom_size]);
+        rom
+    }
+
+    #[test]
+    fn parse_ines_header() {
+        // Two 16KB PRG banks, one 8KB CHR bank, vertical mirroring with battery, mapper 0x12
+        let header: [u8; HEADER_SIZE] = [
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x23, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let parsed: INesHeader = INesHeader::parse(&header).unwrap();
+
+        // Assert results
+        assert!(!parsed.is_nes2);
+        assert_eq!(parsed.mapper_number, 0x12);
+        assert_eq!(parsed.prg_rom_size, 0x8000);
+        assert_eq!(parsed.chr_rom_size, 0x2000);
+        assert_eq!(parsed.prg_nvram_size, 0x2000);
+        assert_eq!(parsed.mirroring, Mirroring::Vertical);
+        assert!(parsed.has_battery);
+        assert!(!parsed.has_trainer);
+    }
+
+    #[test]
+    fn parse_nes2_header() {
+        // Mapper 0x102 submapper 3, 8KB PRG-RAM, 8KB CHR-RAM
+        let header: [u8; HEADER_SIZE] = [
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x20, 0x08, 0x31, 0x00, 0x07, 0x07, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let parsed: INesHeader = INesHeader::parse(&header).unwrap();
+
+        // Assert results
+        assert!(parsed.is_nes2);
+        assert_eq!(parsed.mapper_number, 0x102);
+        assert_eq!(parsed.submapper_number, 3);
+        assert_eq!(parsed.prg_ram_size, 0x2000);
+        assert_eq!(parsed.chr_ram_size, 0x2000);
+        assert_eq!(parsed.chr_rom_size, 0);
+        assert_eq!(parsed.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn parse_nes2_region() {
+        // Prep for the test, the timing byte selects the region
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 1;
+        header[7] = 0x08;
+        header[12] = 0x01;
+        let pal: INesHeader = INesHeader::parse(&header).unwrap();
+        header[12] = 0x03;
+        let dendy: INesHeader = INesHeader::parse(&header).unwrap();
+
+        // Assert results
+        assert_eq!(pal.region, Region::Pal);
+        assert_eq!(dendy.region, Region::Dendy);
+    }
+
+    #[test]
+    fn ignore_garbage_in_ines_header() {
+        // A header with a dumper signature in the unused bytes
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 1;
+        header[6] = 0x40;
+        header[7..16].copy_from_slice(b"DiskDude!");
+        let parsed: INesHeader = INesHeader::parse(&header).unwrap();
+
+        // Only the low nibble of the mapper number can be trusted
+        assert_eq!(parsed.mapper_number, 4);
+    }
+
+    #[test]
+    fn reject_invalid_header() {
+        let header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        assert_eq!(
+            INesHeader::parse(&header),
+            Err(CartridgeError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn load_cartridge_slices_rom() {
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 1;
+        header[5] = 1;
+        let mut rom: Vec<u8> = get_test_rom(header, 0x4000, 0x2000);
+        rom[HEADER_SIZE] = 0xAA;
+        rom[HEADER_SIZE + 0x4000] = 0xBB;
+
+        let cartridge: Cartridge = load_cartridge(&rom, None).unwrap();
+
+        // Assert results
+        assert_eq!(cartridge.prg_rom.len(), 0x4000);
+        assert_eq!(cartridge.chr_rom.len(), 0x2000);
+        assert_eq!(cartridge.prg_rom[0], 0xAA);
+        assert_eq!(cartridge.chr_rom[0], 0xBB);
+        assert!(cartridge.trainer.is_empty());
+    }
+
+    #[test]
+    fn load_cartridge_corrects_header_from_database() {
+        // Prep for the test, an iNES header for mapper 0 that the database says is mapper 2
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 2;
+        let rom: Vec<u8> = get_test_rom(header, 0x8000, 0);
+        let database: Database = Database::parse(&get_test_database(
+            &rom[HEADER_SIZE..],
+            "mapper=\"2\" mirroring=\"V\"",
+            "<chrram size=\"8192\"/>",
+        ))
+        .unwrap();
+
+        // Assert results, the database is only used when given
+        let corrected: Cartridge = load_cartridge(&rom, Some(&database)).unwrap();
+        assert_eq!(corrected.mapper_number, 2);
+        assert_eq!(corrected.mirroring, Mirroring::Vertical);
+        assert_eq!(corrected.prg_ram_size, 0);
+        let uncorrected: Cartridge = load_cartridge(&rom, None).unwrap();
+        assert_eq!(uncorrected.mapper_number, 0);
+        assert_eq!(uncorrected.mirroring, Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn trainer_is_loaded_on_uxrom() {
+        // Prep for the test, a NES 2.0 UxROM rom with a trainer and no PRG-RAM in the header
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 2;
+        header[6] = 0b0010_0100;
+        header[7] = 0b0000_1000;
+        let mut rom: Vec<u8> = get_test_rom(header, TRAINER_SIZE + 0x8000, 0);
+        rom[HEADER_SIZE] = 0x11;
+        rom[HEADER_SIZE + TRAINER_SIZE - 1] = 0x22;
+
+        let cartridge: Cartridge = load_cartridge(&rom, None).unwrap();
+        let trainer: Vec<u8> = cartridge.trainer.clone();
+        let mut mapper: Box<dyn Mapper> = mappers::new_mapper(cartridge).unwrap();
+        mapper.load_trainer(&trainer);
+
+        // Assert results, the trainer is at $7000-$71FF where the game jumps to it
+        assert_eq!(mapper.cpu_read(0x7000), 0x11);
+        assert_eq!(mapper.cpu_read(0x71FF), 0x22);
+    }
+
+    #[test]
+    fn load_cartridge_skips_trainer() {
+        // Prep for the test, a rom with the trainer bit set and no PRG-RAM in the header
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 1;
+        header[5] = 1;
+        header[6] = 0b0000_0100;
+        header[7] = 0b0000_1000;
+        let mut rom: Vec<u8> = get_test_rom(header, TRAINER_SIZE + 0x4000, 0x2000);
+        rom[HEADER_SIZE] = 0x11;
+        rom[HEADER_SIZE + TRAINER_SIZE] = 0xAA;
+        rom[HEADER_SIZE + TRAINER_SIZE + 0x4000] = 0xBB;
+
+        let cartridge: Cartridge = load_cartridge(&rom, None).unwrap();
+
+        // Assert results
+        assert_eq!(cartridge.trainer.len(), TRAINER_SIZE);
+        assert_eq!(cartridge.trainer[0], 0x11);
+        assert_eq!(cartridge.prg_rom[0], 0xAA);
+        assert_eq!(cartridge.chr_rom[0], 0xBB);
+        assert_eq!(cartridge.prg_ram_size, 0x2000);
+
+        // Without the trainer the file would be too short
+        rom.truncate(rom.len() - 1);
+        assert_eq!(
+            load_cartridge(&rom, None).err(),
+            Some(CartridgeError::TruncatedData)
+        );
+    }
+
+    #[test]
+    fn load_truncated_cartridge() {
+        let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A]);
+        header[4] = 2;
+        let rom: Vec<u8> = get_test_rom(header, 0x4000, 0);
+
+        assert_eq!(
+            load_cartridge(&rom, None).err(),
+            Some(CartridgeError::TruncatedData)
+        );
+    }
+}
@@ -0,0 +1,139 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// AxROM (mapper 7)
+// https://www.nesdev.org/wiki/AxROM
+
+use crate::models::cartridge::mappers::{
+    get_bus_conflict_value, has_bus_conflicts, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    // PRG-RAM from the header, which trainer dumps need even though AxROM has none
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    bus_conflicts: bool,
+    // The 32KB PRG bank visible at $8000
+    prg_bank: usize,
+    // AxROM boards select one nametable page for the whole screen
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(cartridge: Cartridge) -> Axrom {
+        Axrom {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            bus_conflicts: has_bus_conflicts(&cartridge),
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_rom: cartridge.prg_rom,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xFFFF => read_bank(&self.prg_rom, self.prg_bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+        } else if address >= 0x8000 {
+            let rom_value: u8 = self.cpu_read(address);
+            let value: u8 = get_bus_conflict_value(self.bus_conflicts, rom_value, value);
+
+            // Bits 0-2 select the PRG bank and bit 4 selects the nametable page
+            self.prg_bank = usize::from(value & 0b0000_0111);
+            self.mirroring = if value & 0b0001_0000 == 0b0001_0000 {
+                Mirroring::SingleScreenUpper
+            } else {
+                Mirroring::SingleScreenLower
+            };
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, 0, 0x2000, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            write_bank(&mut self.chr, 0, 0x2000, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_bank_switch_and_mirroring() {
+        // Prep for the test, eight 32KB banks with CHR-RAM
+        let mut mapper: Axrom = Axrom::new(get_test_cartridge(7, 0, 8, 0x8000, 0, 0));
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenLower);
+
+        // Select bank 6 and the upper nametable
+        mapper.cpu_write(0x8000, 0b0001_0110);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 6);
+        assert_eq!(mapper.cpu_read(0xFFFF), 6);
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenUpper);
+
+        // Select bank 1 and the lower nametable
+        mapper.cpu_write(0x8000, 0b0000_0001);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0xC000), 1);
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn test_bus_conflicts() {
+        // Prep for the test, bank 0 holds zeroes so every write is masked to zero
+        let mut mapper: Axrom = Axrom::new(get_test_cartridge(7, 2, 8, 0x8000, 0, 0));
+
+        mapper.cpu_write(0x8000, 0b0001_0111);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenLower);
+    }
+}
@@ -0,0 +1,421 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Serial EEPROMs used by Bandai boards for saves, driven bit by bit over I2C
+// https://www.nesdev.org/wiki/Bandai_FCG_board#Serial_EEPROM
+//
+// The two lines are SCL (clock) and SDA (data). A start condition is SDA falling while SCL is high,
+// and a stop condition is SDA rising while SCL is high. Otherwise SDA only changes while SCL is low
+// and is sampled as SCL rises. Every byte is followed by an acknowledge bit from the receiver,
+// which pulls SDA low to accept the byte.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EepromKind {
+    // Xicor X24C01, 128 bytes, no device address and bits sent least significant first
+    X24C01,
+    // 24C02, 256 bytes, standard I2C with a device address and bits sent most significant first
+    C24C02,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EepromMode {
+    // Waiting for a start condition
+    Idle,
+    // Receiving the device address and read/write bit, 24C02 only
+    ReceiveDevice,
+    // Receiving the word address
+    ReceiveAddress,
+    // Receiving bytes to write
+    ReceiveData,
+    // Sending bytes to the host
+    Transmit,
+}
+
+pub struct I2cEeprom {
+    kind: EepromKind,
+    data: Vec<u8>,
+    mode: EepromMode,
+    // The mode to switch to once the current acknowledge bit is done
+    next_mode: EepromMode,
+
+    // The last levels driven by the host
+    scl: bool,
+    sda: bool,
+    // The level the EEPROM drives onto SDA, which is pulled high when it is not driving it
+    output: bool,
+
+    shift_register: u8,
+    // Bits of the current byte done so far, 8 is the acknowledge bit and 9 is after it
+    bit_index: u8,
+    address: u8,
+}
+
+impl I2cEeprom {
+    pub fn new(kind: EepromKind) -> I2cEeprom {
+        let size: usize = match kind {
+            EepromKind::X24C01 => 128,
+            EepromKind::C24C02 => 256,
+        };
+
+        I2cEeprom {
+            kind,
+            data: vec![0xFF; size],
+            mode: EepromMode::Idle,
+            next_mode: EepromMode::Idle,
+            scl: false,
+            sda: false,
+            output: true,
+            shift_register: 0,
+            bit_index: 0,
+            address: 0,
+        }
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load_data(&mut self, data: &[u8]) {
+        let length: usize = data.len().min(self.data.len());
+        self.data[..length].copy_from_slice(&data[..length]);
+    }
+
+    // The level of SDA as seen by the host, the EEPROM can only pull the line low
+    pub fn read_sda(&self) -> bool {
+        self.output
+    }
+
+    // Drive the two lines from the host
+    pub fn write_lines(&mut self, scl: bool, sda: bool) {
+        let previous_scl: bool = self.scl;
+        let previous_sda: bool = self.sda;
+        self.scl = scl;
+        self.sda = sda;
+
+        if previous_scl && scl && previous_sda != sda {
+            if sda {
+                self.stop();
+            } else {
+                self.start();
+            }
+        } else if !previous_scl && scl {
+            self.clock_rising();
+        } else if previous_scl && !scl {
+            self.clock_falling();
+        }
+    }
+
+    fn start(&mut self) {
+        self.mode = match self.kind {
+            EepromKind::X24C01 => EepromMode::ReceiveAddress,
+            EepromKind::C24C02 => EepromMode::ReceiveDevice,
+        };
+        self.bit_index = 0;
+        self.shift_register = 0;
+        self.output = true;
+    }
+
+    fn stop(&mut self) {
+        self.mode = EepromMode::Idle;
+        self.output = true;
+    }
+
+    fn clock_rising(&mut self) {
+        match self.mode {
+            EepromMode::Idle => {}
+            EepromMode::Transmit if self.bit_index < 8 => self.bit_index += 1,
+            EepromMode::Transmit if self.bit_index == 8 => {
+                // The host acknowledges to read another byte, or stops acknowledging at the end
+                if self.sda {
+                    self.mode = EepromMode::Idle;
+                } else {
+                    self.bit_index = 9;
+                }
+            }
+            EepromMode::Transmit => {}
+            _ if self.bit_index < 8 => {
+                let bit: u8 = u8::from(self.sda);
+                self.shift_register = match self.kind {
+                    EepromKind::X24C01 => (self.shift_register >> 1) | (bit << 7),
+                    EepromKind::C24C02 => (self.shift_register << 1) | bit,
+                };
+                self.bit_index += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_falling(&mut self) {
+        match self.mode {
+            EepromMode::Idle => {}
+            EepromMode::Transmit => match self.bit_index {
+                0..=7 => self.output = self.get_transmit_bit(),
+                // Release the line so the host can acknowledge
+                8 => self.output = true,
+                _ => {
+                    self.address = ((usize::from(self.address) + 1) % self.data.len()) as u8;
+                    self.begin_transmit();
+                }
+            },
+            _ if self.bit_index == 8 => {
+                // The whole byte is in, so acknowledge it during the next clock
+                let accepted: bool = self.receive_byte(self.shift_register);
+                self.output = !accepted;
+                self.bit_index = 9;
+                if !accepted {
+                    self.next_mode = EepromMode::Idle;
+                }
+            }
+            _ if self.bit_index == 9 => {
+                // The acknowledge bit is done
+                self.output = true;
+                self.bit_index = 0;
+                self.shift_register = 0;
+                self.mode = self.next_mode;
+                if self.mode == EepromMode::Transmit {
+                    self.begin_transmit();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Handle a received byte, returning whether it is acknowledged
+    fn receive_byte(&mut self, value: u8) -> bool {
+        match (self.mode, self.kind) {
+            (EepromMode::ReceiveDevice, _) => {
+                // 1010 AAA R, where the chip select bits are all tied low
+                if value & 0b1111_1110 != 0b1010_0000 {
+                    return false;
+                }
+                self.next_mode = if value & 1 == 1 {
+                    EepromMode::Transmit
+                } else {
+                    EepromMode::ReceiveAddress
+                };
+            }
+            (EepromMode::ReceiveAddress, EepromKind::X24C01) => {
+                // The X24C01 combines the address with the read bit, R AAAAAAA
+                self.address = value & 0x7F;
+                self.next_mode = if value & 0x80 == 0x80 {
+                    EepromMode::Transmit
+                } else {
+                    EepromMode::ReceiveData
+                };
+            }
+            (EepromMode::ReceiveAddress, EepromKind::C24C02) => {
+                self.address = value;
+                self.next_mode = EepromMode::ReceiveData;
+            }
+            _ => {
+                // Writes wrap around within a page
+                let page_size: u8 = match self.kind {
+                    EepromKind::X24C01 => 4,
+                    EepromKind::C24C02 => 8,
+                };
+                self.data[usize::from(self.address)] = value;
+                self.address = (self.address & !(page_size - 1))
+                    | (self.address.wrapping_add(1) & (page_size - 1));
+                self.next_mode = EepromMode::ReceiveData;
+            }
+        }
+        true
+    }
+
+    fn begin_transmit(&mut self) {
+        self.shift_register = self.data[usize::from(self.address)];
+        self.bit_index = 0;
+        self.output = self.get_transmit_bit();
+    }
+
+    fn get_transmit_bit(&self) -> bool {
+        let shift: u8 = match self.kind {
+            EepromKind::X24C01 => self.bit_index,
+            EepromKind::C24C02 => 7 - self.bit_index,
+        };
+        (self.shift_register >> shift) & 1 == 1
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Drives the lines the way a game does, one half clock at a time
+    pub struct I2cHost<'a> {
+        pub eeprom: &'a mut I2cEeprom,
+    }
+
+    impl I2cHost<'_> {
+        pub fn start(&mut self) {
+            self.eeprom.write_lines(false, true);
+            self.eeprom.write_lines(true, true);
+            self.eeprom.write_lines(true, false);
+            self.eeprom.write_lines(false, false);
+        }
+
+        pub fn stop(&mut self) {
+            self.eeprom.write_lines(false, false);
+            self.eeprom.write_lines(true, false);
+            self.eeprom.write_lines(true, true);
+        }
+
+        // Clock a bit out to the EEPROM, and return the level of SDA while SCL was high
+        pub fn clock_bit(&mut self, bit: bool) -> bool {
+            self.eeprom.write_lines(false, bit);
+            self.eeprom.write_lines(true, bit);
+            let sda: bool = bit && self.eeprom.read_sda();
+            self.eeprom.write_lines(false, bit);
+            sda
+        }
+
+        // Send a byte in the given bit order, and return whether it was acknowledged
+        pub fn send_byte(&mut self, value: u8, msb_first: bool) -> bool {
+            for index in 0..8 {
+                let shift: u8 = if msb_first { 7 - index } else { index };
+                self.clock_bit((value >> shift) & 1 == 1);
+            }
+            !self.clock_bit(true)
+        }
+
+        // Receive a byte in the given bit order, then acknowledge it or not
+        pub fn receive_byte(&mut self, msb_first: bool, acknowledge: bool) -> u8 {
+            let mut value: u8 = 0;
+            for index in 0..8 {
+                let shift: u8 = if msb_first { 7 - index } else { index };
+                value |= u8::from(self.clock_bit(true)) << shift;
+            }
+            self.clock_bit(!acknowledge);
+            value
+        }
+    }
+
+    #[test]
+    fn test_24c02_write_and_random_read() {
+        // Prep for the test, write two bytes starting at $10
+        let mut eeprom: I2cEeprom = I2cEeprom::new(EepromKind::C24C02);
+        let mut host: I2cHost = I2cHost {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        assert!(host.send_byte(0xA0, true));
+        assert!(host.send_byte(0x10, true));
+        assert!(host.send_byte(0x12, true));
+        assert!(host.send_byte(0x34, true));
+        host.stop();
+
+        // Read them back with a dummy write to set the address and a repeated start
+        host.start();
+        assert!(host.send_byte(0xA0, true));
+        assert!(host.send_byte(0x10, true));
+        host.start();
+        assert!(host.send_byte(0xA1, true));
+        let first: u8 = host.receive_byte(true, true);
+        let second: u8 = host.receive_byte(true, false);
+        host.stop();
+
+        // Assert results
+        assert_eq!((first, second), (0x12, 0x34));
+        assert_eq!(&eeprom.get_data()[0x10..0x12], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_24c02_rejects_other_devices() {
+        // Prep for the test
+        let mut eeprom: I2cEeprom = I2cEeprom::new(EepromKind::C24C02);
+        let mut host: I2cHost = I2cHost {
+            eeprom: &mut eeprom,
+        };
+
+        // Assert results, a device code other than 1010 is not acknowledged
+        host.start();
+        assert!(!host.send_byte(0x90, true));
+        assert!(!host.send_byte(0x10, true));
+        host.stop();
+    }
+
+    #[test]
+    fn test_24c02_page_wrap() {
+        // Prep for the test, write 3 bytes starting at the last byte of a page
+        let mut eeprom: I2cEeprom = I2cEeprom::new(EepromKind::C24C02);
+        let mut host: I2cHost = I2cHost {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        host.send_byte(0xA0, true);
+        host.send_byte(0x07, true);
+        for value in [1, 2, 3] {
+            host.send_byte(value, true);
+        }
+        host.stop();
+
+        // Assert results, the write wraps to the start of the same page
+        assert_eq!(
+            &eeprom.get_data()[0x00..0x08],
+            &[2, 3, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 1]
+        );
+    }
+
+    #[test]
+    fn test_x24c01_write_and_read() {
+        // Prep for the test, write a byte to $05 with the address and read bit sent LSB first
+        let mut eeprom: I2cEeprom = I2cEeprom::new(EepromKind::X24C01);
+        let mut host: I2cHost = I2cHost {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        assert!(host.send_byte(0x05, false));
+        assert!(host.send_byte(0xC3, false));
+        host.stop();
+
+        // Read it back with the read bit set
+        host.start();
+        assert!(host.send_byte(0x80 | 0x05, false));
+        let value: u8 = host.receive_byte(false, false);
+        host.stop();
+
+        // Assert results
+        assert_eq!(value, 0xC3);
+        assert_eq!(eeprom.get_data()[0x05], 0xC3);
+        assert_eq!(eeprom.get_data().len(), 128);
+    }
+
+    #[test]
+    fn test_stop_ends_transfer() {
+        // Prep for the test, stop in the middle of a write
+        let mut eeprom: I2cEeprom = I2cEeprom::new(EepromKind::C24C02);
+        let mut host: I2cHost = I2cHost {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        host.send_byte(0xA0, true);
+        host.send_byte(0x00, true);
+        host.clock_bit(false);
+        host.stop();
+
+        // Assert results, the EEPROM ignores clocks until the next start
+        assert!(!host.send_byte(0x00, true));
+        assert_eq!(eeprom.get_data()[0x00], 0xFF);
+    }
+}
@@ -0,0 +1,416 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Bandai FCG boards (mappers 16, 153 and 159)
+// https://www.nesdev.org/wiki/Bandai_FCG_board
+//
+// The older FCG-1 and FCG-2 chips have their registers at $6000-$7FFF, while the LZ93D50 has them
+// at $8000-$FFFF and adds a latch for the IRQ counter. The LZ93D50 boards either have a serial
+// EEPROM or, for mapper 153, battery backed PRG-RAM.
+
+pub mod eeprom;
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::models::cartridge::mappers::bandai::eeprom::{EepromKind, I2cEeprom};
+use crate::models::cartridge::mappers::{read_bank, write_bank, write_trainer, Mapper};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+const MAPPER_FCG_PRG_RAM: u16 = 153;
+const MAPPER_FCG_X24C01: u16 = 159;
+
+// NES 2.0 submappers of mapper 16
+const SUBMAPPER_FCG_1_2: u8 = 4;
+const SUBMAPPER_LZ93D50: u8 = 5;
+
+pub struct BandaiFcg {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+
+    // Which address ranges the registers respond to
+    fcg_registers: bool,
+    lz93d50_registers: bool,
+
+    prg_bank: usize,
+    // Mapper 153 uses bit 0 of the CHR registers to select a 256KB outer PRG bank
+    has_prg_outer_bank: bool,
+    prg_outer_bank: usize,
+    prg_ram_enabled: bool,
+    chr_banks: [usize; 8],
+
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_enabled: bool,
+    irq_line: InterruptLine,
+
+    eeprom: Option<I2cEeprom>,
+    // Bit 7 of $800D lets the CPU read SDA
+    eeprom_read_enabled: bool,
+}
+
+impl BandaiFcg {
+    pub fn new(cartridge: Cartridge) -> BandaiFcg {
+        let (fcg_registers, lz93d50_registers): (bool, bool) =
+            match (cartridge.mapper_number, cartridge.submapper_number) {
+                (16, SUBMAPPER_FCG_1_2) => (true, false),
+                (16, SUBMAPPER_LZ93D50) => (false, true),
+                (16, _) => (true, true),
+                _ => (false, true),
+            };
+        let eeprom: Option<I2cEeprom> = match cartridge.mapper_number {
+            MAPPER_FCG_PRG_RAM => None,
+            MAPPER_FCG_X24C01 => Some(I2cEeprom::new(EepromKind::X24C01)),
+            _ if lz93d50_registers => Some(I2cEeprom::new(EepromKind::C24C02)),
+            _ => None,
+        };
+        let prg_ram_size: usize = if cartridge.mapper_number == MAPPER_FCG_PRG_RAM {
+            cartridge.prg_ram_size.max(0x2000)
+        } else {
+            0
+        };
+
+        BandaiFcg {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            mirroring: Mirroring::Vertical,
+            prg_ram: vec![0; prg_ram_size],
+            prg_rom: cartridge.prg_rom,
+            fcg_registers,
+            lz93d50_registers,
+            prg_bank: 0,
+            has_prg_outer_bank: cartridge.mapper_number == MAPPER_FCG_PRG_RAM,
+            prg_outer_bank: 0,
+            prg_ram_enabled: false,
+            chr_banks: [0; 8],
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_enabled: false,
+            irq_line: InterruptLine::new(),
+            eeprom,
+            eeprom_read_enabled: false,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        // The FCG-1 and FCG-2 write the IRQ counter directly rather than a latch
+        let is_fcg: bool = address < 0x8000;
+
+        match address & 0x000F {
+            0x0..=0x7 => {
+                self.chr_banks[usize::from(address & 0b111)] = usize::from(value);
+                if self.has_prg_outer_bank {
+                    self.prg_outer_bank = usize::from(value & 1);
+                }
+            }
+            0x8 => self.prg_bank = usize::from(value & 0x0F),
+            0x9 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xA => {
+                // Any write acknowledges the IRQ, and the LZ93D50 reloads the counter from its latch
+                self.irq_enabled = value & 1 == 1;
+                if !is_fcg {
+                    self.irq_counter = self.irq_latch;
+                }
+                self.irq_line.set(InterruptSource::Mapper, false);
+            }
+            0xB if is_fcg => self.irq_counter = (self.irq_counter & 0xFF00) | u16::from(value),
+            0xC if is_fcg => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (u16::from(value) << 8)
+            }
+            0xB => self.irq_latch = (self.irq_latch & 0xFF00) | u16::from(value),
+            0xC => self.irq_latch = (self.irq_latch & 0x00FF) | (u16::from(value) << 8),
+            0xD => {
+                // RDC- ----, read enable, SDA and SCL, where mapper 153 uses bit 5 to enable PRG-RAM
+                self.prg_ram_enabled = value & 0b0010_0000 == 0b0010_0000;
+                self.eeprom_read_enabled = value & 0b1000_0000 == 0b1000_0000;
+                if let Some(eeprom) = self.eeprom.as_mut() {
+                    eeprom.write_lines(value & 0b0010_0000 != 0, value & 0b0100_0000 != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn get_prg_bank_count(&self) -> usize {
+        // With an outer bank, each 256KB half has its own last bank
+        let bank_count: usize = (self.prg_rom.len() / 0x4000).max(1);
+        if self.has_prg_outer_bank {
+            bank_count.min(16)
+        } else {
+            bank_count
+        }
+    }
+}
+
+impl Mapper for BandaiFcg {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() && self.prg_ram_enabled => {
+                read_bank(&self.prg_ram, 0, 0x2000, address)
+            }
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => 0,
+            0x6000..=0x7FFF => match &self.eeprom {
+                // SDA is returned in bit 4
+                Some(eeprom) if self.eeprom_read_enabled => u8::from(eeprom.read_sda()) << 4,
+                _ => 0,
+            },
+            0x8000..=0xBFFF => {
+                let bank: usize = self.prg_outer_bank * 16 + self.prg_bank;
+                read_bank(&self.prg_rom, bank, 0x4000, address)
+            }
+            0xC000..=0xFFFF => {
+                let bank: usize = self.prg_outer_bank * 16 + self.get_prg_bank_count() - 1;
+                read_bank(&self.prg_rom, bank, 0x4000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            // Only mapper 153 has PRG-RAM, and it has no registers in this range
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() && self.prg_ram_enabled => {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+            }
+            0x6000..=0x7FFF if self.fcg_registers => self.write_register(address, value),
+            0x8000..=0xFFFF if self.lz93d50_registers => self.write_register(address, value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        if self.chr_is_ram {
+            // Boards with CHR-RAM don't bank it
+            return self.chr[usize::from(address) % self.chr.len()];
+        }
+        let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+        read_bank(&self.chr, bank, 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let length: usize = self.chr.len();
+            self.chr[usize::from(address) % length] = value;
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq_line.set(InterruptSource::Mapper, true);
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq_line = line;
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        match &self.eeprom {
+            Some(eeprom) => Some(eeprom.get_data().to_vec()),
+            None if !self.prg_ram.is_empty() => Some(self.prg_ram.clone()),
+            None => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        match self.eeprom.as_mut() {
+            Some(eeprom) => eeprom.load_data(data),
+            None => {
+                let length: usize = data.len().min(self.prg_ram.len());
+                self.prg_ram[..length].copy_from_slice(&data[..length]);
+            }
+        }
+    }
+
+    // $6000-$7FFF holds the registers on boards without PRG-RAM, so the trainer is dropped there
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        write_trainer(&mut self.prg_ram, 0, trainer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    // Drive the EEPROM lines through $800D and read SDA back through $6000
+    fn write_lines(mapper: &mut BandaiFcg, scl: bool, sda: bool) -> bool {
+        let value: u8 = 0b1000_0000 | (u8::from(sda) << 6) | (u8::from(scl) << 5);
+        mapper.cpu_write(0x800D, value);
+        mapper.cpu_read(0x6000) & 0b0001_0000 != 0
+    }
+
+    // Send a byte MSB first and return whether it was acknowledged
+    fn send_byte(mapper: &mut BandaiFcg, value: u8) -> bool {
+        for index in (0..8).rev() {
+            let bit: bool = (value >> index) & 1 == 1;
+            write_lines(mapper, false, bit);
+            write_lines(mapper, true, bit);
+            write_lines(mapper, false, bit);
+        }
+        write_lines(mapper, false, true);
+        let acknowledged: bool = !write_lines(mapper, true, true);
+        write_lines(mapper, false, true);
+        acknowledged
+    }
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        // Prep for the test, 8 16KB PRG banks and 32 1KB CHR banks on an LZ93D50 board
+        let mut mapper: BandaiFcg =
+            BandaiFcg::new(get_test_cartridge(16, 5, 8, 0x4000, 32, 0x0400));
+        mapper.cpu_write(0x8008, 3);
+        mapper.cpu_write(0x8000, 20);
+        mapper.cpu_write(0x8007, 27);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+        assert_eq!(mapper.ppu_read(0x0000), 20);
+        assert_eq!(mapper.ppu_read(0x1C00), 27);
+
+        // The LZ93D50 ignores writes to $6000-$7FFF
+        mapper.cpu_write(0x6008, 1);
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+    }
+
+    #[test]
+    fn test_fcg_registers_and_mirroring() {
+        // Prep for the test, an FCG-2 board with its registers at $6000
+        let mut mapper: BandaiFcg =
+            BandaiFcg::new(get_test_cartridge(16, 4, 8, 0x4000, 32, 0x0400));
+        mapper.cpu_write(0x6008, 2);
+        mapper.cpu_write(0x6009, 1);
+        mapper.cpu_write(0x8008, 5);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+        assert!(mapper.get_save_data().is_none());
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        // Prep for the test, the LZ93D50 loads the counter from the latch when enabling
+        let mut mapper: BandaiFcg = BandaiFcg::new(get_test_cartridge(16, 5, 8, 0x4000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0x800B, 0x01);
+        mapper.cpu_write(0x800C, 0x00);
+        mapper.cpu_write(0x800A, 0x01);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+        mapper.cpu_write(0x800A, 0x00);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_fcg_irq_counter() {
+        // Prep for the test, the FCG-1 and FCG-2 write the counter directly
+        let mut mapper: BandaiFcg = BandaiFcg::new(get_test_cartridge(16, 4, 8, 0x4000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0x600A, 0x01);
+        mapper.cpu_write(0x600B, 0x00);
+        mapper.cpu_write(0x600C, 0x00);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+    }
+
+    #[test]
+    fn test_eeprom_through_registers() {
+        // Prep for the test, write $5A to address $20 of the 24C02 with raw start and stop conditions
+        let mut mapper: BandaiFcg = BandaiFcg::new(get_test_cartridge(16, 5, 8, 0x4000, 8, 0x0400));
+        write_lines(&mut mapper, true, true);
+        write_lines(&mut mapper, true, false);
+        write_lines(&mut mapper, false, false);
+        assert!(send_byte(&mut mapper, 0xA0));
+        assert!(send_byte(&mut mapper, 0x20));
+        assert!(send_byte(&mut mapper, 0x5A));
+        write_lines(&mut mapper, false, false);
+        write_lines(&mut mapper, true, false);
+        write_lines(&mut mapper, true, true);
+
+        // Assert results, the EEPROM is the save data
+        let save: Vec<u8> = mapper.get_save_data().unwrap();
+        assert_eq!(save.len(), 256);
+        assert_eq!(save[0x20], 0x5A);
+
+        // Loading a save restores the EEPROM
+        let mut restored: BandaiFcg =
+            BandaiFcg::new(get_test_cartridge(16, 5, 8, 0x4000, 8, 0x0400));
+        restored.load_save_data(&save);
+        assert_eq!(restored.get_save_data().unwrap()[0x20], 0x5A);
+    }
+
+    #[test]
+    fn test_mapper_159_eeprom_size() {
+        // Prep for the test
+        let mapper: BandaiFcg = BandaiFcg::new(get_test_cartridge(159, 0, 8, 0x4000, 8, 0x0400));
+
+        // Assert results
+        assert_eq!(mapper.get_save_data().unwrap().len(), 128);
+    }
+
+    #[test]
+    fn test_mapper_153_prg_ram_and_outer_bank() {
+        // Prep for the test, 32 16KB PRG banks and CHR-RAM
+        let mut mapper: BandaiFcg = BandaiFcg::new(get_test_cartridge(153, 0, 32, 0x4000, 0, 0));
+        mapper.cpu_write(0x8000, 1);
+        mapper.cpu_write(0x8008, 2);
+
+        // Assert results, the outer bank selects the second 256KB
+        assert_eq!(mapper.cpu_read(0x8000), 18);
+        assert_eq!(mapper.cpu_read(0xC000), 31);
+
+        // PRG-RAM is enabled through bit 5 of $800D
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+        mapper.cpu_write(0x800D, 0b0010_0000);
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+        assert_eq!(mapper.get_save_data().unwrap()[0], 0x12);
+    }
+}
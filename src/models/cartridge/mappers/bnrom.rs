@@ -0,0 +1,178 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// BNROM and NINA-001 (mapper 34)
+// Both boards share a mapper number, NES 2.0 submapper 1 is NINA-001 and submapper 2 is BNROM
+// https://www.nesdev.org/wiki/INES_Mapper_034
+
+use crate::models::cartridge::mappers::{
+    get_bus_conflict_value, read_bank, write_bank, Mapper, SUBMAPPER_BUS_CONFLICTS,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+const SUBMAPPER_NINA_001: u8 = 1;
+
+pub struct Bnrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    // NINA-001 has its registers at $7FFD-$7FFF rather than over the ROM
+    is_nina_001: bool,
+    // Only the BNROM variant marked by its submapper has bus conflicts
+    bus_conflicts: bool,
+    // The 32KB PRG bank visible at $8000
+    prg_bank: usize,
+    // The two 4KB CHR banks used by NINA-001
+    chr_banks: [usize; 2],
+}
+
+impl Bnrom {
+    pub fn new(cartridge: Cartridge) -> Bnrom {
+        // Without a submapper, NINA-001 can be recognised by its banked CHR-ROM
+        let is_nina_001: bool = match cartridge.submapper_number {
+            SUBMAPPER_NINA_001 => true,
+            SUBMAPPER_BUS_CONFLICTS => false,
+            _ => cartridge.chr_rom.len() > 0x2000,
+        };
+
+        Bnrom {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            prg_ram: vec![0; if is_nina_001 { 0x2000 } else { 0 }],
+            bus_conflicts: cartridge.submapper_number == SUBMAPPER_BUS_CONFLICTS,
+            prg_rom: cartridge.prg_rom,
+            mirroring: cartridge.mirroring,
+            is_nina_001,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xFFFF => read_bank(&self.prg_rom, self.prg_bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if self.is_nina_001 {
+            if let 0x6000..=0x7FFF = address {
+                // The registers sit on top of the PRG-RAM, which still receives the write
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+                match address {
+                    0x7FFD => self.prg_bank = usize::from(value & 0b0000_0001),
+                    0x7FFE => self.chr_banks[0] = usize::from(value & 0b0000_1111),
+                    0x7FFF => self.chr_banks[1] = usize::from(value & 0b0000_1111),
+                    _ => {}
+                }
+            }
+        } else if address >= 0x8000 {
+            let rom_value: u8 = self.cpu_read(address);
+            self.prg_bank =
+                usize::from(get_bus_conflict_value(self.bus_conflicts, rom_value, value));
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        if self.is_nina_001 {
+            let bank: usize = self.chr_banks[usize::from(address >> 12) & 1];
+            read_bank(&self.chr, bank, 0x1000, address)
+        } else {
+            read_bank(&self.chr, 0, 0x2000, address)
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            write_bank(&mut self.chr, 0, 0x2000, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_bnrom_bank_switch() {
+        // Prep for the test, four 32KB banks with CHR-RAM
+        let mut mapper: Bnrom = Bnrom::new(get_test_cartridge(34, 0, 4, 0x8000, 0, 0));
+
+        // Select bank 3
+        mapper.cpu_write(0x8000, 3);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xFFFF), 3);
+
+        // CHR-RAM is writable
+        mapper.ppu_write(0x0001, 0x42);
+        assert_eq!(mapper.ppu_read(0x0001), 0x42);
+    }
+
+    #[test]
+    fn test_bnrom_bus_conflicts() {
+        // Prep for the test, bank 0 holds zeroes so every write is masked to zero
+        let mut mapper: Bnrom = Bnrom::new(get_test_cartridge(34, 2, 4, 0x8000, 0, 0));
+
+        mapper.cpu_write(0x8000, 3);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+    }
+
+    #[test]
+    fn test_nina_001_bank_switch() {
+        // Prep for the test, two 32KB PRG banks and sixteen 4KB CHR banks
+        let mut mapper: Bnrom = Bnrom::new(get_test_cartridge(34, 1, 2, 0x8000, 16, 0x1000));
+
+        // Select PRG bank 1 and CHR banks 9 and 14
+        mapper.cpu_write(0x7FFD, 1);
+        mapper.cpu_write(0x7FFE, 9);
+        mapper.cpu_write(0x7FFF, 14);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+        assert_eq!(mapper.ppu_read(0x0000), 9);
+        assert_eq!(mapper.ppu_read(0x1000), 14);
+
+        // Writes to the ROM area do nothing on NINA-001
+        mapper.cpu_write(0x8000, 0);
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+
+        // The register writes also land in PRG-RAM
+        assert_eq!(mapper.cpu_read(0x7FFF), 14);
+    }
+}
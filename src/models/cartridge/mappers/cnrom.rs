@@ -0,0 +1,121 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// CNROM (mapper 3)
+// https://www.nesdev.org/wiki/CNROM
+
+use crate::models::cartridge::mappers::{
+    get_bus_conflict_value, has_bus_conflicts, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    // Only present when the header asks for it, CNROM boards have none of their own
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    // The 8KB CHR bank visible at $0000
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new(cartridge: Cartridge) -> Cnrom {
+        Cnrom {
+            chr: cartridge.get_chr_memory(),
+            bus_conflicts: has_bus_conflicts(&cartridge),
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_rom: cartridge.prg_rom,
+            mirroring: cartridge.mirroring,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xFFFF => read_bank(&self.prg_rom, 0, 0x8000, address),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+        } else if address >= 0x8000 {
+            let rom_value: u8 = self.cpu_read(address);
+            self.chr_bank =
+                usize::from(get_bus_conflict_value(self.bus_conflicts, rom_value, value));
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, self.chr_bank, 0x2000, address)
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) {
+        // CNROM boards only carry CHR-ROM
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_bank_switch() {
+        // Prep for the test, 32KB PRG and four 8KB CHR banks
+        let mut mapper: Cnrom = Cnrom::new(get_test_cartridge(3, 0, 1, 0x8000, 4, 0x2000));
+
+        // Select CHR bank 2
+        mapper.cpu_write(0x8000, 2);
+
+        // Assert results
+        assert_eq!(mapper.ppu_read(0x0000), 2);
+        assert_eq!(mapper.ppu_read(0x1FFF), 2);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+    }
+
+    #[test]
+    fn test_bus_conflicts() {
+        // Prep for the test, the PRG-ROM holds 0x01 so only bit 0 survives a write
+        let mut cartridge: Cartridge = get_test_cartridge(3, 2, 1, 0x8000, 4, 0x2000);
+        cartridge.prg_rom = vec![0x01; 0x8000];
+        let mut mapper: Cnrom = Cnrom::new(cartridge);
+
+        // Writing 3 selects bank 1
+        mapper.cpu_write(0x8000, 3);
+
+        // Assert results
+        assert_eq!(mapper.ppu_read(0x0000), 1);
+    }
+}
@@ -0,0 +1,126 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Color Dreams (mapper 11)
+// https://www.nesdev.org/wiki/Color_Dreams
+
+use crate::models::cartridge::mappers::{
+    get_bus_conflict_value, has_bus_conflicts, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+pub struct ColorDreams {
+    prg_rom: Vec<u8>,
+    // Empty unless the header asks for PRG-RAM
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    // The 32KB PRG bank visible at $8000
+    prg_bank: usize,
+    // The 8KB CHR bank visible at $0000
+    chr_bank: usize,
+}
+
+impl ColorDreams {
+    pub fn new(cartridge: Cartridge) -> ColorDreams {
+        ColorDreams {
+            chr: cartridge.get_chr_memory(),
+            bus_conflicts: has_bus_conflicts(&cartridge),
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_rom: cartridge.prg_rom,
+            mirroring: cartridge.mirroring,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xFFFF => read_bank(&self.prg_rom, self.prg_bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+        } else if address >= 0x8000 {
+            let rom_value: u8 = self.cpu_read(address);
+            let value: u8 = get_bus_conflict_value(self.bus_conflicts, rom_value, value);
+
+            // Bits 0-1 select the PRG bank and bits 4-7 select the CHR bank
+            self.prg_bank = usize::from(value & 0b0000_0011);
+            self.chr_bank = usize::from(value >> 4);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, self.chr_bank, 0x2000, address)
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) {
+        // Color Dreams boards only carry CHR-ROM
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_bank_switch() {
+        // Prep for the test, four 32KB PRG banks and sixteen 8KB CHR banks
+        let mut mapper: ColorDreams =
+            ColorDreams::new(get_test_cartridge(11, 0, 4, 0x8000, 16, 0x2000));
+
+        // Select PRG bank 3 and CHR bank 12
+        mapper.cpu_write(0x8000, 0b1100_0011);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.ppu_read(0x0000), 12);
+    }
+
+    #[test]
+    fn test_bus_conflicts() {
+        // Prep for the test, bank 0 holds zeroes so every write is masked to zero
+        let mut mapper: ColorDreams =
+            ColorDreams::new(get_test_cartridge(11, 2, 4, 0x8000, 16, 0x2000));
+
+        mapper.cpu_write(0x8000, 0b1100_0011);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.ppu_read(0x0000), 0);
+    }
+}
@@ -0,0 +1,440 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Famicom Disk System expansion audio, a 64 step wavetable channel with a frequency modulator
+// https://www.nesdev.org/wiki/FDS_audio
+//
+// The wave and the modulator each step through a 64 entry table using a 16 bit phase accumulator.
+// The modulator table holds steps for a 7 bit signed counter whose value, scaled by the
+// modulator's gain, bends the pitch of the wave.
+
+use crate::models::audio::mixer::PULSE_FULL_VOLUME;
+
+// The gains stop at 32, though a larger gain can be written while the envelope is disabled
+const MAX_GAIN: u8 = 32;
+
+// The master volume in $4089 scales the wave by 2/2, 2/3, 2/4 or 2/5, here as multiples of 1/36
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+
+// The wave output is the sample times the gain times the master volume, divided back to 0 to 63
+const OUTPUT_DIVISOR: u32 = 32 * 36;
+
+// Each modulator table entry adjusts the counter by one of these, apart from 4 which resets it
+const MOD_ADJUSTMENTS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+const MOD_RESET: u8 = 4;
+
+// The envelope speed multiplier in $408A after power on
+const DEFAULT_MASTER_SPEED: u8 = 0xE8;
+
+// The RAM adapter filters the output with a one pole low-pass at about 2kHz
+const LOW_PASS_FACTOR: f32 = 0.0070;
+
+// A full volume wave is treated as about 2.4 times as loud as a console pulse channel
+const OUTPUT_SCALE: f32 = PULSE_FULL_VOLUME * 2.4 / 63.0;
+
+// The volume and modulator gain envelopes, set by $4080 and $4084 in the form DISS SSSS
+struct Envelope {
+    speed: u8,
+    increase: bool,
+    disabled: bool,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            speed: 0,
+            increase: false,
+            disabled: true,
+            gain: 0,
+            timer: 0,
+        }
+    }
+
+    fn write(&mut self, value: u8, master_speed: u8) {
+        self.speed = value & 0b0011_1111;
+        self.increase = value & 0b0100_0000 == 0b0100_0000;
+        self.disabled = value & 0b1000_0000 == 0b1000_0000;
+
+        // With the envelope disabled the speed bits set the gain directly
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (u32::from(self.speed) + 1) * u32::from(master_speed);
+    }
+
+    // Step the envelope, returning whether the gain was updated
+    fn clock(&mut self, master_speed: u8) -> bool {
+        if self.disabled || master_speed == 0 {
+            return false;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return false;
+        }
+
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < MAX_GAIN {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+        true
+    }
+}
+
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    // Bit 7 of $4089 makes the wave table writable and holds the wave in place
+    wave_write_enabled: bool,
+    master_volume: usize,
+    master_speed: u8,
+
+    wave_frequency: u16,
+    wave_halted: bool,
+    envelopes_disabled: bool,
+    wave_accumulator: u32,
+    wave_position: usize,
+    volume: Envelope,
+
+    mod_table: [u8; 64],
+    mod_frequency: u16,
+    mod_disabled: bool,
+    mod_accumulator: u32,
+    mod_position: usize,
+    mod_counter: i8,
+    mod_envelope: Envelope,
+    // The pitch adjustment from the modulator
+    mod_output: i32,
+
+    output: u8,
+    filtered: f32,
+}
+
+impl FdsAudio {
+    pub fn new() -> FdsAudio {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write_enabled: false,
+            master_volume: 0,
+            master_speed: DEFAULT_MASTER_SPEED,
+            wave_frequency: 0,
+            wave_halted: true,
+            envelopes_disabled: false,
+            wave_accumulator: 0,
+            wave_position: 0,
+            volume: Envelope::new(),
+            mod_table: [0; 64],
+            mod_frequency: 0,
+            mod_disabled: true,
+            mod_accumulator: 0,
+            mod_position: 0,
+            mod_counter: 0,
+            mod_envelope: Envelope::new(),
+            mod_output: 0,
+            output: 0,
+            filtered: 0.0,
+        }
+    }
+
+    // Read the wave table at $4040-$407F or the gains at $4090 and $4092
+    // The upper bits are open bus, which usually holds the $40 from the high byte of the address
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            0x4040..=0x407F => self.wave_table[usize::from(address - 0x4040)] | 0x40,
+            0x4090 => self.volume.gain | 0x40,
+            0x4092 => self.mod_envelope.gain | 0x40,
+            _ => 0x40,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_write_enabled => {
+                self.wave_table[usize::from(address - 0x4040)] = value & 0b0011_1111;
+            }
+            0x4080 => self.volume.write(value, self.master_speed),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0x0F00) | u16::from(value),
+            0x4083 => {
+                self.wave_frequency =
+                    (self.wave_frequency & 0x00FF) | u16::from(value & 0b0000_1111) << 8;
+                self.wave_halted = value & 0b1000_0000 == 0b1000_0000;
+                self.envelopes_disabled = value & 0b0100_0000 == 0b0100_0000;
+
+                // Halting the wave restarts it from the beginning of the table
+                if self.wave_halted {
+                    self.wave_position = 0;
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_disabled {
+                    self.volume.reset_timer(self.master_speed);
+                    self.mod_envelope.reset_timer(self.master_speed);
+                }
+            }
+            0x4084 => self.mod_envelope.write(value, self.master_speed),
+            // The counter is 7 bit signed
+            0x4085 => self.mod_counter = ((value << 1) as i8) >> 1,
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | u16::from(value),
+            0x4087 => {
+                self.mod_frequency =
+                    (self.mod_frequency & 0x00FF) | u16::from(value & 0b0000_1111) << 8;
+                self.mod_disabled = value & 0b1000_0000 == 0b1000_0000;
+                if self.mod_disabled {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // Each write fills two entries of the table, which can only be written while halted
+            0x4088 if self.mod_disabled => {
+                let step: u8 = value & 0b0000_0111;
+                self.mod_table[self.mod_position] = step;
+                self.mod_table[(self.mod_position + 1) & 0x3F] = step;
+                self.mod_position = (self.mod_position + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.wave_write_enabled = value & 0b1000_0000 == 0b1000_0000;
+                self.master_volume = usize::from(value & 0b0000_0011);
+            }
+            0x408A => {
+                self.master_speed = value;
+                self.volume.reset_timer(self.master_speed);
+                self.mod_envelope.reset_timer(self.master_speed);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn clock_cpu(&mut self) {
+        let frequency: u16 = self.wave_frequency;
+
+        if !self.wave_halted && !self.envelopes_disabled {
+            self.volume.clock(self.master_speed);
+            if self.mod_envelope.clock(self.master_speed) {
+                self.update_mod_output(frequency);
+            }
+        }
+        if self.clock_modulator() {
+            self.update_mod_output(frequency);
+        }
+
+        // The wave holds still while halted or while the table is being written
+        if !self.wave_halted && !self.wave_write_enabled {
+            let pitch: i32 = i32::from(frequency) + self.mod_output;
+            if pitch > 0 {
+                self.wave_accumulator += pitch as u32;
+                if self.wave_accumulator > 0xFFFF {
+                    self.wave_accumulator &= 0xFFFF;
+                    self.wave_position = (self.wave_position + 1) & 0x3F;
+                }
+            }
+        }
+
+        let level: u32 = u32::from(self.volume.gain.min(MAX_GAIN))
+            * MASTER_VOLUMES[self.master_volume]
+            * u32::from(self.wave_table[self.wave_position]);
+        self.output = (level / OUTPUT_DIVISOR) as u8;
+        self.filtered += (f32::from(self.output) - self.filtered) * LOW_PASS_FACTOR;
+    }
+
+    // Step the modulator, returning whether it moved on to the next table entry
+    fn clock_modulator(&mut self) -> bool {
+        if self.mod_disabled || self.mod_frequency == 0 {
+            return false;
+        }
+        self.mod_accumulator += u32::from(self.mod_frequency);
+        if self.mod_accumulator <= 0xFFFF {
+            return false;
+        }
+        self.mod_accumulator &= 0xFFFF;
+
+        let step: u8 = self.mod_table[self.mod_position];
+        self.mod_counter = if step == MOD_RESET {
+            0
+        } else {
+            // The counter wraps around within 7 bits
+            let counter: i8 = self.mod_counter + MOD_ADJUSTMENTS[usize::from(step)];
+            (counter << 1) >> 1
+        };
+        self.mod_position = (self.mod_position + 1) & 0x3F;
+        true
+    }
+
+    // Work out the pitch adjustment from the counter and gain, with the rounding of the hardware
+    // https://www.nesdev.org/wiki/FDS_audio#Frequency_calculation
+    fn update_mod_output(&mut self, frequency: u16) {
+        let counter: i32 = i32::from(self.mod_counter);
+        let mut temp: i32 = counter * i32::from(self.mod_envelope.gain);
+        let remainder: i32 = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+
+        // The result wraps to the range -64 to 191
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= i32::from(frequency);
+        let remainder: i32 = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_output = temp;
+    }
+
+    pub fn get_output(&self) -> f32 {
+        self.filtered * OUTPUT_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fill the wave table with a square wave and start it at full volume
+    fn get_playing_audio(frequency: u16) -> FdsAudio {
+        let mut audio: FdsAudio = FdsAudio::new();
+        audio.write_register(0x4089, 0x80);
+        for index in 0..64 {
+            audio.write_register(0x4040 + index, if index < 32 { 63 } else { 0 });
+        }
+        audio.write_register(0x4089, 0x00);
+        audio.write_register(0x4080, 0x80 | 32);
+        audio.write_register(0x4082, frequency as u8);
+        audio.write_register(0x4083, (frequency >> 8) as u8);
+        audio
+    }
+
+    // Count the rising edges of the wave over a number of CPU cycles
+    fn count_cycles(audio: &mut FdsAudio, cpu_cycles: u32) -> u32 {
+        let mut edges: u32 = 0;
+        let mut previous: u8 = audio.output;
+        for _ in 0..cpu_cycles {
+            audio.clock_cpu();
+            if audio.output > previous {
+                edges += 1;
+            }
+            previous = audio.output;
+        }
+        edges
+    }
+
+    #[test]
+    fn test_wave_frequency() {
+        // Prep for the test, a period of 2^22 / 1024 = 4096 CPU cycles
+        let mut audio: FdsAudio = get_playing_audio(1024);
+
+        // Assert results
+        assert_eq!(audio.output, 0);
+        audio.clock_cpu();
+        assert_eq!(audio.output, 63);
+        assert_eq!(count_cycles(&mut audio, 4096 * 10), 10);
+    }
+
+    #[test]
+    fn test_master_volume_and_halt() {
+        // Prep for the test
+        let mut audio: FdsAudio = get_playing_audio(1024);
+        audio.write_register(0x4089, 0x03);
+        audio.clock_cpu();
+
+        // Assert results, 2/5 volume
+        assert_eq!(audio.output, 24);
+        audio.write_register(0x4083, 0x84);
+        audio.clock_cpu();
+        assert_eq!(audio.wave_position, 0);
+        assert_eq!(count_cycles(&mut audio, 10000), 0);
+    }
+
+    #[test]
+    fn test_wave_table_write_protect() {
+        // Prep for the test
+        let mut audio: FdsAudio = FdsAudio::new();
+        audio.write_register(0x4040, 0x3F);
+
+        // Assert results, the table only changes while enabled, and keeps 6 bits
+        assert_eq!(audio.read_register(0x4040), 0x40);
+        audio.write_register(0x4089, 0x80);
+        audio.write_register(0x4040, 0xFF);
+        assert_eq!(audio.read_register(0x4040), 0x7F);
+    }
+
+    #[test]
+    fn test_volume_envelope() {
+        // Prep for the test, an increasing envelope at speed 0 ticks every 8 * $E8 CPU cycles
+        let mut audio: FdsAudio = get_playing_audio(1024);
+        audio.write_register(0x4080, 0x40);
+        assert_eq!(audio.read_register(0x4090), 0x40 | 32);
+        audio.write_register(0x4080, 0x80);
+        audio.write_register(0x4080, 0x40);
+        for _ in 0..8 * 0xE8 * 3 {
+            audio.clock_cpu();
+        }
+
+        // Assert results
+        assert_eq!(audio.read_register(0x4090), 0x40 | 3);
+    }
+
+    #[test]
+    fn test_modulation_raises_pitch() {
+        // Prep for the test, a modulator that holds the counter at its highest value
+        let mut audio: FdsAudio = get_playing_audio(1024);
+        audio.write_register(0x4087, 0x80);
+        for _ in 0..32 {
+            audio.write_register(0x4088, 0);
+        }
+        audio.write_register(0x4085, 63);
+        audio.write_register(0x4084, 0x80 | 32);
+        audio.write_register(0x4086, 0xFF);
+        audio.write_register(0x4087, 0x0F);
+
+        // Assert results, the wave runs faster than its unmodulated 10 cycles
+        let edges: u32 = count_cycles(&mut audio, 4096 * 10);
+        assert!(edges > 10, "{} cycles", edges);
+    }
+
+    #[test]
+    fn test_mod_counter_wraps() {
+        // Prep for the test, a step of +4 from 62
+        let mut audio: FdsAudio = FdsAudio::new();
+        audio.write_register(0x4087, 0x80);
+        audio.write_register(0x4088, 3);
+        audio.write_register(0x4085, 62);
+        audio.mod_position = 0;
+        audio.write_register(0x4086, 0xFF);
+        audio.write_register(0x4087, 0x0F);
+        while !audio.clock_modulator() {}
+
+        // Assert results
+        assert_eq!(audio.mod_counter, -62);
+    }
+}
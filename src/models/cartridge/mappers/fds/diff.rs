@@ -0,0 +1,134 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Differences between the disk sides as they were loaded and as they have been written
+//
+// The diff is the size of the disk data as a 4 byte big endian number, followed by records of a
+// 4 byte offset, a 2 byte length and the bytes that changed there. Disks never change size, so a
+// diff for a disk of another size belongs to another game.
+
+// The largest record, limited by its 16 bit length
+const MAX_RECORD_SIZE: usize = 0xFFFF;
+
+// Records are merged across runs of unchanged bytes shorter than a record header
+const MERGE_DISTANCE: usize = 6;
+
+// Build the diff that turns the original data into the modified data of the same size
+pub fn create(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut diff: Vec<u8> = (original.len() as u32).to_be_bytes().to_vec();
+    let is_changed = |index: usize| original[index] != modified[index];
+
+    let mut index: usize = 0;
+    while index < modified.len() {
+        if !is_changed(index) {
+            index += 1;
+            continue;
+        }
+
+        let start: usize = index;
+        let mut end: usize = index + 1;
+        while end < modified.len() && end - start < MAX_RECORD_SIZE {
+            // Keep going through short unchanged runs when there is another change after them
+            let next_change: Option<usize> = (end..modified.len().min(end + MERGE_DISTANCE + 1))
+                .find(|candidate| is_changed(*candidate));
+            match next_change {
+                Some(change) => end = (change + 1).min(start + MAX_RECORD_SIZE),
+                None => break,
+            }
+        }
+
+        diff.extend((start as u32).to_be_bytes());
+        diff.extend(((end - start) as u16).to_be_bytes());
+        diff.extend(&modified[start..end]);
+        index = end;
+    }
+    diff
+}
+
+// Apply a diff to a copy of the original data, or None when it does not fit the data
+pub fn apply(original: &[u8], diff: &[u8]) -> Option<Vec<u8>> {
+    let size: usize = read_number(diff, 0, 4)?;
+    if size != original.len() {
+        return None;
+    }
+
+    let mut patched: Vec<u8> = original.to_vec();
+    let mut position: usize = 4;
+    while position < diff.len() {
+        let offset: usize = read_number(diff, position, 4)?;
+        let length: usize = read_number(diff, position + 4, 2)?;
+        let bytes: &[u8] = diff.get(position + 6..position + 6 + length)?;
+        patched
+            .get_mut(offset..offset + length)?
+            .copy_from_slice(bytes);
+        position += 6 + length;
+    }
+    Some(patched)
+}
+
+// Read a big endian number of the given number of bytes
+fn read_number(diff: &[u8], position: usize, size: usize) -> Option<usize> {
+    let bytes: &[u8] = diff.get(position..position + size)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0, |number, byte| number << 8 | usize::from(*byte)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        // Prep for the test, changes close together and far apart
+        let original: Vec<u8> = (0..0x1000).map(|index| index as u8).collect();
+        let mut modified: Vec<u8> = original.clone();
+        modified[0x10] = 0xFF;
+        modified[0x13] = 0xFF;
+        modified[0x801] = 0x00;
+        let diff: Vec<u8> = create(&original, &modified);
+
+        // Assert results, the nearby changes share a record
+        assert_eq!(diff.len(), 4 + (6 + 4) + (6 + 1));
+        assert_eq!(apply(&original, &diff).unwrap(), modified);
+        assert_eq!(create(&original, &original), vec![0x00, 0x00, 0x10, 0x00]);
+    }
+
+    #[test]
+    fn test_invalid_diffs() {
+        // Prep for the test
+        let original: Vec<u8> = vec![0; 8];
+
+        // Assert results, the size must match and records must stay inside the data
+        assert_eq!(apply(&original, &[0, 0, 0, 9]), None);
+        assert_eq!(
+            apply(&original, &[0, 0, 0, 8, 0, 0, 0, 7, 0, 2, 1, 1]),
+            None
+        );
+        assert_eq!(apply(&original, &[0, 0, 0, 8, 0, 0, 0, 1, 0, 2, 1]), None);
+        assert_eq!(apply(&original, &[0, 0]), None);
+    }
+}
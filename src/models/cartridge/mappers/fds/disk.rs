@@ -0,0 +1,277 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Famicom Disk System disk images
+// https://www.nesdev.org/wiki/FDS_disk_format
+// https://www.nesdev.org/wiki/FDS_file_format
+//
+// Images hold only the blocks on each side. The drive reads the side as a stream of bits, where
+// each block follows a gap of zeros ended by a start mark and is itself followed by a CRC, so the
+// sides are rebuilt in that layout for the drive to read and write.
+
+use crate::models::cartridge::CartridgeError;
+
+// The size of each side in .fds images, which leave out the CRCs
+pub const FDS_SIDE_SIZE: usize = 65500;
+
+// The size of each side in .qd images, which keep the CRCs after each block
+pub const QD_SIDE_SIZE: usize = 0x10000;
+
+// .fds images may start with a header holding the number of sides
+const FDS_HEADER_SIZE: usize = 16;
+const FDS_HEADER_MAGIC: &[u8; 4] = b"FDS\x1A";
+
+// Every side starts with the disk info block, which holds this verification string
+const DISK_INFO_MAGIC: &[u8; 15] = b"\x01*NINTENDO-HVC*";
+
+// The gap before the first block is 28300 bits and the gap after each block is 976 bits
+const LEADING_GAP_SIZE: usize = 28300 / 8;
+const BLOCK_GAP_SIZE: usize = 976 / 8;
+
+// The rebuilt sides are padded to leave room for the gaps and CRCs around a full side of blocks
+pub const RAW_SIDE_SIZE: usize = 80000;
+
+// The bit that ends a gap, read as the byte $80
+pub const BLOCK_START_MARK: u8 = 0x80;
+
+// Block types, each of which has a fixed size apart from the file data
+const BLOCK_DISK_INFO: u8 = 1;
+const BLOCK_FILE_COUNT: u8 = 2;
+const BLOCK_FILE_HEADER: u8 = 3;
+const BLOCK_FILE_DATA: u8 = 4;
+
+pub struct DiskImage {
+    // Each side in the layout the drive reads
+    pub sides: Vec<Vec<u8>>,
+}
+
+impl DiskImage {
+    // Check if a file looks like a disk image, with or without the .fds header
+    pub fn is_disk_image(data: &[u8]) -> bool {
+        data.starts_with(FDS_HEADER_MAGIC) || data.starts_with(DISK_INFO_MAGIC)
+    }
+
+    // Read a .fds image, with or without its header, or a .qd image
+    pub fn parse(data: &[u8]) -> Result<DiskImage, CartridgeError> {
+        let (body, side_size, side_count): (&[u8], usize, usize) =
+            if data.starts_with(FDS_HEADER_MAGIC) {
+                let body: &[u8] = data.get(FDS_HEADER_SIZE..).unwrap_or_default();
+                (body, FDS_SIDE_SIZE, usize::from(data[4]))
+            } else if !data.starts_with(DISK_INFO_MAGIC) {
+                return Err(CartridgeError::InvalidHeader);
+            } else if data.len().is_multiple_of(FDS_SIDE_SIZE) {
+                (data, FDS_SIDE_SIZE, data.len() / FDS_SIDE_SIZE)
+            } else if data.len().is_multiple_of(QD_SIDE_SIZE) {
+                (data, QD_SIDE_SIZE, data.len() / QD_SIDE_SIZE)
+            } else {
+                return Err(CartridgeError::TruncatedData);
+            };
+
+        if side_count == 0 {
+            return Err(CartridgeError::InvalidHeader);
+        }
+        if body.len() < side_count * side_size {
+            return Err(CartridgeError::TruncatedData);
+        }
+
+        let has_crcs: bool = side_size == QD_SIDE_SIZE;
+        let sides: Vec<Vec<u8>> = body
+            .chunks_exact(side_size)
+            .take(side_count)
+            .map(|side| DiskImage::build_raw_side(side, has_crcs))
+            .collect();
+        Ok(DiskImage { sides })
+    }
+
+    // Lay a side's blocks out with the gaps, start marks and CRCs the drive expects
+    fn build_raw_side(side: &[u8], has_crcs: bool) -> Vec<u8> {
+        let mut raw: Vec<u8> = vec![0; LEADING_GAP_SIZE];
+        let mut offset: usize = 0;
+        let mut file_size: usize = 0;
+
+        // The blocks end at the first byte that is not a block type
+        while offset < side.len() {
+            let length: usize = match side[offset] {
+                BLOCK_DISK_INFO => 56,
+                BLOCK_FILE_COUNT => 2,
+                BLOCK_FILE_HEADER => 16,
+                BLOCK_FILE_DATA => 1 + file_size,
+                _ => break,
+            };
+            let block: &[u8] = match side.get(offset..offset + length) {
+                Some(block) => block,
+                None => break,
+            };
+
+            // The size of a file's data is in bytes 13 and 14 of its header
+            if block[0] == BLOCK_FILE_HEADER {
+                file_size = usize::from(block[13]) | usize::from(block[14]) << 8;
+            }
+
+            raw.push(BLOCK_START_MARK);
+            raw.extend(block);
+            raw.extend(get_block_crc(block).to_le_bytes());
+            raw.extend([0; BLOCK_GAP_SIZE]);
+
+            offset += length;
+            if has_crcs {
+                offset += 2;
+            }
+        }
+
+        raw.resize(raw.len().max(RAW_SIDE_SIZE), 0);
+        raw
+    }
+}
+
+// Step the drive's CRC with a byte, least significant bit first
+// The CRC is the CRC-16 with polynomial $8408, where the data bits are shifted into the top
+pub fn update_crc(crc: u16, value: u8) -> u16 {
+    let mut crc: u16 = crc;
+    for bit in 0..8 {
+        let carry: bool = crc & 1 == 1;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if value & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+// The CRC stored after a block, which covers the start mark and the block
+// Reading the block and then the CRC, least significant byte first, leaves the CRC at zero
+pub fn get_block_crc(block: &[u8]) -> u16 {
+    let mut crc: u16 = update_crc(0, BLOCK_START_MARK);
+    for value in block.iter().chain(&[0, 0]) {
+        crc = update_crc(crc, *value);
+    }
+    crc
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Helper function for the tests to build a side holding the disk info block and one file
+    pub fn get_test_side(file: &[u8]) -> Vec<u8> {
+        let mut side: Vec<u8> = DISK_INFO_MAGIC.to_vec();
+        side.resize(56, 0);
+        side.extend([BLOCK_FILE_COUNT, 1]);
+        let mut header: Vec<u8> = vec![BLOCK_FILE_HEADER; 16];
+        header[13] = file.len() as u8;
+        header[14] = (file.len() >> 8) as u8;
+        side.extend(header);
+        side.push(BLOCK_FILE_DATA);
+        side.extend(file);
+        side.resize(FDS_SIDE_SIZE, 0);
+        side
+    }
+
+    #[test]
+    fn test_raw_side_layout() {
+        // Prep for the test, an image with a header and two sides
+        let mut data: Vec<u8> = FDS_HEADER_MAGIC.to_vec();
+        data.push(2);
+        data.resize(FDS_HEADER_SIZE, 0);
+        data.extend(get_test_side(&[0x11, 0x22, 0x33]));
+        data.extend(get_test_side(&[0x44]));
+        let image: DiskImage = DiskImage::parse(&data).unwrap();
+
+        // Assert results, the gap ends with the start mark and each block is followed by its CRC
+        assert_eq!(image.sides.len(), 2);
+        let side: &[u8] = &image.sides[0];
+        assert_eq!(side.len(), RAW_SIDE_SIZE);
+        assert!(side[..LEADING_GAP_SIZE].iter().all(|value| *value == 0));
+        assert_eq!(side[LEADING_GAP_SIZE], BLOCK_START_MARK);
+        assert_eq!(&side[LEADING_GAP_SIZE + 1..][..15], DISK_INFO_MAGIC);
+        let second_block: usize = LEADING_GAP_SIZE + 1 + 56 + 2 + BLOCK_GAP_SIZE;
+        assert_eq!(side[second_block - 1], 0);
+        assert_eq!(side[second_block], BLOCK_START_MARK);
+        assert_eq!(side[second_block + 1], BLOCK_FILE_COUNT);
+        let data_block: usize =
+            second_block + (1 + 2 + 2 + BLOCK_GAP_SIZE) + (1 + 16 + 2 + BLOCK_GAP_SIZE);
+        assert_eq!(
+            &side[data_block..data_block + 5],
+            &[0x80, 4, 0x11, 0x22, 0x33]
+        );
+    }
+
+    #[test]
+    fn test_crc_residue_is_zero() {
+        // Prep for the test
+        let block: [u8; 4] = [BLOCK_FILE_DATA, 0x12, 0x34, 0x56];
+        let crc: u16 = get_block_crc(&block);
+
+        // Assert results, reading the mark, block and CRC leaves nothing in the CRC
+        let mut check: u16 = update_crc(0, BLOCK_START_MARK);
+        for value in block.iter().chain(&crc.to_le_bytes()) {
+            check = update_crc(check, *value);
+        }
+        assert_ne!(crc, 0);
+        assert_eq!(check, 0);
+    }
+
+    #[test]
+    fn test_headerless_and_qd_images() {
+        // Prep for the test, a .qd side keeps a CRC after each block
+        let fds: Vec<u8> = get_test_side(&[0x55]);
+        let mut qd: Vec<u8> = fds[..56].to_vec();
+        qd.extend([0, 0, BLOCK_FILE_COUNT, 1, 0, 0]);
+        qd.extend(&fds[58..74]);
+        qd.extend([0, 0, BLOCK_FILE_DATA, 0x55, 0, 0]);
+        qd.resize(QD_SIDE_SIZE, 0);
+
+        // Assert results, both give the same side
+        let from_fds: DiskImage = DiskImage::parse(&fds).unwrap();
+        let from_qd: DiskImage = DiskImage::parse(&qd).unwrap();
+        assert_eq!(from_fds.sides, from_qd.sides);
+        assert!(DiskImage::is_disk_image(&fds));
+        assert!(!DiskImage::is_disk_image(b"NES\x1A"));
+    }
+
+    #[test]
+    fn test_invalid_images() {
+        // Assert results
+        assert_eq!(
+            DiskImage::parse(b"NES\x1A").err(),
+            Some(CartridgeError::InvalidHeader)
+        );
+        let mut truncated: Vec<u8> = get_test_side(&[]);
+        truncated.pop();
+        assert_eq!(
+            DiskImage::parse(&truncated).err(),
+            Some(CartridgeError::TruncatedData)
+        );
+        let mut header: Vec<u8> = FDS_HEADER_MAGIC.to_vec();
+        header.push(1);
+        header.resize(FDS_HEADER_SIZE, 0);
+        assert_eq!(
+            DiskImage::parse(&header).err(),
+            Some(CartridgeError::TruncatedData)
+        );
+    }
+}
@@ -0,0 +1,377 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// The Famicom Disk System disk drive and the RAM adapter's serial transfer logic
+// https://www.nesdev.org/wiki/Family_Computer_Disk_System#Disk_drive
+//
+// The drive streams the side past the head one byte at a time while the motor runs. Reading
+// skips the gap until the start mark, then hands each byte to the CPU with a transfer flag and an
+// optional IRQ. The drive never reports CRC errors, since sides are rebuilt with correct CRCs and
+// are only changed by writes that store correct CRCs.
+
+use crate::models::cartridge::mappers::fds::disk::update_crc;
+
+// The head takes a while to return to the start of the side before data arrives again
+const HEAD_RETURN_CPU_CYCLES: u32 = 50000;
+
+// The drive transfers about 96.4 kbit/s, a byte every 150 or so CPU cycles
+const BYTE_CPU_CYCLES: u32 = 150;
+
+// The BIOS notices a disk change by seeing the drive empty, so swapped disks stay out this long
+const DISK_CHANGE_CPU_CYCLES: u32 = 1_789_773 / 2;
+
+pub struct DiskDrive {
+    sides: Vec<Vec<u8>>,
+    inserted_side: Option<usize>,
+    // A side waiting to be inserted once the drive has been seen empty
+    pending_side: Option<usize>,
+    disk_change_delay: u32,
+
+    // $4025 control bits
+    motor_on: bool,
+    transfer_reset: bool,
+    read_mode: bool,
+    crc_control: bool,
+    transfer_started: bool,
+    irq_enabled: bool,
+
+    position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    previous_crc_control: bool,
+    crc: u16,
+
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    irq_pending: bool,
+}
+
+impl DiskDrive {
+    // Start with the first side inserted, if there is one
+    pub fn new(sides: Vec<Vec<u8>>) -> DiskDrive {
+        DiskDrive {
+            inserted_side: if sides.is_empty() { None } else { Some(0) },
+            sides,
+            pending_side: None,
+            disk_change_delay: 0,
+            motor_on: false,
+            transfer_reset: false,
+            read_mode: true,
+            crc_control: false,
+            transfer_started: false,
+            irq_enabled: false,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            previous_crc_control: false,
+            crc: 0,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            irq_pending: false,
+        }
+    }
+
+    pub fn get_sides(&self) -> &[Vec<u8>] {
+        &self.sides
+    }
+
+    // Replace the contents of the sides, which must keep their sizes
+    pub fn set_sides(&mut self, sides: Vec<Vec<u8>>) {
+        self.sides = sides;
+    }
+
+    pub fn get_inserted_side(&self) -> Option<usize> {
+        self.inserted_side
+    }
+
+    pub fn eject(&mut self) {
+        self.inserted_side = None;
+        self.pending_side = None;
+    }
+
+    // Eject the current side and insert another once the BIOS has had time to see the drive empty
+    pub fn insert(&mut self, side: usize) {
+        if side >= self.sides.len() {
+            return;
+        }
+        self.eject();
+        self.pending_side = Some(side);
+        self.disk_change_delay = DISK_CHANGE_CPU_CYCLES;
+    }
+
+    // $4025 without the mirroring bit, in the form IS-B -RTD
+    pub fn write_control(&mut self, value: u8) {
+        self.motor_on = value & 0b0000_0001 == 0b0000_0001;
+        self.transfer_reset = value & 0b0000_0010 == 0b0000_0010;
+        self.read_mode = value & 0b0000_0100 == 0b0000_0100;
+        self.crc_control = value & 0b0001_0000 == 0b0001_0000;
+        self.transfer_started = value & 0b0100_0000 == 0b0100_0000;
+        self.irq_enabled = value & 0b1000_0000 == 0b1000_0000;
+        self.irq_pending = false;
+    }
+
+    // $4024 sets the next byte to write and acknowledges the transfer
+    pub fn write_data(&mut self, value: u8) {
+        self.write_data = value;
+        self.transfer_complete = false;
+        self.irq_pending = false;
+    }
+
+    // $4031 returns the last byte read and acknowledges the transfer
+    pub fn read_data(&mut self) -> u8 {
+        self.transfer_complete = false;
+        self.irq_pending = false;
+        self.read_data
+    }
+
+    // The drive's bits of $4030, the transfer flag in bit 1 and the end of the side in bit 6
+    // Reading $4030 acknowledges the transfer IRQ but leaves the flag set
+    pub fn read_status(&mut self) -> u8 {
+        self.irq_pending = false;
+        let mut value: u8 = 0;
+        if self.transfer_complete {
+            value |= 0b0000_0010;
+        }
+        if self.end_of_head {
+            value |= 0b0100_0000;
+        }
+        value
+    }
+
+    // $4032, with bits for no disk, not ready and write protected that are set when there is no disk
+    pub fn read_drive_status(&self) -> u8 {
+        let inserted: bool = self.inserted_side.is_some();
+        let mut value: u8 = 0b0100_0000;
+        if !inserted {
+            value |= 0b0000_0101;
+        }
+        if !inserted || !self.scanning {
+            value |= 0b0000_0010;
+        }
+        value
+    }
+
+    pub fn is_irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn complete_transfer(&mut self, raise_irq: bool) {
+        self.transfer_complete = true;
+        if raise_irq && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    pub fn clock_cpu(&mut self) {
+        if let Some(side) = self.pending_side {
+            self.disk_change_delay -= 1;
+            if self.disk_change_delay == 0 {
+                self.inserted_side = Some(side);
+                self.pending_side = None;
+            }
+        }
+
+        let side: usize = match self.inserted_side {
+            Some(side) if self.motor_on => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        if self.transfer_reset && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            // Start again from the beginning of the side
+            self.delay = HEAD_RETURN_CPU_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        if !self.transfer_started {
+            self.crc = 0;
+        }
+        if self.read_mode {
+            self.read_byte(side);
+        } else {
+            self.write_byte(side);
+        }
+        self.previous_crc_control = self.crc_control;
+
+        // The motor stops when the head reaches the end of the side
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_CPU_CYCLES;
+        }
+    }
+
+    fn read_byte(&mut self, side: usize) {
+        let value: u8 = self.sides[side][self.position];
+        self.crc = update_crc(self.crc, value);
+
+        if !self.transfer_started {
+            self.gap_ended = false;
+        } else if value != 0 && !self.gap_ended {
+            // The start mark ends the gap and is transferred without an IRQ
+            self.gap_ended = true;
+            self.read_data = value;
+            self.complete_transfer(false);
+        } else if self.gap_ended {
+            self.read_data = value;
+            self.complete_transfer(true);
+        }
+    }
+
+    fn write_byte(&mut self, side: usize) {
+        let mut value: u8 = self.write_data;
+        if !self.crc_control {
+            self.complete_transfer(true);
+        }
+
+        // Before the transfer starts the drive writes the gap
+        if !self.transfer_started {
+            value = 0;
+        }
+
+        if self.crc_control {
+            // The CRC is written least significant byte first, after flushing the last data bits
+            if !self.previous_crc_control {
+                self.crc = update_crc(update_crc(self.crc, 0), 0);
+            }
+            value = self.crc as u8;
+            self.crc >>= 8;
+        } else {
+            self.crc = update_crc(self.crc, value);
+        }
+
+        self.sides[side][self.position] = value;
+        self.gap_ended = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::mappers::fds::disk::{get_block_crc, BLOCK_START_MARK};
+
+    // Run the drive until a byte is transferred, returning whether one arrived in time
+    fn wait_for_transfer(drive: &mut DiskDrive) -> bool {
+        for _ in 0..HEAD_RETURN_CPU_CYCLES * 2 {
+            drive.clock_cpu();
+            if drive.transfer_complete {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_read_block() {
+        // Prep for the test, a gap followed by a block
+        let mut side: Vec<u8> = vec![0; 1000];
+        side[100..104].copy_from_slice(&[BLOCK_START_MARK, 0x02, 0x07, 0x00]);
+        let mut drive: DiskDrive = DiskDrive::new(vec![side]);
+        assert_eq!(drive.read_drive_status() & 0b0000_0111, 0b0000_0010);
+
+        // Start the motor in read mode and wait through the gap
+        drive.write_control(0b0100_0101);
+
+        // Assert results, the start mark comes first and then the block
+        assert!(wait_for_transfer(&mut drive));
+        assert_eq!(drive.read_data(), BLOCK_START_MARK);
+        assert_eq!(drive.read_drive_status() & 0b0000_0111, 0);
+        drive.write_control(0b1100_0101);
+        assert!(wait_for_transfer(&mut drive));
+        assert!(drive.is_irq_pending());
+        assert_eq!(drive.read_data(), 0x02);
+        assert!(!drive.is_irq_pending());
+        assert!(wait_for_transfer(&mut drive));
+        assert_eq!(drive.read_data(), 0x07);
+    }
+
+    #[test]
+    fn test_write_block_with_crc() {
+        // Prep for the test, write a gap, the start mark, one byte and the CRC
+        let mut drive: DiskDrive = DiskDrive::new(vec![vec![0xFF; 1000]]);
+        drive.write_control(0b0000_0001);
+        for _ in 0..HEAD_RETURN_CPU_CYCLES + 2 {
+            drive.clock_cpu();
+        }
+        drive.write_data(BLOCK_START_MARK);
+        drive.write_control(0b0100_0001);
+        assert!(wait_for_transfer(&mut drive));
+        drive.write_data(0x02);
+        assert!(wait_for_transfer(&mut drive));
+        drive.write_control(0b0101_0001);
+        for _ in 0..BYTE_CPU_CYCLES * 3 {
+            drive.clock_cpu();
+        }
+
+        // Assert results
+        let side: &[u8] = &drive.get_sides()[0];
+        let start: usize = side.iter().position(|value| *value != 0).unwrap();
+        assert!(start > 0);
+        let crc: [u8; 2] = get_block_crc(&[0x02]).to_le_bytes();
+        assert_eq!(
+            &side[start..start + 4],
+            &[BLOCK_START_MARK, 0x02, crc[0], crc[1]]
+        );
+    }
+
+    #[test]
+    fn test_disk_change() {
+        // Prep for the test
+        let mut drive: DiskDrive = DiskDrive::new(vec![vec![0; 100], vec![0; 100]]);
+        drive.insert(1);
+
+        // Assert results, the drive is empty for a while before the new side goes in
+        assert_eq!(drive.get_inserted_side(), None);
+        assert_eq!(drive.read_drive_status() & 0b0000_0001, 1);
+        for _ in 0..DISK_CHANGE_CPU_CYCLES {
+            drive.clock_cpu();
+        }
+        assert_eq!(drive.get_inserted_side(), Some(1));
+        drive.insert(5);
+        assert_eq!(drive.get_inserted_side(), Some(1));
+        drive.eject();
+        assert_eq!(drive.get_inserted_side(), None);
+    }
+}
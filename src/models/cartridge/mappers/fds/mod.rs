@@ -0,0 +1,381 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Famicom Disk System RAM adapter
+// https://www.nesdev.org/wiki/Family_Computer_Disk_System
+//
+// The RAM adapter plugs into the cartridge slot and holds 32KB of PRG-RAM, 8KB of CHR-RAM, the
+// BIOS, a timer IRQ, the interface to the disk drive and the expansion audio. Games are loaded
+// from disk into the RAM by the BIOS, which has to be supplied by the user.
+//
+// Writes to the disk are saved as a diff against the sides as they were loaded, so the
+// original image is never changed.
+
+pub mod audio;
+pub mod diff;
+pub mod disk;
+pub mod drive;
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::models::cartridge::mappers::fds::audio::FdsAudio;
+use crate::models::cartridge::mappers::fds::disk::DiskImage;
+use crate::models::cartridge::mappers::fds::drive::DiskDrive;
+use crate::models::cartridge::mappers::Mapper;
+use crate::models::cartridge::{CartridgeError, Mirroring};
+
+// The BIOS is an 8KB ROM at $E000
+pub const BIOS_SIZE: usize = 0x2000;
+
+const PRG_RAM_SIZE: usize = 0x8000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    mirroring: Mirroring,
+
+    // $4023 enables the disk and sound registers
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq_pending: bool,
+    irq_line: InterruptLine,
+
+    drive: DiskDrive,
+    // The sides as they were loaded, which saves are diffs against
+    original_sides: Vec<Vec<u8>>,
+
+    audio: FdsAudio,
+}
+
+impl Fds {
+    pub fn new(bios: Vec<u8>, disk: DiskImage) -> Result<Fds, CartridgeError> {
+        if bios.len() != BIOS_SIZE {
+            return Err(CartridgeError::InvalidBios);
+        }
+
+        Ok(Fds {
+            bios,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_ram: vec![0; CHR_RAM_SIZE],
+            mirroring: Mirroring::Vertical,
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq_pending: false,
+            irq_line: InterruptLine::new(),
+            original_sides: disk.sides.clone(),
+            drive: DiskDrive::new(disk.sides),
+            audio: FdsAudio::new(),
+        })
+    }
+
+    // The timer and the disk transfer share the IRQ line
+    fn update_irq_line(&mut self) {
+        let asserted: bool = self.timer_irq_pending || self.drive.is_irq_pending();
+        self.irq_line.set(InterruptSource::Mapper, asserted);
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled || !self.disk_registers_enabled {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq_pending = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        let value: u8 = match address {
+            // Reading the status acknowledges both IRQs
+            0x4030 if self.disk_registers_enabled => {
+                let mut value: u8 = self.drive.read_status();
+                if self.timer_irq_pending {
+                    value |= 0b0000_0001;
+                }
+                self.timer_irq_pending = false;
+                value
+            }
+            0x4031 if self.disk_registers_enabled => self.drive.read_data(),
+            0x4032 if self.disk_registers_enabled => self.drive.read_drive_status(),
+            // Bit 7 of the expansion port reads as a good battery
+            0x4033 if self.disk_registers_enabled => 0b1000_0000,
+            0x4040..=0x407F | 0x4090 | 0x4092 if self.sound_registers_enabled => {
+                self.audio.read_register(address)
+            }
+            0x6000..=0xDFFF => self.prg_ram[usize::from(address - 0x6000)],
+            0xE000..=0xFFFF => self.bios[usize::from(address - 0xE000)],
+            _ => 0,
+        };
+        self.update_irq_line();
+        value
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | u16::from(value),
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | u16::from(value) << 8,
+            0x4022 => {
+                self.timer_repeat = value & 0b0000_0001 == 0b0000_0001;
+                self.timer_enabled =
+                    value & 0b0000_0010 == 0b0000_0010 && self.disk_registers_enabled;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq_pending = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = value & 0b0000_0001 == 0b0000_0001;
+                self.sound_registers_enabled = value & 0b0000_0010 == 0b0000_0010;
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq_pending = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => self.drive.write_data(value),
+            0x4025 if self.disk_registers_enabled => {
+                self.mirroring = if value & 0b0000_1000 == 0b0000_1000 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.drive.write_control(value);
+            }
+            0x4040..=0x408A if self.sound_registers_enabled => {
+                self.audio.write_register(address, value);
+            }
+            0x6000..=0xDFFF => self.prg_ram[usize::from(address - 0x6000)] = value,
+            _ => {}
+        }
+        self.update_irq_line();
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr_ram[usize::from(address & 0x1FFF)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr_ram[usize::from(address & 0x1FFF)] = value;
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self) {
+        self.clock_timer();
+        self.drive.clock_cpu();
+        self.audio.clock_cpu();
+        self.update_irq_line();
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq_line = line;
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        Some(diff::create(
+            &self.original_sides.concat(),
+            &self.drive.get_sides().concat(),
+        ))
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        // A diff that does not fit the disk belongs to another game and is ignored
+        let original: Vec<u8> = self.original_sides.concat();
+        let patched: Vec<u8> = match diff::apply(&original, data) {
+            Some(patched) => patched,
+            None => return,
+        };
+
+        let mut sides: Vec<Vec<u8>> = Vec::new();
+        let mut offset: usize = 0;
+        for side in &self.original_sides {
+            sides.push(patched[offset..offset + side.len()].to_vec());
+            offset += side.len();
+        }
+        self.drive.set_sides(sides);
+    }
+
+    fn get_disk_side_count(&self) -> usize {
+        self.original_sides.len()
+    }
+
+    fn get_inserted_disk_side(&self) -> Option<usize> {
+        self.drive.get_inserted_side()
+    }
+
+    fn insert_disk_side(&mut self, side: usize) {
+        self.drive.insert(side);
+    }
+
+    fn eject_disk(&mut self) {
+        self.drive.eject();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cartridge::mappers::fds::disk::tests::get_test_side;
+
+    // Helper function for the tests to build a two sided disk with a BIOS that only has a reset
+    // vector
+    pub fn get_test_fds() -> Fds {
+        let mut image: Vec<u8> = get_test_side(&[0x11, 0x22]);
+        image.extend(get_test_side(&[0x33]));
+        let mut bios: Vec<u8> = vec![0; BIOS_SIZE];
+        bios[0x1FFC] = 0x24;
+        Fds::new(bios, DiskImage::parse(&image).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_memory_map() {
+        // Prep for the test
+        let mut mapper: Fds = get_test_fds();
+        mapper.cpu_write(0x6000, 0x12);
+        mapper.cpu_write(0xDFFF, 0x34);
+        mapper.cpu_write(0xFFFC, 0x56);
+        mapper.ppu_write(0x1FFF, 0x78);
+
+        // Assert results, the BIOS is not writable
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+        assert_eq!(mapper.cpu_read(0xDFFF), 0x34);
+        assert_eq!(mapper.cpu_read(0xFFFC), 0x24);
+        assert_eq!(mapper.ppu_read(0x1FFF), 0x78);
+        assert_eq!(
+            Fds::new(
+                vec![0; 0x1000],
+                DiskImage::parse(&get_test_side(&[])).unwrap()
+            )
+            .err(),
+            Some(CartridgeError::InvalidBios)
+        );
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        // Prep for the test, a repeating timer of 10 CPU cycles
+        let line: InterruptLine = InterruptLine::new();
+        let mut mapper: Fds = get_test_fds();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0x4023, 0x01);
+        mapper.cpu_write(0x4020, 10);
+        mapper.cpu_write(0x4021, 0);
+        mapper.cpu_write(0x4022, 0b0000_0011);
+
+        // Assert results, it fires after counting down through zero and reading $4030 acknowledges it
+        for _ in 0..10 {
+            mapper.clock_cpu();
+        }
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+        assert_eq!(mapper.cpu_read(0x4030) & 0b0000_0001, 1);
+        assert!(!line.is_asserted());
+        for _ in 0..11 {
+            mapper.clock_cpu();
+        }
+        assert!(line.is_asserted());
+
+        // Disabling the disk registers stops the timer
+        mapper.cpu_write(0x4023, 0x00);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_mirroring_and_register_enable() {
+        // Prep for the test
+        let mut mapper: Fds = get_test_fds();
+        mapper.cpu_write(0x4025, 0b0000_1000);
+
+        // Assert results, $4025 is ignored until the disk registers are enabled
+        assert_eq!(mapper.get_mirroring(), Mirroring::Vertical);
+        mapper.cpu_write(0x4023, 0x03);
+        mapper.cpu_write(0x4025, 0b0000_1000);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+        assert_eq!(mapper.cpu_read(0x4032) & 0b0000_0001, 0);
+        assert_eq!(mapper.cpu_read(0x4033), 0b1000_0000);
+        mapper.cpu_write(0x4089, 0x80);
+        mapper.cpu_write(0x4040, 0x15);
+        assert_eq!(mapper.cpu_read(0x4040) & 0x3F, 0x15);
+    }
+
+    #[test]
+    fn test_disk_api() {
+        // Prep for the test
+        let mut mapper: Fds = get_test_fds();
+
+        // Assert results, flipping to side B goes through an empty drive
+        assert_eq!(mapper.get_disk_side_count(), 2);
+        assert_eq!(mapper.get_inserted_disk_side(), Some(0));
+        mapper.insert_disk_side(1);
+        assert_eq!(mapper.get_inserted_disk_side(), None);
+        while mapper.get_inserted_disk_side().is_none() {
+            mapper.clock_cpu();
+        }
+        assert_eq!(mapper.get_inserted_disk_side(), Some(1));
+        mapper.eject_disk();
+        assert_eq!(mapper.get_inserted_disk_side(), None);
+    }
+
+    #[test]
+    fn test_disk_writes_are_saved_as_a_diff() {
+        // Prep for the test, change a byte on the second side
+        let mut mapper: Fds = get_test_fds();
+        assert_eq!(mapper.get_save_data().unwrap().len(), 4);
+        let mut sides: Vec<Vec<u8>> = mapper.drive.get_sides().to_vec();
+        sides[1][100] = 0x99;
+        mapper.drive.set_sides(sides);
+        let save: Vec<u8> = mapper.get_save_data().unwrap();
+
+        // Assert results
+        let mut restored: Fds = get_test_fds();
+        restored.load_save_data(&save);
+        assert_eq!(restored.drive.get_sides()[1][100], 0x99);
+        restored.load_save_data(b"not a diff");
+        assert_eq!(restored.drive.get_sides()[1][100], 0x99);
+    }
+}
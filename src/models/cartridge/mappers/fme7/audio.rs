@@ -0,0 +1,316 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Sunsoft 5B expansion audio, a licensed copy of the YM2149F (an AY-3-8910 variant) with three
+// square channels, a noise generator and an envelope generator
+// https://www.nesdev.org/wiki/Sunsoft_5B_audio
+
+use crate::models::audio::mixer::PULSE_FULL_VOLUME;
+
+// The chip runs at half of the CPU clock and its counters step every 8 of its own clocks
+const TONE_CPU_CYCLES: u32 = 16;
+
+// The envelope has 32 steps, and each step of the volume registers is two envelope steps
+const ENVELOPE_STEPS: u8 = 32;
+// The volume levels are logarithmic, 1.5dB apart
+const LEVEL_STEP_DB: f32 = 1.5;
+
+// A channel at full volume is treated as being as loud as a console pulse channel
+const OUTPUT_SCALE: f32 = PULSE_FULL_VOLUME;
+
+pub struct Sunsoft5bAudio {
+    registers: [u8; 16],
+    selected_register: u8,
+
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+
+    noise_counter: u8,
+    // 17 bit linear feedback shift register, with the output in bit 0
+    noise_shift_register: u32,
+    // The noise steps at half the rate of the tones
+    noise_divider: bool,
+
+    envelope_counter: u16,
+    envelope_step: u8,
+    envelope_rising: bool,
+    envelope_holding: bool,
+    envelope_level: u8,
+
+    cpu_cycles: u32,
+    levels: [f32; ENVELOPE_STEPS as usize],
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Sunsoft5bAudio {
+        let mut levels: [f32; ENVELOPE_STEPS as usize] = [0.0; ENVELOPE_STEPS as usize];
+        for (index, level) in levels.iter_mut().enumerate().skip(1) {
+            let attenuation: f32 = (ENVELOPE_STEPS as usize - 1 - index) as f32 * LEVEL_STEP_DB;
+            *level = 10.0_f32.powf(-attenuation / 20.0);
+        }
+
+        Sunsoft5bAudio {
+            registers: [0; 16],
+            selected_register: 0,
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_shift_register: 1,
+            noise_divider: false,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_rising: false,
+            envelope_holding: false,
+            envelope_level: ENVELOPE_STEPS - 1,
+            cpu_cycles: 0,
+            levels,
+        }
+    }
+
+    // $C000 selects a register
+    pub fn write_address(&mut self, value: u8) {
+        self.selected_register = value & 0x0F;
+    }
+
+    // $E000 writes the selected register
+    pub fn write_data(&mut self, value: u8) {
+        self.registers[usize::from(self.selected_register)] = value;
+
+        // Writing the envelope shape restarts the envelope
+        if self.selected_register == 0x0D {
+            self.envelope_step = 0;
+            self.envelope_counter = 0;
+            self.envelope_holding = false;
+            self.envelope_rising = value & 0b0100 == 0b0100;
+            self.update_envelope_level();
+        }
+    }
+
+    fn get_tone_period(&self, channel: usize) -> u16 {
+        let low: u16 = u16::from(self.registers[channel * 2]);
+        let high: u16 = u16::from(self.registers[channel * 2 + 1] & 0x0F);
+        ((high << 8) | low).max(1)
+    }
+
+    fn get_envelope_period(&self) -> u16 {
+        (u16::from(self.registers[0x0B]) | (u16::from(self.registers[0x0C]) << 8)).max(1)
+    }
+
+    fn update_envelope_level(&mut self) {
+        self.envelope_level = if self.envelope_rising {
+            self.envelope_step
+        } else {
+            ENVELOPE_STEPS - 1 - self.envelope_step
+        };
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < ENVELOPE_STEPS - 1 {
+            self.envelope_step += 1;
+            self.update_envelope_level();
+            return;
+        }
+
+        // At the end of a cycle the shape register decides what happens next, in the form CAtH
+        let shape: u8 = self.registers[0x0D];
+        let continues: bool = shape & 0b1000 == 0b1000;
+        let alternate: bool = shape & 0b0010 == 0b0010;
+        let hold: bool = shape & 0b0001 == 0b0001;
+        if !continues {
+            self.envelope_holding = true;
+            self.envelope_level = 0;
+        } else if hold {
+            self.envelope_holding = true;
+            if alternate {
+                self.envelope_level = ENVELOPE_STEPS - 1 - self.envelope_level;
+            }
+        } else {
+            if alternate {
+                self.envelope_rising = !self.envelope_rising;
+            }
+            self.envelope_step = 0;
+            self.update_envelope_level();
+        }
+    }
+
+    pub fn clock_cpu(&mut self) {
+        self.cpu_cycles += 1;
+        if self.cpu_cycles < TONE_CPU_CYCLES {
+            return;
+        }
+        self.cpu_cycles = 0;
+
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.get_tone_period(channel) {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        self.noise_divider = !self.noise_divider;
+        if self.noise_divider {
+            self.noise_counter += 1;
+            if self.noise_counter >= (self.registers[0x06] & 0x1F).max(1) {
+                self.noise_counter = 0;
+                let feedback: u32 =
+                    (self.noise_shift_register ^ (self.noise_shift_register >> 3)) & 1;
+                self.noise_shift_register = (self.noise_shift_register >> 1) | (feedback << 16);
+            }
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.get_envelope_period() {
+            self.envelope_counter = 0;
+            self.clock_envelope();
+        }
+    }
+
+    // The level of a channel, from 0 to 31
+    fn get_channel_level(&self, channel: usize) -> u8 {
+        // The mixer register disables the tone and noise of each channel, in the form --CBAcba
+        let mixer: u8 = self.registers[0x07];
+        let tone: bool = self.tone_outputs[channel] || mixer & (1 << channel) != 0;
+        let noise: bool = self.noise_shift_register & 1 == 1 || mixer & (0b1000 << channel) != 0;
+        if !(tone && noise) {
+            return 0;
+        }
+
+        let volume: u8 = self.registers[0x08 + channel];
+        if volume & 0b1_0000 == 0b1_0000 {
+            self.envelope_level
+        } else if volume & 0x0F == 0 {
+            0
+        } else {
+            (volume & 0x0F) * 2 + 1
+        }
+    }
+
+    pub fn get_output(&self) -> f32 {
+        let sum: f32 = (0..3)
+            .map(|channel| self.levels[usize::from(self.get_channel_level(channel))])
+            .sum();
+        sum * OUTPUT_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_register(audio: &mut Sunsoft5bAudio, register: u8, value: u8) {
+        audio.write_address(register);
+        audio.write_data(value);
+    }
+
+    #[test]
+    fn test_tone_period() {
+        // Prep for the test, channel A with a period of 2 and noise disabled
+        let mut audio: Sunsoft5bAudio = Sunsoft5bAudio::new();
+        write_register(&mut audio, 0x00, 0x02);
+        write_register(&mut audio, 0x07, 0b0011_1000);
+        write_register(&mut audio, 0x08, 0x0F);
+
+        // Assert results, the square toggles every 2 * 16 CPU cycles
+        let mut outputs: Vec<u8> = Vec::new();
+        for _ in 0..4 {
+            for _ in 0..2 * TONE_CPU_CYCLES {
+                audio.clock_cpu();
+            }
+            outputs.push(audio.get_channel_level(0));
+        }
+        assert_eq!(outputs, [31, 0, 31, 0].to_vec());
+    }
+
+    #[test]
+    fn test_logarithmic_volume() {
+        // Prep for the test, channel A with its tone and noise disabled outputs its volume
+        let mut audio: Sunsoft5bAudio = Sunsoft5bAudio::new();
+        write_register(&mut audio, 0x07, 0b0011_1111);
+        write_register(&mut audio, 0x08, 0x0F);
+        let loud: f32 = audio.get_output();
+        write_register(&mut audio, 0x08, 0x0D);
+        let quiet: f32 = audio.get_output();
+
+        // Assert results, two volume steps are 6dB apart which halves the output
+        assert!((quiet / loud - 0.501).abs() < 0.001);
+        write_register(&mut audio, 0x08, 0x00);
+        assert_eq!(audio.get_output(), 0.0);
+    }
+
+    #[test]
+    fn test_envelope_shapes() {
+        // Prep for the test, an envelope period of 1 on channel A
+        let mut audio: Sunsoft5bAudio = Sunsoft5bAudio::new();
+        write_register(&mut audio, 0x07, 0b0011_1111);
+        write_register(&mut audio, 0x08, 0x10);
+        write_register(&mut audio, 0x0B, 0x01);
+
+        // Rising then holding at the top
+        write_register(&mut audio, 0x0D, 0b1101);
+        assert_eq!(audio.get_channel_level(0), 0);
+        for _ in 0..40 * TONE_CPU_CYCLES {
+            audio.clock_cpu();
+        }
+        assert_eq!(audio.get_channel_level(0), 31);
+
+        // Falling once then going silent
+        write_register(&mut audio, 0x0D, 0b0000);
+        assert_eq!(audio.get_channel_level(0), 31);
+        for _ in 0..40 * TONE_CPU_CYCLES {
+            audio.clock_cpu();
+        }
+        assert_eq!(audio.get_channel_level(0), 0);
+
+        // A triangle turns around at the top
+        write_register(&mut audio, 0x0D, 0b1110);
+        for _ in 0..(31 + 4) * TONE_CPU_CYCLES {
+            audio.clock_cpu();
+        }
+        assert_eq!(audio.get_channel_level(0), 28);
+    }
+
+    #[test]
+    fn test_noise() {
+        // Prep for the test, channel A outputting noise only
+        let mut audio: Sunsoft5bAudio = Sunsoft5bAudio::new();
+        write_register(&mut audio, 0x06, 0x01);
+        write_register(&mut audio, 0x07, 0b0011_0001);
+        write_register(&mut audio, 0x08, 0x0F);
+
+        // Assert results, the output switches between on and off randomly
+        let mut outputs: Vec<u8> = Vec::new();
+        for _ in 0..64 {
+            for _ in 0..2 * TONE_CPU_CYCLES {
+                audio.clock_cpu();
+            }
+            outputs.push(audio.get_channel_level(0));
+        }
+        assert!(outputs.contains(&0));
+        assert!(outputs.contains(&31));
+    }
+}
@@ -0,0 +1,293 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Sunsoft FME-7 and 5B (mapper 69)
+// https://www.nesdev.org/wiki/Sunsoft_FME-7
+
+pub mod audio;
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::models::cartridge::mappers::fme7::audio::Sunsoft5bAudio;
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, write_trainer, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+
+    // The command selected through $8000, whose parameter is written to $A000
+    command: u8,
+    chr_banks: [usize; 8],
+    // The 8KB banks at $8000, $A000 and $C000
+    prg_banks: [usize; 3],
+    // Command 8 maps ROM or RAM at $6000
+    low_prg_bank: usize,
+    low_prg_is_ram: bool,
+    low_prg_ram_enabled: bool,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_line: InterruptLine,
+
+    audio: Sunsoft5bAudio,
+}
+
+impl Fme7 {
+    pub fn new(cartridge: Cartridge) -> Fme7 {
+        Fme7 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            mirroring: Mirroring::Vertical,
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0, 0, 0],
+            low_prg_bank: 0,
+            low_prg_is_ram: false,
+            low_prg_ram_enabled: false,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_line: InterruptLine::new(),
+            audio: Sunsoft5bAudio::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[usize::from(self.command)] = usize::from(value),
+            0x8 => {
+                // ER-B BBBB
+                self.low_prg_bank = usize::from(value & 0x3F);
+                self.low_prg_is_ram = value & 0b0100_0000 == 0b0100_0000;
+                self.low_prg_ram_enabled = value & 0b1000_0000 == 0b1000_0000;
+            }
+            0x9..=0xB => {
+                self.prg_banks[usize::from(self.command - 0x9)] = usize::from(value & 0x3F)
+            }
+            0xC => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xD => {
+                // C--- ---T, and any write acknowledges the IRQ
+                self.irq_enabled = value & 0b0000_0001 == 0b0000_0001;
+                self.irq_counter_enabled = value & 0b1000_0000 == 0b1000_0000;
+                self.irq_line.set(InterruptSource::Mapper, false);
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | u16::from(value),
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (u16::from(value) << 8),
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.low_prg_is_ram && self.low_prg_ram_enabled => {
+                read_bank(&self.prg_ram, self.low_prg_bank, 0x2000, address)
+            }
+            // Disabled RAM is open bus
+            0x6000..=0x7FFF if self.low_prg_is_ram => 0,
+            0x6000..=0x7FFF => read_bank(&self.prg_rom, self.low_prg_bank, 0x2000, address),
+            0x8000..=0xDFFF => {
+                let slot: usize = usize::from((address - 0x8000) >> 13);
+                read_bank(&self.prg_rom, self.prg_banks[slot], 0x2000, address)
+            }
+            0xE000..=0xFFFF => {
+                let last: usize = (self.prg_rom.len() / 0x2000).max(1) - 1;
+                read_bank(&self.prg_rom, last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.low_prg_is_ram && self.low_prg_ram_enabled => {
+                write_bank(&mut self.prg_ram, self.low_prg_bank, 0x2000, address, value);
+            }
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.write_address(value),
+            0xE000..=0xFFFF => self.audio.write_data(value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+        read_bank(&self.chr, bank, 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+            write_bank(&mut self.chr, bank, 0x0400, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self) {
+        // The counter decrements every cycle and raises an IRQ when it wraps from $0000 to $FFFF
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_line.set(InterruptSource::Mapper, true);
+            }
+        }
+        self.audio.clock_cpu();
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq_line = line;
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        write_trainer(&mut self.prg_ram, 0, trainer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    fn write_command(mapper: &mut Fme7, command: u8, value: u8) {
+        mapper.cpu_write(0x8000, command);
+        mapper.cpu_write(0xA000, value);
+    }
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        // Prep for the test, 16 8KB PRG banks and 32 1KB CHR banks
+        let mut mapper: Fme7 = Fme7::new(get_test_cartridge(69, 0, 16, 0x2000, 32, 0x0400));
+        write_command(&mut mapper, 0x9, 3);
+        write_command(&mut mapper, 0xA, 4);
+        write_command(&mut mapper, 0xB, 5);
+        write_command(&mut mapper, 0x0, 20);
+        write_command(&mut mapper, 0x7, 27);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xA000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+        assert_eq!(mapper.ppu_read(0x0000), 20);
+        assert_eq!(mapper.ppu_read(0x1C00), 27);
+    }
+
+    #[test]
+    fn test_low_prg_bank() {
+        // Prep for the test, ROM bank 7 at $6000
+        let mut mapper: Fme7 = Fme7::new(get_test_cartridge(69, 0, 16, 0x2000, 8, 0x0400));
+        write_command(&mut mapper, 0x8, 7);
+        assert_eq!(mapper.cpu_read(0x6000), 7);
+
+        // Disabled RAM ignores writes
+        write_command(&mut mapper, 0x8, 0b0100_0000);
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+
+        // Assert results
+        write_command(&mut mapper, 0x8, 0b1100_0000);
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        // Prep for the test
+        let mut mapper: Fme7 = Fme7::new(get_test_cartridge(69, 0, 4, 0x2000, 8, 0x0400));
+
+        // Assert results
+        write_command(&mut mapper, 0xC, 1);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+        write_command(&mut mapper, 0xC, 3);
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        // Prep for the test, the counter wraps after 2 cycles
+        let mut mapper: Fme7 = Fme7::new(get_test_cartridge(69, 0, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        write_command(&mut mapper, 0xE, 0x01);
+        write_command(&mut mapper, 0xF, 0x00);
+        write_command(&mut mapper, 0xD, 0b1000_0001);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+
+        // Writing the control register acknowledges the IRQ
+        write_command(&mut mapper, 0xD, 0b1000_0000);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_audio_registers() {
+        // Prep for the test, channel A at full volume with its tone and noise disabled
+        let mut mapper: Fme7 = Fme7::new(get_test_cartridge(69, 0, 4, 0x2000, 8, 0x0400));
+        assert_eq!(mapper.get_audio_output(), 0.0);
+        mapper.cpu_write(0xC000, 0x07);
+        mapper.cpu_write(0xE000, 0b0011_1111);
+        mapper.cpu_write(0xC000, 0x08);
+        mapper.cpu_write(0xE000, 0x0F);
+
+        // Assert results
+        assert!(mapper.get_audio_output() > 0.0);
+    }
+}
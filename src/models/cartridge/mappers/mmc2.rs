@@ -0,0 +1,272 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// MMC2 (mapper 9) and MMC4 (mapper 10)
+// https://www.nesdev.org/wiki/MMC2
+// https://www.nesdev.org/wiki/MMC4
+//
+// Each pattern table has two CHR banks and a latch that picks between them. The latch is switched
+// when the PPU fetches tile $FD or $FE from that pattern table, and the switch only takes effect
+// after the fetch that triggered it, so the rest of that tile still comes from the old bank.
+
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+const MAPPER_MMC4: u16 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Latch {
+    Fd,
+    Fe,
+}
+
+pub struct Mmc2 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    mirroring: Mirroring,
+    // MMC4 switches 16KB of PRG instead of 8KB, and watches a wider range of addresses for latch 0
+    is_mmc4: bool,
+
+    prg_bank: usize,
+    // The 4KB CHR banks for each pattern table, selected by the latch
+    chr_banks: [[usize; 2]; 2],
+    latches: [Latch; 2],
+}
+
+impl Mmc2 {
+    pub fn new(cartridge: Cartridge) -> Mmc2 {
+        Mmc2 {
+            chr: cartridge.get_chr_memory(),
+            is_mmc4: cartridge.mapper_number == MAPPER_MMC4,
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            mirroring: Mirroring::Vertical,
+            prg_bank: 0,
+            chr_banks: [[0, 0], [0, 0]],
+            latches: [Latch::Fe, Latch::Fe],
+        }
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let table: usize = usize::from((address >> 12) & 1);
+        match self.latches[table] {
+            Latch::Fd => self.chr_banks[table][0],
+            Latch::Fe => self.chr_banks[table][1],
+        }
+    }
+
+    // Switch a latch if the PPU has just fetched from one of the trigger addresses
+    fn update_latch(&mut self, address: u16) {
+        // MMC2 only watches the single address $0FD8 or $0FE8 for latch 0, otherwise the latch is
+        // switched by either bit plane of the last row of the tile, between $xFD8-$xFDF or $xFE8-$xFEF
+        let exact: bool = address & 0x1000 == 0 && !self.is_mmc4;
+        let row_mask: u16 = if exact { 0x1FFF } else { 0x1FF8 };
+        let table: usize = usize::from((address >> 12) & 1);
+
+        match address & row_mask & 0x0FFF {
+            0x0FD8 => self.latches[table] = Latch::Fd,
+            0x0FE8 => self.latches[table] = Latch::Fe,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        let bank_count: usize = (self.prg_rom.len() / 0x2000).max(1);
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                read_bank(&self.prg_ram, 0, 0x2000, address)
+            }
+            // MMC4 has a switchable 16KB bank at $8000 and the last 16KB fixed at $C000
+            0x8000..=0xBFFF if self.is_mmc4 => {
+                read_bank(&self.prg_rom, self.prg_bank, 0x4000, address)
+            }
+            0xC000..=0xFFFF if self.is_mmc4 => read_bank(
+                &self.prg_rom,
+                (bank_count / 2).saturating_sub(1),
+                0x4000,
+                address,
+            ),
+            // MMC2 has a switchable 8KB bank at $8000 and the last three 8KB banks fixed after it,
+            // with smaller ROMs mirrored so the last bank stays at $E000
+            0x8000..=0x9FFF => read_bank(&self.prg_rom, self.prg_bank, 0x2000, address),
+            0xA000..=0xFFFF => {
+                let bank: usize =
+                    bank_count.saturating_sub(4) + usize::from((address - 0x8000) >> 13);
+                read_bank(&self.prg_rom, bank, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+            }
+            0xA000..=0xAFFF => self.prg_bank = usize::from(value & 0x0F),
+            0xB000..=0xBFFF => self.chr_banks[0][0] = usize::from(value & 0x1F),
+            0xC000..=0xCFFF => self.chr_banks[0][1] = usize::from(value & 0x1F),
+            0xD000..=0xDFFF => self.chr_banks[1][0] = usize::from(value & 0x1F),
+            0xE000..=0xEFFF => self.chr_banks[1][1] = usize::from(value & 0x1F),
+            0xF000..=0xFFFF => {
+                self.mirroring = if value & 1 == 1 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let value: u8 = read_bank(&self.chr, self.get_chr_bank(address), 0x1000, address);
+        self.update_latch(address);
+        value
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) {}
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_mmc2_prg_banks() {
+        // Prep for the test, 16 8KB PRG banks
+        let mut mapper: Mmc2 = Mmc2::new(get_test_cartridge(9, 0, 16, 0x2000, 32, 0x1000));
+        mapper.cpu_write(0xA000, 5);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xA000), 13);
+        assert_eq!(mapper.cpu_read(0xC000), 14);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_mmc4_prg_banks() {
+        // Prep for the test, 8 16KB PRG banks
+        let mut mapper: Mmc2 = Mmc2::new(get_test_cartridge(10, 0, 8, 0x4000, 32, 0x1000));
+        mapper.cpu_write(0xA000, 5);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+    }
+
+    #[test]
+    fn test_small_prg_roms() {
+        // Prep for the test, 16KB of PRG for MMC2 and 8KB for MMC4
+        let mut mmc2: Mmc2 = Mmc2::new(get_test_cartridge(9, 0, 2, 0x2000, 32, 0x1000));
+        let mut mmc4: Mmc2 = Mmc2::new(get_test_cartridge(10, 0, 1, 0x2000, 32, 0x1000));
+
+        // Assert results, the fixed banks wrap around the ROM instead of underflowing
+        assert_eq!(mmc2.cpu_read(0xA000), 1);
+        assert_eq!(mmc2.cpu_read(0xC000), 0);
+        assert_eq!(mmc2.cpu_read(0xE000), 1);
+        assert_eq!(mmc4.cpu_read(0xC000), 0);
+    }
+
+    #[test]
+    fn test_latch_switches_after_fetch() {
+        // Prep for the test, banks 1 and 2 for the lower pattern table
+        let mut mapper: Mmc2 = Mmc2::new(get_test_cartridge(9, 0, 16, 0x2000, 32, 0x1000));
+        mapper.cpu_write(0xB000, 1);
+        mapper.cpu_write(0xC000, 2);
+        assert_eq!(mapper.ppu_read(0x0000), 2);
+
+        // The fetch of $0FD8 still comes from the $FE bank, and later fetches use the $FD bank
+        assert_eq!(mapper.ppu_read(0x0FD8), 2);
+        assert_eq!(mapper.ppu_read(0x0FD8), 1);
+        assert_eq!(mapper.ppu_read(0x0000), 1);
+
+        // Assert results, fetching $0FE8 switches back
+        assert_eq!(mapper.ppu_read(0x0FE8), 1);
+        assert_eq!(mapper.ppu_read(0x0000), 2);
+    }
+
+    #[test]
+    fn test_latch_trigger_ranges() {
+        // Prep for the test, banks 3 and 4 for the upper pattern table and 1 and 2 for the lower one
+        let mut mmc2: Mmc2 = Mmc2::new(get_test_cartridge(9, 0, 16, 0x2000, 32, 0x1000));
+        let mut mmc4: Mmc2 = Mmc2::new(get_test_cartridge(10, 0, 8, 0x4000, 32, 0x1000));
+        for mapper in [&mut mmc2, &mut mmc4] {
+            mapper.cpu_write(0xB000, 1);
+            mapper.cpu_write(0xC000, 2);
+            mapper.cpu_write(0xD000, 3);
+            mapper.cpu_write(0xE000, 4);
+        }
+
+        // The upper pattern table latch responds to the whole row on both chips
+        mmc2.ppu_read(0x1FDF);
+        assert_eq!(mmc2.ppu_read(0x1000), 3);
+
+        // Assert results, only MMC4 switches latch 0 on $0FD9 to $0FDF
+        mmc2.ppu_read(0x0FDB);
+        assert_eq!(mmc2.ppu_read(0x0000), 2);
+        mmc4.ppu_read(0x0FDB);
+        assert_eq!(mmc4.ppu_read(0x0000), 1);
+
+        // Other tiles don't affect the latch
+        mmc4.ppu_read(0x0FC8);
+        mmc4.ppu_read(0x0FF8);
+        assert_eq!(mmc4.ppu_read(0x0000), 1);
+    }
+
+    #[test]
+    fn test_mirroring_and_prg_ram() {
+        // Prep for the test, an MMC4 board with PRG-RAM
+        let mut cartridge: Cartridge = get_test_cartridge(10, 0, 8, 0x4000, 32, 0x1000);
+        cartridge.prg_ram_size = 0x2000;
+        let mut mapper: Mmc2 = Mmc2::new(cartridge);
+        mapper.cpu_write(0xF000, 1);
+        mapper.cpu_write(0x6000, 0x12);
+
+        // Assert results
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+    }
+}
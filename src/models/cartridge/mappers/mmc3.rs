@@ -0,0 +1,496 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// MMC3 (mapper 4)
+// https://www.nesdev.org/wiki/MMC3
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+// The iNES mapper number of MMC3 boards
+const MAPPER_MMC3: u16 = 4;
+
+// NES 2.0 submapper for boards with the MMC3A, which uses the old IRQ behaviour
+const SUBMAPPER_MMC3A: u8 = 4;
+
+// The number of M2 falling edges that PPU A12 must stay low for before a rising edge is counted
+// This filters out the rapid A12 toggling caused by sprite and background fetches within a scanline
+const A12_FILTER_CYCLES: u32 = 3;
+
+// The two known behaviours of the scanline counter when it reaches zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqRevision {
+    // Old behaviour (MMC3A), an IRQ is only raised when the counter is decremented to zero
+    // or is reloaded with zero after a write to $C001
+    Old,
+    // New behaviour (MMC3B and MMC3C), an IRQ is raised every time the counter is zero after
+    // being clocked, so a latch value of zero raises an IRQ on every scanline
+    New,
+}
+
+impl IrqRevision {
+    // Get a behaviour from its name on the command line
+    pub fn from_name(name: &str) -> Option<IrqRevision> {
+        match name {
+            "old" => Some(IrqRevision::Old),
+            "new" => Some(IrqRevision::New),
+            _ => None,
+        }
+    }
+}
+
+// Choose the scanline counter behaviour of an MMC3 board through the submapper that selects it,
+// for dumps whose header does not say which MMC3 the board has
+pub fn select_irq_revision(cartridge: &mut Cartridge, irq_revision: IrqRevision) {
+    if cartridge.mapper_number != MAPPER_MMC3 {
+        return;
+    }
+    match irq_revision {
+        IrqRevision::Old => cartridge.submapper_number = SUBMAPPER_MMC3A,
+        IrqRevision::New if cartridge.submapper_number == SUBMAPPER_MMC3A => {
+            cartridge.submapper_number = 0
+        }
+        IrqRevision::New => {}
+    }
+}
+
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    four_screen: bool,
+    mirroring: Mirroring,
+
+    // The bank select register at $8000
+    bank_select: u8,
+    // The bank registers R0 to R7, written through $8001
+    bank_registers: [usize; 8],
+
+    // The PRG-RAM protect register at $A001
+    prg_ram_enabled: bool,
+    prg_ram_write_protected: bool,
+
+    // Scanline counter state
+    irq_revision: IrqRevision,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_line: InterruptLine,
+
+    // PPU A12 edge detection
+    last_a12: bool,
+    a12_low_cycles: u32,
+}
+
+impl Mmc3 {
+    pub fn new(cartridge: Cartridge) -> Mmc3 {
+        let irq_revision: IrqRevision = if cartridge.submapper_number == SUBMAPPER_MMC3A {
+            IrqRevision::Old
+        } else {
+            IrqRevision::New
+        };
+
+        Mmc3 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            four_screen: cartridge.mirroring == Mirroring::FourScreen,
+            mirroring: cartridge.mirroring,
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_enabled: true,
+            prg_ram_write_protected: false,
+            irq_revision,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_line: InterruptLine::new(),
+            last_a12: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn get_prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / 0x2000).max(1)
+    }
+
+    // Get the 8KB PRG bank mapped at a CPU address between $8000 and $FFFF
+    fn get_prg_bank(&self, address: u16) -> usize {
+        let second_last: usize = self.get_prg_bank_count().saturating_sub(2);
+        let last: usize = self.get_prg_bank_count() - 1;
+        let prg_mode: bool = self.bank_select & 0b0100_0000 == 0b0100_0000;
+
+        match (address >> 13) & 0b11 {
+            0 if prg_mode => second_last,
+            0 => self.bank_registers[6],
+            1 => self.bank_registers[7],
+            2 if prg_mode => self.bank_registers[6],
+            2 => second_last,
+            _ => last,
+        }
+    }
+
+    // Get the 1KB CHR bank mapped at a PPU address between $0000 and $1FFF
+    fn get_chr_bank(&self, address: u16) -> usize {
+        // CHR A12 inversion swaps the 2KB banks and the 1KB banks between the two pattern tables
+        let mut address: u16 = address;
+        if self.bank_select & 0b1000_0000 == 0b1000_0000 {
+            address ^= 0x1000;
+        }
+
+        match (address >> 10) & 0b111 {
+            // R0 and R1 select 2KB banks and ignore their lowest bit
+            0 => self.bank_registers[0] & !1,
+            1 => self.bank_registers[0] | 1,
+            2 => self.bank_registers[1] & !1,
+            3 => self.bank_registers[1] | 1,
+            slot => self.bank_registers[usize::from(slot) - 2],
+        }
+    }
+
+    // Clock the scanline counter on a filtered rising edge of PPU A12
+    fn clock_irq_counter(&mut self) {
+        let previous_counter: u8 = self.irq_counter;
+        let reloaded: bool = self.irq_reload;
+
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        self.irq_reload = false;
+
+        let trigger: bool = match self.irq_revision {
+            IrqRevision::Old => self.irq_counter == 0 && (previous_counter != 0 || reloaded),
+            IrqRevision::New => self.irq_counter == 0,
+        };
+
+        if trigger && self.irq_enabled {
+            self.irq_line.set(InterruptSource::Mapper, true);
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xFFFF => {
+                read_bank(&self.prg_rom, self.get_prg_bank(address), 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        // Registers are selected by the address range and whether the address is even or odd
+        let odd: bool = address & 1 == 1;
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protected => {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+            }
+            0x8000..=0x9FFF if odd => {
+                let register: usize = usize::from(self.bank_select & 0b0000_0111);
+                self.bank_registers[register] = match register {
+                    // The PRG registers only have 6 bits
+                    6 | 7 => usize::from(value & 0b0011_1111),
+                    _ => usize::from(value),
+                };
+            }
+            0x8000..=0x9FFF => self.bank_select = value,
+            0xA000..=0xBFFF if odd => {
+                self.prg_ram_enabled = value & 0b1000_0000 == 0b1000_0000;
+                self.prg_ram_write_protected = value & 0b0100_0000 == 0b0100_0000;
+            }
+            // Boards wired for four screen mirroring ignore the mirroring register
+            0xA000..=0xBFFF if !self.four_screen => {
+                self.mirroring = if value & 1 == 1 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            0xC000..=0xDFFF if odd => {
+                // The counter is cleared and reloaded on the next clock
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xC000..=0xDFFF => self.irq_latch = value,
+            0xE000..=0xFFFF if odd => self.irq_enabled = true,
+            0xE000..=0xFFFF => {
+                // Disabling the IRQ also acknowledges any pending interrupt
+                self.irq_enabled = false;
+                self.irq_line.set(InterruptSource::Mapper, false);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, self.get_chr_bank(address), 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let bank: usize = self.get_chr_bank(address);
+            write_bank(&mut self.chr, bank, 0x0400, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn notify_ppu_address(&mut self, address: u16) {
+        let a12: bool = address & 0x1000 == 0x1000;
+
+        // Only rising edges that follow a long enough low period clock the counter
+        if a12 && !self.last_a12 && self.a12_low_cycles >= A12_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        if !a12 && self.last_a12 {
+            self.a12_low_cycles = 0;
+        }
+
+        self.last_a12 = a12;
+    }
+
+    fn clock_cpu(&mut self) {
+        if !self.last_a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq_line = line;
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    // Helper to produce one filtered scanline clock, A12 low for a while followed by a rising edge
+    fn clock_scanline(mapper: &mut Mmc3) {
+        mapper.notify_ppu_address(0x0000);
+        for _ in 0..A12_FILTER_CYCLES {
+            mapper.clock_cpu();
+        }
+        mapper.notify_ppu_address(0x1000);
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        // Prep for the test, sixteen 8KB PRG banks
+        let mut mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 0, 16, 0x2000, 8, 0x2000));
+
+        // Set R6 to 3 and R7 to 4
+        mapper.cpu_write(0x8000, 6);
+        mapper.cpu_write(0x8001, 3);
+        mapper.cpu_write(0x8000, 7);
+        mapper.cpu_write(0x8001, 4);
+
+        // PRG mode 0 has R6 at $8000 and the second last bank at $C000
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xA000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 14);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+
+        // PRG mode 1 swaps $8000 and $C000
+        mapper.cpu_write(0x8000, 0b0100_0000);
+        assert_eq!(mapper.cpu_read(0x8000), 14);
+        assert_eq!(mapper.cpu_read(0xA000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_chr_banks_and_inversion() {
+        // Prep for the test, 32 1KB CHR banks
+        let mut mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 0, 4, 0x2000, 32, 0x0400));
+
+        // Fill R0 to R5 with 2KB banks 10 and 12 and 1KB banks 20 to 23
+        let values: [u8; 6] = [10, 12, 20, 21, 22, 23];
+        for (register, value) in values.iter().enumerate() {
+            mapper.cpu_write(0x8000, register as u8);
+            mapper.cpu_write(0x8001, *value);
+        }
+
+        // Without inversion the 2KB banks are in the lower pattern table
+        assert_eq!(mapper.ppu_read(0x0000), 10);
+        assert_eq!(mapper.ppu_read(0x0400), 11);
+        assert_eq!(mapper.ppu_read(0x0800), 12);
+        assert_eq!(mapper.ppu_read(0x0C00), 13);
+        assert_eq!(mapper.ppu_read(0x1000), 20);
+        assert_eq!(mapper.ppu_read(0x1C00), 23);
+
+        // With inversion they move to the upper pattern table
+        mapper.cpu_write(0x8000, 0b1000_0000);
+        assert_eq!(mapper.ppu_read(0x0000), 20);
+        assert_eq!(mapper.ppu_read(0x0C00), 23);
+        assert_eq!(mapper.ppu_read(0x1000), 10);
+        assert_eq!(mapper.ppu_read(0x1C00), 13);
+    }
+
+    #[test]
+    fn test_mirroring_and_prg_ram_protect() {
+        // Prep for the test
+        let mut mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 0, 4, 0x2000, 8, 0x0400));
+
+        // Mirroring is selected by $A000
+        mapper.cpu_write(0xA000, 0);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Vertical);
+        mapper.cpu_write(0xA000, 1);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+
+        // PRG-RAM is writable while enabled
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+
+        // Write protected RAM keeps its contents
+        mapper.cpu_write(0xA001, 0b1100_0000);
+        mapper.cpu_write(0x6000, 0x34);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+
+        // Disabled RAM is not readable
+        mapper.cpu_write(0xA001, 0b0000_0000);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        // Prep for the test, connect an IRQ line
+        let mut mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 0, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+
+        // Fire after 3 scanlines
+        mapper.cpu_write(0xC000, 2);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        // The first clock reloads the counter, and two more bring it to zero
+        clock_scanline(&mut mapper);
+        clock_scanline(&mut mapper);
+        assert!(!line.is_asserted());
+        clock_scanline(&mut mapper);
+        assert!(line.is_asserted());
+
+        // Writing $E000 acknowledges the interrupt
+        mapper.cpu_write(0xE000, 0);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_a12_filter() {
+        // Prep for the test
+        let mut mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 0, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0xC000, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        // Rapid toggling without M2 cycles in between is ignored
+        for _ in 0..8 {
+            mapper.notify_ppu_address(0x0000);
+            mapper.notify_ppu_address(0x1000);
+        }
+        assert!(!line.is_asserted());
+
+        // A properly filtered edge clocks the counter
+        clock_scanline(&mut mapper);
+        assert!(line.is_asserted());
+    }
+
+    #[test]
+    fn test_irq_revisions_with_zero_latch() {
+        // Prep for the test, a latch of zero
+        let mut mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 0, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0xC000, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        // The new behaviour raises an IRQ on every clock
+        clock_scanline(&mut mapper);
+        assert!(line.is_asserted());
+        mapper.cpu_write(0xE000, 0);
+        mapper.cpu_write(0xE001, 0);
+        clock_scanline(&mut mapper);
+        assert!(line.is_asserted());
+
+        // The old behaviour only raises an IRQ after a reload requested through $C001
+        let mut cartridge: Cartridge = get_test_cartridge(4, 0, 4, 0x2000, 8, 0x0400);
+        select_irq_revision(&mut cartridge, IrqRevision::Old);
+        let mut mapper: Mmc3 = Mmc3::new(cartridge);
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0xC000, 0);
+        mapper.cpu_write(0xE001, 0);
+        clock_scanline(&mut mapper);
+        assert!(!line.is_asserted());
+        mapper.cpu_write(0xC001, 0);
+        clock_scanline(&mut mapper);
+        assert!(line.is_asserted());
+    }
+
+    #[test]
+    fn test_mmc3a_submapper_uses_old_behaviour() {
+        let mapper: Mmc3 = Mmc3::new(get_test_cartridge(4, 4, 4, 0x2000, 8, 0x0400));
+        assert_eq!(mapper.irq_revision, IrqRevision::Old);
+    }
+
+    #[test]
+    fn test_select_irq_revision() {
+        // Prep for the test
+        let mut cartridge: Cartridge = get_test_cartridge(4, 4, 4, 0x2000, 8, 0x0400);
+        let mut other: Cartridge = get_test_cartridge(1, 0, 4, 0x2000, 8, 0x0400);
+
+        // Assert results, only MMC3 boards are changed
+        select_irq_revision(&mut cartridge, IrqRevision::New);
+        assert_eq!(Mmc3::new(cartridge).irq_revision, IrqRevision::New);
+        select_irq_revision(&mut other, IrqRevision::Old);
+        assert_eq!(other.submapper_number, 0);
+        assert_eq!(IrqRevision::from_name("old"), Some(IrqRevision::Old));
+        assert_eq!(IrqRevision::from_name("sharp"), None);
+    }
+}
@@ -0,0 +1,197 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// MMC5 expansion audio, two pulse channels and a PCM channel
+// https://www.nesdev.org/wiki/MMC5_audio
+
+use crate::models::audio::pulse::Pulse;
+
+// The MMC5 has its own 240Hz frame sequencer which clocks the envelopes and length counters
+// This is the number of CPU cycles between clocks at the NTSC CPU rate
+const FRAME_PERIOD_CPU_CYCLES: u16 = 7457;
+
+#[derive(Default)]
+pub struct Mmc5Audio {
+    pulses: [Pulse; 2],
+    // Set when the PCM channel takes its samples from CPU reads of $8000-$BFFF
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq_pending: bool,
+    pcm_output: u8,
+    // Pulse timers are clocked on every second CPU cycle
+    odd_cycle: bool,
+    frame_divider: u16,
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Mmc5Audio {
+        Mmc5Audio::default()
+    }
+
+    // Write one of the audio registers between $5000 and $5015
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5003 => self.pulses[0].write_register((address & 0b11) as u8, value),
+            0x5004..=0x5007 => self.pulses[1].write_register((address & 0b11) as u8, value),
+            0x5010 => {
+                self.pcm_read_mode = value & 0b0000_0001 == 0b0000_0001;
+                self.pcm_irq_enabled = value & 0b1000_0000 == 0b1000_0000;
+            }
+            // Writing zero is ignored, as zero is reserved for the IRQ in read mode
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm_output = value,
+            0x5015 => {
+                self.pulses[0]
+                    .length_counter
+                    .set_enabled(value & 0b01 == 0b01);
+                self.pulses[1]
+                    .length_counter
+                    .set_enabled(value & 0b10 == 0b10);
+            }
+            _ => {}
+        }
+    }
+
+    // Read one of the readable audio registers, or None for open bus
+    pub fn read_register(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5010 => {
+                // Reading acknowledges the PCM IRQ
+                let value: u8 = if self.pcm_irq_pending { 0b1000_0000 } else { 0 }
+                    | u8::from(self.pcm_read_mode);
+                self.pcm_irq_pending = false;
+                Some(value)
+            }
+            0x5015 => Some(
+                u8::from(self.pulses[0].length_counter.is_active())
+                    | u8::from(self.pulses[1].length_counter.is_active()) << 1,
+            ),
+            _ => None,
+        }
+    }
+
+    // In read mode the PCM channel samples the data bus whenever the CPU reads $8000-$BFFF
+    pub fn notify_prg_read(&mut self, address: u16, value: u8) {
+        if self.pcm_read_mode && (0x8000..=0xBFFF).contains(&address) {
+            if value == 0 {
+                self.pcm_irq_pending = true;
+            } else {
+                self.pcm_output = value;
+            }
+        }
+    }
+
+    pub fn is_irq_asserted(&self) -> bool {
+        self.pcm_irq_pending && self.pcm_irq_enabled
+    }
+
+    pub fn clock_cpu(&mut self) {
+        if self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_timer();
+            }
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        // Envelopes and length counters are both clocked at the 240Hz rate
+        self.frame_divider += 1;
+        if self.frame_divider >= FRAME_PERIOD_CPU_CYCLES {
+            self.frame_divider = 0;
+            for pulse in self.pulses.iter_mut() {
+                pulse.envelope.clock();
+                pulse.length_counter.clock();
+            }
+        }
+    }
+
+    // The mixed output, using the same non-linear mixing as the console's own pulse channels
+    // The PCM channel is mixed at roughly the level of the console's DMC channel
+    pub fn get_output(&self) -> f32 {
+        let pulse_sum: f32 = f32::from(self.pulses[0].get_output() + self.pulses[1].get_output());
+        let pulse_out: f32 = if pulse_sum == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse_sum + 100.0)
+        };
+        let pcm_out: f32 = f32::from(self.pcm_output) * 0.00335 / 2.0;
+        pulse_out + pcm_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pulse_status() {
+        // Prep for the test
+        let mut audio: Mmc5Audio = Mmc5Audio::new();
+
+        // Enable pulse 2 and load its length counter
+        audio.write_register(0x5015, 0b10);
+        audio.write_register(0x5007, 0b0000_1000);
+        audio.write_register(0x5003, 0b0000_1000);
+
+        // Only pulse 2 reports an active length counter
+        assert_eq!(audio.read_register(0x5015), Some(0b10));
+    }
+
+    #[test]
+    fn test_pulse_output() {
+        // Prep for the test, pulse 1 at 75% duty and constant volume 15
+        let mut audio: Mmc5Audio = Mmc5Audio::new();
+        audio.write_register(0x5015, 0b01);
+        audio.write_register(0x5000, 0b1101_1111);
+        audio.write_register(0x5002, 0x10);
+        audio.write_register(0x5003, 0b0000_1000);
+
+        // The waveform starts high and produces output
+        assert!(audio.get_output() > 0.0);
+    }
+
+    #[test]
+    fn test_pcm_modes() {
+        // Prep for the test
+        let mut audio: Mmc5Audio = Mmc5Audio::new();
+
+        // Write mode takes samples from $5011
+        audio.write_register(0x5011, 0x80);
+        let write_level: f32 = audio.get_output();
+        assert!(write_level > 0.0);
+
+        // Read mode takes samples from reads of $8000-$BFFF
+        audio.write_register(0x5010, 0b1000_0001);
+        audio.notify_prg_read(0x8000, 0x40);
+        assert!(audio.get_output() < write_level);
+
+        // Reads outside that range are ignored
+        audio.notify_prg_read(0xC000, 0x00);
+        assert!(!audio.is_irq_asserted());
+
+        // Reading a zero raises the IRQ, which is acknowledged by reading $5010
+        audio.notify_prg_read(0x9000, 0x00);
+        assert!(audio.is_irq_asserted());
+        assert_eq!(audio.read_register(0x5010), Some(0b1000_0001));
+        assert!(!audio.is_irq_asserted());
+    }
+}
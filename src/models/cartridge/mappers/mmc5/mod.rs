@@ -0,0 +1,884 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// MMC5 (mapper 5)
+// https://www.nesdev.org/wiki/MMC5
+
+pub mod audio;
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::models::cartridge::mappers::mmc5::audio::Mmc5Audio;
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_trainer, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+// The number of CPU cycles without a PPU read after which the MMC5 decides rendering has stopped
+const IDLE_CPU_CYCLES: u8 = 3;
+
+// Positions of the PPU reads within a scanline, counted from the read that detected the scanline
+// The detection happens on the nametable fetch of the third tile, as the two dummy fetches at the
+// end of the previous scanline read the same address
+const SPRITE_FETCH_START: u16 = 128;
+const PREFETCH_START: u16 = 160;
+const PREFETCH_END: u16 = 168;
+
+// Each pair of bits in $5105 selects the source of one of the four logical nametables
+const NAMETABLE_CIRAM_LOWER: u8 = 0;
+const NAMETABLE_CIRAM_UPPER: u8 = 1;
+const NAMETABLE_EXRAM: u8 = 2;
+
+// ExRAM modes selected by $5104
+const EXRAM_MODE_EXTENDED_ATTRIBUTES: u8 = 1;
+const EXRAM_MODE_RAM: u8 = 2;
+
+// The two sets of CHR bank registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChrSet {
+    // $5120-$5127, used for sprites in 8x16 mode
+    A,
+    // $5128-$512B, used for backgrounds in 8x16 mode
+    B,
+}
+
+// What the PPU is fetching, as worked out by counting reads since the start of the scanline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Fetch {
+    // A background tile, numbered from the left of the scanline
+    // The last two tiles are fetched at the end of the previous scanline
+    Background { tile: u8, next_line: bool },
+    Sprite,
+    // Reads outside of rendering, or the dummy fetches at the end of a scanline
+    Other,
+}
+
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    exram: [u8; 0x400],
+
+    // Configuration registers
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+
+    // PRG bank registers $5113 to $5117
+    prg_registers: [u8; 5],
+
+    // CHR bank registers, with the upper bits from $5130 applied when they were written
+    chr_registers_a: [usize; 8],
+    chr_registers_b: [usize; 4],
+    chr_upper: u8,
+    last_chr_set: ChrSet,
+
+    // Vertical split registers
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    // Scanline IRQ
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    irq_line: InterruptLine,
+
+    // Scanline detection
+    in_frame: bool,
+    scanline: u8,
+    last_nametable_address: Option<u16>,
+    matching_reads: u8,
+    fetch_index: u16,
+    idle_cycles: u8,
+    fetch: Fetch,
+    // The ExRAM byte for the background tile being fetched in extended attribute mode
+    extended_attribute: u8,
+
+    // PPU registers watched by the MMC5
+    sprite_8x16: bool,
+
+    // Unsigned 8x8 to 16 bit multiplier
+    multiplicand: u8,
+    multiplier: u8,
+
+    audio: Mmc5Audio,
+}
+
+impl Mmc5 {
+    pub fn new(cartridge: Cartridge) -> Mmc5 {
+        Mmc5 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            exram: [0; 0x400],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_registers: [0, 0, 0, 0, 0xFF],
+            chr_registers_a: [0; 8],
+            chr_registers_b: [0; 4],
+            chr_upper: 0,
+            last_chr_set: ChrSet::A,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            irq_line: InterruptLine::new(),
+            in_frame: false,
+            scanline: 0,
+            last_nametable_address: None,
+            matching_reads: 0,
+            fetch_index: 0,
+            idle_cycles: 0,
+            fetch: Fetch::Other,
+            extended_attribute: 0,
+            sprite_8x16: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            audio: Mmc5Audio::new(),
+        }
+    }
+
+    fn update_irq(&mut self) {
+        let asserted: bool = (self.irq_pending && self.irq_enabled) || self.audio.is_irq_asserted();
+        self.irq_line.set(InterruptSource::Mapper, asserted);
+    }
+
+    // PRG-RAM can only be written after the two magic values are written to $5102 and $5103
+    fn is_prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    // Work out which 8KB bank is mapped at a CPU address, and whether it is RAM or ROM
+    fn get_prg_bank(&self, address: u16) -> (bool, usize) {
+        if address < 0x8000 {
+            return (true, usize::from(self.prg_registers[0] & 0b0111));
+        }
+
+        // Slot 0 to 3 for $8000, $A000, $C000 and $E000
+        let slot: u8 = ((address - 0x8000) >> 13) as u8;
+        let (register, bank): (usize, u8) = match (self.prg_mode, slot) {
+            (0, _) => (4, (self.prg_registers[4] & 0x7C) | slot),
+            (1, 0..=1) | (2, 0..=1) => (2, (self.prg_registers[2] & 0x7E) | (slot & 1)),
+            (1, _) => (4, (self.prg_registers[4] & 0x7E) | (slot & 1)),
+            (2, 2) => (3, self.prg_registers[3]),
+            (2, _) => (4, self.prg_registers[4]),
+            (_, slot) => (
+                usize::from(slot) + 1,
+                self.prg_registers[usize::from(slot) + 1],
+            ),
+        };
+
+        // Bit 7 selects ROM, and $E000-$FFFF is always ROM
+        let is_rom: bool = register == 4 || self.prg_registers[register] & 0x80 == 0x80;
+        if is_rom {
+            (false, usize::from(bank & 0x7F))
+        } else {
+            (true, usize::from(bank & 0b0111))
+        }
+    }
+
+    // Get the offset into CHR memory for a pattern table address using one of the register sets
+    fn get_chr_offset(&self, address: u16, set: ChrSet) -> usize {
+        let address: usize = usize::from(address) & 0x1FFF;
+        let a: &[usize; 8] = &self.chr_registers_a;
+        let b: &[usize; 4] = &self.chr_registers_b;
+        let offset: usize = match (set, self.chr_mode) {
+            (ChrSet::A, 0) => a[7] * 0x2000 + address,
+            (ChrSet::A, 1) => a[(address >> 12) * 4 + 3] * 0x1000 + (address & 0x0FFF),
+            (ChrSet::A, 2) => a[(address >> 11) * 2 + 1] * 0x0800 + (address & 0x07FF),
+            (ChrSet::A, _) => a[address >> 10] * 0x0400 + (address & 0x03FF),
+            // The B set only covers 4KB, and is repeated in both pattern tables
+            (ChrSet::B, 0) => b[3] * 0x2000 + address,
+            (ChrSet::B, 1) => b[3] * 0x1000 + (address & 0x0FFF),
+            (ChrSet::B, 2) => b[((address >> 11) & 1) * 2 + 1] * 0x0800 + (address & 0x07FF),
+            (ChrSet::B, _) => b[(address >> 10) & 0b11] * 0x0400 + (address & 0x03FF),
+        };
+        offset % self.chr.len()
+    }
+
+    // Choose the CHR register set for the current fetch
+    fn get_chr_set(&self) -> ChrSet {
+        if !self.sprite_8x16 {
+            return self.last_chr_set;
+        }
+        match self.fetch {
+            Fetch::Background { .. } => ChrSet::B,
+            Fetch::Sprite => ChrSet::A,
+            Fetch::Other => self.last_chr_set,
+        }
+    }
+
+    // Watch every PPU read to detect scanlines and work out what is being fetched
+    fn track_ppu_read(&mut self, address: u16) {
+        self.idle_cycles = 0;
+
+        // Three reads in a row from the same nametable address mark the start of a scanline
+        let is_nametable: bool = (0x2000..=0x2FFF).contains(&address);
+        if is_nametable && self.last_nametable_address == Some(address) {
+            self.matching_reads = self.matching_reads.saturating_add(1);
+        } else {
+            self.matching_reads = 0;
+        }
+        self.last_nametable_address = if is_nametable { Some(address) } else { None };
+
+        if self.matching_reads == 2 {
+            self.detect_scanline();
+            self.fetch_index = 0;
+        } else {
+            self.fetch_index = self.fetch_index.saturating_add(1);
+        }
+
+        self.fetch = if !self.in_frame {
+            Fetch::Other
+        } else if self.fetch_index < SPRITE_FETCH_START {
+            Fetch::Background {
+                tile: (self.fetch_index / 4 + 2) as u8,
+                next_line: false,
+            }
+        } else if self.fetch_index < PREFETCH_START {
+            Fetch::Sprite
+        } else if self.fetch_index < PREFETCH_END {
+            Fetch::Background {
+                tile: ((self.fetch_index - PREFETCH_START) / 4) as u8,
+                next_line: true,
+            }
+        } else {
+            Fetch::Other
+        };
+    }
+
+    fn detect_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_compare {
+                self.irq_pending = true;
+            }
+        } else {
+            // The first scanline of the frame
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        }
+        self.update_irq();
+    }
+
+    fn leave_frame(&mut self) {
+        self.in_frame = false;
+        self.last_nametable_address = None;
+        self.matching_reads = 0;
+        self.fetch = Fetch::Other;
+    }
+
+    // Check if a background tile falls within the vertical split region
+    fn is_in_split(&self, tile: u8) -> bool {
+        let enabled: bool = self.split_control & 0x80 == 0x80 && self.exram_mode < EXRAM_MODE_RAM;
+        let threshold: u8 = self.split_control & 0b0001_1111;
+        let right_side: bool = self.split_control & 0x40 == 0x40;
+        enabled && (right_side == (tile >= threshold))
+    }
+
+    // The vertical scroll position within the split region, which wraps at the bottom of the screen
+    fn get_split_y(&self, next_line: bool) -> usize {
+        let line: usize = usize::from(self.scanline) + usize::from(next_line);
+        (usize::from(self.split_scroll) + line) % 240
+    }
+
+    // Read from the nametables as they are arranged by $5105
+    fn read_mapped_nametable(&self, address: u16, vram: &[u8]) -> u8 {
+        let offset: usize = usize::from(address) & 0x03FF;
+        let slot: u16 = (address >> 10) & 0b11;
+        match (self.nametable_mapping >> (slot * 2)) & 0b11 {
+            NAMETABLE_CIRAM_LOWER => vram[offset % vram.len()],
+            NAMETABLE_CIRAM_UPPER => vram[(0x0400 + offset) % vram.len()],
+            NAMETABLE_EXRAM if self.exram_mode < EXRAM_MODE_RAM => self.exram[offset],
+            NAMETABLE_EXRAM => 0,
+            // Fill mode returns the same tile and attribute everywhere
+            _ if offset < 0x03C0 => self.fill_tile,
+            _ => (self.fill_attribute & 0b11) * 0b0101_0101,
+        }
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x5010 | 0x5015 => {
+                let value: Option<u8> = self.audio.read_register(address);
+                self.update_irq();
+                value.unwrap_or(0)
+            }
+            0x5204 => {
+                // Reading the status acknowledges the scanline IRQ
+                let value: u8 = if self.irq_pending { 0b1000_0000 } else { 0 }
+                    | if self.in_frame { 0b0100_0000 } else { 0 };
+                self.irq_pending = false;
+                self.update_irq();
+                value
+            }
+            0x5205 => (u16::from(self.multiplicand) * u16::from(self.multiplier)) as u8,
+            0x5206 => ((u16::from(self.multiplicand) * u16::from(self.multiplier)) >> 8) as u8,
+            // ExRAM is only readable by the CPU in the RAM modes
+            0x5C00..=0x5FFF if self.exram_mode >= EXRAM_MODE_RAM => {
+                self.exram[usize::from(address - 0x5C00)]
+            }
+            0x6000..=0xFFFF => {
+                let (is_ram, bank) = self.get_prg_bank(address);
+                let value: u8 = if is_ram {
+                    read_bank(&self.prg_ram, bank, 0x2000, address)
+                } else {
+                    read_bank(&self.prg_rom, bank, 0x2000, address)
+                };
+                self.audio.notify_prg_read(address, value);
+                self.update_irq();
+                value
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5015 => {
+                self.audio.write_register(address, value);
+                self.update_irq();
+            }
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 => self.prg_ram_protect[0] = value & 0b11,
+            0x5103 => self.prg_ram_protect[1] = value & 0b11,
+            0x5104 => self.exram_mode = value & 0b11,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0b11,
+            0x5113..=0x5117 => self.prg_registers[usize::from(address - 0x5113)] = value,
+            0x5120..=0x5127 => {
+                let bank: usize = usize::from(value) | usize::from(self.chr_upper) << 8;
+                self.chr_registers_a[usize::from(address - 0x5120)] = bank;
+                self.last_chr_set = ChrSet::A;
+            }
+            0x5128..=0x512B => {
+                let bank: usize = usize::from(value) | usize::from(self.chr_upper) << 8;
+                self.chr_registers_b[usize::from(address - 0x5128)] = bank;
+                self.last_chr_set = ChrSet::B;
+            }
+            0x5130 => self.chr_upper = value & 0b11,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_compare = value,
+            0x5204 => {
+                self.irq_enabled = value & 0b1000_0000 == 0b1000_0000;
+                self.update_irq();
+            }
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let offset: usize = usize::from(address - 0x5C00);
+                match self.exram_mode {
+                    // In the nametable modes the CPU can only write while the PPU is rendering
+                    0 | EXRAM_MODE_EXTENDED_ATTRIBUTES => {
+                        self.exram[offset] = if self.in_frame { value } else { 0 };
+                    }
+                    EXRAM_MODE_RAM => self.exram[offset] = value,
+                    _ => {}
+                }
+            }
+            0x6000..=0xFFFF => {
+                let (is_ram, bank) = self.get_prg_bank(address);
+                if is_ram && self.is_prg_ram_writable() {
+                    let offset: usize = bank * 0x2000 + usize::from(address & 0x1FFF);
+                    let length: usize = self.prg_ram.len();
+                    self.prg_ram[offset % length] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.track_ppu_read(address);
+
+        if let Fetch::Background { tile, next_line } = self.fetch {
+            if self.is_in_split(tile) {
+                // The split uses its own 4KB bank and replaces the fine vertical scroll
+                let fine_y: usize = self.get_split_y(next_line) & 0b111;
+                let offset: usize = usize::from(self.split_bank) * 0x1000
+                    + (usize::from(address) & 0x0FF8)
+                    + fine_y;
+                return self.chr[offset % self.chr.len()];
+            }
+            if self.exram_mode == EXRAM_MODE_EXTENDED_ATTRIBUTES {
+                // Each tile picks its own 4KB bank through ExRAM
+                let bank: usize = usize::from(self.extended_attribute & 0b0011_1111)
+                    | usize::from(self.chr_upper) << 6;
+                let offset: usize = bank * 0x1000 + (usize::from(address) & 0x0FFF);
+                return self.chr[offset % self.chr.len()];
+            }
+        }
+
+        self.chr[self.get_chr_offset(address, self.get_chr_set())]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let offset: usize = self.get_chr_offset(address, self.last_chr_set);
+            self.chr[offset] = value;
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        // The nametable mapping is more flexible than the standard arrangements, so this is the
+        // closest match for the common values
+        match self.nametable_mapping {
+            0x44 => Mirroring::Vertical,
+            0x50 => Mirroring::Horizontal,
+            0x00 => Mirroring::SingleScreenLower,
+            0x55 => Mirroring::SingleScreenUpper,
+            _ => Mirroring::FourScreen,
+        }
+    }
+
+    fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+        self.track_ppu_read(address);
+        let offset: usize = usize::from(address) & 0x03FF;
+
+        if let Fetch::Background { tile, next_line } = self.fetch {
+            if self.is_in_split(tile) {
+                // The split region takes its tiles and attributes from ExRAM
+                let y: usize = self.get_split_y(next_line);
+                let column: usize = usize::from(tile) & 0b1_1111;
+                if offset < 0x03C0 {
+                    return self.exram[(y / 8) * 32 + column];
+                }
+                let attribute: u8 = self.exram[0x03C0 + (y / 32) * 8 + column / 4];
+                let shift: usize = ((y / 16) & 1) * 4 + ((column / 2) & 1) * 2;
+                return ((attribute >> shift) & 0b11) * 0b0101_0101;
+            }
+            if self.exram_mode == EXRAM_MODE_EXTENDED_ATTRIBUTES {
+                if offset < 0x03C0 {
+                    // Latch the ExRAM byte for the tile, the fetch itself is unchanged
+                    self.extended_attribute = self.exram[offset];
+                } else {
+                    // The palette comes from the top two bits of the ExRAM byte
+                    return (self.extended_attribute >> 6) * 0b0101_0101;
+                }
+            }
+        }
+
+        self.read_mapped_nametable(address, vram)
+    }
+
+    fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+        let offset: usize = usize::from(address) & 0x03FF;
+        let slot: u16 = (address >> 10) & 0b11;
+        let length: usize = vram.len();
+        match (self.nametable_mapping >> (slot * 2)) & 0b11 {
+            NAMETABLE_CIRAM_LOWER => vram[offset % length] = value,
+            NAMETABLE_CIRAM_UPPER => vram[(0x0400 + offset) % length] = value,
+            NAMETABLE_EXRAM if self.exram_mode < EXRAM_MODE_RAM => self.exram[offset] = value,
+            _ => {}
+        }
+    }
+
+    fn notify_cpu_write(&mut self, address: u16, value: u8) {
+        // PPUCTRL and PPUMASK are mirrored every 8 bytes
+        match address & 0xE007 {
+            0x2000 => self.sprite_8x16 = value & 0b0010_0000 == 0b0010_0000,
+            // Turning rendering off ends the frame straight away
+            0x2001 if value & 0b0001_1000 == 0 => self.leave_frame(),
+            _ => {}
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.audio.clock_cpu();
+
+        // Rendering has stopped when the PPU has not read anything for a few cycles
+        if self.idle_cycles < IDLE_CPU_CYCLES {
+            self.idle_cycles += 1;
+            if self.idle_cycles == IDLE_CPU_CYCLES {
+                self.leave_frame();
+            }
+        }
+        self.update_irq();
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq_line = line;
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        let (_, bank) = self.get_prg_bank(0x7000);
+        write_trainer(&mut self.prg_ram, bank, trainer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    // The nametable address used for the third tile of every test scanline
+    const DETECT_ADDRESS: u16 = 0x2002;
+
+    // Helper to perform the two dummy fetches that precede the first scanline of a frame
+    fn start_frame(mapper: &mut Mmc5, vram: &[u8]) {
+        mapper.nametable_read(DETECT_ADDRESS, vram);
+        mapper.nametable_read(DETECT_ADDRESS, vram);
+    }
+
+    // Helper to perform the PPU reads for one rendered scanline in hardware order
+    // Returns the attribute and low pattern byte of each background tile as seen by the PPU
+    fn render_scanline(mapper: &mut Mmc5, vram: &[u8]) -> Vec<(u8, u8)> {
+        let mut tiles: Vec<(u8, u8)> = Vec::new();
+
+        // Tiles 2 to 33, the first fetch matches the dummy fetches and detects the scanline
+        for tile in 2..34u16 {
+            let name: u8 = mapper.nametable_read(0x2000 + tile, vram);
+            let attribute: u8 = mapper.nametable_read(0x23C0 + tile / 4, vram);
+            let low: u8 = mapper.ppu_read(u16::from(name) * 16);
+            mapper.ppu_read(u16::from(name) * 16 + 8);
+            tiles.push((attribute, low));
+        }
+
+        // Eight sprites, each with two garbage nametable fetches and two pattern fetches
+        for _ in 0..8 {
+            mapper.nametable_read(0x2000, vram);
+            mapper.nametable_read(0x2000, vram);
+            mapper.ppu_read(0x1000);
+            mapper.ppu_read(0x1008);
+        }
+
+        // Tiles 0 and 1 of the next scanline
+        for tile in 0..2u16 {
+            let name: u8 = mapper.nametable_read(0x2000 + tile, vram);
+            mapper.nametable_read(0x23C0, vram);
+            mapper.ppu_read(u16::from(name) * 16);
+            mapper.ppu_read(u16::from(name) * 16 + 8);
+        }
+
+        // The two dummy fetches
+        start_frame(mapper, vram);
+
+        tiles
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        // Prep for the test, sixteen 8KB banks
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 16, 0x2000, 1, 0x2000));
+
+        // Mode 3 gives four 8KB banks, bit 7 selects ROM
+        mapper.cpu_write(0x5114, 0x81);
+        mapper.cpu_write(0x5115, 0x82);
+        mapper.cpu_write(0x5116, 0x83);
+        mapper.cpu_write(0x5117, 0x04);
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+        assert_eq!(mapper.cpu_read(0xA000), 2);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+        assert_eq!(mapper.cpu_read(0xE000), 4);
+
+        // Mode 0 maps 32KB through $5117
+        mapper.cpu_write(0x5100, 0);
+        mapper.cpu_write(0x5117, 0x06);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xE000), 7);
+
+        // Mode 1 maps two 16KB banks
+        mapper.cpu_write(0x5100, 1);
+        mapper.cpu_write(0x5115, 0x8B);
+        assert_eq!(mapper.cpu_read(0x8000), 10);
+        assert_eq!(mapper.cpu_read(0xA000), 11);
+        assert_eq!(mapper.cpu_read(0xC000), 6);
+
+        // Mode 2 maps 16KB then two 8KB banks
+        mapper.cpu_write(0x5100, 2);
+        mapper.cpu_write(0x5116, 0x8D);
+        mapper.cpu_write(0x5117, 0x0F);
+        assert_eq!(mapper.cpu_read(0xA000), 11);
+        assert_eq!(mapper.cpu_read(0xC000), 13);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_prg_ram_and_protect() {
+        // Prep for the test, 32KB of PRG-RAM
+        let mut cartridge: Cartridge = get_test_cartridge(5, 0, 4, 0x2000, 1, 0x2000);
+        cartridge.prg_ram_size = 0x8000;
+        let mut mapper: Mmc5 = Mmc5::new(cartridge);
+
+        // Writes are ignored until the protect registers are unlocked
+        mapper.cpu_write(0x6000, 0x11);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+        mapper.cpu_write(0x5102, 0b10);
+        mapper.cpu_write(0x5103, 0b01);
+        mapper.cpu_write(0x6000, 0x11);
+        assert_eq!(mapper.cpu_read(0x6000), 0x11);
+
+        // RAM bank 2 mapped into $8000 with bit 7 clear
+        mapper.cpu_write(0x5114, 0x02);
+        mapper.cpu_write(0x8000, 0x22);
+        mapper.cpu_write(0x5113, 0x02);
+        assert_eq!(mapper.cpu_read(0x6000), 0x22);
+    }
+
+    #[test]
+    fn test_trainer_bypasses_protect() {
+        // Prep for the test, a trainer loaded while PRG-RAM is still write protected
+        let mut cartridge: Cartridge = get_test_cartridge(5, 0, 4, 0x2000, 1, 0x2000);
+        cartridge.prg_ram_size = 0x2000;
+        let mut mapper: Mmc5 = Mmc5::new(cartridge);
+        let mut trainer: Vec<u8> = vec![0; 512];
+        trainer[0] = 0x12;
+        trainer[511] = 0x34;
+        mapper.load_trainer(&trainer);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x6FFF), 0x00);
+        assert_eq!(mapper.cpu_read(0x7000), 0x12);
+        assert_eq!(mapper.cpu_read(0x71FF), 0x34);
+        assert_eq!(mapper.cpu_read(0x7200), 0x00);
+    }
+
+    #[test]
+    fn test_chr_modes() {
+        // Prep for the test, 64 1KB CHR banks
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 64, 0x0400));
+
+        // 1KB mode
+        mapper.cpu_write(0x5101, 3);
+        for register in 0..8u16 {
+            mapper.cpu_write(0x5120 + register, 10 + register as u8);
+        }
+        assert_eq!(mapper.ppu_read(0x0000), 10);
+        assert_eq!(mapper.ppu_read(0x1C00), 17);
+
+        // 4KB mode uses $5123 and $5127 in units of 4KB
+        mapper.cpu_write(0x5101, 1);
+        assert_eq!(mapper.ppu_read(0x0000), 52);
+        assert_eq!(mapper.ppu_read(0x1000), 68 % 64);
+
+        // 2KB mode uses the odd registers in units of 2KB
+        mapper.cpu_write(0x5101, 2);
+        assert_eq!(mapper.ppu_read(0x0800), 26);
+        assert_eq!(mapper.ppu_read(0x0C00), 27);
+
+        // Writing the B set makes it the last written set
+        mapper.cpu_write(0x5101, 3);
+        mapper.cpu_write(0x512B, 40);
+        assert_eq!(mapper.ppu_read(0x0C00), 40);
+        assert_eq!(mapper.ppu_read(0x1C00), 40);
+
+        // The upper bits are applied when a register is written
+        mapper.cpu_write(0x5130, 0);
+        assert_eq!(mapper.chr_registers_b[3], 40);
+        mapper.cpu_write(0x5130, 1);
+        mapper.cpu_write(0x5128, 5);
+        assert_eq!(mapper.chr_registers_b[0], 0x105);
+    }
+
+    #[test]
+    fn test_separate_sprite_and_background_banks() {
+        // Prep for the test, 1KB mode with A set banks 1-8 and B set banks 20-23
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 32, 0x0400));
+        let vram: Vec<u8> = vec![0; 0x800];
+        mapper.cpu_write(0x5101, 3);
+        for register in 0..8u16 {
+            mapper.cpu_write(0x5120 + register, 1 + register as u8);
+        }
+        for register in 0..4u16 {
+            mapper.cpu_write(0x5128 + register, 20 + register as u8);
+        }
+
+        // Enable 8x16 sprites through PPUCTRL
+        mapper.notify_cpu_write(0x2000, 0b0010_0000);
+
+        // Background fetches use the B set
+        start_frame(&mut mapper, &vram);
+        for (_, low) in render_scanline(&mut mapper, &vram) {
+            assert_eq!(low, 20);
+        }
+
+        // Sprite fetches use the A set
+        mapper.nametable_read(DETECT_ADDRESS, &vram);
+        mapper.fetch_index = SPRITE_FETCH_START - 1;
+        assert_eq!(mapper.ppu_read(0x1000), 5);
+
+        // Outside of rendering the last written set is used
+        mapper.leave_frame();
+        assert_eq!(mapper.ppu_read(0x1000), 20);
+    }
+
+    #[test]
+    fn test_nametable_mapping_and_fill_mode() {
+        // Prep for the test
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 1, 0x2000));
+        let mut vram: Vec<u8> = vec![0; 0x800];
+        vram[0x0000] = 0x10;
+        vram[0x0400] = 0x20;
+
+        // Map CIRAM 0, CIRAM 1, ExRAM and fill mode into the four nametables
+        mapper.cpu_write(0x5105, 0b11_10_01_00);
+        mapper.cpu_write(0x5106, 0x33);
+        mapper.cpu_write(0x5107, 0b10);
+        mapper.nametable_write(0x2800, 0x44, &mut vram);
+
+        // Assert results
+        assert_eq!(mapper.nametable_read(0x2000, &vram), 0x10);
+        assert_eq!(mapper.nametable_read(0x2400, &vram), 0x20);
+        assert_eq!(mapper.nametable_read(0x2800, &vram), 0x44);
+        assert_eq!(mapper.nametable_read(0x2C00, &vram), 0x33);
+        assert_eq!(mapper.nametable_read(0x2FC0, &vram), 0b1010_1010);
+    }
+
+    #[test]
+    fn test_exram_cpu_access() {
+        // Prep for the test
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 1, 0x2000));
+
+        // In mode 2 ExRAM is ordinary RAM
+        mapper.cpu_write(0x5104, 2);
+        mapper.cpu_write(0x5C10, 0x5A);
+        assert_eq!(mapper.cpu_read(0x5C10), 0x5A);
+
+        // In mode 3 it is read only
+        mapper.cpu_write(0x5104, 3);
+        mapper.cpu_write(0x5C10, 0x00);
+        assert_eq!(mapper.cpu_read(0x5C10), 0x5A);
+
+        // In mode 0 writes outside of rendering store zero and reads are open bus
+        mapper.cpu_write(0x5104, 0);
+        mapper.cpu_write(0x5C10, 0x77);
+        mapper.cpu_write(0x5104, 2);
+        assert_eq!(mapper.cpu_read(0x5C10), 0x00);
+    }
+
+    #[test]
+    fn test_extended_attributes() {
+        // Prep for the test, 16 4KB CHR banks
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 16, 0x1000));
+        let vram: Vec<u8> = vec![0; 0x800];
+
+        // Tile 5 uses bank 9 with palette 3 through ExRAM
+        mapper.cpu_write(0x5104, 2);
+        mapper.cpu_write(0x5C05, 0b1100_1001);
+        mapper.cpu_write(0x5104, EXRAM_MODE_EXTENDED_ATTRIBUTES);
+
+        // Assert results
+        start_frame(&mut mapper, &vram);
+        let tiles: Vec<(u8, u8)> = render_scanline(&mut mapper, &vram);
+        assert_eq!(tiles[3], (0xFF, 9));
+        assert_eq!(tiles[4], (0x00, 0));
+    }
+
+    #[test]
+    fn test_vertical_split() {
+        // Prep for the test, 4KB banks and a split covering the 4 leftmost tiles
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 8, 0x1000));
+        let vram: Vec<u8> = vec![0; 0x800];
+        mapper.cpu_write(0x5104, 2);
+        // The split scrolls to row 1, where tile 3 has name 0x01 and palette 2
+        mapper.cpu_write(0x5C00 + 32 + 3, 0x01);
+        mapper.cpu_write(0x5FC0, 0b0000_1000);
+        mapper.cpu_write(0x5104, 0);
+        mapper.cpu_write(0x5200, 0b1000_0100);
+        mapper.cpu_write(0x5201, 8);
+        mapper.cpu_write(0x5202, 6);
+
+        // Assert results, tiles left of the threshold come from the split
+        start_frame(&mut mapper, &vram);
+        let tiles: Vec<(u8, u8)> = render_scanline(&mut mapper, &vram);
+        assert_eq!(tiles[1], (0b1010_1010, 6));
+        assert_eq!(tiles[2], (0x00, 0));
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        // Prep for the test, IRQ on scanline 3
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 1, 0x2000));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        let vram: Vec<u8> = vec![0; 0x800];
+        mapper.cpu_write(0x5203, 3);
+        mapper.cpu_write(0x5204, 0x80);
+
+        // Render up to the start of scanline 3
+        start_frame(&mut mapper, &vram);
+        for _ in 0..3 {
+            render_scanline(&mut mapper, &vram);
+            assert!(!line.is_asserted());
+        }
+        mapper.nametable_read(DETECT_ADDRESS, &vram);
+
+        // Assert results
+        assert!(line.is_asserted());
+        assert_eq!(mapper.cpu_read(0x5204), 0b1100_0000);
+        assert!(!line.is_asserted());
+
+        // With the PPU idle the frame ends
+        for _ in 0..IDLE_CPU_CYCLES {
+            mapper.clock_cpu();
+        }
+        assert_eq!(mapper.cpu_read(0x5204), 0b0000_0000);
+    }
+
+    #[test]
+    fn test_multiplier() {
+        // Prep for the test
+        let mut mapper: Mmc5 = Mmc5::new(get_test_cartridge(5, 0, 4, 0x2000, 1, 0x2000));
+        mapper.cpu_write(0x5205, 200);
+        mapper.cpu_write(0x5206, 150);
+
+        // 200 * 150 = 30000 = 0x7530
+        assert_eq!(mapper.cpu_read(0x5205), 0x30);
+        assert_eq!(mapper.cpu_read(0x5206), 0x75);
+    }
+}
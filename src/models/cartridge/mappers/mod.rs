@@ -0,0 +1,306 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Mapper implementations, one per board family
+// A list of mappers and the boards that use them is available on the nes wiki
+// https://www.nesdev.org/wiki/Mapper
+
+use crate::common::interrupt::InterruptLine;
+use crate::models::cartridge::{Cartridge, CartridgeError, Mirroring};
+
+pub mod axrom;
+pub mod bandai;
+pub mod bnrom;
+pub mod cnrom;
+pub mod color_dreams;
+pub mod fds;
+pub mod fme7;
+pub mod gxrom;
+pub mod mmc2;
+pub mod mmc3;
+pub mod mmc5;
+pub mod namco163;
+pub mod nrom;
+pub mod unrom512;
+pub mod uxrom;
+pub mod vrc4;
+pub mod vrc6;
+pub mod vrc7;
+pub mod vrc_irq;
+
+use crate::models::cartridge::mappers::axrom::Axrom;
+use crate::models::cartridge::mappers::bandai::BandaiFcg;
+use crate::models::cartridge::mappers::bnrom::Bnrom;
+use crate::models::cartridge::mappers::cnrom::Cnrom;
+use crate::models::cartridge::mappers::color_dreams::ColorDreams;
+use crate::models::cartridge::mappers::fme7::Fme7;
+use crate::models::cartridge::mappers::gxrom::Gxrom;
+use crate::models::cartridge::mappers::mmc2::Mmc2;
+use crate::models::cartridge::mappers::mmc3::Mmc3;
+use crate::models::cartridge::mappers::mmc5::Mmc5;
+use crate::models::cartridge::mappers::namco163::Namco163;
+use crate::models::cartridge::mappers::nrom::Nrom;
+use crate::models::cartridge::mappers::unrom512::Unrom512;
+use crate::models::cartridge::mappers::uxrom::Uxrom;
+use crate::models::cartridge::mappers::vrc4::Vrc4;
+use crate::models::cartridge::mappers::vrc6::Vrc6;
+use crate::models::cartridge::mappers::vrc7::Vrc7;
+
+// The submapper that marks a discrete logic board as having bus conflicts
+// https://www.nesdev.org/wiki/NES_2.0_submappers#002,_003,_007:_UxROM,_CNROM,_AxROM
+pub const SUBMAPPER_BUS_CONFLICTS: u8 = 2;
+
+pub trait Mapper {
+    // Read a byte from the cartridge as seen by the CPU, between $4020 and $FFFF
+    fn cpu_read(&mut self, address: u16) -> u8;
+
+    // Write a byte to the cartridge as seen by the CPU, between $4020 and $FFFF
+    fn cpu_write(&mut self, address: u16, value: u8);
+
+    // Read a byte from the pattern tables as seen by the PPU, between $0000 and $1FFF
+    // Every pattern fetch comes through here with its exact address and in hardware order, which
+    // boards like MMC2 rely on to watch which tiles are being drawn
+    fn ppu_read(&mut self, address: u16) -> u8;
+
+    // Write a byte to the pattern tables as seen by the PPU, between $0000 and $1FFF
+    fn ppu_write(&mut self, address: u16, value: u8);
+
+    // Get the current nametable mirroring
+    fn get_mirroring(&self) -> Mirroring;
+
+    // Read a byte from the nametables as seen by the PPU, between $2000 and $3EFF
+    // The console's nametable RAM is passed in, and is arranged according to the mirroring by default
+    fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+        vram[get_nametable_offset(self.get_mirroring(), address) % vram.len()]
+    }
+
+    // Write a byte to the nametables as seen by the PPU, between $2000 and $3EFF
+    fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+        let length: usize = vram.len();
+        vram[get_nametable_offset(self.get_mirroring(), address) % length] = value;
+    }
+
+    // Called for CPU writes outside the cartridge address space, for boards that watch other registers
+    fn notify_cpu_write(&mut self, _address: u16, _value: u8) {}
+
+    // Called whenever the PPU drives an address onto its bus, so that mappers can watch its fetches
+    fn notify_ppu_address(&mut self, _address: u16) {}
+
+    // Called once for every CPU cycle, on the falling edge of M2
+    fn clock_cpu(&mut self) {}
+
+    // Connect the mapper to the CPU's IRQ line, for boards that are able to raise interrupts
+    fn connect_irq_line(&mut self, _line: InterruptLine) {}
+
+    // The current expansion audio level, on the same scale as the console's mixed audio output
+    fn get_audio_output(&self) -> f32 {
+        0.0
+    }
+
+    // The memory that the board keeps while the console is off, like battery backed PRG-RAM or an
+    // EEPROM, for boards that have any
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    // Restore the memory returned by get_save_data from a save file
+    fn load_save_data(&mut self, _data: &[u8]) {}
+
+    // Copy a trainer to $7000-$71FF before the CPU is reset, boards that protect their PRG-RAM at
+    // power-on write it straight into the RAM instead
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        for (offset, value) in trainer.iter().enumerate() {
+            self.cpu_write(TRAINER_ADDRESS + offset as u16, *value);
+        }
+    }
+
+    // The number of disk sides that can be put in the drive, for boards that read disks
+    fn get_disk_side_count(&self) -> usize {
+        0
+    }
+
+    // The disk side in the drive, if there is one
+    fn get_inserted_disk_side(&self) -> Option<usize> {
+        None
+    }
+
+    // Swap the disk side in the drive for another, such as flipping the disk over to side ^ 1
+    fn insert_disk_side(&mut self, _side: usize) {}
+
+    // Take the disk out of the drive
+    fn eject_disk(&mut self) {}
+}
+
+// Build the mapper implementation for a cartridge
+pub fn new_mapper(cartridge: Cartridge) -> Result<Box<dyn Mapper>, CartridgeError> {
+    match cartridge.mapper_number {
+        0 => Ok(Box::new(Nrom::new(cartridge))),
+        2 => Ok(Box::new(Uxrom::new(cartridge))),
+        3 => Ok(Box::new(Cnrom::new(cartridge))),
+        4 => Ok(Box::new(Mmc3::new(cartridge))),
+        5 => Ok(Box::new(Mmc5::new(cartridge))),
+        7 => Ok(Box::new(Axrom::new(cartridge))),
+        9 | 10 => Ok(Box::new(Mmc2::new(cartridge))),
+        11 => Ok(Box::new(ColorDreams::new(cartridge))),
+        16 | 153 | 159 => Ok(Box::new(BandaiFcg::new(cartridge))),
+        19 => Ok(Box::new(Namco163::new(cartridge))),
+        21 | 22 | 23 | 25 => Ok(Box::new(Vrc4::new(cartridge))),
+        24 | 26 => Ok(Box::new(Vrc6::new(cartridge))),
+        30 => Ok(Box::new(Unrom512::new(cartridge))),
+        34 => Ok(Box::new(Bnrom::new(cartridge))),
+        66 => Ok(Box::new(Gxrom::new(cartridge))),
+        69 => Ok(Box::new(Fme7::new(cartridge))),
+        85 => Ok(Box::new(Vrc7::new(cartridge))),
+        number => Err(CartridgeError::UnsupportedMapper(number)),
+    }
+}
+
+// Get the offset into nametable RAM for a PPU address between $2000 and $3EFF
+// Four screen boards use 4KB of nametable RAM, all other arrangements use 2KB
+pub fn get_nametable_offset(mirroring: Mirroring, address: u16) -> usize {
+    let address: usize = usize::from(address) & 0x0FFF;
+    match mirroring {
+        Mirroring::Horizontal => ((address & 0x0800) >> 1) | (address & 0x03FF),
+        Mirroring::Vertical => address & 0x07FF,
+        Mirroring::SingleScreenLower => address & 0x03FF,
+        Mirroring::SingleScreenUpper => 0x0400 | (address & 0x03FF),
+        Mirroring::FourScreen => address,
+    }
+}
+
+// Read a byte from a bank of switchable memory
+// Bank numbers past the end of the memory wrap around, as the unused high bank lines are not connected
+pub fn read_bank(memory: &[u8], bank: usize, bank_size: usize, address: u16) -> u8 {
+    if memory.is_empty() {
+        return 0;
+    }
+    let offset: usize = bank * bank_size + usize::from(address) % bank_size;
+    memory[offset % memory.len()]
+}
+
+// The trainer is loaded to $7000-$71FF
+const TRAINER_ADDRESS: u16 = 0x7000;
+
+// Copy a trainer into the 8KB bank of PRG-RAM that is mapped at $6000-$7FFF
+pub fn write_trainer(prg_ram: &mut [u8], bank: usize, trainer: &[u8]) {
+    for (offset, value) in trainer.iter().enumerate() {
+        write_bank(
+            prg_ram,
+            bank,
+            0x2000,
+            TRAINER_ADDRESS + offset as u16,
+            *value,
+        );
+    }
+}
+
+// Write a byte to a bank of switchable memory
+pub fn write_bank(memory: &mut [u8], bank: usize, bank_size: usize, address: u16, value: u8) {
+    if memory.is_empty() {
+        return;
+    }
+    let offset: usize = bank * bank_size + usize::from(address) % bank_size;
+    let length: usize = memory.len();
+    memory[offset % length] = value;
+}
+
+// Determine if writes to the board's registers conflict with the PRG-ROM on the data bus
+pub fn has_bus_conflicts(cartridge: &Cartridge) -> bool {
+    cartridge.submapper_number == SUBMAPPER_BUS_CONFLICTS
+}
+
+// When a board has bus conflicts the ROM drives the data bus at the same time as the CPU
+// The 0 bits win, so the value that reaches the register is the AND of the two
+pub fn get_bus_conflict_value(enabled: bool, rom_value: u8, value: u8) -> u8 {
+    if enabled {
+        rom_value & value
+    } else {
+        value
+    }
+}
+
+// Get the battery backed part of the PRG-RAM as save data, for boards that use it for saves
+pub fn get_battery_ram(prg_ram: &[u8], nvram_size: usize) -> Option<Vec<u8>> {
+    if nvram_size == 0 {
+        return None;
+    }
+    Some(prg_ram[..nvram_size.min(prg_ram.len())].to_vec())
+}
+
+// Restore the battery backed part of the PRG-RAM, ignoring anything past the end of it
+pub fn load_battery_ram(prg_ram: &mut [u8], nvram_size: usize, data: &[u8]) {
+    let length: usize = data.len().min(nvram_size).min(prg_ram.len());
+    prg_ram[..length].copy_from_slice(&data[..length]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn read_bank_wraps() {
+        let memory: Vec<u8> = [0, 1, 2, 3].to_vec();
+
+        // Bank 3 of a two bank memory should mirror bank 1
+        assert_eq!(read_bank(&memory, 3, 2, 0x8000), 2);
+        assert_eq!(read_bank(&memory, 3, 2, 0x8001), 3);
+    }
+
+    #[test]
+    fn nametable_offsets() {
+        // The second logical nametable at $2400
+        assert_eq!(get_nametable_offset(Mirroring::Horizontal, 0x2400), 0x0000);
+        assert_eq!(get_nametable_offset(Mirroring::Vertical, 0x2400), 0x0400);
+        assert_eq!(
+            get_nametable_offset(Mirroring::SingleScreenLower, 0x2400),
+            0x0000
+        );
+        assert_eq!(
+            get_nametable_offset(Mirroring::SingleScreenUpper, 0x2400),
+            0x0400
+        );
+        assert_eq!(get_nametable_offset(Mirroring::FourScreen, 0x2400), 0x0400);
+
+        // The third logical nametable at $2800, seen through the $3000 mirror
+        assert_eq!(get_nametable_offset(Mirroring::Horizontal, 0x3801), 0x0401);
+        assert_eq!(get_nametable_offset(Mirroring::Vertical, 0x3801), 0x0001);
+        assert_eq!(get_nametable_offset(Mirroring::FourScreen, 0x3801), 0x0801);
+    }
+
+    #[test]
+    fn bus_conflicts_and_values() {
+        assert_eq!(get_bus_conflict_value(true, 0b1100, 0b1010), 0b1000);
+        assert_eq!(get_bus_conflict_value(false, 0b1100, 0b1010), 0b1010);
+    }
+
+    #[test]
+    fn unsupported_mapper() {
+        let cartridge: Cartridge = get_test_cartridge(1000, 0, 1, 0x4000, 1, 0x2000);
+        assert_eq!(
+            new_mapper(cartridge).err(),
+            Some(CartridgeError::UnsupportedMapper(1000))
+        );
+    }
+}
@@ -0,0 +1,234 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Namco 163 expansion audio, up to 8 wavetable channels that share one DAC
+// https://www.nesdev.org/wiki/Namco_163_audio
+//
+// The chip updates one channel every 15 CPU cycles and outputs it until the next update, cycling
+// through the enabled channels. The output is not mixed, so with more channels enabled each one
+// is heard for a smaller share of the time and the channels get quieter.
+
+use crate::models::audio::mixer::PULSE_FULL_VOLUME;
+
+// The internal RAM holds both the wavetables and the channel registers
+pub const RAM_SIZE: usize = 0x80;
+
+// The number of CPU cycles spent on each channel
+pub const CHANNEL_CPU_CYCLES: u32 = 15;
+
+// The registers of channel 7 start at $78, and the others are below it
+const CHANNEL_REGISTERS_START: usize = 0x40;
+// Bits 4 to 6 of $7F hold the number of enabled channels minus one
+const CHANNEL_COUNT_REGISTER: usize = 0x7F;
+
+// A single channel at full volume is treated as being as loud as a console pulse channel
+const OUTPUT_SCALE: f32 = PULSE_FULL_VOLUME / 120.0;
+
+pub struct Namco163Audio {
+    ram: [u8; RAM_SIZE],
+    // The RAM address port at $F800, with auto increment in bit 7
+    address: u8,
+    auto_increment: bool,
+
+    // The channel that will be updated next, counting down from 7
+    current_channel: usize,
+    cpu_cycles: u32,
+    output: i32,
+}
+
+impl Namco163Audio {
+    pub fn new() -> Namco163Audio {
+        Namco163Audio {
+            ram: [0; RAM_SIZE],
+            address: 0,
+            auto_increment: false,
+            current_channel: 7,
+            cpu_cycles: 0,
+            output: 0,
+        }
+    }
+
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value & 0x7F;
+        self.auto_increment = value & 0b1000_0000 == 0b1000_0000;
+    }
+
+    // $4800 reads the RAM at the selected address
+    pub fn read_data(&mut self) -> u8 {
+        let value: u8 = self.ram[usize::from(self.address)];
+        self.increment_address();
+        value
+    }
+
+    // $4800 writes the RAM at the selected address
+    pub fn write_data(&mut self, value: u8) {
+        self.ram[usize::from(self.address)] = value;
+        self.increment_address();
+    }
+
+    fn increment_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+    }
+
+    pub fn get_channel_count(&self) -> usize {
+        usize::from((self.ram[CHANNEL_COUNT_REGISTER] >> 4) & 0b111) + 1
+    }
+
+    pub fn clock_cpu(&mut self) {
+        self.cpu_cycles += 1;
+        if self.cpu_cycles < CHANNEL_CPU_CYCLES {
+            return;
+        }
+        self.cpu_cycles = 0;
+
+        self.output = self.update_channel(self.current_channel);
+
+        // Move on to the next enabled channel, wrapping from the lowest back to channel 7
+        let lowest_channel: usize = 8 - self.get_channel_count();
+        self.current_channel = if self.current_channel <= lowest_channel {
+            7
+        } else {
+            self.current_channel - 1
+        };
+    }
+
+    // Step the phase of a channel and return its output
+    fn update_channel(&mut self, channel: usize) -> i32 {
+        let base: usize = CHANNEL_REGISTERS_START + channel * 8;
+        let registers: &[u8] = &self.ram[base..base + 8];
+
+        // The frequency and phase are 18 and 24 bits, split across the registers
+        let frequency: u32 = u32::from(registers[0])
+            | (u32::from(registers[2]) << 8)
+            | (u32::from(registers[4] & 0b11) << 16);
+        let phase: u32 = u32::from(registers[1])
+            | (u32::from(registers[3]) << 8)
+            | (u32::from(registers[5]) << 16);
+        let length: u32 = 256 - u32::from(registers[4] & 0b1111_1100);
+        let wave_address: u32 = u32::from(registers[6]);
+        let volume: i32 = i32::from(registers[7] & 0x0F);
+
+        let phase: u32 = (phase + frequency) % (length << 16);
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+
+        // Each byte of the RAM holds two 4 bit samples, low nibble first
+        let sample_address: usize = (((phase >> 16) + wave_address) & 0xFF) as usize;
+        let byte: u8 = self.ram[sample_address / 2];
+        let sample: i32 = if sample_address & 1 == 0 {
+            i32::from(byte & 0x0F)
+        } else {
+            i32::from(byte >> 4)
+        };
+
+        (sample - 8) * volume
+    }
+
+    pub fn get_output(&self) -> f32 {
+        self.output as f32 * OUTPUT_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write a channel's registers for a 4 sample wave at address 0 with a frequency of zero, so it
+    // keeps outputting the first sample
+    fn setup_channel(audio: &mut Namco163Audio, channel: u8, volume: u8) {
+        audio.write_address(0x80 | (0x40 + channel * 8));
+        for value in [0x00, 0x00, 0x00, 0x00, 0xFC, 0x00, 0x00, volume] {
+            audio.write_data(value);
+        }
+    }
+
+    // Run the chip for a number of channel updates, returning the output after each one
+    fn run_updates(audio: &mut Namco163Audio, updates: usize) -> Vec<i32> {
+        let mut outputs: Vec<i32> = Vec::new();
+        for _ in 0..updates {
+            for _ in 0..CHANNEL_CPU_CYCLES {
+                audio.clock_cpu();
+            }
+            outputs.push(audio.output);
+        }
+        outputs
+    }
+
+    #[test]
+    fn test_ram_port() {
+        // Prep for the test, write with auto increment
+        let mut audio: Namco163Audio = Namco163Audio::new();
+        audio.write_address(0x80 | 0x10);
+        audio.write_data(0x12);
+        audio.write_data(0x34);
+
+        // Assert results, reads without auto increment stay on the same address
+        audio.write_address(0x11);
+        assert_eq!(audio.read_data(), 0x34);
+        assert_eq!(audio.read_data(), 0x34);
+        audio.write_address(0x80 | 0x7F);
+        audio.read_data();
+        assert_eq!(audio.address, 0x00);
+    }
+
+    #[test]
+    fn test_wave_playback() {
+        // Prep for the test, a 4 sample wave of 0, 15, 8, 8 at address 0 played at one sample per
+        // update, which is a frequency of 0x10000, with $7C giving a length of 256 - 0xFC
+        let mut audio: Namco163Audio = Namco163Audio::new();
+        audio.write_address(0x80);
+        audio.write_data(0xF0);
+        audio.write_data(0x88);
+        audio.write_address(0x80 | 0x78);
+        for value in [0x00, 0x00, 0x00, 0x00, 0xFD, 0x00, 0x00, 0x0F] {
+            audio.write_data(value);
+        }
+
+        // Assert results, the phase is advanced before the sample is read
+        assert_eq!(run_updates(&mut audio, 4), [7 * 15, 0, 0, -8 * 15].to_vec());
+    }
+
+    #[test]
+    fn test_channel_multiplexing() {
+        // Prep for the test, channels 7 and 6 with constant outputs of -120 and -64
+        let mut audio: Namco163Audio = Namco163Audio::new();
+        setup_channel(&mut audio, 7, 0x0F);
+        setup_channel(&mut audio, 6, 0x08);
+        audio.ram[CHANNEL_COUNT_REGISTER] |= 0b0001_0000;
+        assert_eq!(audio.get_channel_count(), 2);
+
+        // Assert results, the output alternates between the channels
+        assert_eq!(run_updates(&mut audio, 4), [-120, -64, -120, -64].to_vec());
+
+        // With all 8 channels enabled the two channels are only heard for a quarter of the time
+        audio.ram[CHANNEL_COUNT_REGISTER] |= 0b0111_0000;
+        assert_eq!(
+            run_updates(&mut audio, 8),
+            [-120, -64, 0, 0, 0, 0, 0, 0].to_vec()
+        );
+    }
+}
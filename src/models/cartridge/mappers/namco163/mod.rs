@@ -0,0 +1,358 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Namco 163 (mapper 19)
+// https://www.nesdev.org/wiki/Namco_163
+
+pub mod audio;
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::models::cartridge::mappers::namco163::audio::Namco163Audio;
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, write_trainer, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+// CHR and nametable bank values from $E0 up select one of the console's nametables instead of CHR
+const NAMETABLE_BANK_START: usize = 0xE0;
+
+// The IRQ counter counts up to this value, raises an IRQ and then stops
+const IRQ_COUNTER_MAX: u16 = 0x7FFF;
+
+// Writing this value to the top 4 bits of $F800 allows PRG-RAM writes
+const PRG_RAM_WRITE_KEY: u8 = 0x40;
+
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    // The 8KB PRG banks at $8000, $A000 and $C000
+    prg_banks: [usize; 3],
+    // The 1KB banks for the pattern tables, followed by the banks for the four nametables
+    chr_banks: [usize; 12],
+    // Bits 6 and 7 of $E800 stop the lower and upper pattern tables from using the nametables
+    nametable_chr_disabled: [bool; 2],
+
+    // The PRG-RAM write protection at $F800
+    prg_ram_write_enabled: bool,
+    prg_ram_protected_pages: u8,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_line: InterruptLine,
+
+    audio: Namco163Audio,
+    audio_disabled: bool,
+}
+
+impl Namco163 {
+    pub fn new(cartridge: Cartridge) -> Namco163 {
+        Namco163 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            prg_banks: [0, 0, 0],
+            chr_banks: [0; 12],
+            nametable_chr_disabled: [false, false],
+            prg_ram_write_enabled: false,
+            prg_ram_protected_pages: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_line: InterruptLine::new(),
+            audio: Namco163Audio::new(),
+            audio_disabled: false,
+        }
+    }
+
+    fn acknowledge_irq(&mut self) {
+        self.irq_line.set(InterruptSource::Mapper, false);
+    }
+
+    // Each bit protects one 2KB page of PRG-RAM
+    fn is_prg_ram_writable(&self, address: u16) -> bool {
+        let page: u16 = (address - 0x6000) >> 11;
+        self.prg_ram_write_enabled && self.prg_ram_protected_pages & (1 << page) == 0
+    }
+
+    // Get the console nametable used by a bank, if the bank is mapped to one
+    fn get_nametable_page(&self, slot: usize) -> Option<usize> {
+        let bank: usize = self.chr_banks[slot];
+        let allowed: bool = slot >= 8 || !self.nametable_chr_disabled[slot / 4];
+        if bank >= NAMETABLE_BANK_START && allowed {
+            Some(bank & 1)
+        } else {
+            None
+        }
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x4800..=0x4FFF => self.audio.read_data(),
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => ((self.irq_counter >> 8) as u8) | (u8::from(self.irq_enabled) << 7),
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xDFFF => {
+                let slot: usize = usize::from((address - 0x8000) >> 13);
+                read_bank(&self.prg_rom, self.prg_banks[slot], 0x2000, address)
+            }
+            0xE000..=0xFFFF => {
+                let last: usize = (self.prg_rom.len() / 0x2000).max(1) - 1;
+                read_bank(&self.prg_rom, last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4800..=0x4FFF => self.audio.write_data(value),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | u16::from(value);
+                self.acknowledge_irq();
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (u16::from(value & 0x7F) << 8);
+                self.irq_enabled = value & 0b1000_0000 == 0b1000_0000;
+                self.acknowledge_irq();
+            }
+            0x6000..=0x7FFF if self.is_prg_ram_writable(address) => {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+            }
+            0x8000..=0xDFFF => {
+                self.chr_banks[usize::from((address - 0x8000) >> 11)] = usize::from(value);
+            }
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = usize::from(value & 0x3F);
+                self.audio_disabled = value & 0b0100_0000 == 0b0100_0000;
+            }
+            0xE800..=0xEFFF => {
+                self.prg_banks[1] = usize::from(value & 0x3F);
+                self.nametable_chr_disabled = [
+                    value & 0b0100_0000 == 0b0100_0000,
+                    value & 0b1000_0000 == 0b1000_0000,
+                ];
+            }
+            0xF000..=0xF7FF => self.prg_banks[2] = usize::from(value & 0x3F),
+            0xF800..=0xFFFF => {
+                // The same register holds the PRG-RAM protection and the audio RAM address
+                self.prg_ram_write_enabled = value & 0xF0 == PRG_RAM_WRITE_KEY;
+                self.prg_ram_protected_pages = value & 0x0F;
+                self.audio.write_address(value);
+            }
+            _ => {}
+        }
+    }
+
+    // Pattern table banks mapped to the console's nametables read from CHR instead, as pattern
+    // fetches are not given the nametable RAM
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+        read_bank(&self.chr, bank, 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+            write_bank(&mut self.chr, bank, 0x0400, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        // The nametables are mapped through the registers, so this is the closest standard match
+        let pages: [Option<usize>; 4] = [8, 9, 10, 11].map(|slot| self.get_nametable_page(slot));
+        match pages {
+            [Some(0), Some(1), Some(0), Some(1)] => Mirroring::Vertical,
+            [Some(0), Some(0), Some(1), Some(1)] => Mirroring::Horizontal,
+            [Some(0), Some(0), Some(0), Some(0)] => Mirroring::SingleScreenLower,
+            [Some(1), Some(1), Some(1), Some(1)] => Mirroring::SingleScreenUpper,
+            _ => Mirroring::FourScreen,
+        }
+    }
+
+    fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+        let slot: usize = 8 + usize::from((address >> 10) & 0b11);
+        match self.get_nametable_page(slot) {
+            Some(page) => vram[page * 0x0400 + usize::from(address & 0x03FF)],
+            None => read_bank(&self.chr, self.chr_banks[slot], 0x0400, address),
+        }
+    }
+
+    fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+        let slot: usize = 8 + usize::from((address >> 10) & 0b11);
+        match self.get_nametable_page(slot) {
+            Some(page) => vram[page * 0x0400 + usize::from(address & 0x03FF)] = value,
+            None if self.chr_is_ram => {
+                write_bank(&mut self.chr, self.chr_banks[slot], 0x0400, address, value)
+            }
+            None => {}
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq_line.set(InterruptSource::Mapper, true);
+            }
+        }
+        self.audio.clock_cpu();
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq_line = line;
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        if self.audio_disabled {
+            0.0
+        } else {
+            self.audio.get_output()
+        }
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        write_trainer(&mut self.prg_ram, 0, trainer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_prg_banks() {
+        // Prep for the test, 16 8KB PRG banks
+        let mut mapper: Namco163 = Namco163::new(get_test_cartridge(19, 0, 16, 0x2000, 8, 0x0400));
+        mapper.cpu_write(0xE000, 3);
+        mapper.cpu_write(0xE800, 4);
+        mapper.cpu_write(0xF000, 5);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xA000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_chr_and_nametable_banks() {
+        // Prep for the test, 32 1KB CHR banks and vertical mirroring through the nametable banks
+        let mut mapper: Namco163 = Namco163::new(get_test_cartridge(19, 0, 4, 0x2000, 32, 0x0400));
+        let mut vram: Vec<u8> = vec![0; 0x1000];
+        mapper.cpu_write(0x8800, 9);
+        for (index, bank) in [0xE0, 0xE1, 0xE0, 0xE1].iter().enumerate() {
+            mapper.cpu_write(0xC000 + index as u16 * 0x0800, *bank);
+        }
+        assert_eq!(mapper.get_mirroring(), Mirroring::Vertical);
+
+        // Assert results
+        assert_eq!(mapper.ppu_read(0x0400), 9);
+        mapper.nametable_write(0x2C05, 0x12, &mut vram);
+        assert_eq!(vram[0x0405], 0x12);
+        assert_eq!(mapper.nametable_read(0x2405, &vram), 0x12);
+
+        // Banks below $E0 put CHR-ROM in the nametable, which can't be written
+        mapper.cpu_write(0xD800, 20);
+        assert_eq!(mapper.nametable_read(0x2C00, &vram), 20);
+        mapper.nametable_write(0x2C00, 0x34, &mut vram);
+        assert_eq!(mapper.nametable_read(0x2C00, &vram), 20);
+        assert_eq!(mapper.get_mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn test_prg_ram_protection() {
+        // Prep for the test
+        let mut mapper: Namco163 = Namco163::new(get_test_cartridge(19, 0, 4, 0x2000, 8, 0x0400));
+
+        // Writes are ignored without the key in $F800
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+
+        // Assert results, the second 2KB page is protected
+        mapper.cpu_write(0xF800, 0x42);
+        mapper.cpu_write(0x6000, 0x12);
+        mapper.cpu_write(0x6800, 0x34);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+        assert_eq!(mapper.cpu_read(0x6800), 0x00);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        // Prep for the test, 2 cycles before the counter reaches $7FFF
+        let mut mapper: Namco163 = Namco163::new(get_test_cartridge(19, 0, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0x5000, 0xFD);
+        mapper.cpu_write(0x5800, 0xFF);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+
+        // The counter stops at $7FFF and can be read back
+        mapper.clock_cpu();
+        assert_eq!(mapper.cpu_read(0x5000), 0xFF);
+        assert_eq!(mapper.cpu_read(0x5800), 0xFF);
+
+        // Writing the counter acknowledges the IRQ
+        mapper.cpu_write(0x5800, 0x00);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_audio_port() {
+        // Prep for the test, write the volume of channel 7 through the audio ports
+        let mut mapper: Namco163 = Namco163::new(get_test_cartridge(19, 0, 4, 0x2000, 8, 0x0400));
+        mapper.cpu_write(0xF800, 0x80 | 0x7C);
+        for value in [0xFC, 0x00, 0x00, 0x0F] {
+            mapper.cpu_write(0x4800, value);
+        }
+        for _ in 0..audio::CHANNEL_CPU_CYCLES {
+            mapper.clock_cpu();
+        }
+        assert!(mapper.get_audio_output() != 0.0);
+
+        // Assert results, the sound disable bit silences the output
+        mapper.cpu_write(0xE000, 0b0100_0000);
+        assert_eq!(mapper.get_audio_output(), 0.0);
+    }
+}
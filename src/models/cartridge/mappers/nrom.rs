@@ -0,0 +1,120 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// NROM (mapper 0)
+// https://www.nesdev.org/wiki/NROM
+
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(cartridge: Cartridge) -> Nrom {
+        Nrom {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            mirroring: cartridge.mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            // A 16KB ROM is mirrored into both halves of the 32KB window
+            0x8000..=0xFFFF => read_bank(&self.prg_rom, 0, 0x8000, address),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, 0, 0x2000, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            write_bank(&mut self.chr, 0, 0x2000, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_prg_mirroring() {
+        // Prep for the test
+        let mut cartridge: Cartridge = get_test_cartridge(0, 0, 1, 0x4000, 1, 0x2000);
+        cartridge.prg_rom[0] = 0xAA;
+        let mut mapper: Nrom = Nrom::new(cartridge);
+
+        // A 16KB ROM is visible at both $8000 and $C000
+        assert_eq!(mapper.cpu_read(0x8000), 0xAA);
+        assert_eq!(mapper.cpu_read(0xC000), 0xAA);
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        // Prep for the test
+        let mut mapper: Nrom = Nrom::new(get_test_cartridge(0, 0, 2, 0x4000, 1, 0x2000));
+
+        // Writes to CHR-ROM are ignored
+        mapper.ppu_write(0x0010, 0x55);
+        assert_eq!(mapper.ppu_read(0x0010), 0x00);
+    }
+}
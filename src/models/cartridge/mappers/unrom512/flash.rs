@@ -0,0 +1,206 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// SST39SF040 512KB flash memory, which boards can reprogram through its command sequences
+// https://www.nesdev.org/wiki/UNROM_512#Flash_ROM
+//
+// Each command starts with an unlock sequence of $AA written to $5555 and $55 written to $2AAA.
+// Programming and erasing finish instantly here, where the real chip takes a few microseconds and
+// is polled until done, which reads the final data either way.
+
+// The addresses of the unlock sequence, compared on the lowest 15 bits
+const UNLOCK_ADDRESS_1: usize = 0x5555;
+const UNLOCK_ADDRESS_2: usize = 0x2AAA;
+
+// The size of the blocks erased by the sector erase command
+const SECTOR_SIZE: usize = 0x1000;
+
+// The values returned at addresses 0 and 1 in software ID mode
+const MANUFACTURER_ID: u8 = 0xBF;
+const DEVICE_ID: u8 = 0xB7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FlashState {
+    Ready,
+    Unlock1,
+    Unlock2,
+    // The next write programs a byte
+    Program,
+    // The erase command needs a second unlock sequence
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+pub struct Sst39sf040 {
+    state: FlashState,
+    id_mode: bool,
+}
+
+impl Sst39sf040 {
+    pub fn new() -> Sst39sf040 {
+        Sst39sf040 {
+            state: FlashState::Ready,
+            id_mode: false,
+        }
+    }
+
+    pub fn read(&self, memory: &[u8], address: usize) -> u8 {
+        if self.id_mode {
+            return if address & 1 == 0 {
+                MANUFACTURER_ID
+            } else {
+                DEVICE_ID
+            };
+        }
+        memory[address % memory.len()]
+    }
+
+    // Write to the chip, returning whether its contents were changed
+    pub fn write(&mut self, memory: &mut [u8], address: usize, value: u8) -> bool {
+        let command_address: usize = address & 0x7FFF;
+        let mut modified: bool = false;
+
+        self.state = match (self.state, command_address, value) {
+            (FlashState::Program, _, _) => {
+                // Programming can only clear bits, setting them again needs an erase
+                let offset: usize = address % memory.len();
+                memory[offset] &= value;
+                modified = true;
+                FlashState::Ready
+            }
+            // $F0 resets the chip and leaves software ID mode from any other state
+            (_, _, 0xF0) => {
+                self.id_mode = false;
+                FlashState::Ready
+            }
+            (FlashState::Ready, UNLOCK_ADDRESS_1, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, UNLOCK_ADDRESS_2, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, UNLOCK_ADDRESS_1, 0xA0) => FlashState::Program,
+            (FlashState::Unlock2, UNLOCK_ADDRESS_1, 0x80) => FlashState::Erase,
+            (FlashState::Unlock2, UNLOCK_ADDRESS_1, 0x90) => {
+                self.id_mode = true;
+                FlashState::Ready
+            }
+            (FlashState::Erase, UNLOCK_ADDRESS_1, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, UNLOCK_ADDRESS_2, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, UNLOCK_ADDRESS_1, 0x10) => {
+                memory.fill(0xFF);
+                modified = true;
+                FlashState::Ready
+            }
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                let start: usize = (address % memory.len()) & !(SECTOR_SIZE - 1);
+                let end: usize = (start + SECTOR_SIZE).min(memory.len());
+                memory[start..end].fill(0xFF);
+                modified = true;
+                FlashState::Ready
+            }
+            _ => FlashState::Ready,
+        };
+
+        modified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlock(flash: &mut Sst39sf040, memory: &mut [u8]) {
+        flash.write(memory, 0x5555, 0xAA);
+        flash.write(memory, 0x2AAA, 0x55);
+    }
+
+    #[test]
+    fn test_program() {
+        // Prep for the test
+        let mut flash: Sst39sf040 = Sst39sf040::new();
+        let mut memory: Vec<u8> = vec![0xFF; 0x80000];
+        unlock(&mut flash, &mut memory);
+        flash.write(&mut memory, 0x5555, 0xA0);
+        assert!(flash.write(&mut memory, 0x12345, 0x5A));
+
+        // Assert results, a plain write does nothing and programming again only clears bits
+        assert_eq!(flash.read(&memory, 0x12345), 0x5A);
+        assert!(!flash.write(&mut memory, 0x12345, 0x00));
+        unlock(&mut flash, &mut memory);
+        flash.write(&mut memory, 0x5555, 0xA0);
+        flash.write(&mut memory, 0x12345, 0xF0);
+        assert_eq!(flash.read(&memory, 0x12345), 0x50);
+    }
+
+    #[test]
+    fn test_sector_and_chip_erase() {
+        // Prep for the test
+        let mut flash: Sst39sf040 = Sst39sf040::new();
+        let mut memory: Vec<u8> = vec![0x00; 0x80000];
+
+        // A sector erase clears only the 4KB around the address
+        unlock(&mut flash, &mut memory);
+        flash.write(&mut memory, 0x5555, 0x80);
+        unlock(&mut flash, &mut memory);
+        assert!(flash.write(&mut memory, 0x21234, 0x30));
+        assert_eq!(memory[0x20FFF], 0x00);
+        assert_eq!(memory[0x21000], 0xFF);
+        assert_eq!(memory[0x21FFF], 0xFF);
+        assert_eq!(memory[0x22000], 0x00);
+
+        // Assert results, a chip erase clears everything
+        unlock(&mut flash, &mut memory);
+        flash.write(&mut memory, 0x5555, 0x80);
+        unlock(&mut flash, &mut memory);
+        flash.write(&mut memory, 0x5555, 0x10);
+        assert!(memory.iter().all(|value| *value == 0xFF));
+    }
+
+    #[test]
+    fn test_broken_sequence_is_ignored() {
+        // Prep for the test, the second unlock write goes to the wrong address
+        let mut flash: Sst39sf040 = Sst39sf040::new();
+        let mut memory: Vec<u8> = vec![0xFF; 0x80000];
+        flash.write(&mut memory, 0x5555, 0xAA);
+        flash.write(&mut memory, 0x2AAB, 0x55);
+        flash.write(&mut memory, 0x5555, 0xA0);
+
+        // Assert results
+        assert!(!flash.write(&mut memory, 0x0000, 0x00));
+        assert_eq!(memory[0x0000], 0xFF);
+    }
+
+    #[test]
+    fn test_software_id() {
+        // Prep for the test
+        let mut flash: Sst39sf040 = Sst39sf040::new();
+        let mut memory: Vec<u8> = vec![0x12; 0x80000];
+        unlock(&mut flash, &mut memory);
+        flash.write(&mut memory, 0x5555, 0x90);
+
+        // Assert results
+        assert_eq!(flash.read(&memory, 0), MANUFACTURER_ID);
+        assert_eq!(flash.read(&memory, 1), DEVICE_ID);
+        flash.write(&mut memory, 0x0000, 0xF0);
+        assert_eq!(flash.read(&memory, 0), 0x12);
+    }
+}
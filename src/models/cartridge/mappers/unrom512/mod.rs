@@ -0,0 +1,265 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// UNROM 512 (mapper 30)
+// https://www.nesdev.org/wiki/UNROM_512
+//
+// The self-flashable version of the board, marked by the battery bit, lets the game rewrite its
+// own PRG flash to save, so the whole PRG is the save data.
+
+pub mod flash;
+
+use crate::models::cartridge::mappers::unrom512::flash::Sst39sf040;
+use crate::models::cartridge::mappers::{get_bus_conflict_value, read_bank, write_bank, Mapper};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+// The board has 32KB of CHR-RAM in four 8KB banks
+const CHR_RAM_SIZE: usize = 0x8000;
+
+// In four screen mode the last 8KB of CHR-RAM holds the nametables
+const FOUR_SCREEN_NAMETABLE_START: usize = 0x6000;
+
+pub struct Unrom512 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    // The header's four screen bit with the mirroring bit clear selects switchable one screen
+    one_screen: bool,
+    mirroring: Mirroring,
+
+    flashable: bool,
+    flash: Sst39sf040,
+
+    // The register, in the form MCCP PPPP
+    prg_bank: usize,
+    chr_bank: usize,
+    one_screen_page: bool,
+}
+
+impl Unrom512 {
+    pub fn new(cartridge: Cartridge) -> Unrom512 {
+        let one_screen: bool =
+            cartridge.mirroring == Mirroring::FourScreen && !cartridge.mirroring_flag;
+
+        Unrom512 {
+            chr: vec![0; cartridge.chr_ram_size.max(CHR_RAM_SIZE)],
+            one_screen,
+            mirroring: cartridge.mirroring,
+            flashable: cartridge.has_battery,
+            flash: Sst39sf040::new(),
+            prg_rom: cartridge.prg_rom,
+            prg_bank: 0,
+            chr_bank: 0,
+            one_screen_page: false,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        // The board without flashing has bus conflicts
+        let rom_value: u8 = self.cpu_read(address);
+        let value: u8 = get_bus_conflict_value(!self.flashable, rom_value, value);
+
+        self.prg_bank = usize::from(value & 0b0001_1111);
+        self.chr_bank = usize::from((value >> 5) & 0b11);
+        self.one_screen_page = value & 0b1000_0000 == 0b1000_0000;
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xBFFF => {
+                let offset: usize = self.prg_bank * 0x4000 + usize::from(address & 0x3FFF);
+                self.flash.read(&self.prg_rom, offset)
+            }
+            0xC000..=0xFFFF => {
+                let last: usize = (self.prg_rom.len() / 0x4000).max(1) - 1;
+                read_bank(&self.prg_rom, last, 0x4000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            // Writes to the switchable bank go to the flash chip, at the address within the chip
+            0x8000..=0xBFFF if self.flashable => {
+                let offset: usize = self.prg_bank * 0x4000 + usize::from(address & 0x3FFF);
+                self.flash.write(&mut self.prg_rom, offset, value);
+            }
+            0x8000..=0xFFFF => self.write_register(address, value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, self.chr_bank, 0x2000, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        write_bank(&mut self.chr, self.chr_bank, 0x2000, address, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        match (self.one_screen, self.one_screen_page) {
+            (true, false) => Mirroring::SingleScreenLower,
+            (true, true) => Mirroring::SingleScreenUpper,
+            _ => self.mirroring,
+        }
+    }
+
+    fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+        if self.get_mirroring() == Mirroring::FourScreen {
+            return self.chr[FOUR_SCREEN_NAMETABLE_START + usize::from(address & 0x1FFF)];
+        }
+        vram[super::get_nametable_offset(self.get_mirroring(), address) % vram.len()]
+    }
+
+    fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+        if self.get_mirroring() == Mirroring::FourScreen {
+            self.chr[FOUR_SCREEN_NAMETABLE_START + usize::from(address & 0x1FFF)] = value;
+            return;
+        }
+        let length: usize = vram.len();
+        vram[super::get_nametable_offset(self.get_mirroring(), address) % length] = value;
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        if self.flashable {
+            Some(self.prg_rom.clone())
+        } else {
+            None
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        // A save from a different sized PRG can't belong to this game
+        if data.len() == self.prg_rom.len() {
+            self.prg_rom.copy_from_slice(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    fn get_flashable_cartridge() -> Cartridge {
+        let mut cartridge: Cartridge = get_test_cartridge(30, 0, 32, 0x4000, 0, 0);
+        cartridge.has_battery = true;
+        cartridge
+    }
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        // Prep for the test, select PRG bank 5 and CHR bank 2
+        let mut mapper: Unrom512 = Unrom512::new(get_flashable_cartridge());
+        mapper.cpu_write(0xC000, 0b0100_0101);
+        mapper.ppu_write(0x0000, 0x12);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 31);
+        assert_eq!(mapper.ppu_read(0x0000), 0x12);
+        mapper.cpu_write(0xC000, 0b0000_0101);
+        assert_eq!(mapper.ppu_read(0x0000), 0x00);
+        assert_eq!(mapper.chr[0x4000], 0x12);
+    }
+
+    #[test]
+    fn test_bus_conflicts_without_flash() {
+        // Prep for the test, the fixed bank is filled with 31
+        let mut mapper: Unrom512 = Unrom512::new(get_test_cartridge(30, 0, 32, 0x4000, 0, 0));
+        mapper.cpu_write(0xC000, 0xFF);
+
+        // Assert results, the written value is ANDed with the ROM
+        assert_eq!(mapper.cpu_read(0x8000), 31);
+        mapper.cpu_write(0xC000, 0x06);
+        assert_eq!(mapper.cpu_read(0x8000), 6);
+        assert!(mapper.get_save_data().is_none());
+    }
+
+    #[test]
+    fn test_one_screen_mirroring() {
+        // Prep for the test, the four screen bit without the mirroring bit
+        let mut cartridge: Cartridge = get_flashable_cartridge();
+        cartridge.mirroring = Mirroring::FourScreen;
+        let mut mapper: Unrom512 = Unrom512::new(cartridge);
+
+        // Assert results, bit 7 selects the screen
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenLower);
+        mapper.cpu_write(0xC000, 0x80);
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_four_screen_uses_chr_ram() {
+        // Prep for the test, both the four screen and mirroring bits
+        let mut cartridge: Cartridge = get_test_cartridge(30, 0, 32, 0x4000, 0, 0);
+        cartridge.mirroring = Mirroring::FourScreen;
+        cartridge.mirroring_flag = true;
+        let mut mapper: Unrom512 = Unrom512::new(cartridge);
+        let mut vram: Vec<u8> = vec![0; 0x1000];
+
+        // Assert results
+        mapper.nametable_write(0x2C00, 0x34, &mut vram);
+        assert_eq!(mapper.nametable_read(0x2C00, &vram), 0x34);
+        assert_eq!(mapper.chr[0x6C00], 0x34);
+        assert!(vram.iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn test_self_flashing_and_save() {
+        // Prep for the test, erase the sector at $8000 in bank 3 and program a byte there
+        let mut mapper: Unrom512 = Unrom512::new(get_flashable_cartridge());
+        let command = |mapper: &mut Unrom512, bank: u8, address: u16, value: u8| {
+            mapper.cpu_write(0xC000, bank);
+            mapper.cpu_write(address, value);
+        };
+        // $5555 is $9555 in bank 1, and $2AAA is $AAAA in bank 0
+        for (bank, address, value) in [(1, 0x9555, 0xAA), (0, 0xAAAA, 0x55), (1, 0x9555, 0x80)] {
+            command(&mut mapper, bank, address, value);
+        }
+        for (bank, address, value) in [(1, 0x9555, 0xAA), (0, 0xAAAA, 0x55), (3, 0x8000, 0x30)] {
+            command(&mut mapper, bank, address, value);
+        }
+        for (bank, address, value) in [(1, 0x9555, 0xAA), (0, 0xAAAA, 0x55), (1, 0x9555, 0xA0)] {
+            command(&mut mapper, bank, address, value);
+        }
+        command(&mut mapper, 3, 0x8010, 0x42);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 0xFF);
+        assert_eq!(mapper.cpu_read(0x8010), 0x42);
+        assert_eq!(mapper.cpu_read(0x9000), 3);
+
+        // The modified PRG is the save data
+        let save: Vec<u8> = mapper.get_save_data().unwrap();
+        assert_eq!(save[3 * 0x4000 + 0x10], 0x42);
+        let mut restored: Unrom512 = Unrom512::new(get_flashable_cartridge());
+        restored.load_save_data(&save);
+        restored.cpu_write(0xC000, 3);
+        assert_eq!(restored.cpu_read(0x8010), 0x42);
+    }
+}
@@ -0,0 +1,137 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// UxROM (mapper 2)
+// https://www.nesdev.org/wiki/UxROM
+
+use crate::models::cartridge::mappers::{
+    get_bus_conflict_value, has_bus_conflicts, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    // The original boards have no PRG-RAM, but some is given when the header asks for it, such
+    // as for a trainer
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    // The 16KB PRG bank visible at $8000
+    prg_bank: usize,
+}
+
+impl Uxrom {
+    pub fn new(cartridge: Cartridge) -> Uxrom {
+        Uxrom {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            bus_conflicts: has_bus_conflicts(&cartridge),
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_rom: cartridge.prg_rom,
+            mirroring: cartridge.mirroring,
+            prg_bank: 0,
+        }
+    }
+
+    fn get_last_prg_bank(&self) -> usize {
+        (self.prg_rom.len() / 0x4000).max(1) - 1
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xBFFF => read_bank(&self.prg_rom, self.prg_bank, 0x4000, address),
+            // The last bank is fixed at $C000
+            0xC000..=0xFFFF => read_bank(&self.prg_rom, self.get_last_prg_bank(), 0x4000, address),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+        } else if address >= 0x8000 {
+            let rom_value: u8 = self.cpu_read(address);
+            self.prg_bank =
+                usize::from(get_bus_conflict_value(self.bus_conflicts, rom_value, value));
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, 0, 0x2000, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            write_bank(&mut self.chr, 0, 0x2000, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_bank_switch() {
+        // Prep for the test, 8 banks of 16KB with CHR-RAM
+        let mut mapper: Uxrom = Uxrom::new(get_test_cartridge(2, 0, 8, 0x4000, 0, 0));
+
+        // At power on bank 0 is at $8000 and the last bank at $C000
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+
+        // Select bank 5
+        mapper.cpu_write(0x8000, 5);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0xBFFF), 5);
+        assert_eq!(mapper.cpu_read(0xFFFF), 7);
+
+        // CHR-RAM is writable
+        mapper.ppu_write(0x1234, 0x56);
+        assert_eq!(mapper.ppu_read(0x1234), 0x56);
+    }
+
+    #[test]
+    fn test_bus_conflicts() {
+        // Prep for the test, the fixed bank holds the value 7 so only the low 3 bits can be set
+        let mut mapper: Uxrom = Uxrom::new(get_test_cartridge(2, 2, 8, 0x4000, 0, 0));
+
+        // Writing 0x0D over ROM holding 0x07 selects bank 5
+        mapper.cpu_write(0xC000, 0x0D);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+    }
+}
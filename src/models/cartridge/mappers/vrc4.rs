@@ -0,0 +1,401 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25)
+// https://www.nesdev.org/wiki/VRC2_and_VRC4
+//
+// Every board uses the same registers, but the two register select lines of the chip are wired
+// to different CPU address lines depending on the board, so the wiring is taken from the NES 2.0
+// submapper. When the submapper is 0 the address lines of all possible boards are combined.
+
+use crate::common::interrupt::InterruptLine;
+use crate::models::cartridge::mappers::vrc_irq::VrcIrq;
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+const A0: u16 = 1 << 0;
+const A1: u16 = 1 << 1;
+const A2: u16 = 1 << 2;
+const A3: u16 = 1 << 3;
+const A6: u16 = 1 << 6;
+const A7: u16 = 1 << 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VrcChip {
+    // No IRQ counter, only horizontal and vertical mirroring, and a microwire latch at $6000
+    Vrc2,
+    Vrc4,
+}
+
+// How a board is wired, worked out from the mapper and submapper numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VrcWiring {
+    pub chip: VrcChip,
+    // The CPU address lines connected to the chip's register select lines, which are ORed together
+    // when more than one line is given
+    pub register_bit0_lines: u16,
+    pub register_bit1_lines: u16,
+    // VRC2a ignores the lowest bit of the CHR bank registers, so every bank number is shifted right
+    pub chr_shifted: bool,
+}
+
+impl VrcWiring {
+    pub fn new(mapper_number: u16, submapper_number: u8) -> VrcWiring {
+        let (chip, register_bit0_lines, register_bit1_lines): (VrcChip, u16, u16) =
+            match (mapper_number, submapper_number) {
+                // VRC4a and VRC4c
+                (21, 1) => (VrcChip::Vrc4, A1, A2),
+                (21, 2) => (VrcChip::Vrc4, A6, A7),
+                (21, _) => (VrcChip::Vrc4, A1 | A6, A2 | A7),
+                // VRC2a
+                (22, _) => (VrcChip::Vrc2, A1, A0),
+                // VRC4f, VRC4e and VRC2b
+                (23, 1) => (VrcChip::Vrc4, A0, A1),
+                (23, 2) => (VrcChip::Vrc4, A2, A3),
+                (23, 3) => (VrcChip::Vrc2, A0, A1),
+                (23, _) => (VrcChip::Vrc4, A0 | A2, A1 | A3),
+                // VRC4b, VRC4d and VRC2c
+                (25, 1) => (VrcChip::Vrc4, A1, A0),
+                (25, 2) => (VrcChip::Vrc4, A3, A2),
+                (25, 3) => (VrcChip::Vrc2, A1, A0),
+                (_, _) => (VrcChip::Vrc4, A1 | A3, A0 | A2),
+            };
+
+        VrcWiring {
+            chip,
+            register_bit0_lines,
+            register_bit1_lines,
+            chr_shifted: mapper_number == 22,
+        }
+    }
+
+    // Translate a CPU address to a register address in the form $X000 to $X003
+    pub fn get_register(&self, address: u16) -> u16 {
+        let mut register: u16 = address & 0xF000;
+        if address & self.register_bit0_lines != 0 {
+            register |= 0b01;
+        }
+        if address & self.register_bit1_lines != 0 {
+            register |= 0b10;
+        }
+        register
+    }
+}
+
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    wiring: VrcWiring,
+    mirroring: Mirroring,
+
+    // The 8KB PRG banks selected by $8000 and $A000
+    prg_banks: [usize; 2],
+    // Set when $8000 selects the bank at $C000 and $8000 is fixed to the second last bank
+    prg_swap_mode: bool,
+    // The CHR registers R0 to R7, each written a nibble at a time
+    chr_registers: [usize; 8],
+
+    // Boards without PRG-RAM have a single bit latch at $6000 that is used to talk to an EEPROM
+    microwire_latch: u8,
+
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(cartridge: Cartridge) -> Vrc4 {
+        Vrc4 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            wiring: VrcWiring::new(cartridge.mapper_number, cartridge.submapper_number),
+            mirroring: Mirroring::Vertical,
+            prg_ram: vec![0; cartridge.prg_ram_size],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            prg_banks: [0, 0],
+            prg_swap_mode: false,
+            chr_registers: [0; 8],
+            microwire_latch: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn get_prg_bank(&self, address: u16) -> usize {
+        let bank_count: usize = (self.prg_rom.len() / 0x2000).max(1);
+        let second_last: usize = bank_count.saturating_sub(2);
+
+        match (address >> 13) & 0b11 {
+            0 if self.prg_swap_mode => second_last,
+            0 => self.prg_banks[0],
+            1 => self.prg_banks[1],
+            2 if self.prg_swap_mode => self.prg_banks[0],
+            2 => second_last,
+            _ => bank_count - 1,
+        }
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let bank: usize = self.chr_registers[usize::from((address >> 10) & 0b111)];
+        if self.wiring.chr_shifted {
+            bank >> 1
+        } else {
+            bank
+        }
+    }
+
+    // Write half of a CHR register, from $B000 to $E003 each pair of registers forms one bank number
+    fn write_chr_register(&mut self, register: u16, value: u8) {
+        let index: usize = usize::from(((register >> 12) - 0xB) * 2 + ((register >> 1) & 1));
+        let value: usize = usize::from(value);
+        self.chr_registers[index] = if register & 1 == 0 {
+            (self.chr_registers[index] & !0x0F) | (value & 0x0F)
+        } else {
+            // The VRC4 has one more CHR bank bit than the VRC2
+            let high_mask: usize = match self.wiring.chip {
+                VrcChip::Vrc2 => 0x0F,
+                VrcChip::Vrc4 => 0x1F,
+            };
+            (self.chr_registers[index] & 0x0F) | ((value & high_mask) << 4)
+        };
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                read_bank(&self.prg_ram, 0, 0x2000, address)
+            }
+            0x6000..=0x6FFF => self.microwire_latch,
+            0x8000..=0xFFFF => {
+                read_bank(&self.prg_rom, self.get_prg_bank(address), 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+                return;
+            }
+            0x6000..=0x6FFF => {
+                self.microwire_latch = value & 1;
+                return;
+            }
+            0x8000..=0xFFFF => {}
+            _ => return,
+        }
+
+        let register: u16 = self.wiring.get_register(address);
+        let is_vrc4: bool = self.wiring.chip == VrcChip::Vrc4;
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = usize::from(value & 0x1F),
+            0x9000 | 0x9001 if is_vrc4 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002 if is_vrc4 => self.prg_swap_mode = value & 0b10 == 0b10,
+            0x9003 if is_vrc4 => {}
+            0x9000..=0x9003 => {
+                self.mirroring = if value & 1 == 1 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            0xA000..=0xA003 => self.prg_banks[1] = usize::from(value & 0x1F),
+            0xB000..=0xEFFF => self.write_chr_register(register, value),
+            0xF000 if is_vrc4 => self.irq.write_latch_low(value),
+            0xF001 if is_vrc4 => self.irq.write_latch_high(value),
+            0xF002 if is_vrc4 => self.irq.write_control(value),
+            0xF003 if is_vrc4 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, self.get_chr_bank(address), 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let bank: usize = self.get_chr_bank(address);
+            write_bank(&mut self.chr, bank, 0x0400, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.wiring.chip == VrcChip::Vrc4 {
+            self.irq.clock_cpu();
+        }
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq.connect_irq_line(line);
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_wiring_from_submapper() {
+        // VRC4c uses A6 and A7, so $9080 is $9002
+        let vrc4c: VrcWiring = VrcWiring::new(21, 2);
+        assert_eq!(vrc4c.chip, VrcChip::Vrc4);
+        assert_eq!(vrc4c.get_register(0x9080), 0x9002);
+        assert_eq!(vrc4c.get_register(0x9002), 0x9000);
+
+        // VRC4d uses A3 and A2 in reverse order, so $B004 is $B002
+        let vrc4d: VrcWiring = VrcWiring::new(25, 2);
+        assert_eq!(vrc4d.get_register(0xB004), 0xB002);
+        assert_eq!(vrc4d.get_register(0xB008), 0xB001);
+
+        // VRC2a swaps A0 and A1 and shifts its CHR banks
+        let vrc2a: VrcWiring = VrcWiring::new(22, 0);
+        assert_eq!(vrc2a.chip, VrcChip::Vrc2);
+        assert_eq!(vrc2a.get_register(0xE001), 0xE002);
+        assert!(vrc2a.chr_shifted);
+
+        // Submapper 0 combines the lines of every variant
+        let combined: VrcWiring = VrcWiring::new(23, 0);
+        assert_eq!(combined.get_register(0xF001), 0xF001);
+        assert_eq!(combined.get_register(0xF004), 0xF001);
+        assert_eq!(combined.get_register(0xF00C), 0xF003);
+    }
+
+    #[test]
+    fn test_prg_banks_and_swap_mode() {
+        // Prep for the test, a VRC4f board with 16 8KB PRG banks
+        let mut mapper: Vrc4 = Vrc4::new(get_test_cartridge(23, 1, 16, 0x2000, 8, 0x0400));
+        mapper.cpu_write(0x8000, 3);
+        mapper.cpu_write(0xA000, 5);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xA000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 14);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+
+        // Swap mode moves the bank at $8000 to $C000
+        mapper.cpu_write(0x9002, 0b10);
+        assert_eq!(mapper.cpu_read(0x8000), 14);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+    }
+
+    #[test]
+    fn test_chr_banks() {
+        // Prep for the test, a VRC4f board with 256 1KB CHR banks
+        let mut mapper: Vrc4 = Vrc4::new(get_test_cartridge(23, 1, 4, 0x2000, 256, 0x0400));
+
+        // R3 is written through $C002 and $C003
+        mapper.cpu_write(0xC002, 0x0A);
+        mapper.cpu_write(0xC003, 0x0B);
+        assert_eq!(mapper.ppu_read(0x0C00), 0xBA);
+
+        // R7 is written through $E002 and $E003
+        mapper.cpu_write(0xE002, 0x01);
+        mapper.cpu_write(0xE003, 0x02);
+        assert_eq!(mapper.ppu_read(0x1C00), 0x21);
+    }
+
+    #[test]
+    fn test_vrc2a_shifted_chr() {
+        // Prep for the test, a VRC2a board where R0 is written through $B000 and $B002
+        let mut mapper: Vrc4 = Vrc4::new(get_test_cartridge(22, 0, 4, 0x2000, 128, 0x0400));
+        mapper.cpu_write(0xB000, 0x07);
+        mapper.cpu_write(0xB002, 0x01);
+
+        // Assert results, bank 0x17 is shifted down to 0x0B
+        assert_eq!(mapper.ppu_read(0x0000), 0x0B);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        // Prep for the test
+        let mut vrc4: Vrc4 = Vrc4::new(get_test_cartridge(21, 1, 4, 0x2000, 8, 0x0400));
+        let mut vrc2: Vrc4 = Vrc4::new(get_test_cartridge(23, 3, 4, 0x2000, 8, 0x0400));
+
+        // Assert results, only the VRC4 supports single screen mirroring
+        vrc4.cpu_write(0x9000, 3);
+        assert_eq!(vrc4.get_mirroring(), Mirroring::SingleScreenUpper);
+        vrc2.cpu_write(0x9000, 3);
+        assert_eq!(vrc2.get_mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_microwire_latch() {
+        // Prep for the test, a VRC2b board without PRG-RAM
+        let mut mapper: Vrc4 = Vrc4::new(get_test_cartridge(23, 3, 4, 0x2000, 8, 0x0400));
+
+        // Assert results, only the lowest bit is kept
+        mapper.cpu_write(0x6000, 0xFF);
+        assert_eq!(mapper.cpu_read(0x6000), 0x01);
+        mapper.cpu_write(0x6FFF, 0x02);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+    }
+
+    #[test]
+    fn test_vrc4_irq() {
+        // Prep for the test, a VRC4e board with the latch written a nibble at a time
+        let mut mapper: Vrc4 = Vrc4::new(get_test_cartridge(23, 2, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0xF000, 0x0E);
+        mapper.cpu_write(0xF004, 0x0F);
+        mapper.cpu_write(0xF008, 0b0000_0110);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+        mapper.cpu_write(0xF00C, 0);
+        assert!(!line.is_asserted());
+    }
+}
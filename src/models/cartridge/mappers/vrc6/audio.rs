@@ -0,0 +1,258 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// VRC6 expansion audio, two pulse channels and a sawtooth channel
+// https://www.nesdev.org/wiki/VRC6_audio
+
+use crate::models::audio::mixer::PULSE_FULL_VOLUME;
+
+// A VRC6 pulse at full volume is roughly as loud as one of the console's pulse channels at full volume
+const OUTPUT_SCALE: f32 = PULSE_FULL_VOLUME / 15.0;
+
+#[derive(Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    // When set the duty cycle is ignored and the channel outputs its volume constantly
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                // MDDD VVVV
+                self.ignore_duty = value & 0b1000_0000 == 0b1000_0000;
+                self.duty = (value >> 4) & 0b0111;
+                self.volume = value & 0b0000_1111;
+            }
+            1 => self.period = (self.period & 0x0F00) | u16::from(value),
+            _ => {
+                // E--- PPPP
+                self.period = (self.period & 0x00FF) | (u16::from(value & 0x0F) << 8);
+                self.enabled = value & 0b1000_0000 == 0b1000_0000;
+                if !self.enabled {
+                    // Disabling the channel resets its duty cycle
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn get_output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct Vrc6Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & 0b0011_1111,
+            1 => self.period = (self.period & 0x0F00) | u16::from(value),
+            _ => {
+                self.period = (self.period & 0x00FF) | (u16::from(value & 0x0F) << 8);
+                self.enabled = value & 0b1000_0000 == 0b1000_0000;
+                if !self.enabled {
+                    self.accumulator = 0;
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+
+            // The rate is added on every second step, and the accumulator resets after 7 steps
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step.is_multiple_of(2) {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // The top 5 bits of the accumulator
+    fn get_output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+#[derive(Default)]
+pub struct Vrc6Audio {
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Vrc6Sawtooth,
+    halted: bool,
+    // The periods of all channels are shifted right by 4 or 8 bits through $9003
+    frequency_shift: u8,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Vrc6Audio {
+        let mut audio: Vrc6Audio = Vrc6Audio::default();
+        for pulse in audio.pulses.iter_mut() {
+            pulse.step = 15;
+        }
+        audio
+    }
+
+    // Write an audio register, with the address already corrected for the board's wiring
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        let register: u16 = address & 0b11;
+        match address & 0xF003 {
+            0x9003 => {
+                self.halted = value & 0b0000_0001 == 0b0000_0001;
+                self.frequency_shift = if value & 0b0000_0100 == 0b0000_0100 {
+                    8
+                } else if value & 0b0000_0010 == 0b0000_0010 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000..=0x9002 => self.pulses[0].write_register(register, value),
+            0xA000..=0xA002 => self.pulses[1].write_register(register, value),
+            0xB000..=0xB002 => self.sawtooth.write_register(register, value),
+            _ => {}
+        }
+    }
+
+    pub fn clock_cpu(&mut self) {
+        if self.halted {
+            return;
+        }
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock(self.frequency_shift);
+        }
+        self.sawtooth.clock(self.frequency_shift);
+    }
+
+    pub fn get_output(&self) -> f32 {
+        let sum: u8 =
+            self.pulses[0].get_output() + self.pulses[1].get_output() + self.sawtooth.get_output();
+        f32::from(sum) * OUTPUT_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pulse_duty() {
+        // Prep for the test, pulse 1 with duty 3 (4/16) at volume 10 and a period of 0
+        let mut audio: Vrc6Audio = Vrc6Audio::new();
+        audio.write_register(0x9000, 0b0011_1010);
+        audio.write_register(0x9001, 0x00);
+        audio.write_register(0x9002, 0x80);
+
+        // Count how many of 16 steps are high
+        let mut high_steps: u8 = 0;
+        for _ in 0..16 {
+            audio.clock_cpu();
+            if audio.pulses[0].get_output() == 10 {
+                high_steps += 1;
+            }
+        }
+
+        // Assert results
+        assert_eq!(high_steps, 4);
+    }
+
+    #[test]
+    fn test_sawtooth() {
+        // Prep for the test, a rate of 0x20 with a period of 0
+        let mut audio: Vrc6Audio = Vrc6Audio::new();
+        audio.write_register(0xB000, 0x20);
+        audio.write_register(0xB002, 0x80);
+
+        // The output ramps up by 4 every second step, then resets after 14 steps
+        let mut outputs: Vec<u8> = Vec::new();
+        for _ in 0..14 {
+            audio.clock_cpu();
+            outputs.push(audio.sawtooth.get_output());
+        }
+        assert_eq!(
+            outputs,
+            [0, 4, 4, 8, 8, 12, 12, 16, 16, 20, 20, 24, 24, 0].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_halt_and_frequency_shift() {
+        // Prep for the test, pulse 1 ignoring its duty cycle with a period of 0x100
+        let mut audio: Vrc6Audio = Vrc6Audio::new();
+        audio.write_register(0x9000, 0x8F);
+        audio.write_register(0x9002, 0x81);
+
+        // Halting stops the timers
+        audio.write_register(0x9003, 0b001);
+        audio.clock_cpu();
+        assert_eq!(audio.pulses[0].step, 15);
+
+        // Shifting by 8 turns the period of 0x100 into 1
+        audio.write_register(0x9003, 0b100);
+        audio.clock_cpu();
+        assert_eq!(audio.pulses[0].timer, 1);
+        assert!(audio.get_output() > 0.0);
+    }
+}
@@ -0,0 +1,323 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Konami VRC6 (mappers 24 and 26)
+// https://www.nesdev.org/wiki/VRC6
+
+pub mod audio;
+
+use crate::common::interrupt::InterruptLine;
+use crate::models::cartridge::mappers::vrc6::audio::Vrc6Audio;
+use crate::models::cartridge::mappers::vrc_irq::VrcIrq;
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, write_trainer, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+// Mapper 26 (VRC6b) has the CPU A0 and A1 lines swapped compared to mapper 24 (VRC6a)
+const MAPPER_VRC6B: u16 = 26;
+
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    swap_address_lines: bool,
+
+    // The 16KB bank at $8000 and the 8KB bank at $C000
+    prg_bank_16k: usize,
+    prg_bank_8k: usize,
+    // The CHR registers R0 to R7
+    chr_registers: [usize; 8],
+
+    // The banking control register at $B003
+    banking_control: u8,
+
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(cartridge: Cartridge) -> Vrc6 {
+        Vrc6 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            swap_address_lines: cartridge.mapper_number == MAPPER_VRC6B,
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_registers: [0; 8],
+            banking_control: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    // Translate a CPU address to the register layout of mapper 24
+    fn normalize_address(&self, address: u16) -> u16 {
+        if self.swap_address_lines {
+            (address & !0b11) | ((address & 0b01) << 1) | ((address & 0b10) >> 1)
+        } else {
+            address
+        }
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        self.banking_control & 0b1000_0000 == 0b1000_0000
+    }
+
+    // Get the 1KB CHR bank mapped at a PPU address between $0000 and $1FFF
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let slot: usize = usize::from((address >> 10) & 0b111);
+        let registers: &[usize; 8] = &self.chr_registers;
+
+        // In the 2KB modes bit 5 of $B003 selects whether the lowest bank bit comes from PPU A10
+        let a10: usize = usize::from((address >> 10) & 1);
+        let get_2k_bank = |register: usize| -> usize {
+            if self.banking_control & 0b0010_0000 == 0b0010_0000 {
+                (registers[register] & !1) | a10
+            } else {
+                registers[register]
+            }
+        };
+
+        match self.banking_control & 0b11 {
+            // Eight 1KB banks
+            0 => registers[slot],
+            // Four 2KB banks from R0 to R3
+            1 => get_2k_bank(slot / 2),
+            // Four 1KB banks from R0 to R3 followed by two 2KB banks from R4 and R5
+            _ if slot < 4 => registers[slot],
+            _ => get_2k_bank(4 + (slot - 4) / 2),
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.is_prg_ram_enabled() => {
+                read_bank(&self.prg_ram, 0, 0x2000, address)
+            }
+            0x8000..=0xBFFF => read_bank(&self.prg_rom, self.prg_bank_16k, 0x4000, address),
+            0xC000..=0xDFFF => read_bank(&self.prg_rom, self.prg_bank_8k, 0x2000, address),
+            0xE000..=0xFFFF => {
+                let last: usize = (self.prg_rom.len() / 0x2000).max(1) - 1;
+                read_bank(&self.prg_rom, last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if (0x6000..=0x7FFF).contains(&address) {
+            if self.is_prg_ram_enabled() {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+            }
+            return;
+        }
+
+        let address: u16 = self.normalize_address(address);
+        match address & 0xF003 {
+            0x8000..=0x8003 => self.prg_bank_16k = usize::from(value & 0x0F),
+            0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 => {
+                self.audio.write_register(address, value)
+            }
+            0xB003 => self.banking_control = value,
+            0xC000..=0xC003 => self.prg_bank_8k = usize::from(value & 0x1F),
+            0xD000..=0xD003 => self.chr_registers[usize::from(address & 0b11)] = usize::from(value),
+            0xE000..=0xE003 => {
+                self.chr_registers[4 + usize::from(address & 0b11)] = usize::from(value)
+            }
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        read_bank(&self.chr, self.get_chr_bank(address), 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let bank: usize = self.get_chr_bank(address);
+            write_bank(&mut self.chr, bank, 0x0400, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        match (self.banking_control >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.audio.clock_cpu();
+        self.irq.clock_cpu();
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq.connect_irq_line(line);
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        write_trainer(&mut self.prg_ram, 0, trainer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_prg_banks() {
+        // Prep for the test, 16 8KB PRG banks
+        let mut mapper: Vrc6 = Vrc6::new(get_test_cartridge(24, 0, 16, 0x2000, 8, 0x0400));
+        mapper.cpu_write(0x8000, 3);
+        mapper.cpu_write(0xC000, 9);
+
+        // Assert results, the 16KB bank 3 is made of 8KB banks 6 and 7
+        assert_eq!(mapper.cpu_read(0x8000), 6);
+        assert_eq!(mapper.cpu_read(0xA000), 7);
+        assert_eq!(mapper.cpu_read(0xC000), 9);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_chr_modes() {
+        // Prep for the test, 32 1KB CHR banks
+        let mut mapper: Vrc6 = Vrc6::new(get_test_cartridge(24, 0, 4, 0x2000, 32, 0x0400));
+        for register in 0..8u16 {
+            let base: u16 = if register < 4 { 0xD000 } else { 0xE000 };
+            mapper.cpu_write(base + (register & 0b11), 10 + register as u8);
+        }
+
+        // Mode 0 uses eight 1KB banks
+        mapper.cpu_write(0xB003, 0b0000_0000);
+        assert_eq!(mapper.ppu_read(0x0000), 10);
+        assert_eq!(mapper.ppu_read(0x1C00), 17);
+
+        // Mode 1 uses R0 to R3 as 2KB banks, with A10 taken from the PPU when bit 5 is set
+        mapper.cpu_write(0xB003, 0b0000_0001);
+        assert_eq!(mapper.ppu_read(0x0400), 10);
+        assert_eq!(mapper.ppu_read(0x1800), 13);
+        mapper.cpu_write(0xB003, 0b0010_0001);
+        assert_eq!(mapper.ppu_read(0x0000), 10);
+        assert_eq!(mapper.ppu_read(0x0400), 11);
+
+        // Mode 2 uses R0 to R3 as 1KB banks and R4 and R5 as 2KB banks, so R5 = 15 becomes 14 and 15
+        mapper.cpu_write(0xB003, 0b0010_0010);
+        assert_eq!(mapper.ppu_read(0x0C00), 13);
+        assert_eq!(mapper.ppu_read(0x1000), 14);
+        assert_eq!(mapper.ppu_read(0x1400), 15);
+        assert_eq!(mapper.ppu_read(0x1800), 14);
+        assert_eq!(mapper.ppu_read(0x1C00), 15);
+    }
+
+    #[test]
+    fn test_mirroring_and_prg_ram() {
+        // Prep for the test
+        let mut mapper: Vrc6 = Vrc6::new(get_test_cartridge(24, 0, 4, 0x2000, 8, 0x0400));
+
+        // PRG-RAM is disabled until bit 7 of $B003 is set
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+        mapper.cpu_write(0xB003, 0b1000_0100);
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+
+        // Assert results
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+        mapper.cpu_write(0xB003, 0b0000_1100);
+        assert_eq!(mapper.get_mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_swapped_address_lines() {
+        // Prep for the test, a mapper 26 board
+        let mut mapper: Vrc6 = Vrc6::new(get_test_cartridge(26, 0, 4, 0x2000, 32, 0x0400));
+
+        // $D001 on mapper 26 is $D002 on mapper 24, which is R2
+        mapper.cpu_write(0xD001, 20);
+        mapper.cpu_write(0xD002, 21);
+
+        // Assert results
+        assert_eq!(mapper.ppu_read(0x0800), 20);
+        assert_eq!(mapper.ppu_read(0x0400), 21);
+    }
+
+    #[test]
+    fn test_irq() {
+        // Prep for the test, an IRQ after 2 CPU cycles
+        let mut mapper: Vrc6 = Vrc6::new(get_test_cartridge(24, 0, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0xF000, 0xFE);
+        mapper.cpu_write(0xF001, 0b0000_0110);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+
+        // Acknowledging clears the line
+        mapper.cpu_write(0xF002, 0);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_audio_output() {
+        // Prep for the test, pulse 1 at full volume and ignoring the duty cycle
+        let mut mapper: Vrc6 = Vrc6::new(get_test_cartridge(24, 0, 4, 0x2000, 8, 0x0400));
+        assert_eq!(mapper.get_audio_output(), 0.0);
+        mapper.cpu_write(0x9000, 0b1000_1111);
+        mapper.cpu_write(0x9002, 0b1000_0000);
+        mapper.clock_cpu();
+
+        // Assert results
+        assert!(mapper.get_audio_output() > 0.0);
+    }
+}
@@ -0,0 +1,768 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// VRC7 expansion audio, a cut down YM2413 (OPLL) FM synthesizer with six two-operator channels
+// https://www.nesdev.org/wiki/VRC7_audio
+//
+// Attenuation is tracked in units of 0.1875dB and converted into a log2 scale of 1/256 steps for
+// the log-sin and exponent lookups, following the structure of the Yamaha OPL family.
+
+use std::f64::consts::PI;
+
+use crate::models::audio::mixer::PULSE_FULL_VOLUME;
+
+// The synthesizer runs from the 3.58MHz crystal and produces a sample every 72 clocks, which is
+// every 36 CPU cycles, giving a sample rate of about 49.7kHz
+pub const SAMPLE_CPU_CYCLES: u32 = 36;
+
+pub const CHANNEL_COUNT: usize = 6;
+
+// A full scale channel is treated as being as loud as a console pulse channel at full volume
+const OUTPUT_SCALE: f32 = PULSE_FULL_VOLUME / 4096.0;
+
+// The built-in instruments 1 to 15, instrument 0 is the custom patch in registers $00 to $07
+// https://www.nesdev.org/wiki/VRC7_audio#Internal_patch_set
+const BUILT_IN_PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+// Frequency multipliers, doubled so that the multiplier of 0.5 is a whole number
+const MULTIPLIER_TABLE: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+// Key scale level attenuation for the top 4 bits of the frequency number
+const KEY_SCALE_LEVEL_TABLE: [i32; 16] = [
+    0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64,
+];
+
+// Envelope increments for the lowest 2 bits of a rate, stepped through by the envelope counter
+const ENVELOPE_INCREMENT_TABLE: [[u32; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+
+// Vibrato offsets, scaled by the top 3 bits of the frequency number
+const VIBRATO_TABLE: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+
+// The amplitude modulation is a triangle wave over 210 steps, stepped every 64 samples (3.7Hz)
+const TREMOLO_STEPS: u32 = 210;
+const TREMOLO_SAMPLES: u32 = 64;
+// The vibrato steps every 1024 samples (6.1Hz)
+const VIBRATO_SAMPLES: u32 = 1024;
+
+// The envelope is 7 bits in steps of 0.375dB, where 0 is the loudest
+const ENVELOPE_SILENT: u8 = 127;
+// The rate used to quickly silence an operator before a new note starts
+const DAMP_RATE: u8 = 12;
+// The release rates used when a note is released with sustain on, or by a percussive patch
+const SUSTAIN_RELEASE_RATE: u8 = 5;
+const PERCUSSIVE_RELEASE_RATE: u8 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EnvelopeState {
+    Damp,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+// The settings of one operator, decoded from a patch
+#[derive(Clone, Copy, Default)]
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    // Set for sustained sounds that hold the sustain level while the key is on
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    // Only used by the modulator, the carrier level comes from the channel volume
+    total_level: u8,
+    // Outputs silence for the negative half of the sine wave
+    half_wave: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+}
+
+// A decoded 8 byte patch
+#[derive(Clone, Copy, Default)]
+struct Patch {
+    modulator: OperatorPatch,
+    carrier: OperatorPatch,
+    feedback: u8,
+}
+
+impl Patch {
+    fn new(data: &[u8; 8]) -> Patch {
+        let get_operator = |index: usize| -> OperatorPatch {
+            OperatorPatch {
+                tremolo: data[index] & 0b1000_0000 == 0b1000_0000,
+                vibrato: data[index] & 0b0100_0000 == 0b0100_0000,
+                sustained: data[index] & 0b0010_0000 == 0b0010_0000,
+                key_scale_rate: data[index] & 0b0001_0000 == 0b0001_0000,
+                multiplier: data[index] & 0b0000_1111,
+                key_scale_level: data[2 + index] >> 6,
+                total_level: if index == 0 { data[2] & 0b0011_1111 } else { 0 },
+                half_wave: data[3] & (0b0000_1000 << index) != 0,
+                attack_rate: data[4 + index] >> 4,
+                decay_rate: data[4 + index] & 0b0000_1111,
+                sustain_level: data[6 + index] >> 4,
+                release_rate: data[6 + index] & 0b0000_1111,
+            }
+        };
+
+        Patch {
+            modulator: get_operator(0),
+            carrier: get_operator(1),
+            feedback: data[3] & 0b0000_0111,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Operator {
+    // 19 bit phase accumulator, the top 10 bits index the sine wave
+    phase: u32,
+    envelope: u8,
+    state: EnvelopeState,
+    // The last two outputs, used for the modulator feedback
+    outputs: [i32; 2],
+}
+
+impl Operator {
+    fn new() -> Operator {
+        Operator {
+            phase: 0,
+            envelope: ENVELOPE_SILENT,
+            state: EnvelopeState::Release,
+            outputs: [0, 0],
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    frequency: u16,
+    block: u8,
+    key_on: bool,
+    sustain_on: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+
+impl Channel {
+    fn new() -> Channel {
+        Channel {
+            frequency: 0,
+            block: 0,
+            key_on: false,
+            sustain_on: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+        }
+    }
+
+    fn set_key_on(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.modulator.state = EnvelopeState::Damp;
+            self.carrier.state = EnvelopeState::Damp;
+        } else if !key_on && self.key_on {
+            self.modulator.state = EnvelopeState::Release;
+            self.carrier.state = EnvelopeState::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    // The key scale rate offset, from the block and the top bit of the frequency number
+    fn get_key_scale_rate(&self, patch: &OperatorPatch) -> u32 {
+        let offset: u32 = (u32::from(self.block) << 1) | u32::from(self.frequency >> 8);
+        if patch.key_scale_rate {
+            offset
+        } else {
+            offset >> 2
+        }
+    }
+
+    // The key scale level attenuation in 0.1875dB units
+    fn get_key_scale_level(&self, patch: &OperatorPatch) -> u32 {
+        let level: i32 = (KEY_SCALE_LEVEL_TABLE[usize::from(self.frequency >> 5)] << 2)
+            - ((8 - i32::from(self.block)) << 5);
+        let level: u32 = level.max(0) as u32;
+        match patch.key_scale_level {
+            0 => 0,
+            1 => level >> 2,
+            2 => level >> 1,
+            _ => level,
+        }
+    }
+}
+
+pub struct Vrc7Audio {
+    custom_patch: [u8; 8],
+    channels: [Channel; CHANNEL_COUNT],
+    selected_register: u8,
+
+    // Counts samples for the envelope generator and the LFOs
+    sample_counter: u32,
+    tremolo_position: u32,
+    vibrato_position: u32,
+    cpu_cycles: u32,
+    output: i32,
+
+    // Quarter wave log-sin table and exponent table, both in 1/256 steps of log2
+    log_sin_table: [u32; 256],
+    exp_table: [u32; 256],
+}
+
+impl Vrc7Audio {
+    pub fn new() -> Vrc7Audio {
+        let mut log_sin_table: [u32; 256] = [0; 256];
+        let mut exp_table: [u32; 256] = [0; 256];
+        for index in 0..256 {
+            let angle: f64 = (index as f64 + 0.5) * PI / 512.0;
+            log_sin_table[index] = (-angle.sin().log2() * 256.0).round() as u32;
+            exp_table[index] = (1024.0 * ((255 - index) as f64 / 256.0).exp2()).round() as u32;
+        }
+
+        Vrc7Audio {
+            custom_patch: [0; 8],
+            channels: [Channel::new(); CHANNEL_COUNT],
+            selected_register: 0,
+            sample_counter: 0,
+            tremolo_position: 0,
+            vibrato_position: 0,
+            cpu_cycles: 0,
+            output: 0,
+            log_sin_table,
+            exp_table,
+        }
+    }
+
+    // Silence the synthesizer and clear all of its registers
+    pub fn reset(&mut self) {
+        self.custom_patch = [0; 8];
+        self.channels = [Channel::new(); CHANNEL_COUNT];
+        self.selected_register = 0;
+        self.output = 0;
+    }
+
+    // $9010 selects a register
+    pub fn write_address(&mut self, value: u8) {
+        self.selected_register = value;
+    }
+
+    // $9030 writes the selected register
+    pub fn write_data(&mut self, value: u8) {
+        self.write_register(self.selected_register, value);
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        let channel: usize = usize::from(register & 0x0F);
+        match register {
+            0x00..=0x07 => self.custom_patch[usize::from(register)] = value,
+            0x10..=0x15 => {
+                let channel: &mut Channel = &mut self.channels[channel];
+                channel.frequency = (channel.frequency & 0x100) | u16::from(value);
+            }
+            0x20..=0x25 => {
+                // --SK BBBF
+                let channel: &mut Channel = &mut self.channels[channel];
+                channel.frequency = (channel.frequency & 0xFF) | (u16::from(value & 1) << 8);
+                channel.block = (value >> 1) & 0b111;
+                channel.sustain_on = value & 0b0010_0000 == 0b0010_0000;
+                channel.set_key_on(value & 0b0001_0000 == 0b0001_0000);
+            }
+            0x30..=0x35 => {
+                // IIII VVVV
+                self.channels[channel].instrument = value >> 4;
+                self.channels[channel].volume = value & 0x0F;
+            }
+            // The rhythm and test registers of the YM2413 and channels 6 to 8 do not exist
+            _ => {}
+        }
+    }
+
+    pub fn clock_cpu(&mut self) {
+        self.cpu_cycles += 1;
+        if self.cpu_cycles == SAMPLE_CPU_CYCLES {
+            self.cpu_cycles = 0;
+            self.output = self.generate_sample();
+        }
+    }
+
+    pub fn get_output(&self) -> f32 {
+        self.output as f32 * OUTPUT_SCALE
+    }
+
+    fn get_patch(&self, instrument: u8) -> Patch {
+        match instrument {
+            0 => Patch::new(&self.custom_patch),
+            _ => Patch::new(&BUILT_IN_PATCHES[usize::from(instrument) - 1]),
+        }
+    }
+
+    // Run the synthesizer for one sample and return the sum of the channel outputs
+    pub fn generate_sample(&mut self) -> i32 {
+        self.sample_counter = self.sample_counter.wrapping_add(1);
+        if self.sample_counter.is_multiple_of(TREMOLO_SAMPLES) {
+            self.tremolo_position = (self.tremolo_position + 1) % TREMOLO_STEPS;
+        }
+        if self.sample_counter.is_multiple_of(VIBRATO_SAMPLES) {
+            self.vibrato_position = (self.vibrato_position + 1) % 8;
+        }
+
+        let mut output: i32 = 0;
+        for index in 0..CHANNEL_COUNT {
+            output += self.generate_channel_sample(index);
+        }
+        output
+    }
+
+    fn generate_channel_sample(&mut self, index: usize) -> i32 {
+        let mut channel: Channel = self.channels[index];
+        let patch: Patch = self.get_patch(channel.instrument);
+
+        // The modulator feeds back its previous two outputs into its own phase
+        let mut modulator: Operator = channel.modulator;
+        self.clock_operator(&channel, &patch.modulator, &mut modulator);
+        let feedback: i32 = if patch.feedback == 0 {
+            0
+        } else {
+            (modulator.outputs[0] + modulator.outputs[1]) >> (9 - patch.feedback)
+        };
+        let attenuation: u32 = self.get_attenuation(
+            &channel,
+            &patch.modulator,
+            &modulator,
+            u32::from(patch.modulator.total_level) << 2,
+        );
+        let modulator_output: i32 = self.get_operator_output(
+            &modulator,
+            (modulator.phase >> 9) as i32 + feedback,
+            attenuation,
+            patch.modulator.half_wave,
+        );
+        modulator.outputs = [modulator_output, modulator.outputs[0]];
+
+        // The carrier is phase modulated by the modulator and its level is the channel volume
+        let mut carrier: Operator = channel.carrier;
+        self.clock_operator(&channel, &patch.carrier, &mut carrier);
+        let attenuation: u32 = self.get_attenuation(
+            &channel,
+            &patch.carrier,
+            &carrier,
+            u32::from(channel.volume) << 4,
+        );
+        let carrier_output: i32 = self.get_operator_output(
+            &carrier,
+            (carrier.phase >> 9) as i32 + modulator_output,
+            attenuation,
+            patch.carrier.half_wave,
+        );
+        carrier.outputs = [carrier_output, carrier.outputs[0]];
+
+        channel.modulator = modulator;
+        channel.carrier = carrier;
+        self.channels[index] = channel;
+        carrier_output
+    }
+
+    // Step the envelope generator and the phase of an operator
+    fn clock_operator(&self, channel: &Channel, patch: &OperatorPatch, operator: &mut Operator) {
+        let rate: u8 = match operator.state {
+            EnvelopeState::Damp => DAMP_RATE,
+            EnvelopeState::Attack => patch.attack_rate,
+            EnvelopeState::Decay => patch.decay_rate,
+            EnvelopeState::Sustain if patch.sustained => 0,
+            EnvelopeState::Sustain => patch.release_rate,
+            EnvelopeState::Release if channel.sustain_on => SUSTAIN_RELEASE_RATE,
+            EnvelopeState::Release if patch.sustained => patch.release_rate,
+            EnvelopeState::Release => PERCUSSIVE_RELEASE_RATE,
+        };
+        let increment: u32 = self.get_envelope_increment(rate, channel.get_key_scale_rate(patch));
+        let envelope: u32 = u32::from(operator.envelope);
+
+        match operator.state {
+            EnvelopeState::Attack => {
+                // The attack curve is exponential, and the fastest rates jump straight to full volume
+                if u32::from(rate) * 4 + channel.get_key_scale_rate(patch) >= 60 {
+                    operator.envelope = 0;
+                } else if increment > 0 {
+                    operator.envelope -= ((envelope + 1) * increment).div_ceil(8) as u8;
+                }
+                if operator.envelope == 0 {
+                    operator.state = EnvelopeState::Decay;
+                }
+            }
+            _ => {
+                operator.envelope = (envelope + increment).min(u32::from(ENVELOPE_SILENT)) as u8;
+            }
+        }
+
+        match operator.state {
+            EnvelopeState::Damp if operator.envelope == ENVELOPE_SILENT => {
+                // The new note starts from the beginning of the wave
+                operator.state = EnvelopeState::Attack;
+                operator.phase = 0;
+            }
+            EnvelopeState::Decay if operator.envelope >= patch.sustain_level << 3 => {
+                operator.state = EnvelopeState::Sustain;
+            }
+            _ => {}
+        }
+
+        // The vibrato moves the frequency number by an amount that scales with the frequency
+        let mut frequency: i32 = i32::from(channel.frequency);
+        if patch.vibrato {
+            let range: i32 = frequency >> 6;
+            frequency += (range * VIBRATO_TABLE[self.vibrato_position as usize]) >> 2;
+        }
+        let increment: u32 = ((frequency.max(0) as u32
+            * MULTIPLIER_TABLE[usize::from(patch.multiplier)])
+            << channel.block)
+            >> 1;
+        operator.phase = (operator.phase + increment) & 0x7FFFF;
+    }
+
+    fn get_envelope_increment(&self, rate: u8, key_scale_rate: u32) -> u32 {
+        if rate == 0 {
+            return 0;
+        }
+
+        let rate: u32 = (u32::from(rate) * 4 + key_scale_rate).min(63);
+        let increments: &[u32; 8] = &ENVELOPE_INCREMENT_TABLE[(rate & 0b11) as usize];
+        if rate < 48 {
+            // Slower rates only step every 2^n samples
+            let shift: u32 = 13 - (rate >> 2);
+            if self.sample_counter & ((1 << shift) - 1) != 0 {
+                0
+            } else {
+                increments[((self.sample_counter >> shift) & 0b111) as usize]
+            }
+        } else {
+            increments[(self.sample_counter & 0b111) as usize] << ((rate >> 2) - 12)
+        }
+    }
+
+    // The total attenuation of an operator in 0.1875dB units
+    fn get_attenuation(
+        &self,
+        channel: &Channel,
+        patch: &OperatorPatch,
+        operator: &Operator,
+        level: u32,
+    ) -> u32 {
+        let mut attenuation: u32 =
+            (u32::from(operator.envelope) << 1) + level + channel.get_key_scale_level(patch);
+        if patch.tremolo {
+            attenuation += self.get_tremolo();
+        }
+        attenuation
+    }
+
+    // The amplitude modulation, up to 4.8dB
+    fn get_tremolo(&self) -> u32 {
+        let half: u32 = TREMOLO_STEPS / 2;
+        if self.tremolo_position < half {
+            self.tremolo_position / 4
+        } else {
+            (TREMOLO_STEPS - 1 - self.tremolo_position) / 4
+        }
+    }
+
+    // Look up the output of an operator for a 10 bit phase and attenuation
+    fn get_operator_output(
+        &self,
+        operator: &Operator,
+        phase: i32,
+        attenuation: u32,
+        half_wave: bool,
+    ) -> i32 {
+        // An envelope at its maximum attenuation silences the operator
+        if operator.envelope == ENVELOPE_SILENT {
+            return 0;
+        }
+
+        let phase: u32 = (phase & 0x3FF) as u32;
+        let negative: bool = phase & 0x200 == 0x200;
+        if negative && half_wave {
+            return 0;
+        }
+
+        // The second quarter of each half is the first quarter mirrored
+        let mut index: u32 = phase & 0xFF;
+        if phase & 0x100 == 0x100 {
+            index ^= 0xFF;
+        }
+
+        let total: u32 = self.log_sin_table[index as usize] + (attenuation << 3);
+        let shift: u32 = total >> 8;
+        if shift >= 13 {
+            return 0;
+        }
+
+        let value: i32 = ((self.exp_table[(total & 0xFF) as usize] << 1) >> shift) as i32;
+        if negative {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write registers then render samples from the synthesizer
+    fn render(audio: &mut Vrc7Audio, writes: &[(u8, u8)], samples: usize) -> Vec<i16> {
+        for (register, value) in writes {
+            audio.write_address(*register);
+            audio.write_data(*value);
+        }
+        (0..samples)
+            .map(|_| audio.generate_sample() as i16)
+            .collect()
+    }
+
+    // Decode a reference dump, stored as little endian 16 bit samples
+    // The dumps in test_data are rendered by test_data/render_reference.py, a separate floating
+    // point model of the YM2413 that computes the sine and attenuation without the ROM tables
+    fn decode_dump(data: &[u8]) -> Vec<i16> {
+        data.chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    // Check samples against a reference dump
+    // The synthesizer rounds attenuation to 1/32 of an octave and its sine and exponent lookups to
+    // the steps of the chip's ROMs, and phase modulation by the modulator magnifies those errors in
+    // the carrier, so each sample may differ from the reference by up to 6% of an operator at full
+    // level and the samples may differ by 1.5% on average
+    fn assert_matches_dump(samples: &[i16], reference: &[i16]) {
+        assert_eq!(samples.len(), reference.len());
+        let mut largest: f64 = 0.0;
+        let mut squares: f64 = 0.0;
+        for (sample, expected) in samples.iter().zip(reference) {
+            let difference: f64 = f64::from(*sample - *expected).abs() / 4096.0;
+            largest = largest.max(difference);
+            squares += difference * difference;
+        }
+        let average: f64 = (squares / samples.len() as f64).sqrt();
+        assert!(largest < 0.06, "largest difference {}", largest);
+        assert!(average < 0.015, "average difference {}", average);
+    }
+
+    // A custom patch with a silent modulator and a carrier that plays a pure sine wave
+    const SINE_PATCH: [(u8, u8); 8] = [
+        (0x00, 0x20),
+        (0x01, 0x21),
+        (0x02, 0x3F),
+        (0x03, 0x00),
+        (0x04, 0xF0),
+        (0x05, 0xF0),
+        (0x06, 0x0F),
+        (0x07, 0x0F),
+    ];
+
+    // Entries of the log-sine and exponent ROMs read from decapsulated OPL chips, which the OPLL
+    // shares, from "OPLx decapsulated" by Matthew Gambrell and Olli Niemitalo
+    const LOG_SIN_ROM: [(usize, u32); 10] = [
+        (0, 0x859),
+        (1, 0x6C3),
+        (2, 0x607),
+        (3, 0x58B),
+        (4, 0x52E),
+        (5, 0x4E4),
+        (6, 0x4A6),
+        (7, 0x471),
+        (128, 0x07F),
+        (255, 0x000),
+    ];
+    const EXP_ROM: [(usize, u32); 10] = [
+        (0, 0x7FA),
+        (1, 0x7F5),
+        (2, 0x7EF),
+        (3, 0x7EA),
+        (4, 0x7E4),
+        (5, 0x7DF),
+        (6, 0x7DA),
+        (7, 0x7D4),
+        (128, 0x5A4),
+        (255, 0x400),
+    ];
+
+    #[test]
+    fn test_tables_match_chip_rom() {
+        // Prep for the test
+        let audio: Vrc7Audio = Vrc7Audio::new();
+
+        // Assert results, the generated tables hold the same values as the chip's ROMs
+        for (index, value) in LOG_SIN_ROM {
+            assert_eq!(audio.log_sin_table[index], value);
+        }
+        for (index, value) in EXP_ROM {
+            assert_eq!(audio.exp_table[index], value);
+        }
+    }
+
+    #[test]
+    fn test_built_in_note_matches_reference() {
+        // Prep for the test, instrument 3 playing A4 then released
+        // Instruments with strong feedback are left out, as their modulators are chaotic enough
+        // that rounding differences soon become different waveforms
+        let mut audio: Vrc7Audio = Vrc7Audio::new();
+        let mut samples: Vec<i16> = render(
+            &mut audio,
+            &[(0x30, 0x30), (0x10, 0x22), (0x20, 0x19)],
+            4096,
+        );
+        samples.append(&mut render(&mut audio, &[(0x20, 0x09)], 4096));
+        let reference: Vec<i16> = decode_dump(include_bytes!("test_data/built_in_note.bin"));
+
+        // Assert results
+        assert_matches_dump(&samples, &reference);
+    }
+
+    #[test]
+    fn test_custom_chord_matches_reference() {
+        // Prep for the test, a custom patch with key scaling, feedback and a half wave carrier
+        // playing C5, E5 and G5 then released
+        let mut audio: Vrc7Audio = Vrc7Audio::new();
+        let mut writes: Vec<(u8, u8)> = [0x21, 0x21, 0x4E, 0x95, 0xF0, 0xF2, 0x00, 0x27]
+            .iter()
+            .enumerate()
+            .map(|(register, value)| (register as u8, *value))
+            .collect();
+        writes.append(
+            &mut [
+                (0x30, 0x02),
+                (0x10, 0x59),
+                (0x20, 0x19),
+                (0x31, 0x03),
+                (0x11, 0xB2),
+                (0x21, 0x19),
+                (0x32, 0x04),
+                (0x12, 0x02),
+                (0x22, 0x1B),
+            ]
+            .to_vec(),
+        );
+        let mut samples: Vec<i16> = render(&mut audio, &writes, 6144);
+        samples.append(&mut render(
+            &mut audio,
+            &[(0x20, 0x09), (0x21, 0x09), (0x22, 0x0B)],
+            2048,
+        ));
+        let reference: Vec<i16> = decode_dump(include_bytes!("test_data/custom_chord.bin"));
+
+        // Assert results
+        assert_matches_dump(&samples, &reference);
+    }
+
+    #[test]
+    fn test_sine_pitch() {
+        // Prep for the test, frequency number 0x122 in block 4 with a multiplier of 1
+        let mut audio: Vrc7Audio = Vrc7Audio::new();
+        let mut writes: Vec<(u8, u8)> = SINE_PATCH.to_vec();
+        writes.append(&mut [(0x30, 0x00), (0x10, 0x22), (0x20, 0x19)].to_vec());
+        let samples: Vec<i16> = render(&mut audio, &writes, 49716);
+
+        // Count the rising zero crossings over one second of samples
+        let crossings: usize = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0 && pair[1] >= 0)
+            .count();
+
+        // Assert results, frequency = fnum * 49716 * 2^(block - 1) / 2^18 = 440Hz, and the wave
+        // starts at phase 0 so the first rising crossing is not counted
+        assert!((439..=441).contains(&crossings));
+        assert!(samples.iter().any(|sample| *sample > 4000));
+    }
+
+    #[test]
+    fn test_volume_and_release() {
+        // Prep for the test, the sine patch at full and at reduced volume
+        let mut audio: Vrc7Audio = Vrc7Audio::new();
+        let mut writes: Vec<(u8, u8)> = SINE_PATCH.to_vec();
+        writes.append(&mut [(0x30, 0x00), (0x10, 0x22), (0x20, 0x19)].to_vec());
+        let loud: i16 = *render(&mut audio, &writes, 2048).iter().max().unwrap();
+        let quiet: i16 = *render(&mut audio, &[(0x30, 0x02)], 2048)
+            .iter()
+            .max()
+            .unwrap();
+
+        // Assert results, 6dB of attenuation halves the output
+        assert!((quiet as f32 / loud as f32 - 0.5).abs() < 0.01);
+
+        // The release rate of 15 silences the note quickly after key off
+        let released: Vec<i16> = render(&mut audio, &[(0x20, 0x09)], 2048);
+        assert!(released[1024..].iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn test_sample_clock() {
+        // Prep for the test
+        let mut audio: Vrc7Audio = Vrc7Audio::new();
+        let mut writes: Vec<(u8, u8)> = SINE_PATCH.to_vec();
+        writes.append(&mut [(0x30, 0x00), (0x10, 0xFF), (0x20, 0x1F)].to_vec());
+        render(&mut audio, &writes, 0);
+
+        // Assert results, a new sample is only produced every 36 CPU cycles
+        for _ in 0..SAMPLE_CPU_CYCLES - 1 {
+            audio.clock_cpu();
+        }
+        assert_eq!(audio.get_output(), 0.0);
+        for _ in 0..SAMPLE_CPU_CYCLES * 4 {
+            audio.clock_cpu();
+        }
+        assert!(audio.get_output() != 0.0);
+
+        // Resetting silences the output immediately
+        audio.reset();
+        assert_eq!(audio.get_output(), 0.0);
+    }
+}
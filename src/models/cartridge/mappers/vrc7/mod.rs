@@ -0,0 +1,292 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Konami VRC7 (mapper 85)
+// https://www.nesdev.org/wiki/VRC7
+
+pub mod audio;
+
+use crate::common::interrupt::InterruptLine;
+use crate::models::cartridge::mappers::vrc7::audio::Vrc7Audio;
+use crate::models::cartridge::mappers::vrc_irq::VrcIrq;
+use crate::models::cartridge::mappers::{
+    get_battery_ram, load_battery_ram, read_bank, write_bank, write_trainer, Mapper,
+};
+use crate::models::cartridge::{Cartridge, Mirroring};
+
+// NES 2.0 submappers for the two board wirings, VRC7b selects the second register of each pair
+// with A3 and VRC7a with A4. Submapper 0 responds to both.
+const SUBMAPPER_VRC7B: u8 = 1;
+const SUBMAPPER_VRC7A: u8 = 2;
+const A3: u16 = 1 << 3;
+const A4: u16 = 1 << 4;
+
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    register_select_lines: u16,
+
+    // The 8KB PRG banks at $8000, $A000 and $C000
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+
+    // The control register at $E000
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    audio_silenced: bool,
+
+    irq: VrcIrq,
+    audio: Vrc7Audio,
+}
+
+impl Vrc7 {
+    pub fn new(cartridge: Cartridge) -> Vrc7 {
+        let register_select_lines: u16 = match cartridge.submapper_number {
+            SUBMAPPER_VRC7B => A3,
+            SUBMAPPER_VRC7A => A4,
+            _ => A3 | A4,
+        };
+
+        Vrc7 {
+            chr: cartridge.get_chr_memory(),
+            chr_is_ram: cartridge.has_chr_ram(),
+            register_select_lines,
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
+            prg_nvram_size: cartridge.prg_nvram_size,
+            prg_rom: cartridge.prg_rom,
+            prg_banks: [0, 0, 0],
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            prg_ram_enabled: false,
+            audio_silenced: false,
+            irq: VrcIrq::new(),
+            audio: Vrc7Audio::new(),
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        // RS-- --MM
+        self.prg_ram_enabled = value & 0b1000_0000 == 0b1000_0000;
+        self.mirroring = match value & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        };
+
+        // The synthesizer is held in reset while the silence bit is set
+        self.audio_silenced = value & 0b0100_0000 == 0b0100_0000;
+        if self.audio_silenced {
+            self.audio.reset();
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => read_bank(&self.prg_ram, 0, 0x2000, address),
+            0x8000..=0xDFFF => {
+                let slot: usize = usize::from((address - 0x8000) >> 13);
+                read_bank(&self.prg_rom, self.prg_banks[slot], 0x2000, address)
+            }
+            0xE000..=0xFFFF => {
+                let last: usize = (self.prg_rom.len() / 0x2000).max(1) - 1;
+                read_bank(&self.prg_rom, last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        // The audio registers are decoded from A4 and A5 on both board wirings
+        match address & 0xF030 {
+            0x9010 if !self.audio_silenced => return self.audio.write_address(value),
+            0x9030 if !self.audio_silenced => return self.audio.write_data(value),
+            0x9010 | 0x9030 => return,
+            _ => {}
+        }
+
+        let second: bool = address & self.register_select_lines != 0;
+        match (address & 0xF000, second) {
+            (0x6000 | 0x7000, _) if self.prg_ram_enabled => {
+                write_bank(&mut self.prg_ram, 0, 0x2000, address, value);
+            }
+            (0x8000, false) => self.prg_banks[0] = usize::from(value & 0x3F),
+            (0x8000, true) => self.prg_banks[1] = usize::from(value & 0x3F),
+            (0x9000, false) => self.prg_banks[2] = usize::from(value & 0x3F),
+            (0xA000..=0xD000, _) => {
+                let index: usize = usize::from((address >> 12) - 0xA) * 2 + usize::from(second);
+                self.chr_banks[index] = usize::from(value);
+            }
+            (0xE000, false) => self.write_control(value),
+            (0xE000, true) => self.irq.write_latch(value),
+            (0xF000, false) => self.irq.write_control(value),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+        read_bank(&self.chr, bank, 0x0400, address)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let bank: usize = self.chr_banks[usize::from((address >> 10) & 0b111)];
+            write_bank(&mut self.chr, bank, 0x0400, address, value);
+        }
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self) {
+        if !self.audio_silenced {
+            self.audio.clock_cpu();
+        }
+        self.irq.clock_cpu();
+    }
+
+    fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.irq.connect_irq_line(line);
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_battery_ram(&self.prg_ram, self.prg_nvram_size)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_battery_ram(&mut self.prg_ram, self.prg_nvram_size, data);
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) {
+        write_trainer(&mut self.prg_ram, 0, trainer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::mappers::vrc7::audio::SAMPLE_CPU_CYCLES;
+    use crate::models::cartridge::tests::get_test_cartridge;
+
+    #[test]
+    fn test_prg_and_chr_banks() {
+        // Prep for the test, a VRC7a board with 16 8KB PRG banks and 32 1KB CHR banks
+        let mut mapper: Vrc7 = Vrc7::new(get_test_cartridge(85, 2, 16, 0x2000, 32, 0x0400));
+        mapper.cpu_write(0x8000, 3);
+        mapper.cpu_write(0x8010, 4);
+        mapper.cpu_write(0x9000, 5);
+        mapper.cpu_write(0xA000, 20);
+        mapper.cpu_write(0xA010, 21);
+        mapper.cpu_write(0xD010, 27);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xA000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+        assert_eq!(mapper.ppu_read(0x0000), 20);
+        assert_eq!(mapper.ppu_read(0x0400), 21);
+        assert_eq!(mapper.ppu_read(0x1C00), 27);
+    }
+
+    #[test]
+    fn test_vrc7b_wiring() {
+        // Prep for the test, a VRC7b board selects the second register with A3
+        let mut mapper: Vrc7 = Vrc7::new(get_test_cartridge(85, 1, 16, 0x2000, 32, 0x0400));
+        mapper.cpu_write(0x8008, 6);
+        mapper.cpu_write(0xB008, 9);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0xA000), 6);
+        assert_eq!(mapper.ppu_read(0x0C00), 9);
+    }
+
+    #[test]
+    fn test_control_register() {
+        // Prep for the test
+        let mut mapper: Vrc7 = Vrc7::new(get_test_cartridge(85, 0, 4, 0x2000, 8, 0x0400));
+
+        // PRG-RAM is disabled until bit 7 is set
+        mapper.cpu_write(0x6000, 0x12);
+        assert_eq!(mapper.cpu_read(0x6000), 0x00);
+        mapper.cpu_write(0xE000, 0b1000_0001);
+        mapper.cpu_write(0x6000, 0x12);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x6000), 0x12);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_irq() {
+        // Prep for the test, an IRQ after 2 CPU cycles
+        let mut mapper: Vrc7 = Vrc7::new(get_test_cartridge(85, 2, 4, 0x2000, 8, 0x0400));
+        let line: InterruptLine = InterruptLine::new();
+        mapper.connect_irq_line(line.clone());
+        mapper.cpu_write(0xE010, 0xFE);
+        mapper.cpu_write(0xF000, 0b0000_0110);
+
+        // Assert results
+        mapper.clock_cpu();
+        assert!(!line.is_asserted());
+        mapper.clock_cpu();
+        assert!(line.is_asserted());
+        mapper.cpu_write(0xF010, 0);
+        assert!(!line.is_asserted());
+    }
+
+    #[test]
+    fn test_audio_and_silence() {
+        // Prep for the test, key on instrument 3 on channel 0
+        let mut mapper: Vrc7 = Vrc7::new(get_test_cartridge(85, 2, 4, 0x2000, 8, 0x0400));
+        let writes: [(u8, u8); 3] = [(0x30, 0x30), (0x10, 0x80), (0x20, 0x1C)];
+        for (register, value) in writes {
+            mapper.cpu_write(0x9010, register);
+            mapper.cpu_write(0x9030, value);
+        }
+        for _ in 0..SAMPLE_CPU_CYCLES * 64 {
+            mapper.clock_cpu();
+        }
+        assert!(mapper.get_audio_output() != 0.0);
+
+        // Assert results, the silence bit resets the synthesizer
+        mapper.cpu_write(0xE000, 0b0100_0000);
+        for _ in 0..SAMPLE_CPU_CYCLES * 64 {
+            mapper.clock_cpu();
+        }
+        assert_eq!(mapper.get_audio_output(), 0.0);
+    }
+}
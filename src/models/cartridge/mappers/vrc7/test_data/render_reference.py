@@ -0,0 +1,319 @@
+#!/usr/bin/env python3
+#
+# MIT License
+#
+# Copyright (c) 2021-2024 fontivan
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+#
+# Renders the reference dumps that the VRC7 audio tests compare against
+#
+# This is a floating point model of the YM2413 written from its datasheet and the VRC7 audio page
+# on the nesdev wiki, kept apart from the emulator's fixed point synthesizer so that the two can
+# catch each other's mistakes. The sine wave, attenuation, key scale level and feedback are all
+# computed in floating point here instead of through the log-sine and exponent tables. Vibrato and
+# tremolo are not modelled, so the scenarios do not use them.
+#
+# The dumps are little endian 16 bit samples at the chip's rate of 3579545 / 72 Hz, scaled so that
+# an operator at full level swings between -4096 and 4096.
+#
+# Usage: python3 render_reference.py
+
+import math
+import os
+import struct
+
+FULL_SCALE = 4096.0
+
+# Instruments 1 to 15 of the VRC7
+BUILT_IN_PATCHES = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+]
+
+# The datasheet's frequency multipliers
+MULTIPLIERS = [0.5, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 10, 12, 12, 15, 15]
+
+# Key scale level in dB for the top 4 bits of the frequency number in block 7, falling by 6dB for
+# each lower block
+KEY_SCALE_LEVEL_DB = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25,
+    36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25, 42.0,
+]
+# 0, 1.5, 3 and 6dB per octave
+KEY_SCALE_LEVEL_FRACTION = [0.0, 0.25, 0.5, 1.0]
+
+# The envelope generator counts in steps of 0.375dB, from 0 for full level to 127 for silence
+ENVELOPE_STEP_DB = 0.375
+ENVELOPE_MAX = 127
+
+# The pattern of envelope steps taken over 8 updates for the bottom 2 bits of an effective rate
+STEP_PATTERNS = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+]
+
+# Fixed rates of the chip, for damping a note before it starts, and releasing a note with the
+# sustain bit set or a percussive note
+DAMP_RATE = 12
+SUSTAIN_ON_RELEASE_RATE = 5
+PERCUSSIVE_RELEASE_RATE = 7
+
+
+def decode_operator(patch, index):
+    return {
+        "sustained": bool(patch[index] & 0x20),
+        "key_scale_rate": bool(patch[index] & 0x10),
+        "multiplier": MULTIPLIERS[patch[index] & 0x0F],
+        "key_scale_level": patch[2 + index] >> 6,
+        "total_level_db": (patch[2] & 0x3F) * 0.75 if index == 0 else 0.0,
+        "rectified": bool(patch[3] & (0x08 << index)),
+        "attack": patch[4 + index] >> 4,
+        "decay": patch[4 + index] & 0x0F,
+        "sustain_level": patch[6 + index] >> 4,
+        "release": patch[6 + index] & 0x0F,
+    }
+
+
+class Operator:
+    def __init__(self):
+        # Phase in cycles of the sine wave
+        self.phase = 0.0
+        self.envelope = ENVELOPE_MAX
+        self.stage = "release"
+        self.last_outputs = [0.0, 0.0]
+
+    def effective_rate(self, rate, channel, settings):
+        if rate == 0:
+            return 0
+        offset = channel.block * 2 + (channel.fnum >> 8)
+        if not settings["key_scale_rate"]:
+            offset //= 4
+        return min(rate * 4 + offset, 63)
+
+    def envelope_step(self, rate, counter):
+        if rate == 0:
+            return 0
+        if rate < 48:
+            # Slow rates only update every 2^n samples
+            period = 1 << (13 - rate // 4)
+            if counter % period != 0:
+                return 0
+            return STEP_PATTERNS[rate % 4][(counter // period) % 8]
+        return STEP_PATTERNS[rate % 4][counter % 8] << (rate // 4 - 12)
+
+    def clock(self, channel, settings, counter):
+        if self.stage == "damp":
+            rate = DAMP_RATE
+        elif self.stage == "attack":
+            rate = settings["attack"]
+        elif self.stage == "decay":
+            rate = settings["decay"]
+        elif self.stage == "sustain":
+            rate = 0 if settings["sustained"] else settings["release"]
+        elif channel.sustain:
+            rate = SUSTAIN_ON_RELEASE_RATE
+        elif settings["sustained"]:
+            rate = settings["release"]
+        else:
+            rate = PERCUSSIVE_RELEASE_RATE
+        rate = self.effective_rate(rate, channel, settings)
+        step = self.envelope_step(rate, counter)
+
+        if self.stage == "attack":
+            # The attack is exponential, falling by a fraction of the remaining attenuation
+            if rate >= 60:
+                self.envelope = 0
+            elif step:
+                self.envelope -= -(-(self.envelope + 1) * step // 8)
+            if self.envelope <= 0:
+                self.envelope = 0
+                self.stage = "decay"
+        else:
+            self.envelope = min(self.envelope + step, ENVELOPE_MAX)
+
+        if self.stage == "damp" and self.envelope == ENVELOPE_MAX:
+            self.stage = "attack"
+            self.phase = 0.0
+        elif self.stage == "decay" and self.envelope >= settings["sustain_level"] * 8:
+            self.stage = "sustain"
+
+        # f = fnum * rate * 2^(block - 1) / 2^18 * multiplier
+        cycles = channel.fnum * 2 ** (channel.block - 1) * settings["multiplier"] / 2 ** 18
+        self.phase = (self.phase + cycles) % 1.0
+
+    def attenuation_db(self, channel, settings, level_db):
+        fraction = KEY_SCALE_LEVEL_FRACTION[settings["key_scale_level"]]
+        key_scale_db = KEY_SCALE_LEVEL_DB[channel.fnum >> 5] - 6.0 * (7 - channel.block)
+        return (
+            self.envelope * ENVELOPE_STEP_DB
+            + level_db
+            + max(key_scale_db, 0.0) * fraction
+        )
+
+    def output(self, modulation, attenuation_db, settings):
+        if self.envelope >= ENVELOPE_MAX:
+            return 0.0
+        # The sine wave is looked up with a 10 bit phase, to which the modulation is added in units
+        # of 1/1024 of a cycle, and each step of the ROM holds the value from the middle of the step
+        step = math.floor(self.phase * 1024.0 + modulation)
+        angle = 2.0 * math.pi * (step + 0.5) / 1024.0
+        value = math.sin(angle)
+        if value < 0.0 and settings["rectified"]:
+            return 0.0
+        return FULL_SCALE * value * 10.0 ** (-attenuation_db / 20.0)
+
+
+class Channel:
+    def __init__(self):
+        self.fnum = 0
+        self.block = 0
+        self.key = False
+        self.sustain = False
+        self.instrument = 0
+        self.volume = 0
+        self.modulator = Operator()
+        self.carrier = Operator()
+
+
+class Opll:
+    def __init__(self):
+        self.custom = [0] * 8
+        self.channels = [Channel() for _ in range(6)]
+        self.counter = 0
+
+    def write(self, register, value):
+        index = register & 0x0F
+        if register < 0x08:
+            self.custom[register] = value
+        elif 0x10 <= register <= 0x15:
+            channel = self.channels[index]
+            channel.fnum = (channel.fnum & 0x100) | value
+        elif 0x20 <= register <= 0x25:
+            channel = self.channels[index]
+            channel.fnum = (channel.fnum & 0xFF) | ((value & 1) << 8)
+            channel.block = (value >> 1) & 7
+            channel.sustain = bool(value & 0x20)
+            key = bool(value & 0x10)
+            if key and not channel.key:
+                channel.modulator.stage = "damp"
+                channel.carrier.stage = "damp"
+            elif channel.key and not key:
+                channel.modulator.stage = "release"
+                channel.carrier.stage = "release"
+            channel.key = key
+        elif 0x30 <= register <= 0x35:
+            self.channels[index].instrument = value >> 4
+            self.channels[index].volume = value & 0x0F
+
+    def sample(self):
+        self.counter += 1
+        total = 0.0
+        for channel in self.channels:
+            if channel.instrument == 0:
+                patch = self.custom
+            else:
+                patch = BUILT_IN_PATCHES[channel.instrument - 1]
+            modulator_settings = decode_operator(patch, 0)
+            carrier_settings = decode_operator(patch, 1)
+            feedback = patch[3] & 0x07
+
+            modulator = channel.modulator
+            modulator.clock(channel, modulator_settings, self.counter)
+            # Feedback of n adds a modulation of pi/16 * 2^(n-1) radians at full level
+            feedback_modulation = 0.0
+            if feedback:
+                feedback_modulation = sum(modulator.last_outputs) / 2 ** (9 - feedback)
+            modulator_out = modulator.output(
+                feedback_modulation,
+                modulator.attenuation_db(
+                    channel, modulator_settings, modulator_settings["total_level_db"]
+                ),
+                modulator_settings,
+            )
+            modulator.last_outputs = [modulator_out, modulator.last_outputs[0]]
+
+            carrier = channel.carrier
+            carrier.clock(channel, carrier_settings, self.counter)
+            carrier_out = carrier.output(
+                modulator_out,
+                carrier.attenuation_db(channel, carrier_settings, channel.volume * 3.0),
+                carrier_settings,
+            )
+            total += carrier_out
+        return total
+
+
+def render(chip, writes, samples):
+    for register, value in writes:
+        chip.write(register, value)
+    return [chip.sample() for _ in range(samples)]
+
+
+# Instrument 3 on channel 0 playing A4, frequency number 0x122 in block 4, then released
+def built_in_note():
+    chip = Opll()
+    samples = render(chip, [(0x30, 0x30), (0x10, 0x22), (0x20, 0x19)], 4096)
+    samples += render(chip, [(0x20, 0x09)], 4096)
+    return samples
+
+
+# A custom patch with key scaling, feedback and a half wave carrier, playing C5, E5 and G5 on three
+# channels, then released
+CUSTOM_PATCH = [0x21, 0x21, 0x4E, 0x95, 0xF0, 0xF2, 0x00, 0x27]
+
+
+def custom_chord():
+    chip = Opll()
+    writes = [(register, value) for register, value in enumerate(CUSTOM_PATCH)]
+    writes += [
+        (0x30, 0x02), (0x10, 0x59), (0x20, 0x19),
+        (0x31, 0x03), (0x11, 0xB2), (0x21, 0x19),
+        (0x32, 0x04), (0x12, 0x02), (0x22, 0x1B),
+    ]
+    samples = render(chip, writes, 6144)
+    samples += render(chip, [(0x20, 0x09), (0x21, 0x09), (0x22, 0x0B)], 2048)
+    return samples
+
+
+def save(name, samples):
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), name)
+    with open(path, "wb") as file:
+        for sample in samples:
+            file.write(struct.pack("<h", int(round(sample))))
+
+
+if __name__ == "__main__":
+    save("built_in_note.bin", built_in_note())
+    save("custom_chord.bin", custom_chord())
@@ -0,0 +1,171 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// IRQ counter shared by the Konami VRC4, VRC6 and VRC7
+// https://www.nesdev.org/wiki/VRC_IRQ
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+
+// In scanline mode the prescaler counts down by 3 every CPU cycle from 341, the number of PPU
+// dots in a scanline, so the counter is clocked roughly once per scanline
+const PRESCALER_PERIOD: i16 = 341;
+const PRESCALER_STEP: i16 = 3;
+
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    // The enable state restored when the IRQ is acknowledged
+    enable_after_acknowledge: bool,
+    // Set for cycle mode, where the counter is clocked on every CPU cycle
+    cycle_mode: bool,
+    line: InterruptLine,
+}
+
+impl VrcIrq {
+    pub fn new() -> VrcIrq {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enabled: false,
+            enable_after_acknowledge: false,
+            cycle_mode: false,
+            line: InterruptLine::new(),
+        }
+    }
+
+    pub fn connect_irq_line(&mut self, line: InterruptLine) {
+        self.line = line;
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    // VRC4 boards write the latch a nibble at a time
+    pub fn write_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xF0) | (value & 0x0F);
+    }
+
+    pub fn write_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0F) | ((value & 0x0F) << 4);
+    }
+
+    // Write the control register, in the form ---- -MEA
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_acknowledge = value & 0b0000_0001 == 0b0000_0001;
+        self.enabled = value & 0b0000_0010 == 0b0000_0010;
+        self.cycle_mode = value & 0b0000_0100 == 0b0000_0100;
+
+        // Enabling reloads the counter and resets the prescaler, and any write acknowledges
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+        self.line.set(InterruptSource::Mapper, false);
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.enabled = self.enable_after_acknowledge;
+        self.line.set(InterruptSource::Mapper, false);
+    }
+
+    pub fn clock_cpu(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= PRESCALER_STEP;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    // The counter counts up and raises an IRQ when it overflows
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.line.set(InterruptSource::Mapper, true);
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_mode() {
+        // Prep for the test, 3 cycles until the counter overflows
+        let mut irq: VrcIrq = VrcIrq::new();
+        let line: InterruptLine = InterruptLine::new();
+        irq.connect_irq_line(line.clone());
+        irq.write_latch(0xFD);
+        irq.write_control(0b0000_0111);
+
+        // Assert results
+        irq.clock_cpu();
+        irq.clock_cpu();
+        assert!(!line.is_asserted());
+        irq.clock_cpu();
+        assert!(line.is_asserted());
+
+        // Acknowledging restores the enable flag from the A bit and releases the line
+        irq.acknowledge();
+        assert!(!line.is_asserted());
+        assert!(irq.enabled);
+    }
+
+    #[test]
+    fn test_scanline_mode() {
+        // Prep for the test, overflow after one scanline
+        let mut irq: VrcIrq = VrcIrq::new();
+        let line: InterruptLine = InterruptLine::new();
+        irq.connect_irq_line(line.clone());
+        irq.write_latch_low(0x0F);
+        irq.write_latch_high(0x0F);
+        irq.write_control(0b0000_0010);
+
+        // A scanline is 341 / 3 = 113.67 CPU cycles, so the counter is clocked on the 114th
+        for _ in 0..113 {
+            irq.clock_cpu();
+        }
+        assert!(!line.is_asserted());
+        irq.clock_cpu();
+        assert!(line.is_asserted());
+
+        // Acknowledging with the A bit clear disables the counter
+        irq.acknowledge();
+        assert!(!irq.enabled);
+    }
+}
@@ -0,0 +1,267 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Cartridge descriptions and the mappers that give the CPU and PPU access to them.
+// Board and mapper behaviour is derived from the nesdev wiki
+// https://www.nesdev.org/wiki/Mapper
+
+pub mod archive;
+pub mod database;
+pub mod ines;
+pub mod mappers;
+pub mod patch;
+pub mod save;
+pub mod unif;
+
+use crate::common::memory::MemoryMappedDevice;
+use crate::models::cartridge::mappers::Mapper;
+use crate::models::region::Region;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// The arrangement of the two physical nametables within the PPU's four logical nametables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    // $2000 and $2400 share a nametable, as do $2800 and $2C00
+    Horizontal,
+    // $2000 and $2800 share a nametable, as do $2400 and $2C00
+    Vertical,
+    // All four logical nametables show the first physical nametable
+    SingleScreenLower,
+    // All four logical nametables show the second physical nametable
+    SingleScreenUpper,
+    // The cartridge provides extra memory for four unique nametables
+    FourScreen,
+}
+
+// Errors that can occur while loading a cartridge
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    // The file does not start with a recognised header
+    InvalidHeader,
+    // The file is shorter than its header says it should be
+    TruncatedData,
+    // There is no mapper implementation for this mapper number
+    UnsupportedMapper(u16),
+    // There is no mapper implementation for this UNIF board name
+    UnsupportedBoard(String),
+    // The Famicom Disk System BIOS is not the right size
+    InvalidBios,
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::InvalidHeader => write!(f, "ROM header is not valid"),
+            CartridgeError::TruncatedData => {
+                write!(f, "ROM data is shorter than the header claims")
+            }
+            CartridgeError::UnsupportedMapper(number) => {
+                write!(f, "Mapper {} is not supported", number)
+            }
+            CartridgeError::UnsupportedBoard(name) => {
+                write!(f, "Board {} is not supported", name)
+            }
+            CartridgeError::InvalidBios => write!(f, "FDS BIOS must be 8KB"),
+        }
+    }
+}
+
+// Everything needed to build a mapper for a cartridge, independent of the file format it came from
+pub struct Cartridge {
+    // The iNES mapper number
+    pub mapper_number: u16,
+
+    // The NES 2.0 submapper number, zero when not specified
+    pub submapper_number: u8,
+
+    // The program ROM, visible to the CPU
+    pub prg_rom: Vec<u8>,
+
+    // The character ROM, visible to the PPU. Empty when the board uses CHR-RAM instead.
+    pub chr_rom: Vec<u8>,
+
+    // The size of the PRG-RAM in bytes
+    pub prg_ram_size: usize,
+
+    // The size of the CHR-RAM in bytes
+    pub chr_ram_size: usize,
+
+    // How many bytes at the start of the PRG-RAM are battery backed, zero without a battery
+    pub prg_nvram_size: usize,
+
+    // The nametable mirroring that is hard wired on the board
+    pub mirroring: Mirroring,
+
+    // Bit 0 of header byte 6, which normally selects vertical mirroring but is kept for boards
+    // that give it another meaning when the four screen bit is also set
+    pub mirroring_flag: bool,
+
+    // Whether the PRG-RAM is battery backed
+    pub has_battery: bool,
+
+    // The trainer that copier hardware loaded into PRG-RAM before starting the game, usually
+    // patches for boards the copier imitated. Empty when the file has none.
+    pub trainer: Vec<u8>,
+
+    // The console region the game was made for
+    pub region: Region,
+}
+
+impl Cartridge {
+    // Get the pattern table memory for the board, either a copy of the CHR-ROM or zeroed CHR-RAM
+    pub fn get_chr_memory(&self) -> Vec<u8> {
+        if self.chr_rom.is_empty() {
+            // Boards without CHR-ROM have at least 8KB of CHR-RAM
+            vec![0; self.chr_ram_size.max(0x2000)]
+        } else {
+            self.chr_rom.clone()
+        }
+    }
+
+    // Whether the pattern table memory is writable
+    pub fn has_chr_ram(&self) -> bool {
+        self.chr_rom.is_empty()
+    }
+}
+
+// Gives the CPU access to a mapper through the memory map, between $4020 and $FFFF
+pub struct CartridgeSlot {
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+}
+
+impl CartridgeSlot {
+    pub fn new(mapper: Rc<RefCell<Box<dyn Mapper>>>) -> CartridgeSlot {
+        CartridgeSlot { mapper }
+    }
+}
+
+impl MemoryMappedDevice for CartridgeSlot {
+    fn read_byte(&mut self, address: usize) -> u8 {
+        self.mapper.borrow_mut().cpu_read(address as u16)
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) {
+        self.mapper.borrow_mut().cpu_write(address as u16, value)
+    }
+}
+
+// Sits in front of a device outside the cartridge address space, such as the PPU's registers, and
+// tells the mapper about every CPU write to it, for boards that watch those writes
+pub struct WriteMonitor {
+    device: Rc<RefCell<dyn MemoryMappedDevice>>,
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+}
+
+impl WriteMonitor {
+    pub fn new(
+        device: Rc<RefCell<dyn MemoryMappedDevice>>,
+        mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    ) -> WriteMonitor {
+        WriteMonitor { device, mapper }
+    }
+}
+
+impl MemoryMappedDevice for WriteMonitor {
+    fn read_byte(&mut self, address: usize) -> u8 {
+        self.device.borrow_mut().read_byte(address)
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) {
+        self.device.borrow_mut().write_byte(address, value);
+        self.mapper
+            .borrow_mut()
+            .notify_cpu_write(address as u16, value);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Helper function for the tests to build a cartridge with recognisable bank contents
+    // Every byte of each PRG and CHR bank is filled with the index of the bank it belongs to
+    pub fn get_test_cartridge(
+        mapper_number: u16,
+        submapper_number: u8,
+        prg_banks: usize,
+        prg_bank_size: usize,
+        chr_banks: usize,
+        chr_bank_size: usize,
+    ) -> Cartridge {
+        let mut prg_rom: Vec<u8> = Vec::new();
+        for bank in 0..prg_banks {
+            prg_rom.extend(vec![bank as u8; prg_bank_size]);
+        }
+
+        let mut chr_rom: Vec<u8> = Vec::new();
+        for bank in 0..chr_banks {
+            chr_rom.extend(vec![bank as u8; chr_bank_size]);
+        }
+
+        Cartridge {
+            mapper_number,
+            submapper_number,
+            prg_rom,
+            chr_rom,
+            prg_ram_size: 0,
+            chr_ram_size: 0,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Horizontal,
+            mirroring_flag: false,
+            has_battery: false,
+            trainer: Vec::new(),
+            region: Region::Ntsc,
+        }
+    }
+
+    #[test]
+    fn chr_ram_is_provided_without_chr_rom() {
+        // A board with no CHR-ROM
+        let cartridge: Cartridge = get_test_cartridge(0, 0, 1, 0x4000, 0, 0);
+
+        // It should be given 8KB of writable pattern memory
+        assert!(cartridge.has_chr_ram());
+        assert_eq!(cartridge.get_chr_memory().len(), 0x2000);
+    }
+
+    #[test]
+    fn trainer_is_written_to_prg_ram() {
+        // Prep for the test
+        let mut cartridge: Cartridge = get_test_cartridge(0, 0, 1, 0x4000, 1, 0x2000);
+        cartridge.prg_ram_size = 0x2000;
+        let mut mapper: Box<dyn Mapper> = mappers::new_mapper(cartridge).unwrap();
+        let mut trainer: Vec<u8> = vec![0; 512];
+        trainer[0] = 0x12;
+        trainer[511] = 0x34;
+        mapper.load_trainer(&trainer);
+
+        // Assert results
+        assert_eq!(mapper.cpu_read(0x6FFF), 0x00);
+        assert_eq!(mapper.cpu_read(0x7000), 0x12);
+        assert_eq!(mapper.cpu_read(0x71FF), 0x34);
+        assert_eq!(mapper.cpu_read(0x7200), 0x00);
+    }
+}
@@ -0,0 +1,207 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// BPS patches, which build the new data from copies out of the original, the patch, or the new
+// data written so far
+// https://www.romhacking.net/documents/746/
+//
+// After "BPS1" come the source and target sizes and any metadata, then actions that each hold a
+// command in the low 2 bits and a length above them. The patch ends with three CRC-32 checksums.
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::patch::{self, Checksums, PatchError};
+
+pub const HEADER: &[u8; 4] = b"BPS1";
+
+const SOURCE_READ: usize = 0;
+const TARGET_READ: usize = 1;
+const SOURCE_COPY: usize = 2;
+
+// Apply a patch to the data, which must be the data the patch was made for
+pub fn apply(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(HEADER) {
+        return Err(PatchError::InvalidHeader);
+    }
+    let (checksums, footer_start): (Checksums, usize) = patch::read_checksums(patch)?;
+
+    let mut position: usize = HEADER.len();
+    let source_size: usize = patch::read_number(patch, &mut position)?;
+    let target_size: usize = patch::read_number(patch, &mut position)?;
+    let metadata_size: usize = patch::read_number(patch, &mut position)?;
+    position += metadata_size;
+    if data.len() != source_size {
+        return Err(PatchError::SizeMismatch);
+    }
+    if Utils::get_crc32(data) != checksums.source {
+        return Err(PatchError::ChecksumMismatch);
+    }
+
+    let mut patched: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while position < footer_start {
+        let action: usize = patch::read_number(patch, &mut position)?;
+        let length: usize = (action >> 2) + 1;
+        if patched.len() + length > target_size {
+            return Err(PatchError::InvalidRecord);
+        }
+
+        match action & 0b11 {
+            // Bytes from the source at the same offset as they are written to
+            SOURCE_READ => {
+                let start: usize = patched.len();
+                let bytes: &[u8] = data
+                    .get(start..start + length)
+                    .ok_or(PatchError::InvalidRecord)?;
+                patched.extend_from_slice(bytes);
+            }
+            // Bytes stored in the patch
+            TARGET_READ => {
+                if position + length > footer_start {
+                    return Err(PatchError::TruncatedData);
+                }
+                patched.extend_from_slice(&patch[position..position + length]);
+                position += length;
+            }
+            // Bytes from anywhere in the source
+            SOURCE_COPY => {
+                source_offset = read_relative_offset(patch, &mut position, source_offset)?;
+                let bytes: &[u8] = data
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::InvalidRecord)?;
+                patched.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // Bytes from earlier in the target, copied one at a time as the copy can overlap
+            // the bytes it writes to repeat a pattern
+            _ => {
+                target_offset = read_relative_offset(patch, &mut position, target_offset)?;
+                for _ in 0..length {
+                    let value: u8 = *patched
+                        .get(target_offset)
+                        .ok_or(PatchError::InvalidRecord)?;
+                    patched.push(value);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if patched.len() != target_size {
+        return Err(PatchError::TruncatedData);
+    }
+    if Utils::get_crc32(&patched) != checksums.target {
+        return Err(PatchError::ChecksumMismatch);
+    }
+    Ok(patched)
+}
+
+// Copy offsets are stored relative to where the last copy of the same kind ended, with the
+// direction in the low bit
+fn read_relative_offset(
+    patch: &[u8],
+    position: &mut usize,
+    offset: usize,
+) -> Result<usize, PatchError> {
+    let value: usize = patch::read_number(patch, position)?;
+    let distance: usize = value >> 1;
+    if value & 1 == 1 {
+        offset
+            .checked_sub(distance)
+            .ok_or(PatchError::InvalidRecord)
+    } else {
+        Ok(offset + distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::patch::tests::{add_checksums, get_number};
+
+    const SOURCE: &[u8; 11] = b"HELLO WORLD";
+    const TARGET: &[u8; 20] = b"HELLO HELLO WORLD!!!";
+
+    // A patch using each of the four actions
+    fn get_test_patch() -> Vec<u8> {
+        let mut patch: Vec<u8> = HEADER.to_vec();
+        patch.extend(get_number(SOURCE.len()));
+        patch.extend(get_number(TARGET.len()));
+        patch.extend(get_number(4));
+        patch.extend(b"meta");
+
+        // "HELLO " from the same place in the source
+        patch.extend(get_number((5 << 2) | SOURCE_READ));
+        // "HELLO WORLD" from the start of the source
+        patch.extend(get_number((10 << 2) | SOURCE_COPY));
+        patch.extend(get_number(0));
+        // "!" from the patch
+        patch.extend(get_number(TARGET_READ));
+        patch.push(b'!');
+        // "!!" by copying the last byte of the target over itself, 17 bytes forward
+        patch.extend(get_number((1 << 2) | 3));
+        patch.extend(get_number(17 << 1));
+
+        add_checksums(&mut patch, SOURCE, TARGET);
+        patch
+    }
+
+    #[test]
+    fn test_apply() {
+        // Assert results
+        assert_eq!(apply(SOURCE, &get_test_patch()), Ok(TARGET.to_vec()));
+    }
+
+    #[test]
+    fn test_verification() {
+        // Prep for the test
+        let patch: Vec<u8> = get_test_patch();
+
+        // Assert results
+        assert_eq!(apply(b"HELLO", &patch), Err(PatchError::SizeMismatch));
+        assert_eq!(
+            apply(b"HELLO THERE", &patch),
+            Err(PatchError::ChecksumMismatch)
+        );
+        let mut corrupted: Vec<u8> = patch.clone();
+        corrupted[10] ^= 0xFF;
+        assert_eq!(apply(SOURCE, &corrupted), Err(PatchError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn test_invalid_copy() {
+        // Prep for the test, a copy from before the start of the source
+        let target: &[u8; 1] = b"H";
+        let mut patch: Vec<u8> = HEADER.to_vec();
+        patch.extend(get_number(SOURCE.len()));
+        patch.extend(get_number(target.len()));
+        patch.extend(get_number(0));
+        patch.extend(get_number(SOURCE_COPY));
+        patch.extend(get_number((1 << 1) | 1));
+        add_checksums(&mut patch, SOURCE, target);
+
+        // Assert results
+        assert_eq!(apply(SOURCE, &patch), Err(PatchError::InvalidRecord));
+    }
+}
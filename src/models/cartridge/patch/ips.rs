@@ -0,0 +1,133 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// IPS patches, a list of records that each replace bytes at a 24 bit offset
+// https://zerosoft.zophar.net/ips.php
+//
+// A record is a 3 byte offset, a 2 byte length and the data, or a length of zero followed by a
+// 2 byte count and a single byte to repeat. The patch starts with "PATCH" and ends with "EOF",
+// which can be followed by a 3 byte size to truncate the data to.
+
+use crate::models::cartridge::patch::PatchError;
+
+const HEADER: &[u8; 5] = b"PATCH";
+const FOOTER: &[u8; 3] = b"EOF";
+
+// Apply a patch to a copy of the data, growing it when a record writes past its end
+pub fn apply(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(HEADER) {
+        return Err(PatchError::InvalidHeader);
+    }
+
+    let mut patched: Vec<u8> = data.to_vec();
+    let mut position: usize = HEADER.len();
+    loop {
+        let header: &[u8] = patch
+            .get(position..position + 3)
+            .ok_or(PatchError::TruncatedData)?;
+        if header == FOOTER {
+            // The truncate extension shrinks the data to the size after the footer
+            if let Some(size) = patch.get(position + 3..position + 6) {
+                let size: usize =
+                    usize::from(size[0]) << 16 | usize::from(size[1]) << 8 | usize::from(size[2]);
+                patched.truncate(size);
+            }
+            return Ok(patched);
+        }
+        let offset: usize =
+            usize::from(header[0]) << 16 | usize::from(header[1]) << 8 | usize::from(header[2]);
+        let length: usize = read_u16(patch, position + 3)?;
+        position += 5;
+
+        // A zero length marks a run of a single repeated byte
+        let (bytes, record_size): (Vec<u8>, usize) = if length == 0 {
+            let count: usize = read_u16(patch, position)?;
+            let value: u8 = *patch.get(position + 2).ok_or(PatchError::TruncatedData)?;
+            (vec![value; count], 3)
+        } else {
+            let bytes: &[u8] = patch
+                .get(position..position + length)
+                .ok_or(PatchError::TruncatedData)?;
+            (bytes.to_vec(), length)
+        };
+        position += record_size;
+
+        if patched.len() < offset + bytes.len() {
+            patched.resize(offset + bytes.len(), 0);
+        }
+        patched[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+}
+
+fn read_u16(patch: &[u8], position: usize) -> Result<usize, PatchError> {
+    let bytes: &[u8] = patch
+        .get(position..position + 2)
+        .ok_or(PatchError::TruncatedData)?;
+    Ok(usize::from(bytes[0]) << 8 | usize::from(bytes[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_records() {
+        // Prep for the test, a normal record and a run of repeated bytes past the end of the data
+        let data: Vec<u8> = vec![0; 8];
+        let mut patch: Vec<u8> = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend([0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+        patch.extend(b"EOF");
+
+        // Assert results
+        assert_eq!(
+            apply(&data, &patch).unwrap(),
+            vec![0, 0, 0xAA, 0xBB, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC]
+        );
+    }
+
+    #[test]
+    fn test_apply_truncate() {
+        // Prep for the test, a record followed by the footer and a size of 3
+        let data: Vec<u8> = vec![0; 8];
+        let mut patch: Vec<u8> = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x01, 0xAA]);
+        patch.extend(b"EOF");
+        patch.extend([0x00, 0x00, 0x03]);
+
+        // Assert results
+        assert_eq!(apply(&data, &patch).unwrap(), vec![0, 0xAA, 0]);
+    }
+
+    #[test]
+    fn test_invalid_patches() {
+        // Assert results
+        assert_eq!(apply(&[0], b"PATCX").err(), Some(PatchError::InvalidHeader));
+        assert_eq!(
+            apply(&[0], b"PATCH\x00\x00\x00\x00\x04\x01").err(),
+            Some(PatchError::TruncatedData)
+        );
+        assert_eq!(apply(&[0], b"PATCH").err(), Some(PatchError::TruncatedData));
+    }
+}
@@ -0,0 +1,222 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Patch formats for applying changes to ROM data
+// Patches found next to a ROM are applied to the whole file before its header is read
+
+pub mod bps;
+pub mod ips;
+pub mod ups;
+
+use crate::common::utils::Utils;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// The extensions patches are looked for under, in order of preference
+const PATCH_EXTENSIONS: [&str; 3] = ["bps", "ups", "ips"];
+
+// Errors that can occur while applying a patch
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    // The patch does not start with a recognised header
+    InvalidHeader,
+    // The patch ends part way through a record
+    TruncatedData,
+    // A record reads or writes outside of the data
+    InvalidRecord,
+    // The data is not the size the patch was made for
+    SizeMismatch,
+    // A checksum of the patch, the data it was made for or the patched result does not match
+    ChecksumMismatch,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::InvalidHeader => write!(f, "Patch header is not valid"),
+            PatchError::TruncatedData => write!(f, "Patch data ends part way through a record"),
+            PatchError::InvalidRecord => write!(f, "Patch record reaches outside of the data"),
+            PatchError::SizeMismatch => write!(f, "Patch was made for data of a different size"),
+            PatchError::ChecksumMismatch => write!(f, "Patch checksum does not match"),
+        }
+    }
+}
+
+// Apply a patch in any of the supported formats, recognised by its header
+pub fn apply(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(bps::HEADER) {
+        bps::apply(data, patch)
+    } else if patch.starts_with(ups::HEADER) {
+        ups::apply(data, patch)
+    } else {
+        ips::apply(data, patch)
+    }
+}
+
+// Find a patch with the same name as the ROM in the same folder, such as game.ips for game.nes
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+// Read a variable length number as used by UPS and BPS, 7 bits at a time with the top bit set on
+// the last byte, where each continuation also adds one to remove duplicate encodings
+// https://www.romhacking.net/documents/746/
+pub fn read_number(patch: &[u8], position: &mut usize) -> Result<usize, PatchError> {
+    let mut value: usize = 0;
+    let mut shift: usize = 1;
+    loop {
+        let byte: u8 = *patch.get(*position).ok_or(PatchError::TruncatedData)?;
+        *position += 1;
+        value += usize::from(byte & 0x7F) * shift;
+        if byte & 0x80 == 0x80 {
+            return Ok(value);
+        }
+        shift <<= 7;
+        value += shift;
+    }
+}
+
+// UPS and BPS patches end with the CRC-32 of the source, the target and the patch itself
+pub struct Checksums {
+    pub source: u32,
+    pub target: u32,
+}
+
+// Check the patch's own checksum and return the other two, along with where the footer starts
+pub fn read_checksums(patch: &[u8]) -> Result<(Checksums, usize), PatchError> {
+    let footer_start: usize = patch
+        .len()
+        .checked_sub(12)
+        .ok_or(PatchError::TruncatedData)?;
+    let get_crc = |index: usize| -> u32 {
+        let start: usize = footer_start + index * 4;
+        u32::from_le_bytes([
+            patch[start],
+            patch[start + 1],
+            patch[start + 2],
+            patch[start + 3],
+        ])
+    };
+    if Utils::get_crc32(&patch[..footer_start + 8]) != get_crc(2) {
+        return Err(PatchError::ChecksumMismatch);
+    }
+    Ok((
+        Checksums {
+            source: get_crc(0),
+            target: get_crc(1),
+        },
+        footer_start,
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    // Encode a number in the UPS and BPS variable length format
+    pub fn get_number(mut value: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        loop {
+            let low: u8 = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(low | 0x80);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    // Append the source, target and patch checksums
+    pub fn add_checksums(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+        patch.extend(Utils::get_crc32(source).to_le_bytes());
+        patch.extend(Utils::get_crc32(target).to_le_bytes());
+        let patch_crc: u32 = Utils::get_crc32(patch);
+        patch.extend(patch_crc.to_le_bytes());
+    }
+
+    #[test]
+    fn test_read_number() {
+        // Prep for the test
+        let mut bytes: Vec<u8> = Vec::new();
+        for value in [0, 0x7F, 0x80, 0x4000, 0x12345] {
+            bytes.extend(get_number(value));
+        }
+
+        // Assert results
+        assert_eq!(get_number(0x80), [0x00, 0x80].to_vec());
+        let mut position: usize = 0;
+        for value in [0, 0x7F, 0x80, 0x4000, 0x12345] {
+            assert_eq!(read_number(&bytes, &mut position), Ok(value));
+        }
+        assert_eq!(position, bytes.len());
+        assert_eq!(
+            read_number(&[0x00], &mut position),
+            Err(PatchError::TruncatedData)
+        );
+    }
+
+    #[test]
+    fn test_apply_detects_format() {
+        // Prep for the test, the same change as an IPS and a UPS patch
+        let data: Vec<u8> = vec![1, 2, 3];
+        let mut ips_patch: Vec<u8> = b"PATCH".to_vec();
+        ips_patch.extend([0x00, 0x00, 0x01, 0x00, 0x01, 0x09]);
+        ips_patch.extend(b"EOF");
+        let mut ups_patch: Vec<u8> = b"UPS1".to_vec();
+        ups_patch.extend(get_number(3));
+        ups_patch.extend(get_number(3));
+        ups_patch.extend(get_number(1));
+        ups_patch.extend([2 ^ 9, 0x00]);
+        add_checksums(&mut ups_patch, &data, &[1, 9, 3]);
+
+        // Assert results
+        assert_eq!(apply(&data, &ips_patch), Ok(vec![1, 9, 3]));
+        assert_eq!(apply(&data, &ups_patch), Ok(vec![1, 9, 3]));
+        assert_eq!(apply(&data, b"XYZ"), Err(PatchError::InvalidHeader));
+    }
+
+    #[test]
+    fn test_find_patch() {
+        // Prep for the test, a ROM with an IPS patch next to it
+        let folder: PathBuf = env::temp_dir().join("rusty-nes-patch-find");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let rom_path: PathBuf = folder.join("game.nes");
+        fs::write(folder.join("game.ips"), b"PATCHEOF").unwrap();
+        assert_eq!(find_patch(&rom_path), Some(folder.join("game.ips")));
+
+        // Assert results, a UPS patch is preferred and other ROMs have no patch
+        fs::write(folder.join("game.ups"), b"UPS1").unwrap();
+        assert_eq!(find_patch(&rom_path), Some(folder.join("game.ups")));
+        assert_eq!(find_patch(&folder.join("other.nes")), None);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// UPS patches, which store changes as runs of bytes XORed with the original
+// https://www.romhacking.net/documents/392/
+//
+// After "UPS1" come the source and target sizes, then records of a count of unchanged bytes to
+// skip followed by XOR bytes ending in a zero. The patch ends with three CRC-32 checksums.
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::patch::{self, Checksums, PatchError};
+
+pub const HEADER: &[u8; 4] = b"UPS1";
+
+// Apply a patch to a copy of the data, which must be the data the patch was made for
+pub fn apply(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(HEADER) {
+        return Err(PatchError::InvalidHeader);
+    }
+    let (checksums, footer_start): (Checksums, usize) = patch::read_checksums(patch)?;
+
+    let mut position: usize = HEADER.len();
+    let source_size: usize = patch::read_number(patch, &mut position)?;
+    let target_size: usize = patch::read_number(patch, &mut position)?;
+    if data.len() != source_size {
+        return Err(PatchError::SizeMismatch);
+    }
+    if Utils::get_crc32(data) != checksums.source {
+        return Err(PatchError::ChecksumMismatch);
+    }
+
+    // Bytes past the end of the source are XORed with zero
+    let mut patched: Vec<u8> = data.to_vec();
+    patched.resize(target_size, 0);
+    let mut offset: usize = 0;
+    while position < footer_start {
+        offset += patch::read_number(patch, &mut position)?;
+        loop {
+            if position >= footer_start {
+                return Err(PatchError::TruncatedData);
+            }
+            let value: u8 = patch[position];
+            position += 1;
+
+            // The terminating zero also stands for an unchanged byte
+            if value != 0 {
+                *patched.get_mut(offset).ok_or(PatchError::InvalidRecord)? ^= value;
+            }
+            offset += 1;
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    if Utils::get_crc32(&patched) != checksums.target {
+        return Err(PatchError::ChecksumMismatch);
+    }
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::patch::tests::{add_checksums, get_number};
+
+    // A patch that changes bytes 1 and 2 and then grows the data by two bytes
+    fn get_test_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch: Vec<u8> = HEADER.to_vec();
+        patch.extend(get_number(source.len()));
+        patch.extend(get_number(target.len()));
+        patch.extend(get_number(1));
+        patch.extend([source[1] ^ target[1], source[2] ^ target[2], 0x00]);
+        patch.extend(get_number(1));
+        patch.extend([target[5], target[6], 0x00]);
+        add_checksums(&mut patch, source, target);
+        patch
+    }
+
+    #[test]
+    fn test_apply() {
+        // Prep for the test
+        let source: Vec<u8> = vec![1, 2, 3, 4, 5];
+        let target: Vec<u8> = vec![1, 7, 8, 4, 5, 6, 7];
+        let patch: Vec<u8> = get_test_patch(&source, &target);
+
+        // Assert results
+        assert_eq!(apply(&source, &patch), Ok(target));
+    }
+
+    #[test]
+    fn test_verification() {
+        // Prep for the test
+        let source: Vec<u8> = vec![1, 2, 3, 4, 5];
+        let target: Vec<u8> = vec![1, 7, 8, 4, 5, 6, 7];
+        let patch: Vec<u8> = get_test_patch(&source, &target);
+
+        // Assert results, the source size and checksum and the patch checksum are all checked
+        assert_eq!(apply(&[1, 2, 3, 4], &patch), Err(PatchError::SizeMismatch));
+        assert_eq!(
+            apply(&[1, 2, 3, 4, 6], &patch),
+            Err(PatchError::ChecksumMismatch)
+        );
+        let mut corrupted: Vec<u8> = patch.clone();
+        corrupted[8] ^= 0xFF;
+        assert_eq!(
+            apply(&source, &corrupted),
+            Err(PatchError::ChecksumMismatch)
+        );
+        assert_eq!(apply(&source, b"UPS1"), Err(PatchError::TruncatedData));
+        assert_eq!(apply(&source, b"BPS1"), Err(PatchError::InvalidHeader));
+    }
+}
@@ -0,0 +1,325 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Save files for boards with memory that is kept while the console is off, like EEPROMs and
+// battery backed PRG-RAM. The save file sits next to the ROM with the extension .sav.
+//
+// Saves end with a small footer holding the CRC-32 of the ROM they belong to, so that a save can
+// still be found after its ROM is renamed. Files without the footer, like those written by other
+// emulators, are loaded by name as plain memory dumps.
+
+use crate::common::utils::Utils;
+use crate::models::cartridge::mappers::Mapper;
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Marks the footer, which is followed by the little endian ROM hash
+const FOOTER_MAGIC: &[u8; 4] = b"RSAV";
+const FOOTER_SIZE: usize = 8;
+
+// The size of the iNES header, which is left out of the ROM hash so that header fixes keep saves
+const HEADER_SIZE: usize = 16;
+
+// How often a running game's save memory is checked and written if it changed
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn get_save_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+// The hash that identifies the ROM a save belongs to
+pub fn get_rom_hash(rom_content: &[u8]) -> u32 {
+    Utils::get_crc32(rom_content.get(HEADER_SIZE..).unwrap_or_default())
+}
+
+// Split a save file into its data and the ROM hash from its footer, if it has one
+fn split_footer(contents: &[u8]) -> (&[u8], Option<u32>) {
+    if contents.len() < FOOTER_SIZE {
+        return (contents, None);
+    }
+    let (data, footer) = contents.split_at(contents.len() - FOOTER_SIZE);
+    if &footer[..4] != FOOTER_MAGIC {
+        return (contents, None);
+    }
+    let hash: u32 = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+    (data, Some(hash))
+}
+
+// Look for a save belonging to the ROM among the other save files in the same folder
+fn find_save_by_hash(save_path: &Path, rom_hash: u32) -> io::Result<Option<Vec<u8>>> {
+    let folder: &Path = match save_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if !folder.is_dir() {
+        return Ok(None);
+    }
+
+    for entry in fs::read_dir(folder)? {
+        let path: PathBuf = entry?.path();
+        if path.extension() != Some(OsStr::new("sav")) || !path.is_file() {
+            continue;
+        }
+        let contents: Vec<u8> = fs::read(&path)?;
+        if let (data, Some(hash)) = split_footer(&contents) {
+            if hash == rom_hash {
+                return Ok(Some(data.to_vec()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Load a save file into the mapper, returning whether there was one to load
+// When there is no save with the ROM's name, a save for the same ROM under another name is used
+pub fn load_save_file(mapper: &mut dyn Mapper, path: &Path, rom_hash: u32) -> io::Result<bool> {
+    if mapper.get_save_data().is_none() {
+        return Ok(false);
+    }
+
+    let data: Vec<u8> = if path.exists() {
+        let contents: Vec<u8> = fs::read(path)?;
+        split_footer(&contents).0.to_vec()
+    } else {
+        match find_save_by_hash(path, rom_hash)? {
+            Some(data) => data,
+            None => return Ok(false),
+        }
+    };
+    mapper.load_save_data(&data);
+    Ok(true)
+}
+
+// Write the mapper's save data, if the board has any
+// The file is written beside the save and then renamed over it, so a crash part way through
+// leaves the previous save intact
+pub fn write_save_file(mapper: &dyn Mapper, path: &Path, rom_hash: u32) -> io::Result<()> {
+    let data: Vec<u8> = match mapper.get_save_data() {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    let temporary_path: PathBuf = path.with_extension("sav.tmp");
+    let mut file: fs::File = fs::File::create(&temporary_path)?;
+    file.write_all(&data)?;
+    file.write_all(FOOTER_MAGIC)?;
+    file.write_all(&rom_hash.to_le_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temporary_path, path)
+}
+
+// Keeps a running game's save file up to date, writing it periodically and when dropped
+pub struct SaveFile {
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    path: PathBuf,
+    rom_hash: u32,
+    // The data as of the last write, so that unchanged saves are not rewritten
+    written_data: Option<Vec<u8>>,
+    last_check: Instant,
+}
+
+impl SaveFile {
+    // Load the save for a ROM into its mapper and start tracking it
+    pub fn open(
+        mapper: Rc<RefCell<Box<dyn Mapper>>>,
+        rom_path: &Path,
+        rom_content: &[u8],
+    ) -> io::Result<SaveFile> {
+        let path: PathBuf = get_save_path(rom_path);
+        let rom_hash: u32 = get_rom_hash(rom_content);
+        load_save_file(mapper.borrow_mut().as_mut(), &path, rom_hash)?;
+        let written_data: Option<Vec<u8>> = mapper.borrow().get_save_data();
+
+        Ok(SaveFile {
+            mapper,
+            path,
+            rom_hash,
+            written_data,
+            last_check: Instant::now(),
+        })
+    }
+
+    // Write the save if the memory changed since it was last written
+    pub fn flush(&mut self) -> io::Result<()> {
+        // The mapper may still be borrowed when unwinding from a panic inside it
+        let mapper = match self.mapper.try_borrow() {
+            Ok(mapper) => mapper,
+            Err(_) => return Ok(()),
+        };
+        let data: Option<Vec<u8>> = mapper.get_save_data();
+        if data.is_some() && data != self.written_data {
+            write_save_file(mapper.as_ref(), &self.path, self.rom_hash)?;
+            self.written_data = data;
+        }
+        Ok(())
+    }
+
+    // Flush if enough time has passed since the last check
+    pub fn flush_if_due(&mut self) -> io::Result<()> {
+        if self.last_check.elapsed() < FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.last_check = Instant::now();
+        self.flush()
+    }
+}
+
+impl Drop for SaveFile {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush() {
+            eprintln!("Failed to write {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::mappers::bandai::BandaiFcg;
+    use crate::models::cartridge::mappers::nrom::Nrom;
+    use crate::models::cartridge::tests::get_test_cartridge;
+    use crate::models::cartridge::Cartridge;
+    use std::env;
+
+    // A folder of its own for each test, since the tests run in parallel
+    fn get_test_folder(name: &str) -> PathBuf {
+        let folder: PathBuf = env::temp_dir().join(format!("rusty-nes-save-{}", name));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn get_battery_cartridge() -> Cartridge {
+        let mut cartridge: Cartridge = get_test_cartridge(0, 0, 2, 0x4000, 1, 0x2000);
+        cartridge.prg_ram_size = 0x2000;
+        cartridge.prg_nvram_size = 0x2000;
+        cartridge.has_battery = true;
+        cartridge
+    }
+
+    #[test]
+    fn test_save_path() {
+        // Assert results
+        assert_eq!(
+            get_save_path(Path::new("roms/game.nes")),
+            PathBuf::from("roms/game.sav")
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Prep for the test, a board with an EEPROM
+        let path: PathBuf = get_test_folder("round-trip").join("game.sav");
+        let mut mapper: BandaiFcg =
+            BandaiFcg::new(get_test_cartridge(159, 0, 8, 0x4000, 8, 0x0400));
+        let mut data: Vec<u8> = vec![0; 128];
+        data[5] = 0x42;
+        mapper.load_save_data(&data);
+        write_save_file(&mapper, &path, 0x1234).unwrap();
+
+        // Assert results, the footer follows the data and is left out when loading
+        assert_eq!(fs::read(&path).unwrap().len(), 128 + FOOTER_SIZE);
+        let mut restored: BandaiFcg =
+            BandaiFcg::new(get_test_cartridge(159, 0, 8, 0x4000, 8, 0x0400));
+        assert!(load_save_file(&mut restored, &path, 0x1234).unwrap());
+        assert_eq!(restored.get_save_data().unwrap(), data);
+        assert!(!path.with_extension("sav.tmp").exists());
+    }
+
+    #[test]
+    fn test_plain_dump_is_loaded() {
+        // Prep for the test, a save without the footer
+        let path: PathBuf = get_test_folder("plain-dump").join("game.sav");
+        let mut data: Vec<u8> = vec![0; 0x2000];
+        data[0x1FFF] = 0x99;
+        fs::write(&path, &data).unwrap();
+        let mut mapper: Nrom = Nrom::new(get_battery_cartridge());
+
+        // Assert results
+        assert!(load_save_file(&mut mapper, &path, 0x1234).unwrap());
+        assert_eq!(mapper.cpu_read(0x7FFF), 0x99);
+    }
+
+    #[test]
+    fn test_renamed_rom_finds_save_by_hash() {
+        // Prep for the test, a save written under the ROM's old name
+        let folder: PathBuf = get_test_folder("renamed");
+        let mut mapper: Nrom = Nrom::new(get_battery_cartridge());
+        mapper.cpu_write(0x6000, 0x42);
+        write_save_file(&mapper, &folder.join("other.sav"), 0x5678).unwrap();
+        write_save_file(&mapper, &folder.join("old.sav"), 0x1234).unwrap();
+
+        // Assert results, only the save with the matching hash is used
+        let mut restored: Nrom = Nrom::new(get_battery_cartridge());
+        let path: PathBuf = folder.join("new.sav");
+        assert!(load_save_file(&mut restored, &path, 0x1234).unwrap());
+        assert_eq!(restored.cpu_read(0x6000), 0x42);
+        assert!(!load_save_file(&mut restored, &path, 0x9999).unwrap());
+    }
+
+    #[test]
+    fn test_boards_without_save_data() {
+        // Prep for the test
+        let path: PathBuf = get_test_folder("without-data").join("game.sav");
+        let mut mapper: Nrom = Nrom::new(get_test_cartridge(0, 0, 2, 0x4000, 1, 0x2000));
+
+        // Assert results, nothing is read or written
+        write_save_file(&mapper, &path, 0).unwrap();
+        assert!(!path.exists());
+        assert!(!load_save_file(&mut mapper, &path, 0).unwrap());
+    }
+
+    #[test]
+    fn test_save_file_flushes_changes_and_on_drop() {
+        // Prep for the test
+        let folder: PathBuf = get_test_folder("flush");
+        let rom_path: PathBuf = folder.join("game.nes");
+        let rom_content: Vec<u8> = vec![0x55; 0x40];
+        let mapper: Rc<RefCell<Box<dyn Mapper>>> =
+            Rc::new(RefCell::new(Box::new(Nrom::new(get_battery_cartridge()))));
+        let mut save_file: SaveFile =
+            SaveFile::open(mapper.clone(), &rom_path, &rom_content).unwrap();
+
+        // Nothing is written until the memory changes
+        save_file.flush().unwrap();
+        assert!(!folder.join("game.sav").exists());
+        mapper.borrow_mut().cpu_write(0x6001, 0x24);
+        save_file.flush().unwrap();
+        let contents: Vec<u8> = fs::read(folder.join("game.sav")).unwrap();
+        assert_eq!(contents[1], 0x24);
+        assert_eq!(split_footer(&contents).1, Some(get_rom_hash(&rom_content)));
+
+        // Assert results, dropping writes the latest memory
+        mapper.borrow_mut().cpu_write(0x6002, 0x33);
+        drop(save_file);
+        assert_eq!(fs::read(folder.join("game.sav")).unwrap()[2], 0x33);
+    }
+}
@@ -0,0 +1,328 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Loader for the UNIF file format, used by some multicart and pirate dumps
+// https://www.nesdev.org/wiki/UNIF
+//
+// A UNIF file is a header followed by chunks, each a four letter ID, a 32 bit little endian length
+// and the data. The board is named by a string rather than a mapper number.
+
+use crate::models::cartridge::{Cartridge, CartridgeError, Mirroring};
+use crate::models::region::Region;
+
+// The header is "UNIF", a revision number and padding
+pub const HEADER_SIZE: usize = 32;
+
+// Each chunk starts with its ID and length
+const CHUNK_HEADER_SIZE: usize = 8;
+
+// Prefixes that name the maker of a board rather than the board itself
+const BOARD_PREFIXES: [&str; 6] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-"];
+
+// The mapper and submapper numbers for each board with an implementation, by name without prefix
+// The multicart (BMC-) boards found in UNIF dumps all use mappers that are not implemented yet
+const BOARDS: [(&str, u16, u8); 41] = [
+    ("NROM", 0, 0),
+    ("NROM-128", 0, 0),
+    ("NROM-256", 0, 0),
+    ("UNROM", 2, 2),
+    ("UOROM", 2, 2),
+    ("CNROM", 3, 2),
+    ("TBROM", 4, 0),
+    ("TEROM", 4, 0),
+    ("TFROM", 4, 0),
+    ("TGROM", 4, 0),
+    ("TKROM", 4, 0),
+    ("TLROM", 4, 0),
+    ("TR1ROM", 4, 0),
+    ("TSROM", 4, 0),
+    ("TVROM", 4, 0),
+    ("B4", 4, 0),
+    ("EKROM", 5, 0),
+    ("ELROM", 5, 0),
+    ("ETROM", 5, 0),
+    ("EWROM", 5, 0),
+    ("AMROM", 7, 2),
+    ("ANROM", 7, 0),
+    ("AN1ROM", 7, 0),
+    ("AOROM", 7, 0),
+    ("PNROM", 9, 0),
+    ("PEEOROM", 9, 0),
+    ("FJROM", 10, 0),
+    ("FKROM", 10, 0),
+    ("BNROM", 34, 2),
+    ("GNROM", 66, 0),
+    ("MHROM", 66, 0),
+    ("BTR", 69, 0),
+    ("JLROM", 69, 0),
+    ("JSROM", 69, 0),
+    // Unlicensed and homebrew boards, which are usually named with UNL- or their maker's name
+    ("CHINA_ER_SAN2", 19, 0),
+    ("UNROM-512-8", 30, 0),
+    ("UNROM-512-16", 30, 0),
+    ("UNROM-512-32", 30, 0),
+    ("AVE-NINA-01", 34, 1),
+    ("AVE-NINA-02", 34, 1),
+    ("VRC7", 85, 0),
+];
+
+pub fn is_unif(rom_content: &[u8]) -> bool {
+    rom_content.starts_with(b"UNIF")
+}
+
+// Find the mapper and submapper numbers for a board name
+pub fn get_board_mapper(board: &str) -> Option<(u16, u8)> {
+    let find = |name: &str| {
+        BOARDS
+            .iter()
+            .find(|(board_name, _, _)| *board_name == name)
+            .map(|(_, mapper, submapper)| (*mapper, *submapper))
+    };
+
+    find(board).or_else(|| {
+        BOARD_PREFIXES
+            .iter()
+            .find_map(|prefix| board.strip_prefix(prefix))
+            .and_then(find)
+    })
+}
+
+// Load a UNIF file into a cartridge description
+pub fn load_cartridge(rom_content: &[u8]) -> Result<Cartridge, CartridgeError> {
+    if rom_content.len() < HEADER_SIZE || !is_unif(rom_content) {
+        return Err(CartridgeError::InvalidHeader);
+    }
+
+    // ROM chunks are numbered with a hex digit and joined in order of that number
+    let mut prg_chunks: [&[u8]; 16] = [&[]; 16];
+    let mut chr_chunks: [&[u8]; 16] = [&[]; 16];
+    let mut board: Option<String> = None;
+    let mut mirroring: Mirroring = Mirroring::Horizontal;
+    let mut has_battery: bool = false;
+    let mut region: Region = Region::Ntsc;
+
+    let mut offset: usize = HEADER_SIZE;
+    while offset < rom_content.len() {
+        if offset + CHUNK_HEADER_SIZE > rom_content.len() {
+            return Err(CartridgeError::TruncatedData);
+        }
+        let id: &[u8] = &rom_content[offset..offset + 4];
+        let length: usize = u32::from_le_bytes([
+            rom_content[offset + 4],
+            rom_content[offset + 5],
+            rom_content[offset + 6],
+            rom_content[offset + 7],
+        ]) as usize;
+        let start: usize = offset + CHUNK_HEADER_SIZE;
+        let end: usize = start
+            .checked_add(length)
+            .filter(|end| *end <= rom_content.len())
+            .ok_or(CartridgeError::TruncatedData)?;
+        let data: &[u8] = &rom_content[start..end];
+        offset = end;
+
+        match id {
+            // The board name is a null terminated string
+            b"MAPR" => {
+                let name: &[u8] = data.split(|byte| *byte == 0).next().unwrap_or_default();
+                board = Some(String::from_utf8_lossy(name).trim().to_string());
+            }
+            [b'P', b'R', b'G', digit] => {
+                if let Some(index) = char::from(*digit).to_digit(16) {
+                    prg_chunks[index as usize] = data;
+                }
+            }
+            [b'C', b'H', b'R', digit] => {
+                if let Some(index) = char::from(*digit).to_digit(16) {
+                    chr_chunks[index as usize] = data;
+                }
+            }
+            // Mapper controlled mirroring, value 5, is left to the mapper
+            b"MIRR" => {
+                mirroring = match data.first() {
+                    Some(1) => Mirroring::Vertical,
+                    Some(2) => Mirroring::SingleScreenLower,
+                    Some(3) => Mirroring::SingleScreenUpper,
+                    Some(4) => Mirroring::FourScreen,
+                    _ => Mirroring::Horizontal,
+                }
+            }
+            b"BATR" => has_battery = true,
+            // The TV system, where games for both are flagged with 2
+            b"TVCI" => {
+                region = match data.first() {
+                    Some(1) => Region::Pal,
+                    Some(2) => Region::Multi,
+                    _ => Region::Ntsc,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let board: String = board.ok_or(CartridgeError::InvalidHeader)?;
+    let (mapper_number, submapper_number) =
+        get_board_mapper(&board).ok_or(CartridgeError::UnsupportedBoard(board))?;
+    let prg_rom: Vec<u8> = prg_chunks.concat();
+    let chr_rom: Vec<u8> = chr_chunks.concat();
+
+    // UNIF has no RAM sizes, so boards get 8KB of PRG-RAM with a battery and 8KB of CHR-RAM
+    // without CHR-ROM, and mappers that always have PRG-RAM provide it themselves
+    let prg_ram_size: usize = if has_battery { 0x2000 } else { 0 };
+    let chr_ram_size: usize = if chr_rom.is_empty() { 0x2000 } else { 0 };
+
+    Ok(Cartridge {
+        mapper_number,
+        submapper_number,
+        prg_rom,
+        chr_rom,
+        prg_ram_size,
+        chr_ram_size,
+        prg_nvram_size: prg_ram_size,
+        mirroring,
+        mirroring_flag: mirroring == Mirroring::Vertical,
+        has_battery,
+        trainer: Vec::new(),
+        region,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper function for the tests to build a UNIF file from its chunks
+    fn get_test_unif(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut rom: Vec<u8> = b"UNIF".to_vec();
+        rom.extend(7u32.to_le_bytes());
+        rom.resize(HEADER_SIZE, 0);
+        for (id, data) in chunks {
+            rom.extend(id.iter());
+            rom.extend((data.len() as u32).to_le_bytes());
+            rom.extend(data);
+        }
+        rom
+    }
+
+    #[test]
+    fn test_load_cartridge() {
+        // Prep for the test, PRG chunks out of order and an unknown chunk
+        let rom: Vec<u8> = get_test_unif(&[
+            (b"MAPR", b"NES-TLROM\0".to_vec()),
+            (b"NAME", b"Test\0".to_vec()),
+            (b"PRG1", vec![0x11; 0x4000]),
+            (b"PRG0", vec![0x10; 0x4000]),
+            (b"CHR0", vec![0x20; 0x2000]),
+            (b"MIRR", vec![1]),
+            (b"BATR", vec![0]),
+        ]);
+        let cartridge: Cartridge = load_cartridge(&rom).unwrap();
+
+        // Assert results
+        assert_eq!(cartridge.mapper_number, 4);
+        assert_eq!(cartridge.prg_rom.len(), 0x8000);
+        assert_eq!(cartridge.prg_rom[0], 0x10);
+        assert_eq!(cartridge.prg_rom[0x4000], 0x11);
+        assert_eq!(cartridge.chr_rom.len(), 0x2000);
+        assert_eq!(cartridge.chr_ram_size, 0);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert!(cartridge.has_battery);
+        assert_eq!(cartridge.prg_nvram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_board_names() {
+        // Assert results
+        assert_eq!(get_board_mapper("NES-NROM-256"), Some((0, 0)));
+        assert_eq!(get_board_mapper("UNL-UNROM-512-32"), Some((30, 0)));
+        assert_eq!(get_board_mapper("UNL-UNROM-512-8"), Some((30, 0)));
+        assert_eq!(get_board_mapper("UNL-VRC7"), Some((85, 0)));
+        assert_eq!(get_board_mapper("UNL-CHINA_ER_SAN2"), Some((19, 0)));
+        assert_eq!(get_board_mapper("BMC-SOMETHING"), None);
+        assert_eq!(get_board_mapper("AVE-NINA-01"), Some((34, 1)));
+        assert_eq!(get_board_mapper("NES-CNROM"), Some((3, 2)));
+        assert_eq!(get_board_mapper("NES-SOMETHING"), None);
+    }
+
+    #[test]
+    fn test_chr_ram_without_chr_chunks() {
+        // Prep for the test
+        let rom: Vec<u8> = get_test_unif(&[
+            (b"MAPR", b"NES-UNROM".to_vec()),
+            (b"PRG0", vec![0; 0x20000]),
+        ]);
+        let cartridge: Cartridge = load_cartridge(&rom).unwrap();
+
+        // Assert results
+        assert_eq!(cartridge.mapper_number, 2);
+        assert!(cartridge.has_chr_ram());
+        assert_eq!(cartridge.chr_ram_size, 0x2000);
+        assert!(!cartridge.has_battery);
+        assert_eq!(cartridge.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn test_tv_system() {
+        // Prep for the test
+        let rom: Vec<u8> = get_test_unif(&[
+            (b"MAPR", b"NES-NROM-128\0".to_vec()),
+            (b"PRG0", vec![0; 0x4000]),
+            (b"TVCI", vec![1]),
+        ]);
+
+        // Assert results
+        assert_eq!(load_cartridge(&rom).unwrap().region, Region::Pal);
+    }
+
+    #[test]
+    fn test_errors() {
+        // Assert results, unsupported boards are reported by name
+        let unsupported: Vec<u8> = get_test_unif(&[(b"MAPR", b"BMC-70in1\0".to_vec())]);
+        assert_eq!(
+            load_cartridge(&unsupported).err(),
+            Some(CartridgeError::UnsupportedBoard("BMC-70in1".to_string()))
+        );
+
+        let no_board: Vec<u8> = get_test_unif(&[(b"PRG0", vec![0; 0x4000])]);
+        assert_eq!(
+            load_cartridge(&no_board).err(),
+            Some(CartridgeError::InvalidHeader)
+        );
+
+        let mut truncated: Vec<u8> = get_test_unif(&[
+            (b"MAPR", b"NES-NROM-128\0".to_vec()),
+            (b"PRG0", vec![0; 0x4000]),
+        ]);
+        truncated.pop();
+        assert_eq!(
+            load_cartridge(&truncated).err(),
+            Some(CartridgeError::TruncatedData)
+        );
+
+        assert_eq!(
+            load_cartridge(b"NES\x1A").err(),
+            Some(CartridgeError::InvalidHeader)
+        );
+    }
+}
@@ -0,0 +1,377 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// The 2A03's DMA unit, which halts the CPU to take over the bus for sprite and sample transfers
+// A write to $4014 copies a page of CPU memory into the PPU's OAM through $2004, and the DMC
+// reads each sample byte it plays through the same unit.
+// DMA reads happen on get cycles and writes on put cycles, with the two alternating, so how long
+// the CPU is stalled depends on the cycle a transfer starts on. Here even cycles are get cycles.
+// https://www.nesdev.org/wiki/DMA
+
+use crate::common::memory::{Memory, MemoryMappedDevice};
+use std::cell::Cell;
+use std::rc::Rc;
+
+// The PPU register the sprite data is written to
+const OAM_DATA_ADDRESS: usize = 0x2004;
+const OAM_SIZE: usize = 0x100;
+
+// The controller ports, which are clocked by every read and so notice when a read is repeated
+const CONTROLLER_PORTS: [usize; 2] = [0x4016, 0x4017];
+
+// The rest of the console, which keeps running while the CPU is halted
+pub trait DmaClient {
+    // Advance by a single CPU cycle
+    fn clock_cpu(&mut self);
+
+    // The address of the sample byte the DMC is waiting for, if it is waiting for one
+    fn get_dmc_fetch_address(&self) -> Option<u16>;
+
+    // Hand the DMC the sample byte it was waiting for
+    fn load_dmc_sample(&mut self, value: u8);
+}
+
+pub struct Dma {
+    // The page written to $4014, shared with the register so it can be picked up between instructions
+    oam_page: Rc<Cell<Option<u8>>>,
+}
+
+impl Dma {
+    pub fn new() -> Dma {
+        Dma {
+            oam_page: Rc::new(Cell::new(None)),
+        }
+    }
+
+    // Get the $4014 register to map into the address space
+    pub fn get_register(&self) -> OamDmaRegister {
+        OamDmaRegister {
+            oam_page: self.oam_page.clone(),
+        }
+    }
+
+    // Run any transfers that are waiting, starting on the given CPU cycle
+    // The client is clocked for every cycle the CPU is halted, which is the number returned
+    pub fn run(&mut self, memory: &mut Memory, cycle: u64, client: &mut dyn DmaClient) -> u32 {
+        let mut oam_page: Option<u8> = self.oam_page.take();
+        let mut dmc_address: Option<u16> = client.get_dmc_fetch_address();
+        if oam_page.is_none() && dmc_address.is_none() {
+            return 0;
+        }
+
+        // The halted CPU keeps repeating the read it was making, and a sample fetch can halt it on
+        // the last read of an instruction, so a controller port being read loses a bit
+        if dmc_address.is_some() {
+            if let Some(address) = memory.get_last_read_address() {
+                if CONTROLLER_PORTS.contains(&address) {
+                    memory.read(address, 1);
+                }
+            }
+        }
+
+        // Each transfer spends its first cycle halting the CPU, and a sample fetch also needs a
+        // dummy cycle, before either can use the bus
+        let mut oam_index: usize = 0;
+        let mut oam_value: Option<u8> = None;
+        let mut dmc_ready_cycle: u32 = 2;
+        let mut cycles: u32 = 0;
+        while oam_page.is_some() || dmc_address.is_some() {
+            let is_get_cycle: bool = (cycle + u64::from(cycles)).is_multiple_of(2);
+            if is_get_cycle {
+                // The sample fetch takes priority, which leaves sprite DMA to realign afterwards
+                match (dmc_address, oam_page) {
+                    (Some(address), _) if cycles >= dmc_ready_cycle => {
+                        let value: u8 = memory.read(address.into(), 1)[0];
+                        client.load_dmc_sample(value);
+                        dmc_address = None;
+                    }
+                    (_, Some(page)) if cycles >= 1 && oam_value.is_none() => {
+                        let address: usize = usize::from(page) << 8 | oam_index;
+                        oam_value = Some(memory.read(address, 1)[0]);
+                    }
+                    _ => {}
+                }
+            } else if let Some(value) = oam_value.take() {
+                memory.write(OAM_DATA_ADDRESS, [value].to_vec());
+                oam_index += 1;
+                if oam_index == OAM_SIZE {
+                    oam_page = None;
+                }
+            }
+
+            client.clock_cpu();
+            cycles += 1;
+
+            // The DMC can ask for another byte while the CPU is still halted, in which case its
+            // halt and dummy cycles overlap the transfer already running
+            if dmc_address.is_none() {
+                dmc_address = client.get_dmc_fetch_address();
+                dmc_ready_cycle = cycles + 2;
+            }
+        }
+        cycles
+    }
+}
+
+// The OAM DMA register at $4014, which starts a transfer from the page written to it
+pub struct OamDmaRegister {
+    oam_page: Rc<Cell<Option<u8>>>,
+}
+
+impl MemoryMappedDevice for OamDmaRegister {
+    // The register is write only, and nothing drives the data bus when it is read
+    fn read_byte(&mut self, _address: usize) -> u8 {
+        0
+    }
+
+    fn write_byte(&mut self, _address: usize, value: u8) {
+        self.oam_page.set(Some(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::memory::tests::get_test_memory;
+    use std::cell::RefCell;
+
+    // A stand in for the PPU's OAM data register, recording the bytes written to it
+    struct TestOam {
+        data: Vec<u8>,
+    }
+
+    impl MemoryMappedDevice for TestOam {
+        fn read_byte(&mut self, _address: usize) -> u8 {
+            0
+        }
+
+        fn write_byte(&mut self, _address: usize, value: u8) {
+            self.data.push(value);
+        }
+    }
+
+    // A stand in for a controller port, counting how many times it is read
+    struct TestController {
+        reads: u32,
+    }
+
+    impl MemoryMappedDevice for TestController {
+        fn read_byte(&mut self, _address: usize) -> u8 {
+            self.reads += 1;
+            0
+        }
+
+        fn write_byte(&mut self, _address: usize, _value: u8) {}
+    }
+
+    // A stand in for the DMC, which asks for a byte on each of the given cycles
+    struct TestClient {
+        cycles: u32,
+        fetch_cycles: Vec<u32>,
+        is_waiting: bool,
+        samples: Vec<(u32, u8)>,
+    }
+
+    impl TestClient {
+        fn new(fetch_cycles: &[u32]) -> TestClient {
+            TestClient {
+                cycles: 0,
+                fetch_cycles: fetch_cycles.to_vec(),
+                is_waiting: fetch_cycles.contains(&0),
+                samples: Vec::new(),
+            }
+        }
+    }
+
+    impl DmaClient for TestClient {
+        fn clock_cpu(&mut self) {
+            self.cycles += 1;
+            if self.fetch_cycles.contains(&self.cycles) {
+                self.is_waiting = true;
+            }
+        }
+
+        fn get_dmc_fetch_address(&self) -> Option<u16> {
+            self.is_waiting.then_some(0xC000)
+        }
+
+        fn load_dmc_sample(&mut self, value: u8) {
+            self.samples.push((self.cycles, value));
+            self.is_waiting = false;
+        }
+    }
+
+    // Memory with the sprite page at $0200, OAM behind $2004 and a controller behind $4016
+    fn get_test_system() -> (
+        Memory,
+        Dma,
+        Rc<RefCell<TestOam>>,
+        Rc<RefCell<TestController>>,
+    ) {
+        let mut memory: Memory = get_test_memory(0x10000);
+        let page: Vec<u8> = (0..=255).collect();
+        memory.write(0x0200, page);
+        memory.write(0xC000, [0x5A].to_vec());
+
+        let dma: Dma = Dma::new();
+        memory.map_device(0x4014, 0x4014, Rc::new(RefCell::new(dma.get_register())));
+        let oam: Rc<RefCell<TestOam>> = Rc::new(RefCell::new(TestOam { data: Vec::new() }));
+        memory.map_device(0x2004, 0x2004, oam.clone());
+        let controller: Rc<RefCell<TestController>> =
+            Rc::new(RefCell::new(TestController { reads: 0 }));
+        memory.map_device(0x4016, 0x4016, controller.clone());
+        (memory, dma, oam, controller)
+    }
+
+    #[test]
+    fn test_nothing_to_transfer() {
+        // Prep for the test
+        let (mut memory, mut dma, oam, _) = get_test_system();
+        let mut client: TestClient = TestClient::new(&[]);
+
+        // Assert results
+        assert_eq!(dma.run(&mut memory, 0, &mut client), 0);
+        assert_eq!(client.cycles, 0);
+        assert!(oam.borrow().data.is_empty());
+    }
+
+    #[test]
+    fn test_oam_dma_copies_page() {
+        // Prep for the test
+        let (mut memory, mut dma, oam, _) = get_test_system();
+        let mut client: TestClient = TestClient::new(&[]);
+        memory.write(0x4014, [0x02].to_vec());
+
+        // Run the transfer
+        let cycles: u32 = dma.run(&mut memory, 1, &mut client);
+
+        // Assert results
+        let expected: Vec<u8> = (0..=255).collect();
+        assert_eq!(oam.borrow().data, expected);
+        assert_eq!(client.cycles, cycles);
+
+        // The request is used up by the transfer
+        assert_eq!(dma.run(&mut memory, 1 + u64::from(cycles), &mut client), 0);
+    }
+
+    #[test]
+    fn test_oam_dma_alignment() {
+        // Prep for the test
+        let (mut memory, mut dma, _, _) = get_test_system();
+        let mut client: TestClient = TestClient::new(&[]);
+
+        // Starting on a put cycle, the halt is followed by a get cycle
+        memory.write(0x4014, [0x02].to_vec());
+        let aligned: u32 = dma.run(&mut memory, 1, &mut client);
+
+        // Starting on a get cycle needs an extra cycle to line the reads up again
+        memory.write(0x4014, [0x02].to_vec());
+        let misaligned: u32 = dma.run(&mut memory, 2, &mut client);
+
+        // Assert results
+        assert_eq!(aligned, 513);
+        assert_eq!(misaligned, 514);
+    }
+
+    #[test]
+    fn test_dmc_dma_alignment() {
+        // Prep for the test
+        let (mut memory, mut dma, _, _) = get_test_system();
+
+        // The halt and dummy cycles are followed by the get if it lines up
+        let mut client: TestClient = TestClient::new(&[0]);
+        let aligned: u32 = dma.run(&mut memory, 0, &mut client);
+        assert_eq!(client.samples, [(2, 0x5A)].to_vec());
+
+        // Otherwise there is an extra cycle before the get
+        let mut client: TestClient = TestClient::new(&[0]);
+        let misaligned: u32 = dma.run(&mut memory, 1, &mut client);
+        assert_eq!(client.samples, [(3, 0x5A)].to_vec());
+
+        // Assert results
+        assert_eq!(aligned, 3);
+        assert_eq!(misaligned, 4);
+    }
+
+    #[test]
+    fn test_dmc_dma_during_oam_dma() {
+        // Prep for the test
+        let (mut memory, mut dma, oam, _) = get_test_system();
+
+        // A sample fetch in the middle of the transfer costs its get and a realignment cycle,
+        // either on the get it takes from sprite DMA or on the get after a sprite write
+        for fetch_cycle in [100, 101] {
+            let mut client: TestClient = TestClient::new(&[fetch_cycle]);
+            memory.write(0x4014, [0x02].to_vec());
+            let cycles: u32 = dma.run(&mut memory, 1, &mut client);
+            assert_eq!(cycles, 515);
+            assert_eq!(client.samples.len(), 1);
+        }
+
+        // The sprite data is not disturbed by the fetches
+        let expected: Vec<u8> = (0..=255).chain(0..=255).collect();
+        assert_eq!(oam.borrow().data, expected);
+    }
+
+    #[test]
+    fn test_dmc_dma_at_end_of_oam_dma() {
+        // Prep for the test
+        let (mut memory, mut dma, _, _) = get_test_system();
+
+        // A fetch whose get lands just after the last sprite write only adds that get
+        let mut client: TestClient = TestClient::new(&[511]);
+        memory.write(0x4014, [0x02].to_vec());
+        let end: u32 = dma.run(&mut memory, 1, &mut client);
+
+        // A fetch requested on the last cycle still needs its own halt and dummy cycles
+        let mut client: TestClient = TestClient::new(&[513]);
+        memory.write(0x4014, [0x02].to_vec());
+        let after: u32 = dma.run(&mut memory, 1, &mut client);
+
+        // Assert results
+        assert_eq!(end, 514);
+        assert_eq!(after, 516);
+    }
+
+    #[test]
+    fn test_dmc_dma_repeats_controller_read() {
+        // Prep for the test
+        let (mut memory, mut dma, _, controller) = get_test_system();
+
+        // A fetch after an instruction that ended reading the controller reads it again
+        memory.read(0x4016, 1);
+        let mut client: TestClient = TestClient::new(&[0]);
+        dma.run(&mut memory, 0, &mut client);
+        assert_eq!(controller.borrow().reads, 2);
+
+        // Sprite DMA starts after a write, so the controller is left alone
+        memory.read(0x4016, 1);
+        memory.write(0x4014, [0x02].to_vec());
+        let mut client: TestClient = TestClient::new(&[]);
+        dma.run(&mut memory, 0, &mut client);
+
+        // Assert results
+        assert_eq!(controller.borrow().reads, 3);
+    }
+}
@@ -42,9 +42,7 @@ impl Opcode for Opcode0x30 {
             return;
         }
 
-        let operand = _system.get_branch_relative_jump(_system.program_counter);
-
-        _system.register_add(Register::ProgramCounter, operand);
+        _system.take_branch();
     }
 }
 
@@ -24,6 +24,7 @@
 
 use crate::models::mos6502::instructions::Opcode;
 use crate::models::mos6502::Mos6502;
+use crate::models::mos6502::Register;
 
 pub struct Opcode0xbd {}
 
@@ -33,6 +34,81 @@ impl Opcode for Opcode0xbd {
     }
 
     fn execute(mut _system: &mut Mos6502) {
-        panic!("Instruction '0xbd' is not implemented")
+        // Get the operand data from the memory
+        let instruction_arg: u16 = _system.get_instruction_argument(_system.program_counter, 2);
+
+        // Increase PC by amount of bytes read
+        _system.register_add(Register::ProgramCounter, 2);
+
+        // Get the address, using the x index as an offset
+        let address: u16 = _system.get_indexed_read_address(_system.x_index, instruction_arg);
+
+        // Load the data from memory into the accumulator register
+        _system.accumulator = _system.memory.read(address.into(), 1)[0];
+
+        // If the MSB is high then we will need to set N
+        if _system.accumulator & 0b1000_0000 == 0 {
+            _system.clear_n_flag();
+        } else {
+            _system.set_n_flag();
+        }
+
+        // If the value is zero then set Z
+        if _system.accumulator == 0 {
+            _system.set_z_flag();
+        } else {
+            _system.clear_z_flag();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::mos6502::tests::get_test_mos6502;
+
+    #[test]
+    fn test_load_within_page() {
+        // Prep for the test
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+
+        system.program_counter = 0x01;
+        system.memory.write(0, [0xbd, 0x00, 0x44].to_vec());
+        system.memory.write(0x4410, [0x80].to_vec());
+
+        system.x_index = 0x10;
+
+        // Execute instruction
+        Opcode0xbd::execute(&mut system);
+
+        // Assert results
+        assert_eq!(system.accumulator, 0x80);
+        assert!(system.is_n_set());
+        assert!(!system.is_z_set());
+        assert_eq!(system.program_counter, 0x03);
+    }
+
+    #[test]
+    fn test_load_across_page() {
+        // Prep for the test
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+
+        system.program_counter = 0x00;
+        system.memory.write(0, [0xbd, 0xF0, 0x44].to_vec());
+        system.memory.write(0x4500, [0x00].to_vec());
+        system.accumulator = 0x12;
+
+        system.x_index = 0x10;
+
+        // Execute instruction
+        let cycles: u8 = system.execute_next_instruction();
+
+        // Assert results
+        assert_eq!(system.accumulator, 0x00);
+        assert!(!system.is_n_set());
+        assert!(system.is_z_set());
+        assert_eq!(system.program_counter, 0x03);
+        assert_eq!(cycles, 5);
     }
 }
@@ -22,11 +22,14 @@
 // SOFTWARE.
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
+pub mod dma;
 mod instructions;
 
 use crate::common::clock::Clock;
+use crate::common::interrupt::InterruptLine;
 use crate::common::memory::Memory;
 use crate::common::utils::Utils;
+use crate::models::mos6502::dma::{Dma, DmaClient};
 use crate::models::mos6502::instructions::decoder::Decoder;
 use std::convert::TryFrom;
 
@@ -42,8 +45,18 @@ pub enum Register {
 pub struct Mos6502 {
     pub accumulator: u8,
     pub clock: Clock,
+    // The CPU cycles run since power on, which decide how DMA transfers line up
+    pub cycle_count: u64,
+    pub dma: Dma,
+    // Cycles the running instruction takes on top of its base count, from taking a branch or
+    // reading across a page boundary
+    extra_cycles: u8,
     pub flags: u8,
+    pub irq_line: InterruptLine,
     pub memory: Memory,
+    pub nmi_line: InterruptLine,
+    // The NMI line's level when interrupts were last checked, to find the edge that triggers it
+    nmi_was_asserted: bool,
     pub program_counter: u16,
     pub stack: u8,
     pub x_index: u8,
@@ -55,8 +68,14 @@ impl Mos6502 {
         Mos6502 {
             accumulator: 0,
             clock: Clock::new(clock_speed_hz),
+            cycle_count: 0,
+            dma: Dma::new(),
+            extra_cycles: 0,
             flags: 0,
+            irq_line: InterruptLine::new(),
             memory: Memory::new(memory_size).unwrap(),
+            nmi_line: InterruptLine::new(),
+            nmi_was_asserted: false,
             program_counter: 0x34,
             stack: 0xFD,
             x_index: 0,
@@ -64,20 +83,77 @@ impl Mos6502 {
         }
     }
 
-    pub fn run(&mut self) {
-        loop {
-            // Wait for clock cycle
-            self.clock.tick();
+    // Run a single instruction, returning the CPU cycles it took
+    pub fn step(&mut self) -> u8 {
+        // Wait for clock cycle
+        self.clock.tick();
 
-            // Fetch the address from memory
-            let instruction_data: Vec<u8> = self.memory.read(self.program_counter.into(), 1);
+        self.execute_next_instruction()
+    }
+
+    // Run a single instruction without waiting for the clock, returning the CPU cycles it took
+    // Used when emulating faster than real time, such as when rendering audio to a file
+    pub fn execute_next_instruction(&mut self) -> u8 {
+        // Handle any pending interrupts before the next instruction
+        let interrupt_cycles: u8 = if self.service_interrupts() { 7 } else { 0 };
+
+        // Fetch the address from memory
+        let instruction_data: Vec<u8> = self.memory.read(self.program_counter.into(), 1);
+
+        // Increment program counter
+        self.program_counter = self.program_counter + 1;
+
+        // Decode and execute
+        self.extra_cycles = 0;
+        Decoder::execute(self, instruction_data[0]);
+        let cycles: u8 =
+            interrupt_cycles + Decoder::get_cycles(instruction_data[0]) + self.extra_cycles;
+        self.cycle_count += u64::from(cycles);
+        cycles
+    }
+
+    // Halt for any DMA transfers started since the last instruction, returning the cycles stalled
+    // The rest of the console is clocked through the client while the CPU is halted
+    pub fn run_dma(&mut self, client: &mut dyn DmaClient) -> u32 {
+        let cycles: u32 = self.dma.run(&mut self.memory, self.cycle_count, client);
+        self.cycle_count += u64::from(cycles);
+        cycles
+    }
 
-            // Increment program counter
-            self.program_counter = self.program_counter + 1;
+    // Check the interrupt lines and enter the interrupt handler if one is being requested
+    // Returns whether an interrupt was taken
+    pub fn service_interrupts(&mut self) -> bool {
+        // NMI is edge triggered, so it is taken once each time the line becomes asserted and
+        // regardless of the interrupt flag
+        let nmi_asserted: bool = self.nmi_line.is_asserted();
+        let nmi_edge: bool = nmi_asserted && !self.nmi_was_asserted;
+        self.nmi_was_asserted = nmi_asserted;
+        if nmi_edge {
+            self.interrupt(0xFFFA);
+            return true;
+        }
 
-            // Decode and execute
-            Decoder::execute(self, instruction_data[0]);
+        // IRQ is level triggered and is ignored while the interrupt flag is set
+        if self.irq_line.is_asserted() && !self.is_i_set() {
+            self.interrupt(0xFFFE);
+            return true;
         }
+        false
+    }
+
+    // Push the program counter and flags then jump through an interrupt vector
+    pub fn interrupt(&mut self, vector: u16) {
+        let address_bytes = Utils::get_u8_pair_from_u16(self.program_counter);
+        self.stack_push(address_bytes.0);
+        self.stack_push(address_bytes.1);
+
+        // The copy of the flags pushed by a hardware interrupt has bit 5 set and the break flag clear
+        self.stack_push((self.flags | 0b0010_0000) & 0b1110_1111);
+
+        // Further interrupts are masked until the handler clears the flag
+        self.set_i_flag();
+
+        self.program_counter = self.get_instruction_argument(vector, 2);
     }
 
     // Flag bit 0 - Carry
@@ -303,6 +379,29 @@ impl Mos6502 {
         return offset.try_into().unwrap();
     }
 
+    // Jump by the relative operand at the program counter
+    // Taking a branch costs a cycle, and another if it lands on a different page
+    pub fn take_branch(&mut self) {
+        let next_instruction: u16 = self.program_counter.wrapping_add(1);
+        let operand: isize = self.get_branch_relative_jump(self.program_counter);
+        self.register_add(Register::ProgramCounter, operand);
+
+        self.extra_cycles += 1;
+        if next_instruction & 0xFF00 != self.program_counter & 0xFF00 {
+            self.extra_cycles += 1;
+        }
+    }
+
+    // Get the address an indexed read comes from
+    // The CPU takes a cycle to fix up the high byte when indexing crosses a page
+    pub fn get_indexed_read_address(&mut self, index: u8, operand: u16) -> u16 {
+        let address: u16 = operand.wrapping_add(index.into());
+        if address & 0xFF00 != operand & 0xFF00 {
+            self.extra_cycles += 1;
+        }
+        address
+    }
+
     pub fn get_instruction_argument(&mut self, offset: u16, size: usize) -> u16 {
         // We expect this to be between 1 and 4 bytes
         assert!(size >= 1);
@@ -375,6 +474,9 @@ impl Mos6502 {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::common::interrupt::InterruptSource;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     pub fn get_test_mos6502(memory_size: usize, clock_speed_hz: f64) -> Mos6502 {
         let mut system: Mos6502 = Mos6502::new(memory_size, clock_speed_hz);
@@ -382,6 +484,178 @@ pub mod tests {
         return system;
     }
 
+    #[test]
+    pub fn test_irq() {
+        // Get a system
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+        system.program_counter = 0x1234;
+        system.memory.write(0xFFFE, [0x00, 0x80].to_vec());
+
+        // Nothing happens while the line is released
+        system.service_interrupts();
+        assert_eq!(system.program_counter, 0x1234);
+
+        // Assert the line
+        system.irq_line.set(InterruptSource::Mapper, true);
+        system.service_interrupts();
+
+        // Verify the handler was entered with the return address and flags on the stack
+        assert_eq!(system.program_counter, 0x8000);
+        assert!(system.is_i_set());
+        assert_eq!(system.stack, 0xFA);
+        assert_eq!(system.memory.read(0x01FB, 3), [0x20, 0x34, 0x12].to_vec());
+
+        // The interrupt flag masks the line from now on
+        system.program_counter = 0x1234;
+        system.service_interrupts();
+        assert_eq!(system.program_counter, 0x1234);
+    }
+
+    #[test]
+    pub fn test_nmi() {
+        // Get a system with the interrupt flag set, which does not mask NMI
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+        system.program_counter = 0x1234;
+        system.set_i_flag();
+        system.memory.write(0xFFFA, [0x00, 0x90].to_vec());
+
+        // Assert the line
+        system.nmi_line.set(InterruptSource::Ppu, true);
+        assert!(system.service_interrupts());
+        assert_eq!(system.program_counter, 0x9000);
+
+        // Holding the line asserted does not trigger it again
+        system.program_counter = 0x1234;
+        assert!(!system.service_interrupts());
+        assert_eq!(system.program_counter, 0x1234);
+
+        // Releasing and asserting it again does
+        system.nmi_line.set(InterruptSource::Ppu, false);
+        assert!(!system.service_interrupts());
+        system.nmi_line.set(InterruptSource::Ppu, true);
+        assert!(system.service_interrupts());
+        assert_eq!(system.program_counter, 0x9000);
+    }
+
+    #[test]
+    pub fn test_instruction_cycles() {
+        // Get a system with LDA #$01, JMP $0000 and an IRQ handler at $8000
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+        system
+            .memory
+            .write(0x0000, [0xA9, 0x01, 0x4C, 0x00, 0x00].to_vec());
+        system.memory.write(0x8000, [0xEA].to_vec());
+        system.memory.write(0xFFFE, [0x00, 0x80].to_vec());
+
+        // Verify the base cycle counts
+        assert_eq!(system.execute_next_instruction(), 2);
+        assert_eq!(system.execute_next_instruction(), 3);
+        assert_eq!(system.program_counter, 0x0000);
+
+        // Taking an interrupt adds its own seven cycles to the handler's first instruction
+        system.irq_line.set(InterruptSource::Mapper, true);
+        assert_eq!(system.execute_next_instruction(), 9);
+        assert_eq!(system.program_counter, 0x8001);
+    }
+
+    // Counts the cycles the rest of the console is clocked for while the CPU is halted
+    struct CountingClient {
+        cycles: u32,
+    }
+
+    impl DmaClient for CountingClient {
+        fn clock_cpu(&mut self) {
+            self.cycles += 1;
+        }
+
+        fn get_dmc_fetch_address(&self) -> Option<u16> {
+            None
+        }
+
+        fn load_dmc_sample(&mut self, _value: u8) {}
+    }
+
+    #[test]
+    pub fn test_oam_dma_stall() {
+        // Get a system with NOP, NOP, NOP, JMP $0000
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+        system
+            .memory
+            .write(0x0000, [0xEA, 0xEA, 0xEA, 0x4C, 0x00, 0x00].to_vec());
+        system.memory.write(0x0200, [0xAA; 256].to_vec());
+        let register = Rc::new(RefCell::new(system.dma.get_register()));
+        system.memory.map_device(0x4014, 0x4014, register);
+        let mut client: CountingClient = CountingClient { cycles: 0 };
+
+        // Nothing stalls the CPU before the register is written
+        system.execute_next_instruction();
+        system.execute_next_instruction();
+        system.execute_next_instruction();
+        assert_eq!(system.run_dma(&mut client), 0);
+
+        // A transfer starting on cycle 6 needs an extra cycle to line up
+        system.memory.write(0x4014, [0x02].to_vec());
+        assert_eq!(system.run_dma(&mut client), 514);
+        assert_eq!(system.cycle_count, 520);
+
+        // Starting on cycle 523 lines up after the halt cycle
+        system.execute_next_instruction();
+        system.memory.write(0x4014, [0x02].to_vec());
+        assert_eq!(system.run_dma(&mut client), 513);
+        assert_eq!(system.cycle_count, 1036);
+        assert_eq!(client.cycles, 1027);
+    }
+
+    #[test]
+    pub fn test_branch_cycles() {
+        // Get a system with BCC +2 at $0000 and BCC +5 at $00FD
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+        system.memory.write(0x0000, [0x90, 0x02].to_vec());
+        system.memory.write(0x00FD, [0x90, 0x05].to_vec());
+        system.memory.write(0x0010, [0xB0, 0x05].to_vec());
+        system.clear_c_flag();
+
+        // A taken branch on the same page costs one extra cycle
+        system.program_counter = 0x0000;
+        assert_eq!(system.execute_next_instruction(), 3);
+
+        // Landing on the next page costs another
+        system.program_counter = 0x00FD;
+        assert_eq!(system.execute_next_instruction(), 4);
+
+        // A branch not taken only costs the base cycles
+        system.program_counter = 0x0010;
+        assert_eq!(system.execute_next_instruction(), 2);
+        assert_eq!(system.cycle_count, 9);
+    }
+
+    #[test]
+    pub fn test_oam_dma_parity_after_extra_cycles() {
+        // Get a system with a taken BCC +2, then LDA $44F0,X
+        let mut system: Mos6502 = get_test_mos6502(0x10000, 1000000.0);
+        system.memory.write(0x0000, [0x90, 0x02].to_vec());
+        system.memory.write(0x0003, [0xBD, 0xF0, 0x44].to_vec());
+        system.memory.write(0x0200, [0xAA; 256].to_vec());
+        let register = Rc::new(RefCell::new(system.dma.get_register()));
+        system.memory.map_device(0x4014, 0x4014, register);
+        let mut client: CountingClient = CountingClient { cycles: 0 };
+        system.clear_c_flag();
+        system.x_index = 0x10;
+        system.program_counter = 0x0000;
+
+        // The taken branch leaves the transfer starting on odd cycle 3
+        system.execute_next_instruction();
+        system.memory.write(0x4014, [0x02].to_vec());
+        assert_eq!(system.run_dma(&mut client), 513);
+        assert_eq!(system.cycle_count, 516);
+
+        // Reading across a page leaves it starting on odd cycle 521
+        system.execute_next_instruction();
+        system.memory.write(0x4014, [0x02].to_vec());
+        assert_eq!(system.run_dma(&mut client), 513);
+        assert_eq!(system.cycle_count, 1034);
+    }
+
     #[test]
     pub fn test_c_flag() {
         // Get a system
@@ -0,0 +1,424 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// The memory map NSF drivers are run on, in place of a cartridge
+// Program data is banked into $8000-$FFFF in 4KB pages through $5FF8-$5FFF, with PRG-RAM at
+// $6000-$7FFF, the console's APU and the registers of any expansion chips the file uses.
+// Files for the Famicom Disk System run from RAM at $6000-$FFFF instead, and bankswitching
+// copies pages into that RAM, including $6000-$7FFF through $5FF6 and $5FF7.
+// https://www.nesdev.org/wiki/NSF#Bankswitching
+
+use crate::common::memory::MemoryMappedDevice;
+use crate::models::audio::apu::Apu;
+use crate::models::audio::mixer;
+use crate::models::cartridge::mappers::fds::audio::FdsAudio;
+use crate::models::cartridge::mappers::fme7::audio::Sunsoft5bAudio;
+use crate::models::cartridge::mappers::mmc5::audio::Mmc5Audio;
+use crate::models::cartridge::mappers::namco163::audio::Namco163Audio;
+use crate::models::cartridge::mappers::vrc6::audio::Vrc6Audio;
+use crate::models::cartridge::mappers::vrc7::audio::Vrc7Audio;
+use crate::models::nsf::{
+    Nsf, NsfError, EXPANSION_FDS, EXPANSION_MMC5, EXPANSION_NAMCO163, EXPANSION_SUNSOFT5B,
+    EXPANSION_VRC6, EXPANSION_VRC7,
+};
+
+const BANK_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+// The disk system's RAM covers $6000-$FFFF
+const FDS_RAM_SIZE: usize = 0xA000;
+const MMC5_EXRAM_SIZE: usize = 0x0400;
+
+pub struct NsfBus {
+    pub apu: Apu,
+    // The program data, padded at the front so it lines up with the 4KB banks
+    image: Vec<u8>,
+    banks: [usize; 8],
+    // PRG-RAM at $6000-$7FFF, or the disk system's RAM at $6000-$FFFF
+    ram: Vec<u8>,
+    is_fds: bool,
+    vrc6: Option<Vrc6Audio>,
+    vrc7: Option<Vrc7Audio>,
+    fds: Option<FdsAudio>,
+    mmc5: Option<Mmc5Audio>,
+    namco163: Option<Namco163Audio>,
+    sunsoft5b: Option<Sunsoft5bAudio>,
+    // The MMC5's expansion RAM and multiplier are usable as general purpose hardware
+    mmc5_exram: Vec<u8>,
+    mmc5_multiplicand: u8,
+    mmc5_multiplier: u8,
+}
+
+impl NsfBus {
+    pub fn new(nsf: &Nsf) -> Result<NsfBus, NsfError> {
+        let is_fds: bool = nsf.has_expansion(EXPANSION_FDS);
+        let lowest_address: u16 = if is_fds { 0x6000 } else { 0x8000 };
+        if nsf.load_address < lowest_address {
+            return Err(NsfError::InvalidLoadAddress(nsf.load_address));
+        }
+
+        // Without bankswitching the data sits at its load address in a linear 32KB
+        let (padding, banks): (usize, [usize; 8]) = if nsf.is_bankswitched() {
+            (
+                usize::from(nsf.load_address) % BANK_SIZE,
+                nsf.bank_init.map(usize::from),
+            )
+        } else {
+            (
+                usize::from(nsf.load_address.saturating_sub(0x8000)),
+                [0, 1, 2, 3, 4, 5, 6, 7],
+            )
+        };
+        let mut image: Vec<u8> = vec![0; padding];
+        image.extend_from_slice(&nsf.data);
+
+        let mut bus: NsfBus = NsfBus {
+            apu: Apu::new(nsf.get_region().get_profile()),
+            image,
+            banks,
+            ram: vec![0; if is_fds { FDS_RAM_SIZE } else { PRG_RAM_SIZE }],
+            is_fds,
+            vrc6: nsf.has_expansion(EXPANSION_VRC6).then(Vrc6Audio::new),
+            vrc7: nsf.has_expansion(EXPANSION_VRC7).then(Vrc7Audio::new),
+            fds: is_fds.then(FdsAudio::new),
+            mmc5: nsf.has_expansion(EXPANSION_MMC5).then(Mmc5Audio::new),
+            namco163: nsf
+                .has_expansion(EXPANSION_NAMCO163)
+                .then(Namco163Audio::new),
+            sunsoft5b: nsf
+                .has_expansion(EXPANSION_SUNSOFT5B)
+                .then(Sunsoft5bAudio::new),
+            mmc5_exram: vec![0; MMC5_EXRAM_SIZE],
+            mmc5_multiplicand: 0xFF,
+            mmc5_multiplier: 0xFF,
+        };
+
+        // The disk system has no ROM to bank in, so the data is copied into its RAM
+        if is_fds {
+            if nsf.is_bankswitched() {
+                bus.write_bank(6, nsf.bank_init[6]);
+                bus.write_bank(7, nsf.bank_init[7]);
+                for (index, bank) in nsf.bank_init.iter().enumerate() {
+                    bus.write_bank(8 + index, *bank);
+                }
+            } else {
+                let start: usize = usize::from(nsf.load_address) - 0x6000;
+                let length: usize = nsf.data.len().min(FDS_RAM_SIZE - start);
+                bus.ram[start..start + length].copy_from_slice(&nsf.data[..length]);
+            }
+        }
+        Ok(bus)
+    }
+
+    pub fn clock_cpu(&mut self) {
+        self.apu.clock_cpu();
+
+        // The DMC reads its samples through the CPU's address space
+        if let Some(address) = self.apu.dmc.get_fetch_address() {
+            let value: u8 = self.read_byte(usize::from(address));
+            self.apu.dmc.load_sample_byte(value);
+        }
+
+        if let Some(vrc6) = self.vrc6.as_mut() {
+            vrc6.clock_cpu();
+        }
+        if let Some(vrc7) = self.vrc7.as_mut() {
+            vrc7.clock_cpu();
+        }
+        if let Some(fds) = self.fds.as_mut() {
+            fds.clock_cpu();
+        }
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            mmc5.clock_cpu();
+        }
+        if let Some(namco163) = self.namco163.as_mut() {
+            namco163.clock_cpu();
+        }
+        if let Some(sunsoft5b) = self.sunsoft5b.as_mut() {
+            sunsoft5b.clock_cpu();
+        }
+    }
+
+    // The console's channels mixed with the expansion chips
+    pub fn get_output(&self) -> f32 {
+        let expansion: f32 = self.vrc6.as_ref().map_or(0.0, |audio| audio.get_output())
+            + self.vrc7.as_ref().map_or(0.0, |audio| audio.get_output())
+            + self.fds.as_ref().map_or(0.0, |audio| audio.get_output())
+            + self.mmc5.as_ref().map_or(0.0, |audio| audio.get_output())
+            + self
+                .namco163
+                .as_ref()
+                .map_or(0.0, |audio| audio.get_output())
+            + self
+                .sunsoft5b
+                .as_ref()
+                .map_or(0.0, |audio| audio.get_output());
+        mixer::mix(&self.apu.get_levels(), expansion)
+    }
+
+    // Select the bank for one of the 4KB pages from $6000 upwards, where page 8 is $8000
+    // Only the disk system has pages below $8000, which are copied rather than mapped
+    fn write_bank(&mut self, page: usize, bank: u8) {
+        if self.is_fds {
+            let start: usize = (page - 6) * BANK_SIZE;
+            for index in 0..BANK_SIZE {
+                self.ram[start + index] = self.read_image(usize::from(bank), index);
+            }
+        } else if page >= 8 {
+            self.banks[page - 8] = usize::from(bank);
+        }
+    }
+
+    // Banks past the end of the data read as zero
+    fn read_image(&self, bank: usize, offset: usize) -> u8 {
+        *self.image.get(bank * BANK_SIZE + offset).unwrap_or(&0)
+    }
+
+    // Registers of the expansion chips, several chips can share an address
+    fn write_expansion(&mut self, address: u16, value: u8) {
+        if let Some(vrc6) = self.vrc6.as_mut() {
+            if let 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 = address {
+                vrc6.write_register(address, value);
+            }
+        }
+        if let Some(vrc7) = self.vrc7.as_mut() {
+            match address {
+                0x9010 => vrc7.write_address(value),
+                0x9030 => vrc7.write_data(value),
+                _ => {}
+            }
+        }
+        if let Some(fds) = self.fds.as_mut() {
+            if let 0x4040..=0x408A = address {
+                fds.write_register(address, value);
+            }
+        }
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            match address {
+                0x5000..=0x5015 => mmc5.write_register(address, value),
+                0x5205 => self.mmc5_multiplicand = value,
+                0x5206 => self.mmc5_multiplier = value,
+                0x5C00..=0x5FF5 => self.mmc5_exram[usize::from(address - 0x5C00)] = value,
+                _ => {}
+            }
+        }
+        if let Some(namco163) = self.namco163.as_mut() {
+            match address {
+                0x4800..=0x4FFF => namco163.write_data(value),
+                0xF800..=0xFFFF => namco163.write_address(value),
+                _ => {}
+            }
+        }
+        if let Some(sunsoft5b) = self.sunsoft5b.as_mut() {
+            match address {
+                0xC000..=0xDFFF => sunsoft5b.write_address(value),
+                0xE000..=0xFFFF => sunsoft5b.write_data(value),
+                _ => {}
+            }
+        }
+    }
+
+    // Registers of the expansion chips that can be read, or None when nothing responds
+    fn read_expansion(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407F | 0x4090 | 0x4092 => {
+                self.fds.as_ref().map(|fds| fds.read_register(address))
+            }
+            0x4800..=0x4FFF => self.namco163.as_mut().map(|namco163| namco163.read_data()),
+            0x5010 | 0x5015 => self
+                .mmc5
+                .as_mut()
+                .and_then(|mmc5| mmc5.read_register(address)),
+            0x5205 | 0x5206 if self.mmc5.is_some() => {
+                let product: u16 =
+                    u16::from(self.mmc5_multiplicand) * u16::from(self.mmc5_multiplier);
+                Some(if address == 0x5205 {
+                    product as u8
+                } else {
+                    (product >> 8) as u8
+                })
+            }
+            0x5C00..=0x5FF5 if self.mmc5.is_some() => {
+                Some(self.mmc5_exram[usize::from(address - 0x5C00)])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl MemoryMappedDevice for NsfBus {
+    fn read_byte(&mut self, address: usize) -> u8 {
+        let address: u16 = address as u16;
+        if let Some(value) = self.read_expansion(address) {
+            return value;
+        }
+
+        match address {
+            0x4015 => self.apu.read_status(),
+            0x6000..=0xFFFF if self.is_fds => self.ram[usize::from(address - 0x6000)],
+            0x6000..=0x7FFF => self.ram[usize::from(address - 0x6000)],
+            0x8000..=0xFFFF => {
+                let page: usize = usize::from(address - 0x8000) / BANK_SIZE;
+                let value: u8 = self.read_image(self.banks[page], usize::from(address) % BANK_SIZE);
+                if let Some(mmc5) = self.mmc5.as_mut() {
+                    mmc5.notify_prg_read(address, value);
+                }
+                value
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) {
+        let address: u16 = address as u16;
+        self.write_expansion(address, value);
+
+        match address {
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write_register(address, value),
+            0x5FF6..=0x5FF7 if self.is_fds => self.write_bank(usize::from(address - 0x5FF0), value),
+            0x5FF8..=0x5FFF => self.write_bank(usize::from(address - 0x5FF0), value),
+            // The disk system's BIOS area at $E000-$FFFF stays read only
+            0x6000..=0xDFFF if self.is_fds => self.ram[usize::from(address - 0x6000)] = value,
+            0x6000..=0x7FFF => self.ram[usize::from(address - 0x6000)] = value,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nsf::tests::get_test_nsf_file;
+
+    // Data where each 4KB bank is filled with its own bank number
+    fn get_banked_data(bank_count: u8) -> Vec<u8> {
+        (0..bank_count)
+            .flat_map(|bank| vec![bank; BANK_SIZE])
+            .collect()
+    }
+
+    #[test]
+    fn test_linear_load() {
+        // Prep for the test, data loaded part way into the address space
+        let content: Vec<u8> =
+            get_test_nsf_file([0x8100, 0x8100, 0x8100], [0; 8], 0, &[0x12, 0x34]);
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        let mut bus: NsfBus = NsfBus::new(&nsf).unwrap();
+
+        // Assert results
+        assert_eq!(bus.read_byte(0x80FF), 0x00);
+        assert_eq!(bus.read_byte(0x8100), 0x12);
+        assert_eq!(bus.read_byte(0x8101), 0x34);
+
+        // PRG-RAM is writable, the program is not
+        bus.write_byte(0x6123, 0x56);
+        bus.write_byte(0x8100, 0x56);
+        assert_eq!(bus.read_byte(0x6123), 0x56);
+        assert_eq!(bus.read_byte(0x8100), 0x12);
+
+        // Data can only be loaded into ROM
+        let content: Vec<u8> = get_test_nsf_file([0x6000, 0x6000, 0x6000], [0; 8], 0, &[0x60]);
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        assert_eq!(
+            NsfBus::new(&nsf).err(),
+            Some(NsfError::InvalidLoadAddress(0x6000))
+        );
+    }
+
+    #[test]
+    fn test_bankswitching() {
+        // Prep for the test, four banks loaded at an offset into the first bank
+        let content: Vec<u8> = get_test_nsf_file(
+            [0x8010, 0x8010, 0x8010],
+            [0, 1, 2, 3, 3, 2, 1, 0],
+            0,
+            &get_banked_data(4),
+        );
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        let mut bus: NsfBus = NsfBus::new(&nsf).unwrap();
+
+        // Assert results, the padding shifts the data by the offset of the load address
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+        assert_eq!(bus.read_byte(0x9010), 0x01);
+        assert_eq!(bus.read_byte(0xC010), 0x03);
+        assert_eq!(bus.read_byte(0xF010), 0x00);
+
+        // Switching a page, and banks past the end of the data read as zero
+        bus.write_byte(0x5FFF, 2);
+        assert_eq!(bus.read_byte(0xF010), 0x02);
+        bus.write_byte(0x5FFF, 9);
+        assert_eq!(bus.read_byte(0xF010), 0x00);
+    }
+
+    #[test]
+    fn test_fds_ram() {
+        // Prep for the test, bankswitched disk system data
+        let content: Vec<u8> = get_test_nsf_file(
+            [0x6000, 0x6000, 0x6000],
+            [1, 1, 1, 1, 1, 1, 2, 3],
+            EXPANSION_FDS,
+            &get_banked_data(4),
+        );
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        let mut bus: NsfBus = NsfBus::new(&nsf).unwrap();
+
+        // Assert results, $5FF6 and $5FF7 start with the same banks as $5FFE and $5FFF
+        assert_eq!(bus.read_byte(0x6000), 0x02);
+        assert_eq!(bus.read_byte(0x7000), 0x03);
+        assert_eq!(bus.read_byte(0x8000), 0x01);
+        assert_eq!(bus.read_byte(0xF000), 0x03);
+
+        // Banks are copies, so the RAM can be written over
+        bus.write_byte(0x8000, 0x99);
+        assert_eq!(bus.read_byte(0x8000), 0x99);
+        bus.write_byte(0x5FF8, 0);
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+
+        // The disk system's wave table is connected
+        bus.write_byte(0x4089, 0b1000_0000);
+        bus.write_byte(0x4040, 0x3F);
+        assert_eq!(bus.read_byte(0x4040), 0x7F);
+    }
+
+    #[test]
+    fn test_expansion_selection() {
+        // Prep for the test, only the MMC5 is present
+        let content: Vec<u8> =
+            get_test_nsf_file([0x8000, 0x8000, 0x8000], [0; 8], EXPANSION_MMC5, &[0x60]);
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        let mut bus: NsfBus = NsfBus::new(&nsf).unwrap();
+
+        // Assert results, the MMC5 multiplier and expansion RAM respond
+        bus.write_byte(0x5205, 0x12);
+        bus.write_byte(0x5206, 0x34);
+        assert_eq!(bus.read_byte(0x5205), 0xA8);
+        assert_eq!(bus.read_byte(0x5206), 0x03);
+        bus.write_byte(0x5C00, 0x77);
+        assert_eq!(bus.read_byte(0x5C00), 0x77);
+
+        // Chips that are not in the header do not
+        assert!(bus.vrc6.is_none());
+        assert_eq!(bus.read_byte(0x4040), 0x00);
+        assert_eq!(bus.read_byte(0x4800), 0x00);
+    }
+}
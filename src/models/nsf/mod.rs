@@ -0,0 +1,490 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// NSF and NSFe music files, which hold a game's sound driver and music without the rest of the game
+// The driver is run by the player on a minimal memory map instead of through a cartridge mapper
+// https://www.nesdev.org/wiki/NSF
+// https://www.nesdev.org/wiki/NSFe
+
+pub mod bus;
+pub mod player;
+
+use crate::models::region::Region;
+use std::fmt;
+
+const NSF_MAGIC: &[u8; 5] = b"NESM\x1A";
+const NSFE_MAGIC: &[u8; 4] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+
+// Each NSFe chunk starts with a 4 byte length and a 4 byte identifier
+const CHUNK_HEADER_SIZE: usize = 8;
+
+// The INFO chunk must hold at least the addresses, the region and the expansion chips
+const MINIMUM_INFO_SIZE: usize = 8;
+
+// Play routine rates in microseconds used when an NSFe file has no RATE chunk
+const DEFAULT_NTSC_PLAY_SPEED: u16 = 16639;
+const DEFAULT_PAL_PLAY_SPEED: u16 = 19997;
+
+// Expansion chips, as bits of the header's expansion byte
+pub const EXPANSION_VRC6: u8 = 0b0000_0001;
+pub const EXPANSION_VRC7: u8 = 0b0000_0010;
+pub const EXPANSION_FDS: u8 = 0b0000_0100;
+pub const EXPANSION_MMC5: u8 = 0b0000_1000;
+pub const EXPANSION_NAMCO163: u8 = 0b0001_0000;
+pub const EXPANSION_SUNSOFT5B: u8 = 0b0010_0000;
+
+// Errors that can occur while loading or playing an NSF file
+#[derive(Debug, PartialEq, Eq)]
+pub enum NsfError {
+    // The file does not start with a recognised header
+    InvalidHeader,
+    // The file ends part way through its header or a chunk
+    TruncatedData,
+    // An NSFe file is missing a chunk it needs
+    MissingChunk(String),
+    // An NSFe file has a chunk that must be understood to play it, but is not
+    UnsupportedChunk(String),
+    // The data would be loaded outside of the memory the player can bank into
+    InvalidLoadAddress(u16),
+    // The requested track is not in the file
+    InvalidTrack(usize),
+}
+
+impl fmt::Display for NsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NsfError::InvalidHeader => write!(f, "NSF header is not valid"),
+            NsfError::TruncatedData => write!(f, "NSF data is shorter than the header claims"),
+            NsfError::MissingChunk(name) => write!(f, "NSFe file has no {} chunk", name),
+            NsfError::UnsupportedChunk(name) => {
+                write!(f, "NSFe chunk {} is required but not supported", name)
+            }
+            NsfError::InvalidLoadAddress(address) => {
+                write!(f, "NSF load address ${:04X} is not valid", address)
+            }
+            NsfError::InvalidTrack(track) => write!(f, "Track {} is not in the file", track),
+        }
+    }
+}
+
+// Metadata for one track, only NSFe files carry it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackInfo {
+    pub name: Option<String>,
+    // How long the track plays before fading out
+    pub length_ms: Option<u32>,
+    pub fade_ms: Option<u32>,
+}
+
+pub struct Nsf {
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+
+    // Play routine rates in microseconds
+    pub ntsc_play_speed: u16,
+    pub pal_play_speed: u16,
+
+    // The initial 4KB banks for $8000-$FFFF, all zero when the file does not bankswitch
+    pub bank_init: [u8; 8],
+
+    // Bit 0 set for PAL, bit 1 set when the file supports both NTSC and PAL
+    pub region_flags: u8,
+
+    // The EXPANSION_ bits for the chips the driver writes to
+    pub expansion_chips: u8,
+
+    // Zero based
+    pub starting_track: usize,
+
+    // One entry per track
+    pub tracks: Vec<TrackInfo>,
+
+    // The driver and music, loaded at the load address
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn is_nsf(content: &[u8]) -> bool {
+        content.starts_with(NSF_MAGIC) || content.starts_with(NSFE_MAGIC)
+    }
+
+    pub fn parse(content: &[u8]) -> Result<Nsf, NsfError> {
+        if content.starts_with(NSF_MAGIC) {
+            Nsf::parse_nsf(content)
+        } else if content.starts_with(NSFE_MAGIC) {
+            Nsf::parse_nsfe(content)
+        } else {
+            Err(NsfError::InvalidHeader)
+        }
+    }
+
+    pub fn is_bankswitched(&self) -> bool {
+        self.bank_init.iter().any(|bank| *bank != 0)
+    }
+
+    pub fn has_expansion(&self, chip: u8) -> bool {
+        self.expansion_chips & chip == chip
+    }
+
+    // Files that support both regions are played as NTSC
+    pub fn is_pal(&self) -> bool {
+        self.region_flags & 0b11 == 0b01
+    }
+
+    pub fn get_region(&self) -> Region {
+        match self.region_flags & 0b11 {
+            0b00 => Region::Ntsc,
+            0b01 => Region::Pal,
+            _ => Region::Multi,
+        }
+    }
+
+    // https://www.nesdev.org/wiki/NSF#Header_Overview
+    fn parse_nsf(content: &[u8]) -> Result<Nsf, NsfError> {
+        if content.len() < NSF_HEADER_SIZE {
+            return Err(NsfError::TruncatedData);
+        }
+        let header: &[u8] = &content[..NSF_HEADER_SIZE];
+        let track_count: usize = usize::from(header[0x06]);
+        let mut bank_init: [u8; 8] = [0; 8];
+        bank_init.copy_from_slice(&header[0x70..0x78]);
+
+        // NSF2 files can follow the data with metadata, in which case the data length is given
+        let mut data: &[u8] = &content[NSF_HEADER_SIZE..];
+        let data_length: usize = usize::from(header[0x7D])
+            | usize::from(header[0x7E]) << 8
+            | usize::from(header[0x7F]) << 16;
+        if header[0x05] >= 2 && data_length != 0 {
+            data = data.get(..data_length).ok_or(NsfError::TruncatedData)?;
+        }
+
+        Ok(Nsf {
+            load_address: get_u16(header, 0x08),
+            init_address: get_u16(header, 0x0A),
+            play_address: get_u16(header, 0x0C),
+            title: get_string(&header[0x0E..0x2E]),
+            artist: get_string(&header[0x2E..0x4E]),
+            copyright: get_string(&header[0x4E..0x6E]),
+            ntsc_play_speed: get_u16(header, 0x6E),
+            pal_play_speed: get_u16(header, 0x78),
+            bank_init,
+            region_flags: header[0x7A],
+            expansion_chips: header[0x7B],
+            starting_track: usize::from(header[0x07].saturating_sub(1)),
+            tracks: vec![TrackInfo::default(); track_count],
+            data: data.to_vec(),
+        })
+    }
+
+    // NSFe files are a list of chunks, where chunks starting with a capital letter are required
+    // to play the file and the rest are optional metadata
+    // https://www.nesdev.org/wiki/NSFe#Chunks
+    fn parse_nsfe(content: &[u8]) -> Result<Nsf, NsfError> {
+        let mut info: Option<&[u8]> = None;
+        let mut data: Option<&[u8]> = None;
+        let mut bank_init: [u8; 8] = [0; 8];
+        let mut ntsc_play_speed: u16 = DEFAULT_NTSC_PLAY_SPEED;
+        let mut pal_play_speed: u16 = DEFAULT_PAL_PLAY_SPEED;
+        let mut authors: Vec<String> = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        let mut lengths: Vec<Option<u32>> = Vec::new();
+        let mut fades: Vec<Option<u32>> = Vec::new();
+
+        let mut offset: usize = NSFE_MAGIC.len();
+        while offset < content.len() {
+            let chunk_header: &[u8] = content
+                .get(offset..offset + CHUNK_HEADER_SIZE)
+                .ok_or(NsfError::TruncatedData)?;
+            let length: usize = u32::from_le_bytes([
+                chunk_header[0],
+                chunk_header[1],
+                chunk_header[2],
+                chunk_header[3],
+            ]) as usize;
+            let id: &[u8] = &chunk_header[4..8];
+            let start: usize = offset + CHUNK_HEADER_SIZE;
+            let chunk: &[u8] = content
+                .get(start..start + length)
+                .ok_or(NsfError::TruncatedData)?;
+            offset = start + length;
+
+            match id {
+                b"INFO" => info = Some(chunk),
+                b"DATA" => data = Some(chunk),
+                b"BANK" => {
+                    let count: usize = chunk.len().min(bank_init.len());
+                    bank_init[..count].copy_from_slice(&chunk[..count]);
+                }
+                b"RATE" => {
+                    if chunk.len() >= 2 {
+                        ntsc_play_speed = get_u16(chunk, 0);
+                    }
+                    if chunk.len() >= 4 {
+                        pal_play_speed = get_u16(chunk, 2);
+                    }
+                }
+                b"NEND" => break,
+                b"auth" => authors = get_strings(chunk),
+                b"tlbl" => names = get_strings(chunk),
+                b"time" => lengths = get_times(chunk),
+                b"fade" => fades = get_times(chunk),
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(NsfError::UnsupportedChunk(
+                        String::from_utf8_lossy(id).to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        let info: &[u8] = info.ok_or_else(|| NsfError::MissingChunk("INFO".to_string()))?;
+        let data: &[u8] = data.ok_or_else(|| NsfError::MissingChunk("DATA".to_string()))?;
+        if info.len() < MINIMUM_INFO_SIZE {
+            return Err(NsfError::TruncatedData);
+        }
+
+        // The track count and zero based starting track are optional
+        let track_count: usize = usize::from(*info.get(8).unwrap_or(&1));
+        let tracks: Vec<TrackInfo> = (0..track_count)
+            .map(|track| TrackInfo {
+                name: names.get(track).cloned(),
+                length_ms: lengths.get(track).copied().flatten(),
+                fade_ms: fades.get(track).copied().flatten(),
+            })
+            .collect();
+        let get_author =
+            |index: usize| -> String { authors.get(index).cloned().unwrap_or_default() };
+
+        Ok(Nsf {
+            load_address: get_u16(info, 0),
+            init_address: get_u16(info, 2),
+            play_address: get_u16(info, 4),
+            title: get_author(0),
+            artist: get_author(1),
+            copyright: get_author(2),
+            ntsc_play_speed,
+            pal_play_speed,
+            bank_init,
+            region_flags: info[6],
+            expansion_chips: info[7],
+            starting_track: usize::from(*info.get(9).unwrap_or(&0)),
+            tracks,
+            data: data.to_vec(),
+        })
+    }
+}
+
+fn get_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+// A string padded or terminated with zeros
+fn get_string(data: &[u8]) -> String {
+    let end: usize = data
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
+
+// A list of zero terminated strings
+fn get_strings(data: &[u8]) -> Vec<String> {
+    let mut strings: Vec<String> = data.split(|byte| *byte == 0).map(get_string).collect();
+
+    // The terminator of the last string leaves an empty entry behind
+    if data.last() == Some(&0) {
+        strings.pop();
+    }
+    strings
+}
+
+// A list of signed 32 bit millisecond times, where negative values mean the default is used
+fn get_times(data: &[u8]) -> Vec<Option<u32>> {
+    data.chunks_exact(4)
+        .map(|bytes| {
+            let time: i32 = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            u32::try_from(time).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Build an NSF file with the given addresses, banks, expansion chips and data
+    pub fn get_test_nsf_file(
+        addresses: [u16; 3],
+        bank_init: [u8; 8],
+        expansion_chips: u8,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut content: Vec<u8> = vec![0; NSF_HEADER_SIZE];
+        content[0..5].copy_from_slice(NSF_MAGIC);
+        content[0x05] = 1;
+        content[0x06] = 3;
+        content[0x07] = 2;
+        for (index, address) in addresses.iter().enumerate() {
+            content[0x08 + index * 2..0x0A + index * 2].copy_from_slice(&address.to_le_bytes());
+        }
+        content[0x0E..0x13].copy_from_slice(b"Title");
+        content[0x2E..0x34].copy_from_slice(b"Artist");
+        content[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+        content[0x70..0x78].copy_from_slice(&bank_init);
+        content[0x78..0x7A].copy_from_slice(&19997u16.to_le_bytes());
+        content[0x7B] = expansion_chips;
+        content.extend_from_slice(data);
+        content
+    }
+
+    fn get_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk: Vec<u8> = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(id);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    #[test]
+    fn test_parse_nsf() {
+        // Prep for the test
+        let content: Vec<u8> = get_test_nsf_file(
+            [0x8000, 0x8010, 0x8020],
+            [0, 1, 2, 3, 4, 5, 6, 7],
+            EXPANSION_VRC6 | EXPANSION_FDS,
+            &[0xEA; 16],
+        );
+        assert!(Nsf::is_nsf(&content));
+
+        // Assert results
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        assert_eq!(nsf.load_address, 0x8000);
+        assert_eq!(nsf.init_address, 0x8010);
+        assert_eq!(nsf.play_address, 0x8020);
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "");
+        assert_eq!(nsf.ntsc_play_speed, 16639);
+        assert_eq!(nsf.pal_play_speed, 19997);
+        assert!(nsf.is_bankswitched());
+        assert!(nsf.has_expansion(EXPANSION_VRC6));
+        assert!(nsf.has_expansion(EXPANSION_FDS));
+        assert!(!nsf.has_expansion(EXPANSION_VRC7));
+        assert!(!nsf.is_pal());
+        assert_eq!(nsf.get_region(), Region::Ntsc);
+        assert_eq!(nsf.starting_track, 1);
+        assert_eq!(nsf.tracks.len(), 3);
+        assert_eq!(nsf.data, [0xEA; 16].to_vec());
+
+        // A header on its own is too short
+        assert_eq!(
+            Nsf::parse(&content[..0x40]).err(),
+            Some(NsfError::TruncatedData)
+        );
+    }
+
+    #[test]
+    fn test_parse_nsfe() {
+        // Prep for the test, two tracks where only the first has a length and fade
+        let mut info: Vec<u8> = Vec::new();
+        for address in [0x8000u16, 0x8010, 0x8020] {
+            info.extend_from_slice(&address.to_le_bytes());
+        }
+        info.extend_from_slice(&[0b01, EXPANSION_NAMCO163, 2, 1]);
+        let mut times: Vec<u8> = 90000i32.to_le_bytes().to_vec();
+        times.extend_from_slice(&(-1i32).to_le_bytes());
+
+        let mut content: Vec<u8> = NSFE_MAGIC.to_vec();
+        content.extend(get_chunk(b"INFO", &info));
+        content.extend(get_chunk(b"DATA", &[0x60]));
+        content.extend(get_chunk(b"BANK", &[0, 1]));
+        content.extend(get_chunk(b"RATE", &16000u16.to_le_bytes()));
+        content.extend(get_chunk(b"auth", b"Game\0Composer\0Company\0Ripper\0"));
+        content.extend(get_chunk(b"tlbl", b"Intro\0Ending\0"));
+        content.extend(get_chunk(b"time", &times));
+        content.extend(get_chunk(b"fade", &5000i32.to_le_bytes()));
+        content.extend(get_chunk(b"text", b"Skipped"));
+        content.extend(get_chunk(b"NEND", &[]));
+        assert!(Nsf::is_nsf(&content));
+
+        // Assert results
+        let nsf: Nsf = Nsf::parse(&content).unwrap();
+        assert_eq!(nsf.play_address, 0x8020);
+        assert_eq!(nsf.title, "Game");
+        assert_eq!(nsf.artist, "Composer");
+        assert_eq!(nsf.copyright, "Company");
+        assert_eq!(nsf.ntsc_play_speed, 16000);
+        assert_eq!(nsf.pal_play_speed, DEFAULT_PAL_PLAY_SPEED);
+        assert_eq!(nsf.bank_init, [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert!(nsf.is_pal());
+        assert_eq!(nsf.get_region(), Region::Pal);
+        assert!(nsf.has_expansion(EXPANSION_NAMCO163));
+        assert_eq!(nsf.starting_track, 1);
+        assert_eq!(
+            nsf.tracks,
+            [
+                TrackInfo {
+                    name: Some("Intro".to_string()),
+                    length_ms: Some(90000),
+                    fade_ms: Some(5000),
+                },
+                TrackInfo {
+                    name: Some("Ending".to_string()),
+                    length_ms: None,
+                    fade_ms: None,
+                },
+            ]
+            .to_vec()
+        );
+        assert_eq!(nsf.data, [0x60].to_vec());
+    }
+
+    #[test]
+    fn test_nsfe_errors() {
+        // Prep for the test
+        let info: [u8; 8] = [0; 8];
+        let mut content: Vec<u8> = NSFE_MAGIC.to_vec();
+        content.extend(get_chunk(b"INFO", &info));
+
+        // Assert results
+        assert_eq!(
+            Nsf::parse(&content).err(),
+            Some(NsfError::MissingChunk("DATA".to_string()))
+        );
+        let mut unsupported: Vec<u8> = content.clone();
+        unsupported.extend(get_chunk(b"ABCD", &[]));
+        assert_eq!(
+            Nsf::parse(&unsupported).err(),
+            Some(NsfError::UnsupportedChunk("ABCD".to_string()))
+        );
+        content.extend_from_slice(&[0xFF, 0, 0, 0]);
+        assert_eq!(Nsf::parse(&content).err(), Some(NsfError::TruncatedData));
+        assert_eq!(Nsf::parse(b"NES\x1A").err(), Some(NsfError::InvalidHeader));
+    }
+}
@@ -0,0 +1,269 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Plays NSF tracks by calling the driver's init routine once and its play routine at the file's
+// rate, running the CPU as fast as possible and collecting the audio it produces
+// https://www.nesdev.org/wiki/NSF#Initializing_a_tune
+
+use crate::common::memory::MemoryMappedDevice;
+use crate::models::audio::mixer::Mixer;
+use crate::models::mos6502::Mos6502;
+use crate::models::nsf::bus::NsfBus;
+use crate::models::nsf::{Nsf, NsfError};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Routines return here, where nothing is mapped, which hands control back to the player
+const RETURN_ADDRESS: u16 = 0x4100;
+
+// A routine that has not returned after this many cycles is abandoned, so a broken driver
+// cannot hang the player
+const ROUTINE_CYCLE_LIMIT: u32 = 1_000_000;
+
+// Tracks without a length are rendered for this long
+pub const DEFAULT_TRACK_SECONDS: f64 = 150.0;
+
+// Feedback of the filter that removes the mixer's DC offset, as the console's own output does
+const DC_FILTER_FEEDBACK: f32 = 0.999;
+
+pub struct NsfPlayer {
+    cpu: Mos6502,
+    bus: Rc<RefCell<NsfBus>>,
+    mixer: Mixer,
+    play_address: u16,
+    // The play routine period, and the time left until the next call, in CPU cycles
+    play_period: f64,
+    cycles_until_play: f64,
+    // Samples made beyond what was last asked for
+    pending_samples: Vec<f32>,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl NsfPlayer {
+    // Set up the memory map and run the init routine for a zero based track
+    pub fn new(nsf: &Nsf, track: usize, sample_rate: u32) -> Result<NsfPlayer, NsfError> {
+        if track >= nsf.tracks.len() {
+            return Err(NsfError::InvalidTrack(track));
+        }
+
+        let cpu_clock_hz: f64 = nsf.get_region().get_profile().get_cpu_clock_hz();
+        let play_speed: u16 = if nsf.is_pal() {
+            nsf.pal_play_speed
+        } else {
+            nsf.ntsc_play_speed
+        };
+        let bus: Rc<RefCell<NsfBus>> = Rc::new(RefCell::new(NsfBus::new(nsf)?));
+        let mut cpu: Mos6502 = Mos6502::new(0x10000, cpu_clock_hz);
+        cpu.memory.map_device(0x4000, 0xFFFF, bus.clone());
+
+        let mut player: NsfPlayer = NsfPlayer {
+            cpu,
+            bus,
+            mixer: Mixer::new(cpu_clock_hz, f64::from(sample_rate)),
+            play_address: nsf.play_address,
+            play_period: f64::from(play_speed) * cpu_clock_hz / 1_000_000.0,
+            cycles_until_play: 0.0,
+            pending_samples: Vec::new(),
+            previous_input: 0.0,
+            previous_output: 0.0,
+        };
+
+        // Silence the channels and enable all but the DMC, with the frame IRQ turned off
+        {
+            let mut bus = player.bus.borrow_mut();
+            for address in 0x4000..=0x4013 {
+                bus.write_byte(address, 0x00);
+            }
+            bus.write_byte(0x4015, 0x0F);
+            bus.write_byte(0x4017, 0x40);
+        }
+
+        // The init routine takes the track in A and the region in X
+        player.cpu.accumulator = track as u8;
+        player.cpu.x_index = u8::from(nsf.is_pal());
+        player.call_routine(nsf.init_address);
+        Ok(player)
+    }
+
+    // Run the track until the given number of samples has been made
+    pub fn render(&mut self, sample_count: usize) -> Vec<f32> {
+        while self.pending_samples.len() < sample_count {
+            if self.cycles_until_play <= 0.0 {
+                self.cycles_until_play += self.play_period;
+                self.call_routine(self.play_address);
+            } else {
+                self.clock(1);
+            }
+            let samples: Vec<f32> = self.mixer.take_samples();
+            for sample in samples {
+                let filtered: f32 =
+                    sample - self.previous_input + DC_FILTER_FEEDBACK * self.previous_output;
+                self.previous_input = sample;
+                self.previous_output = filtered;
+                self.pending_samples.push(filtered);
+            }
+        }
+        self.pending_samples.drain(..sample_count).collect()
+    }
+
+    // Call a routine with a fresh stack, running it until it returns
+    fn call_routine(&mut self, address: u16) {
+        // Lay out the return address where RTS pulls it from, with the stack otherwise empty
+        self.cpu.stack = 0xFC;
+        let stack_pointer: usize = self.cpu.get_stack_pointer().into();
+        self.cpu
+            .memory
+            .write(stack_pointer, (RETURN_ADDRESS - 1).to_le_bytes().to_vec());
+        self.cpu.program_counter = address;
+
+        let mut cycles: u32 = 0;
+        while self.cpu.program_counter != RETURN_ADDRESS && cycles < ROUTINE_CYCLE_LIMIT {
+            let instruction_cycles: u8 = self.cpu.execute_next_instruction();
+            self.clock(instruction_cycles.into());
+            cycles += u32::from(instruction_cycles);
+        }
+    }
+
+    // Advance the audio hardware by a number of CPU cycles
+    fn clock(&mut self, cycles: u32) {
+        let mut bus = self.bus.borrow_mut();
+        for _ in 0..cycles {
+            bus.clock_cpu();
+            self.mixer.push(bus.get_output());
+        }
+        self.cycles_until_play -= f64::from(cycles);
+    }
+}
+
+// Render a zero based track, for the given number of seconds or else for the track's own length
+// and fade, fading out over the end of the track when it has a fade
+pub fn render_track(
+    nsf: &Nsf,
+    track: usize,
+    seconds: Option<f64>,
+    sample_rate: u32,
+) -> Result<Vec<f32>, NsfError> {
+    let mut player: NsfPlayer = NsfPlayer::new(nsf, track, sample_rate)?;
+    let info = &nsf.tracks[track];
+    let fade_seconds: f64 = f64::from(info.fade_ms.unwrap_or(0)) / 1000.0;
+    let seconds: f64 = seconds.unwrap_or_else(|| match info.length_ms {
+        Some(length_ms) => f64::from(length_ms) / 1000.0 + fade_seconds,
+        None => DEFAULT_TRACK_SECONDS,
+    });
+
+    let sample_count: usize = (seconds * f64::from(sample_rate)) as usize;
+    let mut samples: Vec<f32> = player.render(sample_count);
+
+    let fade_samples: usize = ((fade_seconds * f64::from(sample_rate)) as usize).min(sample_count);
+    let fade_start: usize = sample_count - fade_samples;
+    for (index, sample) in samples[fade_start..].iter_mut().enumerate() {
+        *sample *= 1.0 - index as f32 / fade_samples as f32;
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nsf::tests::get_test_nsf_file;
+    use crate::models::nsf::TrackInfo;
+
+    // A driver whose init routine only returns, and whose play routine counts its calls in X
+    //   $8000 init: RTS
+    //   $8001 play: INX, RTS
+    fn get_counting_nsf() -> Nsf {
+        let content: Vec<u8> =
+            get_test_nsf_file([0x8000, 0x8000, 0x8001], [0; 8], 0, &[0x60, 0xE8, 0x60]);
+        Nsf::parse(&content).unwrap()
+    }
+
+    #[test]
+    fn test_init() {
+        // Prep for the test
+        let nsf: Nsf = get_counting_nsf();
+        let player: NsfPlayer = NsfPlayer::new(&nsf, 2, 44100).unwrap();
+
+        // Assert results, the init routine returned to the player with the track and region
+        assert_eq!(player.cpu.program_counter, RETURN_ADDRESS);
+        assert_eq!(player.cpu.accumulator, 2);
+        assert_eq!(player.cpu.x_index, 0);
+        assert_eq!(
+            NsfPlayer::new(&nsf, 3, 44100).err(),
+            Some(NsfError::InvalidTrack(3))
+        );
+    }
+
+    #[test]
+    fn test_play_rate() {
+        // Prep for the test
+        let nsf: Nsf = get_counting_nsf();
+        let mut player: NsfPlayer = NsfPlayer::new(&nsf, 0, 44100).unwrap();
+
+        // One second at a period of 16639us calls the play routine 61 times
+        let samples: Vec<f32> = player.render(44100);
+
+        // Assert results
+        assert_eq!(samples.len(), 44100);
+        assert_eq!(player.cpu.x_index, 61);
+    }
+
+    #[test]
+    fn test_render_track_length_and_fade() {
+        // Prep for the test, a tenth of a second with a fade over the last half of it
+        let mut nsf: Nsf = get_counting_nsf();
+        nsf.tracks[0] = TrackInfo {
+            name: None,
+            length_ms: Some(50),
+            fade_ms: Some(50),
+        };
+
+        // Assert results
+        let samples: Vec<f32> = render_track(&nsf, 0, None, 1000).unwrap();
+        assert_eq!(samples.len(), 100);
+        let samples: Vec<f32> = render_track(&nsf, 1, Some(0.5), 1000).unwrap();
+        assert_eq!(samples.len(), 500);
+    }
+
+    #[test]
+    fn test_audio_output() {
+        // Prep for the test, the CPU core has no absolute stores yet so the test starts a pulse
+        // wave itself, at constant volume 15 and a period of 253 for 440Hz
+        let nsf: Nsf = get_counting_nsf();
+        let mut player: NsfPlayer = NsfPlayer::new(&nsf, 0, 44100).unwrap();
+        {
+            let mut bus = player.bus.borrow_mut();
+            bus.write_byte(0x4000, 0b1011_1111);
+            bus.write_byte(0x4002, 0xFD);
+            bus.write_byte(0x4003, 0b0000_1000);
+        }
+
+        // Assert results, a 440Hz square wave swings both ways around zero once filtered
+        let samples: Vec<f32> = player.render(4410);
+        let highest: f32 = samples.iter().cloned().fold(f32::MIN, f32::max);
+        let lowest: f32 = samples.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(highest > 0.05);
+        assert!(lowest < -0.05);
+    }
+}
@@ -0,0 +1,127 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// The background pipeline, which fetches each tile's bytes over eight dots while the previous
+// tile is shifted out one pixel per dot
+// https://www.nesdev.org/wiki/PPU_rendering
+
+#[derive(Default)]
+pub struct Background {
+    // Latches for the tile being fetched, which are moved into the shifters once it is complete
+    pub nametable_byte: u8,
+    pub attribute_bits: u8,
+    pub pattern_low: u8,
+    pub pattern_high: u8,
+
+    // Shift registers whose high bytes hold the tile being drawn and low bytes the next tile
+    // The attribute shifters are filled with copies of the tile's two palette bits
+    pattern_low_shifter: u16,
+    pattern_high_shifter: u16,
+    attribute_low_shifter: u16,
+    attribute_high_shifter: u16,
+}
+
+impl Background {
+    pub fn new() -> Background {
+        Background::default()
+    }
+
+    // Move the fetched tile into the low bytes of the shifters
+    pub fn reload_shifters(&mut self) {
+        self.pattern_low_shifter =
+            (self.pattern_low_shifter & 0xFF00) | u16::from(self.pattern_low);
+        self.pattern_high_shifter =
+            (self.pattern_high_shifter & 0xFF00) | u16::from(self.pattern_high);
+        let attribute_low: u16 = if self.attribute_bits & 0b01 == 0b01 {
+            0xFF
+        } else {
+            0x00
+        };
+        let attribute_high: u16 = if self.attribute_bits & 0b10 == 0b10 {
+            0xFF
+        } else {
+            0x00
+        };
+        self.attribute_low_shifter = (self.attribute_low_shifter & 0xFF00) | attribute_low;
+        self.attribute_high_shifter = (self.attribute_high_shifter & 0xFF00) | attribute_high;
+    }
+
+    pub fn shift(&mut self) {
+        self.pattern_low_shifter <<= 1;
+        self.pattern_high_shifter <<= 1;
+        self.attribute_low_shifter <<= 1;
+        self.attribute_high_shifter <<= 1;
+    }
+
+    // The current pixel as a 4 bit palette index, with the palette in the upper two bits and the
+    // colour within it in the lower two, where the fine X scroll picks which bit is on screen
+    pub fn get_pixel(&self, fine_x_scroll: u8) -> u8 {
+        let bit: u16 = 0x8000 >> fine_x_scroll;
+        let get_bit = |shifter: u16, value: u8| -> u8 {
+            if shifter & bit == bit {
+                value
+            } else {
+                0
+            }
+        };
+        get_bit(self.attribute_high_shifter, 0b1000)
+            | get_bit(self.attribute_low_shifter, 0b0100)
+            | get_bit(self.pattern_high_shifter, 0b0010)
+            | get_bit(self.pattern_low_shifter, 0b0001)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shifters() {
+        // Prep for the test, a tile with colour 1 on the left half and colour 2 on the right half,
+        // then a tile of colour 3 with palette 2
+        let mut background: Background = Background::new();
+        background.pattern_low = 0b1111_0000;
+        background.pattern_high = 0b0000_1111;
+        background.attribute_bits = 0b01;
+        background.reload_shifters();
+        for _ in 0..8 {
+            background.shift();
+        }
+        background.pattern_low = 0xFF;
+        background.pattern_high = 0xFF;
+        background.attribute_bits = 0b10;
+        background.reload_shifters();
+
+        // Assert results
+        assert_eq!(background.get_pixel(0), 0b0101);
+        assert_eq!(background.get_pixel(3), 0b0101);
+        assert_eq!(background.get_pixel(4), 0b0110);
+        assert_eq!(background.get_pixel(7), 0b0110);
+        for _ in 0..4 {
+            background.shift();
+        }
+        assert_eq!(background.get_pixel(0), 0b0110);
+        assert_eq!(background.get_pixel(4), 0b1011);
+    }
+}
@@ -0,0 +1,211 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Writing pictures to PNG and PPM files
+// PNG data is zlib compressed, but only with stored blocks, which keeps the encoder simple at the
+// cost of larger files
+// PNG: https://www.w3.org/TR/png/
+// zlib: https://datatracker.ietf.org/doc/html/rfc1950
+// PPM: https://netpbm.sourceforge.net/doc/ppm.html
+
+use crate::common::utils::Utils;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const BYTES_PER_PIXEL: usize = 4;
+// Eight bits per channel of red, green, blue and alpha
+const PNG_BIT_DEPTH: u8 = 8;
+const PNG_COLOUR_TYPE_RGBA: u8 = 6;
+// Each row starts with the filter used for it, here none
+const PNG_FILTER_NONE: u8 = 0;
+
+// A zlib header for deflate with a 32KB window and no dictionary, which is a multiple of 31
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+const ADLER_MODULUS: u32 = 65521;
+
+// Encode an RGBA picture as a PNG file
+pub fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width * height * BYTES_PER_PIXEL);
+
+    let mut header: Vec<u8> = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth, colour type, then the default compression and filtering without interlacing
+    header.extend_from_slice(&[PNG_BIT_DEPTH, PNG_COLOUR_TYPE_RGBA, 0, 0, 0]);
+
+    let mut image: Vec<u8> = Vec::with_capacity(height * (width * BYTES_PER_PIXEL + 1));
+    if width > 0 {
+        for row in rgba.chunks(width * BYTES_PER_PIXEL) {
+            image.push(PNG_FILTER_NONE);
+            image.extend_from_slice(row);
+        }
+    }
+
+    let mut png: Vec<u8> = PNG_SIGNATURE.to_vec();
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &encode_zlib_stored(&image));
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// Chunks are their length, type and data, then a CRC-32 of the type and data
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start: usize = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc: u32 = Utils::get_crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wrap data in a zlib stream without compressing it
+// Each stored block has a header byte, which marks the last block, then its length and the
+// length's complement
+fn encode_zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream: Vec<u8> = ZLIB_HEADER.to_vec();
+    let mut blocks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_last: bool = blocks.peek().is_none();
+        stream.push(u8::from(is_last));
+        let length: u16 = block.len() as u16;
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&get_adler32(data).to_be_bytes());
+    stream
+}
+
+// The checksum at the end of a zlib stream
+fn get_adler32(data: &[u8]) -> u32 {
+    let mut low: u32 = 1;
+    let mut high: u32 = 0;
+    for byte in data {
+        low = (low + u32::from(*byte)) % ADLER_MODULUS;
+        high = (high + low) % ADLER_MODULUS;
+    }
+    high << 16 | low
+}
+
+// Encode an RGBA picture as a binary PPM file, which has no alpha channel
+pub fn encode_ppm(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width * height * BYTES_PER_PIXEL);
+
+    let mut ppm: Vec<u8> = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for pixel in rgba.chunks_exact(BYTES_PER_PIXEL) {
+        ppm.extend_from_slice(&pixel[..3]);
+    }
+    ppm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::cartridge::archive::inflate;
+
+    // A 2x2 picture of red, green, blue and half transparent white
+    fn get_test_rgba() -> Vec<u8> {
+        [
+            0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0x80,
+        ]
+        .to_vec()
+    }
+
+    #[test]
+    fn test_encode_png() {
+        // Prep for the test
+        let png: Vec<u8> = encode_png(2, 2, &get_test_rgba());
+
+        // Assert results, the header describes a 2x2 RGBA picture
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+        assert_eq!(png[29..33], Utils::get_crc32(&png[12..29]).to_be_bytes());
+
+        // The image data decompresses to the rows, each after a filter byte
+        let length: usize = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+        assert_eq!(png[37..41], *b"IDAT");
+        let stream: &[u8] = &png[41..41 + length];
+        let image: Vec<u8> = inflate::inflate(&stream[2..]).unwrap();
+        let rgba: Vec<u8> = get_test_rgba();
+        let mut expected: Vec<u8> = [0].to_vec();
+        expected.extend_from_slice(&rgba[..8]);
+        expected.push(0);
+        expected.extend_from_slice(&rgba[8..]);
+        assert_eq!(image, expected);
+        assert_eq!(
+            stream[stream.len() - 4..],
+            get_adler32(&image).to_be_bytes()
+        );
+
+        // The file ends with the empty IEND chunk and its well known CRC
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn test_zlib_stored_blocks() {
+        // Prep for the test, with more data than fits in one block
+        let data: Vec<u8> = (0..STORED_BLOCK_SIZE + 10)
+            .map(|index| index as u8)
+            .collect();
+        let stream: Vec<u8> = encode_zlib_stored(&data);
+
+        // Assert results, the header is valid and the blocks decompress to the data
+        assert_eq!(u16::from_be_bytes(ZLIB_HEADER) % 31, 0);
+        assert_eq!(stream[2], 0x00);
+        assert_eq!(stream[3 + 4 + STORED_BLOCK_SIZE], 0x01);
+        assert_eq!(inflate::inflate(&stream[2..]).unwrap(), data);
+
+        // Empty data still needs a final block
+        assert_eq!(inflate::inflate(&encode_zlib_stored(&[])[2..]).unwrap(), []);
+    }
+
+    #[test]
+    fn test_adler32() {
+        // Assert results, using the well known example of the checksum
+        assert_eq!(get_adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(get_adler32(&[]), 1);
+    }
+
+    #[test]
+    fn test_encode_ppm() {
+        // Prep for the test
+        let ppm: Vec<u8> = encode_ppm(2, 2, &get_test_rgba());
+
+        // Assert results, the alpha channel is dropped
+        let header: &[u8] = b"P6\n2 2\n255\n";
+        assert_eq!(ppm[..header.len()], *header);
+        assert_eq!(
+            ppm[header.len()..],
+            [0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+}
@@ -0,0 +1,1254 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// The picture processing unit, which the CPU talks to through eight registers at $2000-$2007 that
+// are mirrored up to $3FFF
+// https://www.nesdev.org/wiki/PPU_registers
+// https://www.nesdev.org/wiki/PPU_scrolling
+//
+// The picture is drawn one dot at a time over 262 scanlines of 341 dots, so that changes made by
+// the CPU part way through a frame, like scroll splits, land where they would on hardware
+// https://www.nesdev.org/wiki/PPU_rendering
+
+pub mod background;
+pub mod image;
+pub mod palette;
+pub mod screenshot;
+pub mod sprites;
+
+use crate::common::interrupt::{InterruptLine, InterruptSource};
+use crate::common::memory::MemoryMappedDevice;
+use crate::models::cartridge::mappers::Mapper;
+use crate::models::ppu::background::Background;
+use crate::models::ppu::sprites::{SpritePixel, Sprites};
+use crate::models::region::RegionProfile;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The size of the picture in pixels
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+// Frame layout, where the visible scanlines are followed by idle lines, the vertical blanking
+// lines and a pre-render line that makes the same fetches as a visible one. The number of idle
+// and vblank lines depends on the region
+const DOTS_PER_SCANLINE: u16 = 341;
+
+// PPUCTRL bits
+const CONTROL_NAMETABLE: u8 = 0b0000_0011;
+const CONTROL_INCREMENT_32: u8 = 0b0000_0100;
+const CONTROL_SPRITE_TABLE: u8 = 0b0000_1000;
+const CONTROL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const CONTROL_SPRITE_SIZE: u8 = 0b0010_0000;
+const CONTROL_NMI: u8 = 0b1000_0000;
+
+// PPUMASK bits
+const MASK_GREYSCALE: u8 = 0b0000_0001;
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+const MASK_EMPHASIS: u8 = 0b1110_0000;
+
+// Palette RAM holds 6 bit colours, and greyscale keeps only the brightness column of a colour
+const COLOUR_MASK: u8 = 0b0011_1111;
+const GREYSCALE_MASK: u8 = 0b0011_0000;
+
+// PPUSTATUS bits, the rest of the byte reads back whatever was last on the register bus
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+const STATUS_MASK: u8 = STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW;
+
+// Bits 2-4 of a sprite's attribute byte do not exist and always read back as zero
+const OAM_ATTRIBUTE_MASK: u8 = 0b1110_0011;
+
+// The PPU's address bus is 14 bits wide, with palette RAM at the top
+const ADDRESS_MASK: u16 = 0x3FFF;
+const NAMETABLE_START: u16 = 0x2000;
+const ATTRIBUTE_START: u16 = 0x23C0;
+const PALETTE_START: u16 = 0x3F00;
+
+// Parts of the scroll registers
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
+const VRAM_SIZE: usize = 0x800;
+const OAM_SIZE: usize = 0x100;
+const PALETTE_SIZE: usize = 0x20;
+
+pub struct Ppu {
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    nmi_line: InterruptLine,
+
+    // The console's 2KB of nametable RAM, which the mapper arranges into four nametables
+    vram: Vec<u8>,
+    palette: [u8; PALETTE_SIZE],
+    // Object attribute memory, four bytes for each of the 64 sprites
+    oam: [u8; OAM_SIZE],
+
+    control: u8,
+    mask: u8,
+    status: u8,
+    oam_address: u8,
+
+    // The internal scroll registers known as v, t, x and w
+    // v is the VRAM address used by $2007 and for rendering, and t is the address it is
+    // reloaded from, both laid out as 0yyy NNYY YYYX XXXX with fine Y, nametable, coarse Y and
+    // coarse X. x is the fine X scroll, and w selects the first or second write of $2005 and $2006
+    vram_address: u16,
+    temporary_address: u16,
+    fine_x_scroll: u8,
+    write_toggle: bool,
+
+    // Reads of $2007 below the palette return the byte fetched by the previous read
+    read_buffer: u8,
+    // The last value driven onto the bus between the CPU and the registers, which is what the
+    // write only registers and the unused bits of PPUSTATUS read back as
+    io_latch: u8,
+
+    // The scanline vblank starts on, the last scanline of the frame, and whether odd frames are
+    // one dot shorter, all from the console region
+    vblank_scanline: u16,
+    pre_render_scanline: u16,
+    skips_odd_frame_dot: bool,
+
+    // The dot about to be drawn, and whether this is an odd frame
+    scanline: u16,
+    dot: u16,
+    odd_frame: bool,
+    // The number of frames that have been completed, counted at the start of vblank
+    frame_count: u64,
+
+    background: Background,
+    sprites: Sprites,
+    // The low plane of the sprite being fetched, until the high plane arrives
+    sprite_pattern_low: u8,
+    // The picture as 6 bit colours from palette RAM, with the emphasis bits of PPUMASK above them
+    frame_buffer: Vec<u16>,
+}
+
+impl Ppu {
+    pub fn new(
+        mapper: Rc<RefCell<Box<dyn Mapper>>>,
+        nmi_line: InterruptLine,
+        region: &RegionProfile,
+    ) -> Ppu {
+        Ppu {
+            mapper,
+            nmi_line,
+            vram: vec![0; VRAM_SIZE],
+            palette: [0; PALETTE_SIZE],
+            oam: [0; OAM_SIZE],
+            control: 0,
+            mask: 0,
+            status: 0,
+            oam_address: 0,
+            vram_address: 0,
+            temporary_address: 0,
+            fine_x_scroll: 0,
+            write_toggle: false,
+            read_buffer: 0,
+            io_latch: 0,
+            vblank_scanline: region.vblank_scanline,
+            pre_render_scanline: region.scanline_count - 1,
+            skips_odd_frame_dot: region.skips_odd_frame_dot,
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            frame_count: 0,
+            background: Background::new(),
+            sprites: Sprites::new(),
+            sprite_pattern_low: 0,
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    // The last completed picture row by row, with each pixel's colour in bits 0-5 and emphasis in
+    // bits 6-8, ready to be looked up in a palette
+    pub fn get_frame_buffer(&self) -> &[u16] {
+        &self.frame_buffer
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    // Run a single dot
+    pub fn clock(&mut self) {
+        let is_visible: bool = self.scanline < SCREEN_HEIGHT as u16;
+        let is_pre_render: bool = self.scanline == self.pre_render_scanline;
+
+        if (is_visible || is_pre_render) && self.is_rendering_enabled() {
+            self.clock_background(is_pre_render);
+            self.clock_sprites(is_pre_render);
+        }
+        if is_visible && (1..=SCREEN_WIDTH as u16).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        if self.dot == 1 {
+            if self.scanline == self.vblank_scanline {
+                self.frame_count += 1;
+                self.start_vblank();
+            } else if is_pre_render {
+                self.end_vblank();
+            }
+        }
+
+        // Odd frames skip the last dot of the pre-render line while rendering is enabled, except on
+        // PAL consoles and the Dendy
+        self.dot += 1;
+        if is_pre_render
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && self.skips_odd_frame_dot
+            && self.is_rendering_enabled()
+        {
+            self.dot = DOTS_PER_SCANLINE;
+        }
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > self.pre_render_scanline {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    fn is_rendering_enabled(&self) -> bool {
+        self.mask & (MASK_BACKGROUND | MASK_SPRITES) != 0
+    }
+
+    // Whether the PPU is on a scanline where it is fetching and moving v for rendering
+    fn is_rendering(&self) -> bool {
+        self.is_rendering_enabled()
+            && (self.scanline < SCREEN_HEIGHT as u16 || self.scanline == self.pre_render_scanline)
+    }
+
+    // Background fetches and scroll updates, which happen on the visible and pre-render lines
+    fn clock_background(&mut self, is_pre_render: bool) {
+        let dot: u16 = self.dot;
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.background.shift();
+        }
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            // Each tile takes two dots for each of its nametable, attribute and two pattern bytes
+            match (dot - 1) % 8 {
+                0 => {
+                    self.background.reload_shifters();
+                    self.fetch_nametable_byte();
+                }
+                2 => self.fetch_attribute_bits(),
+                4 => self.background.pattern_low = self.fetch_pattern_byte(0),
+                6 => self.background.pattern_high = self.fetch_pattern_byte(8),
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.background.reload_shifters();
+                self.copy_horizontal_position();
+            }
+            // Two more nametable fetches that go unused, which some mappers count
+            337 | 339 => self.fetch_nametable_byte(),
+            // The pre-render line reloads the vertical position for the next frame
+            280..=304 if is_pre_render => self.copy_vertical_position(),
+            _ => {}
+        }
+    }
+
+    // Sprite evaluation for the next line, which takes up dots 65 to 256, then the fetches of the
+    // patterns of the sprites that were found, which take 8 dots each
+    fn clock_sprites(&mut self, is_pre_render: bool) {
+        match self.dot {
+            65 if is_pre_render => self.sprites.clear(),
+            65 => {
+                let height: u16 = self.get_sprite_height();
+                if self.sprites.evaluate(&self.oam, self.scanline, height) {
+                    self.status |= STATUS_SPRITE_OVERFLOW;
+                }
+            }
+            257..=320 => {
+                // OAMADDR is held at zero while sprites are fetched
+                self.oam_address = 0;
+                if self.dot == 257 {
+                    self.sprites.start_fetches();
+                }
+                let slot: usize = usize::from(self.dot - 257) / 8;
+                match (self.dot - 257) % 8 {
+                    // Two garbage nametable fetches take the place of the tile and attribute
+                    // fetches, which boards like the MMC5 count to follow the PPU
+                    0 | 2 => {
+                        self.read_bus(NAMETABLE_START | (self.vram_address & 0x0FFF));
+                    }
+                    4 => {
+                        let address: u16 = self.get_sprite_pattern_address(slot);
+                        self.sprite_pattern_low = self.read_bus(address);
+                    }
+                    6 => {
+                        let address: u16 = self.get_sprite_pattern_address(slot) + 8;
+                        let pattern_high: u8 = self.read_bus(address);
+                        self.sprites
+                            .load_slot(slot, self.sprite_pattern_low, pattern_high);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn get_sprite_height(&self) -> u16 {
+        if self.control & CONTROL_SPRITE_SIZE == CONTROL_SPRITE_SIZE {
+            16
+        } else {
+            8
+        }
+    }
+
+    // The address of the low plane of a sprite's row, where 8x16 sprites take their pattern
+    // table from bit 0 of the tile number and are drawn from two tiles one above the other
+    // Empty slots fetch tile $FF, as that is what secondary OAM holds
+    fn get_sprite_pattern_address(&self, slot: usize) -> u16 {
+        let [y, tile, attributes, _] = self.sprites.get_secondary_sprite(slot);
+        let height: u16 = self.get_sprite_height();
+        let row: u16 = sprites::get_sprite_row(self.scanline, y, attributes, height);
+        if height == 16 {
+            let table: u16 = u16::from(tile & 0x01) * 0x1000;
+            let tile: u16 = u16::from(tile & 0xFE) + row / 8;
+            table + tile * 16 + row % 8
+        } else {
+            let table: u16 = if self.control & CONTROL_SPRITE_TABLE == CONTROL_SPRITE_TABLE {
+                0x1000
+            } else {
+                0x0000
+            };
+            table + u16::from(tile) * 16 + row
+        }
+    }
+
+    fn fetch_nametable_byte(&mut self) {
+        let address: u16 = NAMETABLE_START | (self.vram_address & 0x0FFF);
+        self.background.nametable_byte = self.read_bus(address);
+    }
+
+    // Each attribute byte covers a 4x4 tile area, with two bits for each 2x2 quarter
+    fn fetch_attribute_bits(&mut self) {
+        let v: u16 = self.vram_address;
+        let address: u16 = ATTRIBUTE_START
+            | (v & (NAMETABLE_X | NAMETABLE_Y))
+            | ((v >> 4) & 0x38)
+            | ((v >> 2) & 0x07);
+        let shift: u16 = ((v >> 4) & 0b100) | (v & 0b010);
+        self.background.attribute_bits = (self.read_bus(address) >> shift) & 0b11;
+    }
+
+    // One of the two bit planes of the current row of the tile, 8 bytes apart
+    fn fetch_pattern_byte(&mut self, plane_offset: u16) -> u8 {
+        let table: u16 = if self.control & CONTROL_BACKGROUND_TABLE == CONTROL_BACKGROUND_TABLE {
+            0x1000
+        } else {
+            0x0000
+        };
+        let fine_y: u16 = (self.vram_address & FINE_Y) >> 12;
+        let address: u16 =
+            table + u16::from(self.background.nametable_byte) * 16 + plane_offset + fine_y;
+        self.read_bus(address)
+    }
+
+    // Move v one tile to the right, into the next nametable across after the 32nd column
+    fn increment_coarse_x(&mut self) {
+        if self.vram_address & COARSE_X == COARSE_X {
+            self.vram_address &= !COARSE_X;
+            self.vram_address ^= NAMETABLE_X;
+        } else {
+            self.vram_address += 1;
+        }
+    }
+
+    // Move v one row of pixels down, into the next nametable down after the 30th row of tiles
+    // Rows 30 and 31 are attribute bytes, and scrolling into them wraps without switching nametable
+    fn increment_y(&mut self) {
+        if self.vram_address & FINE_Y != FINE_Y {
+            self.vram_address += 0x1000;
+            return;
+        }
+        self.vram_address &= !FINE_Y;
+        let mut coarse_y: u16 = (self.vram_address & COARSE_Y) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.vram_address ^= NAMETABLE_Y;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.vram_address = (self.vram_address & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal_position(&mut self) {
+        let bits: u16 = COARSE_X | NAMETABLE_X;
+        self.vram_address = (self.vram_address & !bits) | (self.temporary_address & bits);
+    }
+
+    fn copy_vertical_position(&mut self) {
+        let bits: u16 = FINE_Y | NAMETABLE_Y | COARSE_Y;
+        self.vram_address = (self.vram_address & !bits) | (self.temporary_address & bits);
+    }
+
+    fn render_pixel(&mut self) {
+        let x: usize = usize::from(self.dot - 1);
+        let mut background: u8 = 0;
+        if self.mask & MASK_BACKGROUND == MASK_BACKGROUND
+            && (x >= 8 || self.mask & MASK_BACKGROUND_LEFT == MASK_BACKGROUND_LEFT)
+        {
+            background = self.background.get_pixel(self.fine_x_scroll);
+        }
+        let mut sprite: Option<SpritePixel> = None;
+        if self.mask & MASK_SPRITES == MASK_SPRITES
+            && (x >= 8 || self.mask & MASK_SPRITES_LEFT == MASK_SPRITES_LEFT)
+        {
+            sprite = self.sprites.get_pixel(x as u8);
+        }
+
+        // Sprite 0 hits where it overlaps an opaque background pixel, which can not happen in the
+        // last column or where either layer is clipped in the left column
+        let is_background_opaque: bool = background & 0b11 != 0;
+        let mut palette_index: u8 = background;
+        if let Some(pixel) = sprite {
+            if pixel.is_sprite_zero && is_background_opaque && x != SCREEN_WIDTH - 1 {
+                self.status |= STATUS_SPRITE_ZERO_HIT;
+            }
+            if !is_background_opaque || !pixel.is_behind_background {
+                palette_index = pixel.palette_index;
+            }
+        }
+
+        // Colour 0 of every palette is the shared backdrop colour, and while rendering is disabled
+        // the backdrop is replaced by the palette entry v points at, if it points into the palette
+        let address: u16 =
+            if !self.is_rendering_enabled() && self.vram_address & ADDRESS_MASK >= PALETTE_START {
+                self.vram_address & ADDRESS_MASK
+            } else if palette_index & 0b11 == 0 {
+                PALETTE_START
+            } else {
+                PALETTE_START | u16::from(palette_index)
+            };
+        let colour: u8 = self.get_greyscale(self.palette[get_palette_index(address)]);
+        self.frame_buffer[usize::from(self.scanline) * SCREEN_WIDTH + x] =
+            u16::from(colour) | u16::from(self.mask & MASK_EMPHASIS) << 1;
+    }
+
+    // Greyscale mode applies to the colours read from palette RAM, both when drawing and by $2007
+    fn get_greyscale(&self, colour: u8) -> u8 {
+        if self.mask & MASK_GREYSCALE == MASK_GREYSCALE {
+            colour & GREYSCALE_MASK
+        } else {
+            colour
+        }
+    }
+
+    // Read one of the registers, which repeat every 8 bytes
+    pub fn read_register(&mut self, address: u16) -> u8 {
+        match address & 0x0007 {
+            // PPUSTATUS, where reading clears the vblank flag and the write toggle
+            0x0002 => {
+                let value: u8 = (self.status & STATUS_MASK) | (self.io_latch & !STATUS_MASK);
+                self.status &= !STATUS_VBLANK;
+                self.write_toggle = false;
+                self.update_nmi();
+                self.io_latch = value;
+            }
+            // OAMDATA, which unlike writes does not move the address
+            0x0004 => {
+                let mut value: u8 = self.oam[usize::from(self.oam_address)];
+                if self.oam_address & 0b11 == 2 {
+                    value &= OAM_ATTRIBUTE_MASK;
+                }
+                self.io_latch = value;
+            }
+            // PPUDATA
+            0x0007 => {
+                let address: u16 = self.vram_address & ADDRESS_MASK;
+                if address >= PALETTE_START {
+                    // Palette reads are not buffered, but the top two bits are not driven, and the
+                    // buffer is filled from the nametable underneath the palette
+                    let value: u8 = self.read_bus(address);
+                    let value: u8 = self.get_greyscale(value);
+                    self.io_latch = (self.io_latch & 0b1100_0000) | (value & 0b0011_1111);
+                    self.read_buffer = self.read_bus(address - 0x1000);
+                } else {
+                    self.io_latch = self.read_buffer;
+                    self.read_buffer = self.read_bus(address);
+                }
+                self.increment_vram_address();
+            }
+            // The other registers are write only
+            _ => {}
+        }
+        self.io_latch
+    }
+
+    // Write one of the registers, which repeat every 8 bytes
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        self.io_latch = value;
+        match address & 0x0007 {
+            // PPUCTRL, where the nametable select goes to t
+            0x0000 => {
+                self.control = value;
+                self.temporary_address = (self.temporary_address & !0x0C00)
+                    | (u16::from(value & CONTROL_NAMETABLE) << 10);
+                self.update_nmi();
+            }
+            // PPUMASK
+            0x0001 => self.mask = value,
+            // OAMADDR
+            0x0003 => self.oam_address = value,
+            // OAMDATA
+            0x0004 => {
+                self.oam[usize::from(self.oam_address)] = value;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            // PPUSCROLL, X then Y
+            0x0005 => {
+                if self.write_toggle {
+                    self.temporary_address = (self.temporary_address & 0x0C1F)
+                        | (u16::from(value & 0b0000_0111) << 12)
+                        | (u16::from(value & 0b1111_1000) << 2);
+                } else {
+                    self.temporary_address =
+                        (self.temporary_address & !0x001F) | u16::from(value >> 3);
+                    self.fine_x_scroll = value & 0b0000_0111;
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            // PPUADDR, high byte then low byte, where only the second write reaches v
+            0x0006 => {
+                if self.write_toggle {
+                    self.temporary_address = (self.temporary_address & 0xFF00) | u16::from(value);
+                    self.vram_address = self.temporary_address;
+                    self.mapper
+                        .borrow_mut()
+                        .notify_ppu_address(self.vram_address & ADDRESS_MASK);
+                } else {
+                    self.temporary_address =
+                        (self.temporary_address & 0x00FF) | (u16::from(value & 0b0011_1111) << 8);
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            // PPUDATA
+            0x0007 => {
+                self.write_bus(self.vram_address & ADDRESS_MASK, value);
+                self.increment_vram_address();
+            }
+            // PPUSTATUS is read only
+            _ => {}
+        }
+    }
+
+    // The vblank flag is set at the start of the vertical blanking interval
+    pub fn start_vblank(&mut self) {
+        self.status |= STATUS_VBLANK;
+        self.update_nmi();
+    }
+
+    // The vblank, sprite 0 hit and sprite overflow flags are all cleared when it ends
+    pub fn end_vblank(&mut self) {
+        self.status &= !STATUS_MASK;
+        self.update_nmi();
+    }
+
+    // The NMI output is low while both the vblank flag and NMI enable are set, so turning NMI on
+    // during vblank triggers one straight away
+    fn update_nmi(&self) {
+        let asserted: bool = self.status & STATUS_VBLANK == STATUS_VBLANK
+            && self.control & CONTROL_NMI == CONTROL_NMI;
+        self.nmi_line.set(InterruptSource::Ppu, asserted);
+    }
+
+    // PPUDATA accesses move v across a row of the nametable or down a column, except while
+    // rendering, where they bump both the coarse X and Y positions at once
+    fn increment_vram_address(&mut self) {
+        if self.is_rendering() {
+            self.increment_coarse_x();
+            self.increment_y();
+            return;
+        }
+        let increment: u16 = if self.control & CONTROL_INCREMENT_32 == CONTROL_INCREMENT_32 {
+            32
+        } else {
+            1
+        };
+        self.vram_address = self.vram_address.wrapping_add(increment) & 0x7FFF;
+    }
+
+    fn read_bus(&mut self, address: u16) -> u8 {
+        let mut mapper = self.mapper.borrow_mut();
+        mapper.notify_ppu_address(address);
+        match address {
+            0x0000..=0x1FFF => mapper.ppu_read(address),
+            0x2000..=0x3EFF => mapper.nametable_read(address, &self.vram),
+            _ => self.palette[get_palette_index(address)],
+        }
+    }
+
+    fn write_bus(&mut self, address: u16, value: u8) {
+        let mut mapper = self.mapper.borrow_mut();
+        mapper.notify_ppu_address(address);
+        match address {
+            0x0000..=0x1FFF => mapper.ppu_write(address, value),
+            0x2000..=0x3EFF => mapper.nametable_write(address, value, &mut self.vram),
+            _ => self.palette[get_palette_index(address)] = value & COLOUR_MASK,
+        }
+    }
+}
+
+// Palette RAM fills $3F00-$3FFF, repeating every 32 bytes
+// The first colour of each sprite palette is shared with the background palette below it, so
+// $3F10, $3F14, $3F18 and $3F1C are mirrors of $3F00, $3F04, $3F08 and $3F0C
+fn get_palette_index(address: u16) -> usize {
+    let index: usize = usize::from(address) % PALETTE_SIZE;
+    if index & 0x13 == 0x10 {
+        index & 0x0F
+    } else {
+        index
+    }
+}
+
+// Gives the CPU access to the registers through the memory map, between $2000 and $3FFF
+impl MemoryMappedDevice for Ppu {
+    fn read_byte(&mut self, address: usize) -> u8 {
+        self.read_register(address as u16)
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) {
+        self.write_register(address as u16, value)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cartridge::mappers;
+    use crate::models::cartridge::tests::get_test_cartridge;
+    use crate::models::region::{DENDY, NTSC, PAL};
+
+    const PRE_RENDER_SCANLINE: u16 = NTSC.scanline_count - 1;
+
+    // Helper function for the tests to build a PPU on an NROM board with CHR-RAM and horizontal
+    // mirroring
+    pub fn get_test_ppu() -> (Ppu, InterruptLine) {
+        get_test_region_ppu(&NTSC)
+    }
+
+    // Helper function for the tests to build the same PPU for another region
+    pub fn get_test_region_ppu(region: &RegionProfile) -> (Ppu, InterruptLine) {
+        let mapper: Box<dyn Mapper> =
+            mappers::new_mapper(get_test_cartridge(0, 0, 1, 0x4000, 0, 0)).unwrap();
+        let nmi_line: InterruptLine = InterruptLine::new();
+        let ppu: Ppu = Ppu::new(Rc::new(RefCell::new(mapper)), nmi_line.clone(), region);
+        (ppu, nmi_line)
+    }
+
+    // Point v at an address through PPUADDR
+    pub fn set_address(ppu: &mut Ppu, address: u16) {
+        ppu.write_register(0x2006, (address >> 8) as u8);
+        ppu.write_register(0x2006, address as u8);
+    }
+
+    // Run dots until the PPU is about to draw the given dot
+    pub fn run_to(ppu: &mut Ppu, scanline: u16, dot: u16) {
+        while ppu.scanline != scanline || ppu.dot != dot {
+            ppu.clock();
+        }
+    }
+
+    // Run until the given number of frames have been completed since power on
+    pub fn run_to_frame(ppu: &mut Ppu, frame: u64) {
+        while ppu.frame_count < frame {
+            ppu.clock();
+        }
+    }
+
+    // Draw a column of solid tiles down the left edge of the first nametable in colour 1 of the
+    // first background palette, over a backdrop of $0F, and turn the background on
+    pub fn set_up_background(ppu: &mut Ppu) {
+        set_address(ppu, 0x0010);
+        for _ in 0..8 {
+            ppu.write_register(0x2007, 0xFF);
+        }
+        ppu.write_register(0x2000, CONTROL_INCREMENT_32);
+        set_address(ppu, 0x2000);
+        for _ in 0..30 {
+            ppu.write_register(0x2007, 0x01);
+        }
+        ppu.write_register(0x2000, 0x00);
+        set_address(ppu, 0x3F00);
+        ppu.write_register(0x2007, 0x0F);
+        ppu.write_register(0x2007, 0x16);
+
+        // Scroll back to the top left of the first nametable
+        ppu.write_register(0x2000, 0x00);
+        ppu.write_register(0x2005, 0x00);
+        ppu.write_register(0x2005, 0x00);
+        ppu.write_register(0x2001, MASK_BACKGROUND | MASK_BACKGROUND_LEFT);
+    }
+
+    // Fill OAM with the given sprites, hiding the rest below the picture, and turn sprites on
+    // Tile 2 has colour 1 in the top left pixel and colour 2 in the bottom right, tile 3 has a
+    // solid top row in colour 1, and the first two sprite palettes start $21 $22 and $25
+    pub fn set_up_sprites(ppu: &mut Ppu, sprites: &[[u8; 4]]) {
+        // Rendering is turned off first, as $2007 moves v differently while it is on
+        ppu.write_register(0x2001, 0x00);
+        set_address(ppu, 0x0020);
+        for value in [0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01] {
+            ppu.write_register(0x2007, value);
+        }
+        ppu.write_register(0x2007, 0xFF);
+        set_address(ppu, 0x3F11);
+        for value in [0x21, 0x22, 0x23, 0x00, 0x25] {
+            ppu.write_register(0x2007, value);
+        }
+        ppu.write_register(0x2003, 0x00);
+        for index in 0..64 {
+            let sprite: [u8; 4] = sprites.get(index).copied().unwrap_or([0xF0; 4]);
+            for value in sprite {
+                ppu.write_register(0x2004, value);
+            }
+        }
+        ppu.write_register(0x2000, 0x00);
+        ppu.write_register(0x2005, 0x00);
+        ppu.write_register(0x2005, 0x00);
+        ppu.write_register(
+            0x2001,
+            MASK_BACKGROUND | MASK_BACKGROUND_LEFT | MASK_SPRITES | MASK_SPRITES_LEFT,
+        );
+    }
+
+    // The colours of one row of the picture
+    pub fn get_row(ppu: &Ppu, y: usize) -> &[u16] {
+        &ppu.frame_buffer[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH]
+    }
+
+    #[test]
+    fn test_status_read() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        ppu.start_vblank();
+        ppu.write_register(0x2000, 0x1F);
+
+        // Assert results, where the low bits come from the last write and vblank is cleared
+        assert_eq!(ppu.read_register(0x2002), 0x9F);
+        assert_eq!(ppu.read_register(0x2002), 0x1F);
+        // The write toggle is cleared, so this is the first write of the pair again
+        ppu.write_register(0x2006, 0x21);
+        ppu.read_register(0x2002);
+        set_address(&mut ppu, 0x2345);
+        assert_eq!(ppu.vram_address, 0x2345);
+    }
+
+    #[test]
+    fn test_scroll_writes() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+
+        // Assert results, following the example on the nesdev wiki
+        ppu.write_register(0x2000, 0b0000_0010);
+        assert_eq!(ppu.temporary_address, 0b000_1000_0000_0000);
+        ppu.write_register(0x2005, 0b0111_1101);
+        assert_eq!(ppu.temporary_address, 0b000_1000_0000_1111);
+        assert_eq!(ppu.fine_x_scroll, 0b101);
+        assert!(ppu.write_toggle);
+        ppu.write_register(0x2005, 0b0101_1110);
+        assert_eq!(ppu.temporary_address, 0b110_1001_0110_1111);
+        assert!(!ppu.write_toggle);
+        ppu.write_register(0x2006, 0b0011_1101);
+        assert_eq!(ppu.temporary_address, 0b011_1101_0110_1111);
+        assert_eq!(ppu.vram_address, 0);
+        ppu.write_register(0x2006, 0b1111_0000);
+        assert_eq!(ppu.temporary_address, 0b011_1101_1111_0000);
+        assert_eq!(ppu.vram_address, 0b011_1101_1111_0000);
+    }
+
+    #[test]
+    fn test_data_read_buffer() {
+        // Prep for the test, writing across the pattern table and a nametable
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_address(&mut ppu, 0x0010);
+        ppu.write_register(0x2007, 0x11);
+        ppu.write_register(0x2007, 0x22);
+        set_address(&mut ppu, 0x2400);
+        ppu.write_register(0x2007, 0x33);
+        set_address(&mut ppu, 0x2000);
+
+        // Assert results, where horizontal mirroring puts $2400 on top of $2000
+        set_address(&mut ppu, 0x0010);
+        assert_eq!(ppu.read_register(0x2007), 0x00);
+        assert_eq!(ppu.read_register(0x2007), 0x11);
+        assert_eq!(ppu.read_register(0x2007), 0x22);
+        set_address(&mut ppu, 0x2000);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x33);
+    }
+
+    #[test]
+    fn test_palette_read() {
+        // Prep for the test, with a byte in the nametable under the palette
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_address(&mut ppu, 0x2F05);
+        ppu.write_register(0x2007, 0x44);
+        set_address(&mut ppu, 0x3F05);
+        ppu.write_register(0x2007, 0x2A);
+        set_address(&mut ppu, 0x3F05);
+
+        // Assert results, the palette comes straight back and the buffer is filled from $2F05
+        assert_eq!(ppu.read_register(0x2007), 0x2A);
+        set_address(&mut ppu, 0x0000);
+        assert_eq!(ppu.read_register(0x2007), 0x44);
+    }
+
+    #[test]
+    fn test_palette_mirroring() {
+        // Prep for the test, writing the first colour of each sprite palette
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_address(&mut ppu, 0x3F10);
+        for value in [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08] {
+            ppu.write_register(0x2007, value);
+        }
+        set_address(&mut ppu, 0x3F1C);
+        ppu.write_register(0x2007, 0x09);
+
+        // Assert results, the sprite backdrops land in the background palettes
+        assert_eq!(ppu.palette[0x00], 0x01);
+        assert_eq!(ppu.palette[0x11], 0x02);
+        assert_eq!(ppu.palette[0x04], 0x05);
+        assert_eq!(ppu.palette[0x0C], 0x09);
+
+        // The mirrors also work when reading, including further up the repeated palette
+        set_address(&mut ppu, 0x3F00);
+        assert_eq!(ppu.read_register(0x2007) & COLOUR_MASK, 0x01);
+        set_address(&mut ppu, 0x3FF4);
+        assert_eq!(ppu.read_register(0x2007) & COLOUR_MASK, 0x05);
+    }
+
+    #[test]
+    fn test_palette_colours() {
+        // Prep for the test, where only 6 bits are stored
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_address(&mut ppu, 0x3F01);
+        ppu.write_register(0x2007, 0xE6);
+        assert_eq!(ppu.palette[0x01], 0x26);
+
+        // The top two bits read back from the register bus
+        set_address(&mut ppu, 0x3F01);
+        ppu.write_register(0x2003, 0x00);
+        assert_eq!(ppu.read_register(0x2007), 0x26);
+        set_address(&mut ppu, 0x3F01);
+        ppu.write_register(0x2003, 0xC0);
+        assert_eq!(ppu.read_register(0x2007), 0xE6);
+
+        // Assert results, greyscale keeps only the brightness column when reading
+        ppu.write_register(0x2001, MASK_GREYSCALE);
+        set_address(&mut ppu, 0x3F01);
+        assert_eq!(ppu.read_register(0x2007) & COLOUR_MASK, 0x20);
+    }
+
+    #[test]
+    fn test_address_increment() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_address(&mut ppu, 0x2000);
+
+        // Assert results
+        ppu.write_register(0x2007, 0x00);
+        assert_eq!(ppu.vram_address, 0x2001);
+        ppu.write_register(0x2000, CONTROL_INCREMENT_32);
+        ppu.write_register(0x2007, 0x00);
+        assert_eq!(ppu.vram_address, 0x2021);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.vram_address, 0x2041);
+    }
+
+    #[test]
+    fn test_oam_access() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        ppu.write_register(0x2003, 0xFE);
+        ppu.write_register(0x2004, 0x11);
+        ppu.write_register(0x2004, 0xFF);
+        ppu.write_register(0x2004, 0x33);
+
+        // Assert results, where the address wraps and the attribute byte loses bits 2-4
+        assert_eq!(ppu.oam[0xFE], 0x11);
+        assert_eq!(ppu.oam[0xFF], 0xFF);
+        assert_eq!(ppu.oam[0x00], 0x33);
+        ppu.write_register(0x2003, 0x00);
+        assert_eq!(ppu.read_register(0x2004), 0x33);
+        assert_eq!(ppu.read_register(0x2004), 0x33);
+        ppu.oam[0x02] = 0xFF;
+        ppu.write_register(0x2003, 0x02);
+        assert_eq!(ppu.read_register(0x2004), 0xE3);
+    }
+
+    #[test]
+    fn test_nmi() {
+        // Prep for the test
+        let (mut ppu, nmi_line): (Ppu, InterruptLine) = get_test_ppu();
+
+        // Assert results, vblank alone does not assert the line while NMI is disabled
+        ppu.start_vblank();
+        assert!(!nmi_line.is_asserted());
+        ppu.write_register(0x2000, CONTROL_NMI);
+        assert!(nmi_line.is_asserted());
+        // Reading the status releases it, so enabling NMI again later in vblank does nothing
+        ppu.read_register(0x2002);
+        assert!(!nmi_line.is_asserted());
+        ppu.write_register(0x2000, 0x00);
+        ppu.write_register(0x2000, CONTROL_NMI);
+        assert!(!nmi_line.is_asserted());
+        // Disabling and enabling NMI while the flag is still set gives another edge
+        ppu.start_vblank();
+        assert!(nmi_line.is_asserted());
+        ppu.write_register(0x2000, 0x00);
+        assert!(!nmi_line.is_asserted());
+        ppu.write_register(0x2000, CONTROL_NMI);
+        assert!(nmi_line.is_asserted());
+        ppu.end_vblank();
+        assert!(!nmi_line.is_asserted());
+    }
+
+    #[test]
+    fn test_frame_timing() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        let mut dots: Vec<u32> = Vec::new();
+
+        // Count the dots in four frames, with rendering enabled for the last two
+        for frame in 1..=4 {
+            if frame == 3 {
+                ppu.write_register(0x2001, MASK_BACKGROUND);
+            }
+            let mut count: u32 = 0;
+            while ppu.frame_count < frame {
+                ppu.clock();
+                count += 1;
+            }
+            dots.push(count);
+        }
+
+        // Assert results, the first frame starts from power on and only one of the rendered
+        // frames is odd
+        assert_eq!(
+            dots,
+            [241 * 341 + 2, 262 * 341, 262 * 341 - 1, 262 * 341].to_vec()
+        );
+        assert_eq!((ppu.scanline, ppu.dot), (241, 2));
+        assert_eq!(ppu.read_register(0x2002) & STATUS_VBLANK, STATUS_VBLANK);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 2);
+        assert_eq!(ppu.read_register(0x2002) & STATUS_VBLANK, 0);
+    }
+
+    #[test]
+    fn test_region_frame_timing() {
+        for (region, vblank_scanline) in [(&PAL, 241), (&DENDY, 291)] {
+            // Prep for the test, with rendering enabled from the second frame
+            let (mut ppu, _): (Ppu, InterruptLine) = get_test_region_ppu(region);
+            run_to_frame(&mut ppu, 1);
+            ppu.write_register(0x2001, MASK_BACKGROUND);
+            let mut dots: Vec<u32> = Vec::new();
+            for frame in 2..=3 {
+                let mut count: u32 = 0;
+                while ppu.frame_count < frame {
+                    ppu.clock();
+                    count += 1;
+                }
+                dots.push(count);
+            }
+
+            // Assert results, every frame is 312 full scanlines and vblank starts on the
+            // region's scanline
+            assert_eq!(dots, [312 * 341, 312 * 341].to_vec());
+            assert_eq!((ppu.scanline, ppu.dot), (vblank_scanline, 2));
+            run_to(&mut ppu, 311, 2);
+            assert_eq!(ppu.read_register(0x2002) & STATUS_VBLANK, 0);
+        }
+    }
+
+    #[test]
+    fn test_scroll_increments() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+
+        // Assert results, coarse X wraps into the next nametable across
+        ppu.vram_address = 0x001E;
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.vram_address, 0x001F);
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.vram_address, 0x0400);
+
+        // Fine Y carries into coarse Y, which wraps into the next nametable down after row 29
+        ppu.vram_address = 0x6000 | (28 << 5);
+        ppu.increment_y();
+        assert_eq!(ppu.vram_address, 0x7000 | (28 << 5));
+        ppu.increment_y();
+        assert_eq!(ppu.vram_address, 29 << 5);
+        ppu.vram_address |= FINE_Y;
+        ppu.increment_y();
+        assert_eq!(ppu.vram_address, NAMETABLE_Y);
+
+        // Rows 30 and 31 wrap to the top of the same nametable
+        ppu.vram_address = FINE_Y | (31 << 5);
+        ppu.increment_y();
+        assert_eq!(ppu.vram_address, 0x0000);
+    }
+
+    #[test]
+    fn test_background_rendering() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results
+        for y in [0, 100, 239] {
+            let row: &[u16] = get_row(&ppu, y);
+            assert_eq!(row[..8], [0x16; 8]);
+            assert_eq!(row[8..], [0x0F; SCREEN_WIDTH - 8]);
+        }
+
+        // Clipping the left column shows the backdrop instead
+        ppu.write_register(0x2001, MASK_BACKGROUND);
+        run_to_frame(&mut ppu, 3);
+        assert_eq!(get_row(&ppu, 0)[..8], [0x0F; 8]);
+    }
+
+    #[test]
+    fn test_scroll_split() {
+        // Prep for the test, changing the X scroll to 12 part way through the frame
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        run_to_frame(&mut ppu, 1);
+        run_to(&mut ppu, 119, 300);
+        ppu.write_register(0x2005, 12);
+        ppu.write_register(0x2005, 0);
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results
+        let row: &[u16] = get_row(&ppu, 119);
+        assert_eq!(row[..8], [0x16; 8]);
+        assert_eq!(row[8..], [0x0F; SCREEN_WIDTH - 8]);
+        // Fine X takes effect straight away, with the start of the mirrored nametable on the
+        // right coming into view
+        let row: &[u16] = get_row(&ppu, 120);
+        assert_eq!(row[..4], [0x16; 4]);
+        assert_eq!(row[4..252], [0x0F; 248]);
+        assert_eq!(row[252..], [0x16; 4]);
+        // Coarse X is copied to v at the end of line 120, so the column only moves from line 121
+        let row: &[u16] = get_row(&ppu, 121);
+        assert_eq!(row[..244], [0x0F; 244]);
+        assert_eq!(row[244..252], [0x16; 8]);
+        assert_eq!(row[252..], [0x0F; 4]);
+    }
+
+    #[test]
+    fn test_sprite_rendering() {
+        // Prep for the test, tile 2 as it is and flipped both ways
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        set_up_sprites(&mut ppu, &[[49, 2, 0x00, 100], [49, 2, 0xC0, 120]]);
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results, where sprites are drawn one line below their Y position
+        assert!(get_row(&ppu, 49)[100..128]
+            .iter()
+            .all(|colour| *colour == 0x0F));
+        assert_eq!(get_row(&ppu, 50)[100..102], [0x21, 0x0F]);
+        assert_eq!(get_row(&ppu, 57)[106..108], [0x0F, 0x22]);
+        assert_eq!(get_row(&ppu, 50)[120..122], [0x22, 0x0F]);
+        assert_eq!(get_row(&ppu, 57)[126..128], [0x0F, 0x21]);
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        // Prep for the test, a sprite behind the background in front of another sprite, and one
+        // behind a transparent part of the background
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        set_up_sprites(
+            &mut ppu,
+            &[[59, 3, 0x20, 0], [59, 3, 0x01, 4], [59, 3, 0x20, 20]],
+        );
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results, the first sprite hides the second even where the background covers it
+        let row: &[u16] = get_row(&ppu, 60);
+        assert_eq!(row[0..8], [0x16; 8]);
+        assert_eq!(row[8..12], [0x25; 4]);
+        assert_eq!(row[12..20], [0x0F; 8]);
+        assert_eq!(row[20..28], [0x21; 8]);
+    }
+
+    #[test]
+    fn test_8x16_sprites() {
+        // Prep for the test, tiles 2 and 3 as one sprite, as it is and flipped vertically
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        set_up_sprites(&mut ppu, &[[49, 2, 0x00, 100], [49, 2, 0x80, 120]]);
+        ppu.write_register(0x2000, CONTROL_SPRITE_SIZE);
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results
+        assert_eq!(get_row(&ppu, 50)[100], 0x21);
+        assert_eq!(get_row(&ppu, 57)[107], 0x22);
+        assert_eq!(get_row(&ppu, 58)[100..108], [0x21; 8]);
+        assert_eq!(get_row(&ppu, 57)[120..128], [0x21; 8]);
+        assert_eq!(
+            get_row(&ppu, 58)[120..128],
+            [0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x22]
+        );
+        assert_eq!(get_row(&ppu, 65)[120], 0x21);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        // Prep for the test, sprite 0 over the column of background tiles
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        set_up_sprites(&mut ppu, &[[99, 3, 0x00, 4]]);
+        run_to_frame(&mut ppu, 1);
+
+        // Assert results, the flag is set as the first overlapping pixel is drawn at dot 5
+        run_to(&mut ppu, 100, 5);
+        assert_eq!(ppu.status & STATUS_SPRITE_ZERO_HIT, 0);
+        run_to(&mut ppu, 100, 6);
+        assert_eq!(ppu.status & STATUS_SPRITE_ZERO_HIT, STATUS_SPRITE_ZERO_HIT);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 2);
+        assert_eq!(ppu.status & STATUS_SPRITE_ZERO_HIT, 0);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_edges() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+
+        // Assert results, no hit in the last column, where the scroll brings the tiles of the
+        // mirrored nametable into view
+        set_up_sprites(&mut ppu, &[[99, 3, 0x00, 255]]);
+        ppu.write_register(0x2005, 8);
+        ppu.write_register(0x2005, 0);
+        run_to_frame(&mut ppu, 2);
+        assert_eq!(get_row(&ppu, 99)[255], 0x16);
+        assert_eq!(get_row(&ppu, 100)[255], 0x21);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 0);
+        assert_eq!(ppu.status & STATUS_SPRITE_ZERO_HIT, 0);
+
+        // Nor in the left column while either layer is clipped there
+        set_up_sprites(&mut ppu, &[[99, 3, 0x00, 0]]);
+        for mask in [
+            MASK_BACKGROUND | MASK_SPRITES | MASK_SPRITES_LEFT,
+            MASK_BACKGROUND | MASK_SPRITES | MASK_BACKGROUND_LEFT,
+        ] {
+            ppu.write_register(0x2001, mask);
+            run_to(&mut ppu, PRE_RENDER_SCANLINE, 0);
+            run_to(&mut ppu, PRE_RENDER_SCANLINE - 1, 0);
+            assert_eq!(ppu.status & STATUS_SPRITE_ZERO_HIT, 0);
+        }
+
+        // Which is where it hits with both layers shown
+        ppu.write_register(
+            0x2001,
+            MASK_BACKGROUND | MASK_SPRITES | MASK_BACKGROUND_LEFT | MASK_SPRITES_LEFT,
+        );
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE - 1, 0);
+        assert_eq!(ppu.status & STATUS_SPRITE_ZERO_HIT, STATUS_SPRITE_ZERO_HIT);
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        // Prep for the test, with nine sprites on line 30
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_sprites(&mut ppu, &[[30, 0, 0, 0]; 9]);
+        run_to_frame(&mut ppu, 1);
+
+        // Assert results, the flag is set during evaluation and cleared with vblank
+        run_to(&mut ppu, 30, 65);
+        assert_eq!(ppu.status & STATUS_SPRITE_OVERFLOW, 0);
+        run_to(&mut ppu, 30, 66);
+        assert_eq!(ppu.status & STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_OVERFLOW);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 2);
+        assert_eq!(ppu.status & STATUS_SPRITE_OVERFLOW, 0);
+    }
+
+    #[test]
+    fn test_greyscale_and_emphasis() {
+        // Prep for the test, with greyscale and red and blue emphasis
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        ppu.write_register(
+            0x2001,
+            MASK_BACKGROUND | MASK_BACKGROUND_LEFT | MASK_GREYSCALE | 0b1010_0000,
+        );
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results, the colours lose their hue and carry the emphasis bits above them
+        let row: &[u16] = get_row(&ppu, 100);
+        assert_eq!(row[..8], [0x0150; 8]);
+        assert_eq!(row[8..], [0x0140; SCREEN_WIDTH - 8]);
+    }
+
+    #[test]
+    fn test_rendering_disabled() {
+        // Prep for the test, with v left pointing into the palette
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        set_up_background(&mut ppu);
+        ppu.write_register(0x2001, 0x00);
+        run_to_frame(&mut ppu, 1);
+        set_address(&mut ppu, 0x3F01);
+        run_to_frame(&mut ppu, 2);
+
+        // Assert results, the whole picture is the palette entry at v rather than the backdrop
+        assert!(ppu.frame_buffer.iter().all(|colour| *colour == 0x16));
+    }
+
+    #[test]
+    fn test_open_bus() {
+        // Prep for the test
+        let (mut ppu, _): (Ppu, InterruptLine) = get_test_ppu();
+        ppu.write_register(0x3FF8, 0x5A);
+
+        // Assert results, where $3FF8 is a mirror of PPUCTRL and write only registers read the latch
+        assert_eq!(ppu.control, 0x5A);
+        assert_eq!(ppu.read_register(0x2000), 0x5A);
+        assert_eq!(ppu.read_register(0x2005), 0x5A);
+    }
+}
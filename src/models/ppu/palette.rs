@@ -0,0 +1,345 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Conversion of the PPU's colours to RGB
+// The PPU puts out a composite video signal rather than RGB, so there is no single right answer
+// for what each colour looks like. Palettes are either loaded from a .pal file, holding the 64
+// colours or all 512 combinations of a colour and the emphasis bits, or generated by decoding the
+// NTSC signal the PPU would make for each colour.
+// https://www.nesdev.org/wiki/PPU_palettes
+
+use std::f64::consts::PI;
+use std::fmt;
+
+const COLOUR_COUNT: usize = 64;
+// Each colour with each of the eight combinations of the emphasis bits
+const PIXEL_VALUE_COUNT: usize = COLOUR_COUNT * 8;
+const BYTES_PER_COLOUR: usize = 3;
+
+// Colours $xE and $xF are black, and are left alone by the emphasis bits
+const BLACK_COLUMN: usize = 0x0E;
+
+// Each emphasis bit darkens the parts of the signal away from its own colour by this much
+const EMPHASIS_ATTENUATION: f64 = 0.746;
+
+// Voltages of the low and high halves of the signal at each brightness level, normalised so that
+// black and white fall at the given levels
+// https://www.nesdev.org/wiki/NTSC_video
+const SIGNAL_LOW: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f64 = 0.518;
+const SIGNAL_WHITE: f64 = 1.962;
+
+// The colour subcarrier is split into 12 phases, one for each hue
+const PHASE_COUNT: usize = 12;
+// Lines the hues up with the colour burst, in phases
+const PHASE_OFFSET: f64 = 3.9;
+// The decoded signal is corrected from the television's gamma to the screen's
+const GAMMA: f64 = 2.2 / 1.8;
+
+// Errors that can occur while loading a palette
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    // The file is neither 64 nor 512 colours long
+    InvalidSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::InvalidSize(size) => write!(
+                f,
+                "Palette files must be {} or {} bytes, not {}",
+                COLOUR_COUNT * BYTES_PER_COLOUR,
+                PIXEL_VALUE_COUNT * BYTES_PER_COLOUR,
+                size
+            ),
+        }
+    }
+}
+
+// Adjustments made to the NTSC signal as it is decoded, like the knobs on a television
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscSettings {
+    // Rotation of every hue, in degrees
+    pub hue: f64,
+    // Scale of the colour signal, where 0 is greyscale
+    pub saturation: f64,
+    // Scale of the brightness signal
+    pub contrast: f64,
+    // Offset of the brightness signal, where 1 is the difference between black and white
+    pub brightness: f64,
+}
+
+impl Default for NtscSettings {
+    fn default() -> NtscSettings {
+        NtscSettings {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+        }
+    }
+}
+
+pub struct Palette {
+    // The RGB colour of each pixel value, indexed by the emphasis bits then the colour
+    colours: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    // Load a palette from a .pal file, which is a list of RGB colours
+    // Files with only the 64 colours have the emphasised colours made by darkening them
+    pub fn from_pal_file(content: &[u8]) -> Result<Palette, PaletteError> {
+        let colours: Vec<[u8; 3]> = content
+            .chunks_exact(BYTES_PER_COLOUR)
+            .map(|colour| [colour[0], colour[1], colour[2]])
+            .collect();
+        if content.len() == PIXEL_VALUE_COUNT * BYTES_PER_COLOUR {
+            return Ok(Palette { colours });
+        }
+        if content.len() != COLOUR_COUNT * BYTES_PER_COLOUR {
+            return Err(PaletteError::InvalidSize(content.len()));
+        }
+
+        let colours: Vec<[u8; 3]> = (0..PIXEL_VALUE_COUNT)
+            .map(|pixel| {
+                let colour: usize = pixel % COLOUR_COUNT;
+                let emphasis: usize = pixel / COLOUR_COUNT;
+                let mut rgb: [u8; 3] = colours[colour];
+                if colour & BLACK_COLUMN != BLACK_COLUMN {
+                    // Each emphasis bit darkens the two channels other than its own
+                    for (channel, value) in rgb.iter_mut().enumerate() {
+                        let other_bits: u32 = (emphasis & !(1 << channel)).count_ones();
+                        let scale: f64 = EMPHASIS_ATTENUATION.powi(other_bits as i32);
+                        *value = (f64::from(*value) * scale).round() as u8;
+                    }
+                }
+                rgb
+            })
+            .collect();
+        Ok(Palette { colours })
+    }
+
+    // Generate a palette by decoding the NTSC signal of each colour
+    // The signal is a square wave between a low and a high level, with the phase of the wave
+    // giving the hue, and the emphasis bits darken the parts of the wave in their colour's phase
+    pub fn generate_ntsc(settings: &NtscSettings) -> Palette {
+        let colours: Vec<[u8; 3]> = (0..PIXEL_VALUE_COUNT)
+            .map(|pixel| generate_ntsc_colour(pixel, settings))
+            .collect();
+        Palette { colours }
+    }
+
+    // Get the RGB colour of a pixel from the PPU's frame buffer, with the colour in bits 0-5 and
+    // the emphasis bits of PPUMASK in bits 6-8
+    pub fn get_rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colours[usize::from(pixel) % PIXEL_VALUE_COUNT]
+    }
+}
+
+// Decode one colour, given with the emphasis bits above it, into RGB
+fn generate_ntsc_colour(pixel: usize, settings: &NtscSettings) -> [u8; 3] {
+    let colour: usize = pixel & 0x0F;
+    let emphasis: usize = pixel >> 6;
+
+    // Colour 0 is a flat high signal, colours $D-$F a flat low one, and $E and $F are always black
+    let level: usize = if colour >= BLACK_COLUMN {
+        1
+    } else {
+        (pixel >> 4) & 0b11
+    };
+    let low: f64 = if colour == 0x00 {
+        SIGNAL_HIGH[level]
+    } else {
+        SIGNAL_LOW[level]
+    };
+    let high: f64 = if colour < 0x0D {
+        SIGNAL_HIGH[level]
+    } else {
+        SIGNAL_LOW[level]
+    };
+
+    // Sample the wave at each phase, splitting it into brightness and the two colour axes
+    let mut y: f64 = 0.0;
+    let mut i: f64 = 0.0;
+    let mut q: f64 = 0.0;
+    for phase in 0..PHASE_COUNT {
+        let is_in_phase = |hue: usize| (hue + phase) % PHASE_COUNT < PHASE_COUNT / 2;
+        let mut signal: f64 = if is_in_phase(colour) { high } else { low };
+        let is_emphasised: bool = (emphasis & 0b001 != 0 && is_in_phase(0x0))
+            || (emphasis & 0b010 != 0 && is_in_phase(0x4))
+            || (emphasis & 0b100 != 0 && is_in_phase(0x8));
+        if is_emphasised && colour < BLACK_COLUMN {
+            signal *= EMPHASIS_ATTENUATION;
+        }
+
+        let signal: f64 = (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK);
+        let angle: f64 = PI * (phase as f64 + PHASE_OFFSET + settings.hue / 30.0) / 6.0;
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+    let y: f64 = y / PHASE_COUNT as f64 * settings.contrast + settings.brightness;
+    let i: f64 = i / PHASE_COUNT as f64 * settings.contrast * settings.saturation;
+    let q: f64 = q / PHASE_COUNT as f64 * settings.contrast * settings.saturation;
+
+    // Convert from YIQ to RGB
+    [
+        get_channel(y + 0.946882 * i + 0.623557 * q),
+        get_channel(y - 0.274788 * i - 0.635691 * q),
+        get_channel(y - 1.108545 * i + 1.709007 * q),
+    ]
+}
+
+// Gamma correct a channel between 0 and 1 and scale it to a byte
+fn get_channel(value: f64) -> u8 {
+    let corrected: f64 = if value <= 0.0 { 0.0 } else { value.powf(GAMMA) };
+    (corrected * 255.95).clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 64 colour palette where each colour is its own index in all three channels, times three
+    fn get_test_pal_file() -> Vec<u8> {
+        (0..COLOUR_COUNT as u8)
+            .flat_map(|colour| [colour * 3, colour * 3 + 1, colour * 3 + 2])
+            .collect()
+    }
+
+    #[test]
+    fn test_pal_file() {
+        // Prep for the test
+        let palette: Palette = Palette::from_pal_file(&get_test_pal_file()).unwrap();
+
+        // Assert results
+        assert_eq!(palette.get_rgb(0x00), [0, 1, 2]);
+        assert_eq!(palette.get_rgb(0x3F), [189, 190, 191]);
+        assert_eq!(palette.get_rgb(0x01), [3, 4, 5]);
+    }
+
+    #[test]
+    fn test_pal_file_emphasis() {
+        // Prep for the test
+        let palette: Palette = Palette::from_pal_file(&get_test_pal_file()).unwrap();
+
+        // Assert results, red emphasis darkens green and blue, and all three darken everything
+        assert_eq!(palette.get_rgb(0x0030), [144, 145, 146]);
+        assert_eq!(palette.get_rgb(0x0070), [144, 108, 109]);
+        assert_eq!(palette.get_rgb(0x01F0), [80, 81, 81]);
+
+        // Black is left alone
+        assert_eq!(palette.get_rgb(0x01CE), palette.get_rgb(0x000E));
+    }
+
+    #[test]
+    fn test_full_pal_file() {
+        // Prep for the test, with a different colour for every emphasis
+        let content: Vec<u8> = (0..PIXEL_VALUE_COUNT)
+            .flat_map(|pixel| [(pixel / COLOUR_COUNT) as u8, pixel as u8, 0])
+            .collect();
+        let palette: Palette = Palette::from_pal_file(&content).unwrap();
+
+        // Assert results
+        assert_eq!(palette.get_rgb(0x0005), [0, 5, 0]);
+        assert_eq!(palette.get_rgb(0x0145), [5, 0x45, 0]);
+    }
+
+    #[test]
+    fn test_invalid_pal_file() {
+        // Assert results
+        assert_eq!(
+            Palette::from_pal_file(&[0; 100]).err(),
+            Some(PaletteError::InvalidSize(100))
+        );
+    }
+
+    #[test]
+    fn test_ntsc_greys() {
+        // Prep for the test
+        let palette: Palette = Palette::generate_ntsc(&NtscSettings::default());
+
+        // Assert results, colour 0 and the white column are grey, and the $xE column is black
+        assert_eq!(palette.get_rgb(0x0F), [0, 0, 0]);
+        assert_eq!(palette.get_rgb(0x1E), [0, 0, 0]);
+        assert_eq!(palette.get_rgb(0x30), [255, 255, 255]);
+        let [red, green, blue] = palette.get_rgb(0x00);
+        assert!(red == green && green == blue && red > 0 && red < 255);
+        let [red, _, _] = palette.get_rgb(0x10);
+        assert!(red > palette.get_rgb(0x00)[0]);
+    }
+
+    #[test]
+    fn test_ntsc_hues() {
+        // Prep for the test
+        let palette: Palette = Palette::generate_ntsc(&NtscSettings::default());
+
+        // Assert results, $12 is blue, $16 is red and $1A is green
+        let [red, green, blue] = palette.get_rgb(0x12);
+        assert!(blue > red && blue > green);
+        let [red, green, blue] = palette.get_rgb(0x16);
+        assert!(red > green && red > blue);
+        let [red, green, blue] = palette.get_rgb(0x1A);
+        assert!(green > red && green > blue);
+    }
+
+    #[test]
+    fn test_ntsc_settings() {
+        // Prep for the test
+        let default: Palette = Palette::generate_ntsc(&NtscSettings::default());
+        let grey: Palette = Palette::generate_ntsc(&NtscSettings {
+            saturation: 0.0,
+            ..NtscSettings::default()
+        });
+        let bright: Palette = Palette::generate_ntsc(&NtscSettings {
+            brightness: 0.2,
+            ..NtscSettings::default()
+        });
+        let rotated: Palette = Palette::generate_ntsc(&NtscSettings {
+            hue: 120.0,
+            ..NtscSettings::default()
+        });
+
+        // Assert results, without saturation every colour is grey
+        let [red, green, blue] = grey.get_rgb(0x16);
+        assert!(red == green && green == blue);
+        assert!(bright.get_rgb(0x00)[0] > default.get_rgb(0x00)[0]);
+
+        // A third of a turn moves each hue four colours along
+        assert_eq!(rotated.get_rgb(0x1A), default.get_rgb(0x16));
+    }
+
+    #[test]
+    fn test_ntsc_emphasis() {
+        // Prep for the test
+        let palette: Palette = Palette::generate_ntsc(&NtscSettings::default());
+
+        // Assert results, emphasising red on white leaves a red tint by darkening the others
+        let [red, green, blue] = palette.get_rgb(0x0070);
+        assert!(red > green && red > blue && red < 255);
+        assert_eq!(palette.get_rgb(0x01CF), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,199 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Screenshots of the PPU's picture, for running without a display
+// The PPU's colours are converted to RGBA through a palette, with the edges that televisions hide
+// behind their bezels optionally cropped off
+
+use crate::models::ppu::image;
+use crate::models::ppu::palette::Palette;
+use crate::models::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Screenshots are opaque
+const ALPHA: u8 = 0xFF;
+
+// The number of pixels cropped from each edge of the picture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    // Four bytes of red, green, blue and alpha for each pixel, row by row
+    pub rgba: Vec<u8>,
+}
+
+impl Screenshot {
+    // Take a screenshot of a frame buffer from the PPU
+    pub fn capture(frame: &[u16], palette: &Palette, overscan: &Overscan) -> Screenshot {
+        assert_eq!(frame.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        let width: usize = SCREEN_WIDTH.saturating_sub(overscan.left + overscan.right);
+        let height: usize = SCREEN_HEIGHT.saturating_sub(overscan.top + overscan.bottom);
+
+        let mut rgba: Vec<u8> = Vec::with_capacity(width * height * 4);
+        for row in frame.chunks(SCREEN_WIDTH).skip(overscan.top).take(height) {
+            for pixel in row.iter().skip(overscan.left).take(width) {
+                rgba.extend_from_slice(&palette.get_rgb(*pixel));
+                rgba.push(ALPHA);
+            }
+        }
+        Screenshot {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        image::encode_png(self.width, self.height, &self.rgba)
+    }
+
+    pub fn encode_ppm(&self) -> Vec<u8> {
+        image::encode_ppm(self.width, self.height, &self.rgba)
+    }
+
+    // Write the screenshot as a PPM file if the path ends in .ppm, or as a PNG file otherwise
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        let is_ppm: bool = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("ppm"));
+        if is_ppm {
+            fs::write(path, self.encode_ppm())
+        } else {
+            fs::write(path, self.encode_png())
+        }
+    }
+}
+
+// Number a screenshot path with a frame, so screenshots taken as the game runs do not overwrite
+// each other, such as shot_000120.png for frame 120 of shot.png
+pub fn get_numbered_path(path: &Path, frame: u64) -> PathBuf {
+    let stem: String = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name: String = match path.extension() {
+        Some(extension) => format!("{}_{:06}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}_{:06}", stem, frame),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A palette where colour n is n in red, n + 1 in green and n + 2 in blue
+    fn get_test_palette() -> Palette {
+        let content: Vec<u8> = (0..64u8)
+            .flat_map(|colour| [colour, colour + 1, colour + 2])
+            .collect();
+        Palette::from_pal_file(&content).unwrap()
+    }
+
+    // A frame where each pixel's colour is its row, and the first column is colour $30
+    fn get_test_frame() -> Vec<u16> {
+        (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|index| {
+                if index % SCREEN_WIDTH == 0 {
+                    0x30
+                } else {
+                    (index / SCREEN_WIDTH % 64) as u16
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_capture() {
+        // Prep for the test
+        let screenshot: Screenshot =
+            Screenshot::capture(&get_test_frame(), &get_test_palette(), &Overscan::default());
+
+        // Assert results
+        assert_eq!(screenshot.width, SCREEN_WIDTH);
+        assert_eq!(screenshot.height, SCREEN_HEIGHT);
+        assert_eq!(screenshot.rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        assert_eq!(
+            screenshot.rgba[..8],
+            [0x30, 0x31, 0x32, 0xFF, 0, 1, 2, 0xFF]
+        );
+        let row: usize = 5 * SCREEN_WIDTH * 4;
+        assert_eq!(screenshot.rgba[row + 4..row + 8], [5, 6, 7, 0xFF]);
+    }
+
+    #[test]
+    fn test_capture_overscan() {
+        // Prep for the test, cropping the usual 8 lines from the top and bottom and a column
+        let overscan: Overscan = Overscan {
+            top: 8,
+            bottom: 8,
+            left: 1,
+            right: 0,
+        };
+        let screenshot: Screenshot =
+            Screenshot::capture(&get_test_frame(), &get_test_palette(), &overscan);
+
+        // Assert results, the first column and the rows above 8 are gone
+        assert_eq!(screenshot.width, SCREEN_WIDTH - 1);
+        assert_eq!(screenshot.height, SCREEN_HEIGHT - 16);
+        assert_eq!(
+            screenshot.rgba.len(),
+            (SCREEN_WIDTH - 1) * (SCREEN_HEIGHT - 16) * 4
+        );
+        assert_eq!(screenshot.rgba[..4], [8, 9, 10, 0xFF]);
+    }
+
+    #[test]
+    fn test_encode() {
+        // Prep for the test
+        let screenshot: Screenshot =
+            Screenshot::capture(&get_test_frame(), &get_test_palette(), &Overscan::default());
+
+        // Assert results
+        assert!(screenshot.encode_png().starts_with(b"\x89PNG"));
+        assert!(screenshot.encode_ppm().starts_with(b"P6\n256 240\n255\n"));
+    }
+
+    #[test]
+    fn test_numbered_path() {
+        // Assert results
+        assert_eq!(
+            get_numbered_path(Path::new("shots/game.png"), 120),
+            PathBuf::from("shots/game_000120.png")
+        );
+        assert_eq!(
+            get_numbered_path(Path::new("game"), 7),
+            PathBuf::from("game_000007")
+        );
+    }
+}
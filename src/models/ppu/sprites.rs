@@ -0,0 +1,306 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Sprite evaluation and drawing
+// During each visible scanline the PPU searches OAM for up to eight sprites on the next line and
+// copies them to secondary OAM, then fetches their patterns at the end of the line
+// https://www.nesdev.org/wiki/PPU_sprite_evaluation
+
+// Sprite attribute bits
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0011;
+const ATTRIBUTE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const ATTRIBUTE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const ATTRIBUTE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+// Sprites use the second half of palette RAM
+const SPRITE_PALETTES: u8 = 0x10;
+
+pub const SPRITES_PER_LINE: usize = 8;
+const SPRITE_COUNT: usize = 64;
+
+// A sprite that has been fetched for the scanline being drawn
+#[derive(Clone, Copy, Default)]
+struct SpriteSlot {
+    // The row of the pattern, already flipped horizontally if the sprite asks for it
+    pattern_low: u8,
+    pattern_high: u8,
+    attributes: u8,
+    x: u8,
+}
+
+// A sprite's opaque pixel
+#[derive(Debug, PartialEq, Eq)]
+pub struct SpritePixel {
+    // The index into palette RAM
+    pub palette_index: u8,
+    pub is_behind_background: bool,
+    pub is_sprite_zero: bool,
+}
+
+pub struct Sprites {
+    // Secondary OAM, holding the sprites found for the next scanline
+    secondary_oam: [u8; SPRITES_PER_LINE * 4],
+    found_count: usize,
+    // Whether the first sprite in secondary OAM is sprite 0 of OAM
+    is_sprite_zero_found: bool,
+
+    // The sprites being drawn on the current scanline
+    slots: [SpriteSlot; SPRITES_PER_LINE],
+    slot_count: usize,
+    is_sprite_zero_in_slots: bool,
+}
+
+impl Sprites {
+    pub fn new() -> Sprites {
+        Sprites {
+            secondary_oam: [0xFF; SPRITES_PER_LINE * 4],
+            found_count: 0,
+            is_sprite_zero_found: false,
+            slots: [SpriteSlot::default(); SPRITES_PER_LINE],
+            slot_count: 0,
+            is_sprite_zero_in_slots: false,
+        }
+    }
+
+    // Secondary OAM is filled with $FF before evaluation, and is left that way on the pre-render
+    // line, so no sprites are drawn on the first scanline
+    pub fn clear(&mut self) {
+        self.secondary_oam = [0xFF; SPRITES_PER_LINE * 4];
+        self.found_count = 0;
+        self.is_sprite_zero_found = false;
+    }
+
+    // Find the sprites that cover the line after this one, returning whether the sprite overflow
+    // flag should be set
+    // Once eight sprites have been found the PPU keeps looking for a ninth, but it wrongly moves
+    // on to the next byte of each sprite as well as the next sprite, so the check can land on a
+    // tile, attribute or X byte instead of the Y position and give false positives and negatives
+    pub fn evaluate(&mut self, oam: &[u8], scanline: u16, height: u16) -> bool {
+        self.clear();
+        let is_in_range = |y: u8| -> bool { scanline.wrapping_sub(u16::from(y)) < height };
+
+        let mut sprite: usize = 0;
+        while sprite < SPRITE_COUNT && self.found_count < SPRITES_PER_LINE {
+            let bytes: &[u8] = &oam[sprite * 4..sprite * 4 + 4];
+            if is_in_range(bytes[0]) {
+                let start: usize = self.found_count * 4;
+                self.secondary_oam[start..start + 4].copy_from_slice(bytes);
+                self.is_sprite_zero_found |= sprite == 0;
+                self.found_count += 1;
+            }
+            sprite += 1;
+        }
+
+        let mut byte: usize = 0;
+        while sprite < SPRITE_COUNT {
+            if is_in_range(oam[sprite * 4 + byte]) {
+                return true;
+            }
+            sprite += 1;
+            byte = (byte + 1) % 4;
+        }
+        false
+    }
+
+    // The Y position, tile, attributes and X position in a slot of secondary OAM, which are all
+    // $FF for slots without a sprite
+    pub fn get_secondary_sprite(&self, slot: usize) -> [u8; 4] {
+        let mut bytes: [u8; 4] = [0; 4];
+        bytes.copy_from_slice(&self.secondary_oam[slot * 4..slot * 4 + 4]);
+        bytes
+    }
+
+    // Start drawing the sprites from secondary OAM, as the last line's pixels are finished
+    pub fn start_fetches(&mut self) {
+        self.slot_count = self.found_count;
+        self.is_sprite_zero_in_slots = self.is_sprite_zero_found;
+    }
+
+    // Store the fetched pattern of a sprite, ready for the next scanline
+    pub fn load_slot(&mut self, slot: usize, pattern_low: u8, pattern_high: u8) {
+        let [_, _, attributes, x] = self.get_secondary_sprite(slot);
+        let flip = |pattern: u8| -> u8 {
+            if attributes & ATTRIBUTE_FLIP_HORIZONTAL == ATTRIBUTE_FLIP_HORIZONTAL {
+                pattern.reverse_bits()
+            } else {
+                pattern
+            }
+        };
+        self.slots[slot] = SpriteSlot {
+            pattern_low: flip(pattern_low),
+            pattern_high: flip(pattern_high),
+            attributes,
+            x,
+        };
+    }
+
+    // The pixel of the first sprite in secondary OAM that is opaque at this X position, as
+    // earlier sprites are in front of later ones whatever their background priority
+    pub fn get_pixel(&self, x: u8) -> Option<SpritePixel> {
+        for (index, slot) in self.slots[..self.slot_count].iter().enumerate() {
+            let column: u8 = match x.checked_sub(slot.x) {
+                Some(column) if column < 8 => column,
+                _ => continue,
+            };
+            let bit: u8 = 0x80 >> column;
+            let low: u8 = u8::from(slot.pattern_low & bit == bit);
+            let high: u8 = u8::from(slot.pattern_high & bit == bit);
+            let colour: u8 = (high << 1) | low;
+            if colour == 0 {
+                continue;
+            }
+            return Some(SpritePixel {
+                palette_index: SPRITE_PALETTES
+                    | ((slot.attributes & ATTRIBUTE_PALETTE) << 2)
+                    | colour,
+                is_behind_background: slot.attributes & ATTRIBUTE_BEHIND_BACKGROUND
+                    == ATTRIBUTE_BEHIND_BACKGROUND,
+                is_sprite_zero: index == 0 && self.is_sprite_zero_in_slots,
+            });
+        }
+        None
+    }
+}
+
+// The row of a sprite's pattern on a scanline, counting from the top of the sprite, after
+// applying vertical flipping
+pub fn get_sprite_row(scanline: u16, y: u8, attributes: u8, height: u16) -> u16 {
+    let row: u16 = scanline.wrapping_sub(u16::from(y)) % height;
+    if attributes & ATTRIBUTE_FLIP_VERTICAL == ATTRIBUTE_FLIP_VERTICAL {
+        height - 1 - row
+    } else {
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Build OAM with every sprite off the bottom of the screen
+    fn get_test_oam() -> [u8; SPRITE_COUNT * 4] {
+        [0xF0; SPRITE_COUNT * 4]
+    }
+
+    #[test]
+    fn test_evaluation() {
+        // Prep for the test, with sprite 0 and ten more on line 20, and one 16 pixels tall sprite
+        // that only reaches it in 8x16 mode
+        let mut oam: [u8; SPRITE_COUNT * 4] = get_test_oam();
+        oam[0..4].copy_from_slice(&[20, 0x01, 0x02, 0x03]);
+        for sprite in 2..12 {
+            oam[sprite * 4] = 13;
+        }
+        oam[4] = 8;
+        let mut sprites: Sprites = Sprites::new();
+
+        // Assert results
+        assert!(sprites.evaluate(&oam, 20, 8));
+        assert_eq!(sprites.found_count, 8);
+        assert!(sprites.is_sprite_zero_found);
+        assert_eq!(sprites.get_secondary_sprite(0), [20, 0x01, 0x02, 0x03]);
+        assert_eq!(sprites.get_secondary_sprite(1)[0], 13);
+        assert!(sprites.evaluate(&oam, 20, 16));
+        assert_eq!(sprites.get_secondary_sprite(1)[0], 8);
+        assert!(!sprites.evaluate(&oam, 21, 1));
+        assert_eq!(sprites.found_count, 0);
+        assert!(!sprites.is_sprite_zero_found);
+        assert_eq!(sprites.get_secondary_sprite(0), [0xFF; 4]);
+    }
+
+    #[test]
+    fn test_overflow_bug() {
+        // Prep for the test, with eight sprites on line 40
+        let mut oam: [u8; SPRITE_COUNT * 4] = get_test_oam();
+        for sprite in 0..8 {
+            oam[sprite * 4] = 40;
+        }
+        let mut sprites: Sprites = Sprites::new();
+
+        // Assert results, a ninth sprite straight after the eighth is found
+        oam[8 * 4] = 40;
+        assert!(sprites.evaluate(&oam, 40, 8));
+        oam[8 * 4] = 0xF0;
+        assert!(!sprites.evaluate(&oam, 40, 8));
+
+        // The tile byte of sprite 9 is checked instead of its Y position, so a tile number in
+        // range gives a false positive
+        oam[9 * 4 + 1] = 40;
+        assert!(sprites.evaluate(&oam, 40, 8));
+
+        // And a sprite in range is missed when its tile number is not
+        oam[9 * 4] = 40;
+        oam[9 * 4 + 1] = 0xF0;
+        assert!(!sprites.evaluate(&oam, 40, 8));
+    }
+
+    #[test]
+    fn test_pixels() {
+        // Prep for the test, two overlapping sprites where the first is flipped and behind the
+        // background, and the second is a solid block
+        let mut oam: [u8; SPRITE_COUNT * 4] = get_test_oam();
+        oam[0..8].copy_from_slice(&[0, 0, 0b0110_0001, 10, 0, 0, 0b0000_0011, 12]);
+        let mut sprites: Sprites = Sprites::new();
+        sprites.evaluate(&oam, 0, 8);
+        sprites.start_fetches();
+        sprites.load_slot(0, 0b1100_0000, 0b1000_0000);
+        sprites.load_slot(1, 0xFF, 0x00);
+
+        // Assert results
+        assert_eq!(sprites.get_pixel(9), None);
+        assert_eq!(
+            sprites.get_pixel(17),
+            Some(SpritePixel {
+                palette_index: 0x17,
+                is_behind_background: true,
+                is_sprite_zero: true,
+            })
+        );
+        assert_eq!(
+            sprites.get_pixel(16),
+            Some(SpritePixel {
+                palette_index: 0x15,
+                is_behind_background: true,
+                is_sprite_zero: true,
+            })
+        );
+        assert_eq!(
+            sprites.get_pixel(15),
+            Some(SpritePixel {
+                palette_index: 0x1D,
+                is_behind_background: false,
+                is_sprite_zero: false,
+            })
+        );
+        assert_eq!(sprites.get_pixel(20), None);
+    }
+
+    #[test]
+    fn test_sprite_row() {
+        // Assert results
+        assert_eq!(get_sprite_row(11, 10, 0x00, 8), 1);
+        assert_eq!(get_sprite_row(11, 10, ATTRIBUTE_FLIP_VERTICAL, 8), 6);
+        assert_eq!(get_sprite_row(21, 10, ATTRIBUTE_FLIP_VERTICAL, 16), 4);
+    }
+}
@@ -0,0 +1,199 @@
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// MIT License
+//
+// Copyright (c) 2021-2024 fontivan
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Console regions and the timing each one runs at
+// NTSC consoles, PAL consoles and the Dendy, a Famiclone sold in Russia, share the same chips but
+// divide different master clocks to drive them, and draw different numbers of scanlines
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+// https://www.nesdev.org/wiki/NES_2.0#Byte_12_(CPU/PPU_Timing)
+
+use crate::models::audio::apu::{FrameCounterTiming, NTSC_FRAME_COUNTER, PAL_FRAME_COUNTER};
+use crate::models::audio::dmc;
+use crate::models::audio::noise;
+
+// The timing a console region runs at
+pub struct RegionProfile {
+    pub name: &'static str,
+    // The crystal all of the console's clocks are divided from
+    pub master_clock_hz: f64,
+    // Master clock cycles per CPU cycle and per PPU dot, giving 3 dots per CPU cycle on NTSC
+    // consoles and the Dendy, and 3.2 on PAL consoles
+    pub cpu_divider: u32,
+    pub ppu_divider: u32,
+    // Scanlines in a frame, including the pre-render line
+    pub scanline_count: u16,
+    // The scanline vblank starts on, which is followed by the vblank lines up to the pre-render
+    // line
+    pub vblank_scanline: u16,
+    // Whether odd frames skip a dot of the pre-render line while rendering
+    pub skips_odd_frame_dot: bool,
+    pub frame_counter: &'static FrameCounterTiming,
+    pub noise_periods: &'static [u16; 16],
+    pub dmc_rates: &'static [u16; 16],
+}
+
+impl RegionProfile {
+    pub fn get_cpu_clock_hz(&self) -> f64 {
+        self.master_clock_hz / f64::from(self.cpu_divider)
+    }
+
+    // The number of vblank scanlines, between the start of vblank and the pre-render line
+    pub fn get_vblank_length(&self) -> u16 {
+        self.scanline_count - 1 - self.vblank_scanline
+    }
+}
+
+pub const NTSC: RegionProfile = RegionProfile {
+    name: "NTSC",
+    master_clock_hz: 236250000.0 / 11.0,
+    cpu_divider: 12,
+    ppu_divider: 4,
+    scanline_count: 262,
+    vblank_scanline: 241,
+    skips_odd_frame_dot: true,
+    frame_counter: &NTSC_FRAME_COUNTER,
+    noise_periods: &noise::NTSC_PERIOD_TABLE,
+    dmc_rates: &dmc::NTSC_RATE_TABLE,
+};
+
+// PAL consoles have a longer vblank, and a slower CPU with its own audio timing
+pub const PAL: RegionProfile = RegionProfile {
+    name: "PAL",
+    master_clock_hz: 26601712.5,
+    cpu_divider: 16,
+    ppu_divider: 5,
+    scanline_count: 312,
+    vblank_scanline: 241,
+    skips_odd_frame_dot: false,
+    frame_counter: &PAL_FRAME_COUNTER,
+    noise_periods: &noise::PAL_PERIOD_TABLE,
+    dmc_rates: &dmc::PAL_RATE_TABLE,
+};
+
+// The Dendy draws as many scanlines as a PAL console, but adds them before vblank so that vblank
+// is as long as on NTSC consoles, and its CPU keeps the NTSC ratio and audio timing
+pub const DENDY: RegionProfile = RegionProfile {
+    name: "Dendy",
+    master_clock_hz: 26601712.5,
+    cpu_divider: 15,
+    ppu_divider: 5,
+    scanline_count: 312,
+    vblank_scanline: 291,
+    skips_odd_frame_dot: false,
+    frame_counter: &NTSC_FRAME_COUNTER,
+    noise_periods: &noise::NTSC_PERIOD_TABLE,
+    dmc_rates: &dmc::NTSC_RATE_TABLE,
+};
+
+// The region a game is made for, in the order of the NES 2.0 timing field
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    // The game runs on both NTSC and PAL consoles
+    Multi,
+    Dendy,
+}
+
+impl Region {
+    // Decode the low two bits of NES 2.0 header byte 12, which the ROM database uses as well
+    pub fn from_timing(value: u8) -> Region {
+        match value & 0b11 {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Multi,
+            _ => Region::Dendy,
+        }
+    }
+
+    // Parse a region given by name, such as on the command line
+    pub fn from_name(name: &str) -> Option<Region> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "multi" | "multi-region" => Some(Region::Multi),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    // Games for both regions are run as NTSC
+    pub fn get_profile(&self) -> &'static RegionProfile {
+        match self {
+            Region::Ntsc | Region::Multi => &NTSC,
+            Region::Pal => &PAL,
+            Region::Dendy => &DENDY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_timing() {
+        // Assert results, only the low two bits are used
+        assert_eq!(Region::from_timing(0), Region::Ntsc);
+        assert_eq!(Region::from_timing(1), Region::Pal);
+        assert_eq!(Region::from_timing(2), Region::Multi);
+        assert_eq!(Region::from_timing(0xFF), Region::Dendy);
+    }
+
+    #[test]
+    fn test_from_name() {
+        // Assert results
+        assert_eq!(Region::from_name("PAL"), Some(Region::Pal));
+        assert_eq!(Region::from_name("dendy"), Some(Region::Dendy));
+        assert_eq!(Region::from_name("multi"), Some(Region::Multi));
+        assert_eq!(Region::from_name("secam"), None);
+    }
+
+    #[test]
+    fn test_profiles() {
+        // Assert results, the clocks match the well known CPU rates
+        assert_eq!(
+            Region::Ntsc.get_profile().get_cpu_clock_hz().round(),
+            1789773.0
+        );
+        assert_eq!(
+            Region::Pal.get_profile().get_cpu_clock_hz().round(),
+            1662607.0
+        );
+        assert_eq!(
+            Region::Dendy.get_profile().get_cpu_clock_hz().round(),
+            1773448.0
+        );
+        assert_eq!(Region::Multi.get_profile().name, "NTSC");
+
+        // PAL consoles have 3.2 dots per CPU cycle
+        assert_eq!(f64::from(PAL.cpu_divider) / f64::from(PAL.ppu_divider), 3.2);
+
+        // Vblank lasts 20 lines apart from on PAL consoles
+        assert_eq!(NTSC.get_vblank_length(), 20);
+        assert_eq!(PAL.get_vblank_length(), 70);
+        assert_eq!(DENDY.get_vblank_length(), 20);
+    }
+}